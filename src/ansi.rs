@@ -0,0 +1,939 @@
+// src/ansi.rs
+
+use core::fmt;
+
+const ESC: u8 = 0x1B;
+
+/// A colour in an SGR sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Color {
+    /// One of the 256 palette colours; 0–7 are the standard colours and 8–15 their bright
+    /// variants.
+    Indexed(u8),
+    /// A 24-bit colour.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Standard colour 0, black.
+    pub const BLACK: Color = Color::Indexed(0);
+    /// Standard colour 1, red.
+    pub const RED: Color = Color::Indexed(1);
+    /// Standard colour 2, green.
+    pub const GREEN: Color = Color::Indexed(2);
+    /// Standard colour 3, yellow.
+    pub const YELLOW: Color = Color::Indexed(3);
+    /// Standard colour 4, blue.
+    pub const BLUE: Color = Color::Indexed(4);
+    /// Standard colour 5, magenta.
+    pub const MAGENTA: Color = Color::Indexed(5);
+    /// Standard colour 6, cyan.
+    pub const CYAN: Color = Color::Indexed(6);
+    /// Standard colour 7, white.
+    pub const WHITE: Color = Color::Indexed(7);
+}
+
+/// One attribute change of a Select Graphic Rendition (`ESC [ ... m`) sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sgr {
+    /// Back to the default style.
+    Reset,
+    /// Bold (or bright) text.
+    Bold,
+    /// Neither bold nor faint.
+    NormalIntensity,
+    /// Underlined text.
+    Underline,
+    /// Underlining off.
+    NoUnderline,
+    /// Swap foreground and background.
+    Reverse,
+    /// Stop swapping foreground and background.
+    NoReverse,
+    /// Set the foreground colour.
+    Foreground(Color),
+    /// Back to the default foreground colour.
+    DefaultForeground,
+    /// Set the background colour.
+    Background(Color),
+    /// Back to the default background colour.
+    DefaultBackground,
+}
+
+impl Sgr {
+    fn write_params(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let color = |f: &mut fmt::Formatter<'_>, base: u8, color: &Color| match *color {
+            Color::Indexed(n) if n < 8 => write!(f, "{}", base + n),
+            Color::Indexed(n) if n < 16 => write!(f, "{}", base + 60 + n - 8),
+            Color::Indexed(n) => write!(f, "{};5;{n}", base + 8),
+            Color::Rgb(r, g, b) => write!(f, "{};2;{r};{g};{b}", base + 8),
+        };
+        match self {
+            Sgr::Reset => write!(f, "0"),
+            Sgr::Bold => write!(f, "1"),
+            Sgr::NormalIntensity => write!(f, "22"),
+            Sgr::Underline => write!(f, "4"),
+            Sgr::NoUnderline => write!(f, "24"),
+            Sgr::Reverse => write!(f, "7"),
+            Sgr::NoReverse => write!(f, "27"),
+            Sgr::Foreground(c) => color(f, 30, c),
+            Sgr::DefaultForeground => write!(f, "39"),
+            Sgr::Background(c) => color(f, 40, c),
+            Sgr::DefaultBackground => write!(f, "49"),
+        }
+    }
+}
+
+/// Which part of the screen or line an erase covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EraseMode {
+    /// From the cursor to the end.
+    ToEnd,
+    /// From the start to the cursor.
+    ToStart,
+    /// The whole screen or line.
+    All,
+}
+
+/// A character or control function in terminal output.
+///
+/// [`AnsiParser`] produces these from a byte stream, and their `Display` form is the
+/// sequence that produces them, so a program can write e.g.
+/// `AnsiEvent::EraseDisplay(EraseMode::All).to_string()` to a terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnsiEvent {
+    /// A character to show at the cursor.
+    Print(char),
+    /// Ring the bell (BEL).
+    Bell,
+    /// Move the cursor one column left (BS).
+    Backspace,
+    /// Move the cursor to the next tab stop (HT).
+    Tab,
+    /// Move down a line, scrolling at the bottom; the column is kept.
+    LineFeed,
+    /// Move the cursor to the first column (CR).
+    CarriageReturn,
+    /// Move the cursor up this many rows (`ESC [ n A`).
+    CursorUp(u16),
+    /// Move the cursor down this many rows (`ESC [ n B`).
+    CursorDown(u16),
+    /// Move the cursor right this many columns (`ESC [ n C`).
+    CursorForward(u16),
+    /// Move the cursor left this many columns (`ESC [ n D`).
+    CursorBack(u16),
+    /// Move to a 0-based row and column.
+    CursorPosition { row: u16, column: u16 },
+    /// Erase part of the screen (`ESC [ n J`).
+    EraseDisplay(EraseMode),
+    /// Erase part of the cursor's line (`ESC [ n K`).
+    EraseLine(EraseMode),
+    /// Change the style of the text that follows (`ESC [ ... m`).
+    Graphics(Vec<Sgr>),
+    /// Remember the cursor position (`ESC 7`).
+    SaveCursor,
+    /// Move the cursor back to the remembered position (`ESC 8`).
+    RestoreCursor,
+    /// Show (`true`) or hide (`false`) the cursor (`ESC [ ? 25 h` and `l`).
+    ShowCursor(bool),
+    /// Full reset (`ESC c`).
+    Reset,
+}
+
+impl fmt::Display for AnsiEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = |m: &EraseMode| match m {
+            EraseMode::ToEnd => 0,
+            EraseMode::ToStart => 1,
+            EraseMode::All => 2,
+        };
+        match self {
+            AnsiEvent::Print(c) => write!(f, "{c}"),
+            AnsiEvent::Bell => write!(f, "\x07"),
+            AnsiEvent::Backspace => write!(f, "\x08"),
+            AnsiEvent::Tab => write!(f, "\t"),
+            AnsiEvent::LineFeed => writeln!(f),
+            AnsiEvent::CarriageReturn => write!(f, "\r"),
+            AnsiEvent::CursorUp(n) => write!(f, "\x1b[{n}A"),
+            AnsiEvent::CursorDown(n) => write!(f, "\x1b[{n}B"),
+            AnsiEvent::CursorForward(n) => write!(f, "\x1b[{n}C"),
+            AnsiEvent::CursorBack(n) => write!(f, "\x1b[{n}D"),
+            AnsiEvent::CursorPosition { row, column } => {
+                write!(f, "\x1b[{};{}H", u32::from(*row) + 1, u32::from(*column) + 1)
+            }
+            AnsiEvent::EraseDisplay(m) => write!(f, "\x1b[{}J", mode(m)),
+            AnsiEvent::EraseLine(m) => write!(f, "\x1b[{}K", mode(m)),
+            AnsiEvent::Graphics(attributes) => {
+                write!(f, "\x1b[")?;
+                for (i, attribute) in attributes.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ";")?;
+                    }
+                    attribute.write_params(f)?;
+                }
+                write!(f, "m")
+            }
+            AnsiEvent::SaveCursor => write!(f, "\x1b7"),
+            AnsiEvent::RestoreCursor => write!(f, "\x1b8"),
+            AnsiEvent::ShowCursor(true) => write!(f, "\x1b[?25h"),
+            AnsiEvent::ShowCursor(false) => write!(f, "\x1b[?25l"),
+            AnsiEvent::Reset => write!(f, "\x1bc"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    /// Operating system command, skipped up to BEL or ESC.
+    Osc,
+}
+
+/// Parses terminal output into [`AnsiEvent`]s, one byte at a time.
+///
+/// Understands the VT100 subset full-screen programs use: cursor movement and
+/// positioning, erasing, SGR styles and colours (16, 256 and 24-bit), saving the cursor
+/// and hiding it. Other sequences are consumed and ignored; UTF-8 text is decoded, with
+/// invalid bytes shown as U+FFFD.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{AnsiEvent, AnsiParser};
+///
+/// let mut parser = AnsiParser::new();
+/// let events: Vec<AnsiEvent> = b"\x1b[2;5Hhi".iter().filter_map(|&b| parser.feed(b)).collect();
+/// assert_eq!(events[0], AnsiEvent::CursorPosition { row: 1, column: 4 });
+/// ```
+#[derive(Debug, Clone)]
+pub struct AnsiParser {
+    state: State,
+    params: Vec<u16>,
+    private: bool,
+    utf8: Vec<u8>,
+}
+
+impl Default for AnsiParser {
+    fn default() -> Self {
+        AnsiParser::new()
+    }
+}
+
+impl AnsiParser {
+    /// Creates a parser outside of any sequence.
+    pub fn new() -> Self {
+        AnsiParser { state: State::Ground, params: Vec::new(), private: false, utf8: Vec::new() }
+    }
+
+    /// Feeds one byte of output, returning the event it completes, if any.
+    pub fn feed(&mut self, byte: u8) -> Option<AnsiEvent> {
+        match self.state {
+            State::Ground => self.ground(byte),
+            State::Escape => {
+                self.state = State::Ground;
+                match byte {
+                    b'[' => {
+                        self.state = State::Csi;
+                        self.params.clear();
+                        self.private = false;
+                        None
+                    }
+                    b']' => {
+                        self.state = State::Osc;
+                        None
+                    }
+                    b'7' => Some(AnsiEvent::SaveCursor),
+                    b'8' => Some(AnsiEvent::RestoreCursor),
+                    b'c' => Some(AnsiEvent::Reset),
+                    b'D' => Some(AnsiEvent::LineFeed),
+                    _ => None,
+                }
+            }
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    if self.params.is_empty() {
+                        self.params.push(0);
+                    }
+                    let last = self.params.last_mut()?;
+                    *last = last.saturating_mul(10).saturating_add(u16::from(byte - b'0'));
+                    None
+                }
+                b';' => {
+                    if self.params.is_empty() {
+                        self.params.push(0);
+                    }
+                    self.params.push(0);
+                    None
+                }
+                // Private markers and `:` sub-parameters: the sequence is consumed but
+                // only the `?25` cursor modes are understood.
+                b':' | b'<'..=b'?' => {
+                    self.private = true;
+                    None
+                }
+                // Intermediate bytes.
+                0x20..=0x2F => None,
+                0x40..=0x7E => {
+                    self.state = State::Ground;
+                    self.dispatch(byte)
+                }
+                _ => {
+                    self.state = State::Ground;
+                    None
+                }
+            },
+            State::Osc => {
+                match byte {
+                    0x07 => self.state = State::Ground,
+                    ESC => self.state = State::Escape,
+                    _ => {}
+                }
+                None
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8) -> Option<AnsiEvent> {
+        if byte >= 0x80 {
+            return self.utf8(byte);
+        }
+        // A multi-byte character cut short by this byte is dropped.
+        self.utf8.clear();
+        match byte {
+            ESC => {
+                self.state = State::Escape;
+                None
+            }
+            0x07 => Some(AnsiEvent::Bell),
+            0x08 => Some(AnsiEvent::Backspace),
+            b'\t' => Some(AnsiEvent::Tab),
+            b'\n' | 0x0B | 0x0C => Some(AnsiEvent::LineFeed),
+            b'\r' => Some(AnsiEvent::CarriageReturn),
+            0x20..=0x7E => Some(AnsiEvent::Print(byte as char)),
+            _ => None,
+        }
+    }
+
+    fn utf8(&mut self, byte: u8) -> Option<AnsiEvent> {
+        let continuation = byte & 0xC0 == 0x80;
+        if self.utf8.is_empty() == continuation {
+            // A stray continuation byte, or a new lead byte in the middle of a character.
+            self.utf8.clear();
+            if !continuation {
+                self.utf8.push(byte);
+            }
+            return Some(AnsiEvent::Print(char::REPLACEMENT_CHARACTER));
+        }
+        self.utf8.push(byte);
+        let length = match self.utf8[0] {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        if self.utf8.len() < length {
+            return None;
+        }
+        let c = std::str::from_utf8(&self.utf8)
+            .ok()
+            .and_then(|s| s.chars().next())
+            .unwrap_or(char::REPLACEMENT_CHARACTER);
+        self.utf8.clear();
+        Some(AnsiEvent::Print(c))
+    }
+
+    fn dispatch(&mut self, last: u8) -> Option<AnsiEvent> {
+        let param = |i: usize| self.params.get(i).copied().unwrap_or(0);
+        let count = param(0).max(1);
+        let mode = match param(0) {
+            1 => EraseMode::ToStart,
+            2 | 3 => EraseMode::All,
+            _ => EraseMode::ToEnd,
+        };
+        match last {
+            b'h' | b'l' if self.private && param(0) == 25 => {
+                Some(AnsiEvent::ShowCursor(last == b'h'))
+            }
+            _ if self.private => None,
+            b'A' => Some(AnsiEvent::CursorUp(count)),
+            b'B' => Some(AnsiEvent::CursorDown(count)),
+            b'C' => Some(AnsiEvent::CursorForward(count)),
+            b'D' => Some(AnsiEvent::CursorBack(count)),
+            b'H' | b'f' => Some(AnsiEvent::CursorPosition {
+                row: param(0).max(1) - 1,
+                column: param(1).max(1) - 1,
+            }),
+            b'J' => Some(AnsiEvent::EraseDisplay(mode)),
+            b'K' => Some(AnsiEvent::EraseLine(mode)),
+            b'm' => Some(AnsiEvent::Graphics(parse_sgr(&self.params))),
+            b's' => Some(AnsiEvent::SaveCursor),
+            b'u' => Some(AnsiEvent::RestoreCursor),
+            _ => None,
+        }
+    }
+}
+
+fn parse_sgr(params: &[u16]) -> Vec<Sgr> {
+    if params.is_empty() {
+        return vec![Sgr::Reset];
+    }
+    let mut attributes = Vec::new();
+    let mut rest = params.iter().copied();
+    while let Some(param) = rest.next() {
+        let mut extended = || match rest.next() {
+            Some(5) => rest.next().map(|n| Color::Indexed(n as u8)),
+            Some(2) => {
+                let mut channel = || rest.next().unwrap_or(0) as u8;
+                Some(Color::Rgb(channel(), channel(), channel()))
+            }
+            _ => None,
+        };
+        let attribute = match param {
+            0 => Sgr::Reset,
+            1 => Sgr::Bold,
+            22 => Sgr::NormalIntensity,
+            4 => Sgr::Underline,
+            24 => Sgr::NoUnderline,
+            7 => Sgr::Reverse,
+            27 => Sgr::NoReverse,
+            30..=37 => Sgr::Foreground(Color::Indexed((param - 30) as u8)),
+            90..=97 => Sgr::Foreground(Color::Indexed((param - 90 + 8) as u8)),
+            38 => match extended() {
+                Some(color) => Sgr::Foreground(color),
+                None => continue,
+            },
+            39 => Sgr::DefaultForeground,
+            40..=47 => Sgr::Background(Color::Indexed((param - 40) as u8)),
+            100..=107 => Sgr::Background(Color::Indexed((param - 100 + 8) as u8)),
+            48 => match extended() {
+                Some(color) => Sgr::Background(color),
+                None => continue,
+            },
+            49 => Sgr::DefaultBackground,
+            _ => continue,
+        };
+        attributes.push(attribute);
+    }
+    attributes
+}
+
+/// Rendition of a screen cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    /// Foreground colour, `None` for the default.
+    pub foreground: Option<Color>,
+    /// Background colour, `None` for the default.
+    pub background: Option<Color>,
+    /// Bold (or bright) text.
+    pub bold: bool,
+    /// Underlined text.
+    pub underline: bool,
+    /// Foreground and background swapped.
+    pub reverse: bool,
+}
+
+impl Style {
+    /// Applies one SGR attribute.
+    pub fn apply(&mut self, attribute: Sgr) {
+        match attribute {
+            Sgr::Reset => *self = Style::default(),
+            Sgr::Bold => self.bold = true,
+            Sgr::NormalIntensity => self.bold = false,
+            Sgr::Underline => self.underline = true,
+            Sgr::NoUnderline => self.underline = false,
+            Sgr::Reverse => self.reverse = true,
+            Sgr::NoReverse => self.reverse = false,
+            Sgr::Foreground(color) => self.foreground = Some(color),
+            Sgr::DefaultForeground => self.foreground = None,
+            Sgr::Background(color) => self.background = Some(color),
+            Sgr::DefaultBackground => self.background = None,
+        }
+    }
+}
+
+/// One character position of a [`Screen`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    /// The character shown, a space if nothing was written.
+    pub ch: char,
+    /// The rendition the character was written with.
+    pub style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell { ch: ' ', style: Style::default() }
+    }
+}
+
+/// The contents of a VT100-style screen, updated from terminal output.
+///
+/// Text wraps at the right margin and the screen scrolls up when a line feed reaches the
+/// bottom row. A line feed does not return the carriage; the terminal driver's `crlf`
+/// setting sends `"\r\n"` for that.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::Screen;
+///
+/// let mut screen = Screen::new(24, 80);
+/// screen.feed(b"\x1b[2J\x1b[1;1H\x1b[1mready\x1b[0m\r\n");
+/// assert_eq!(screen.row_text(0), "ready");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Screen {
+    rows: usize,
+    columns: usize,
+    cells: Vec<Cell>,
+    /// Cursor row and column; the column equals `columns` after writing the last column,
+    /// until the next character wraps.
+    cursor: (usize, usize),
+    saved: (usize, usize),
+    style: Style,
+    cursor_visible: bool,
+    parser: AnsiParser,
+}
+
+impl Screen {
+    /// Creates a blank screen of at least one row and column.
+    pub fn new(rows: usize, columns: usize) -> Self {
+        let (rows, columns) = (rows.max(1), columns.max(1));
+        Screen {
+            rows,
+            columns,
+            cells: vec![Cell::default(); rows * columns],
+            cursor: (0, 0),
+            saved: (0, 0),
+            style: Style::default(),
+            cursor_visible: true,
+            parser: AnsiParser::new(),
+        }
+    }
+
+    /// Number of rows.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Number of columns.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Cursor row and column, both 0-based and on the screen.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor.0, self.cursor.1.min(self.columns - 1))
+    }
+
+    /// Returns `false` while the cursor is hidden by [`AnsiEvent::ShowCursor`].
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Cell at `row` and `column`; off-screen positions read as blank.
+    pub fn cell(&self, row: usize, column: usize) -> Cell {
+        if row < self.rows && column < self.columns {
+            self.cells[row * self.columns + column]
+        } else {
+            Cell::default()
+        }
+    }
+
+    /// Text of `row` without trailing blanks.
+    pub fn row_text(&self, row: usize) -> String {
+        let text: String = (0..self.columns).map(|c| self.cell(row, c).ch).collect();
+        text.trim_end().to_string()
+    }
+
+    /// Parses and applies terminal output.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if let Some(event) = self.parser.feed(byte) {
+                self.apply(&event);
+            }
+        }
+    }
+
+    /// Applies one parsed event.
+    pub fn apply(&mut self, event: &AnsiEvent) {
+        let (row, column) = self.cursor;
+        let last_row = self.rows - 1;
+        let last_column = self.columns - 1;
+        let step = |n: &u16| usize::from(*n);
+        match event {
+            AnsiEvent::Print(c) => {
+                if column >= self.columns {
+                    self.cursor.1 = 0;
+                    self.line_feed();
+                }
+                let (row, column) = self.cursor;
+                self.cells[row * self.columns + column] = Cell { ch: *c, style: self.style };
+                self.cursor.1 = column + 1;
+            }
+            AnsiEvent::Bell => {}
+            AnsiEvent::Backspace => self.cursor.1 = column.min(last_column).saturating_sub(1),
+            AnsiEvent::Tab => self.cursor.1 = ((column / 8 + 1) * 8).min(last_column),
+            AnsiEvent::LineFeed => self.line_feed(),
+            AnsiEvent::CarriageReturn => self.cursor.1 = 0,
+            AnsiEvent::CursorUp(n) => self.cursor.0 = row.saturating_sub(step(n)),
+            AnsiEvent::CursorDown(n) => self.cursor.0 = (row + step(n)).min(last_row),
+            AnsiEvent::CursorForward(n) => {
+                self.cursor.1 = (column.min(last_column) + step(n)).min(last_column)
+            }
+            AnsiEvent::CursorBack(n) => {
+                self.cursor.1 = column.min(last_column).saturating_sub(step(n))
+            }
+            AnsiEvent::CursorPosition { row, column } => {
+                self.cursor = (step(row).min(last_row), step(column).min(last_column))
+            }
+            AnsiEvent::EraseDisplay(mode) => {
+                let here = row * self.columns + column.min(last_column);
+                let range = match mode {
+                    EraseMode::ToEnd => here..self.cells.len(),
+                    EraseMode::ToStart => 0..here + 1,
+                    EraseMode::All => 0..self.cells.len(),
+                };
+                self.erase(range);
+            }
+            AnsiEvent::EraseLine(mode) => {
+                let start = row * self.columns;
+                let here = start + column.min(last_column);
+                let range = match mode {
+                    EraseMode::ToEnd => here..start + self.columns,
+                    EraseMode::ToStart => start..here + 1,
+                    EraseMode::All => start..start + self.columns,
+                };
+                self.erase(range);
+            }
+            AnsiEvent::Graphics(attributes) => {
+                for attribute in attributes {
+                    self.style.apply(*attribute);
+                }
+            }
+            AnsiEvent::SaveCursor => self.saved = self.cursor(),
+            AnsiEvent::RestoreCursor => self.cursor = self.saved,
+            AnsiEvent::ShowCursor(visible) => self.cursor_visible = *visible,
+            AnsiEvent::Reset => *self = Screen::new(self.rows, self.columns),
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.cursor.0 + 1 < self.rows {
+            self.cursor.0 += 1;
+        } else {
+            self.cells.drain(..self.columns);
+            self.cells.extend(std::iter::repeat_n(Cell::default(), self.columns));
+        }
+    }
+
+    fn erase(&mut self, range: core::ops::Range<usize>) {
+        // Erased cells keep the current background, as on a VT100.
+        let blank = Cell {
+            ch: ' ',
+            style: Style { background: self.style.background, ..Style::default() },
+        };
+        self.cells[range].fill(blank);
+    }
+}
+
+/// A key decoded from terminal input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A printable character.
+    Char(char),
+    /// A character typed with Alt (sent as ESC followed by the character).
+    Alt(char),
+    /// Ctrl with a letter or one of `@[\]^_`, given in upper case.
+    Ctrl(char),
+    /// Return or line feed.
+    Enter,
+    /// Tab, or Shift-Tab (`ESC [ Z`).
+    Tab,
+    /// Backspace or DEL.
+    Backspace,
+    /// The Escape key on its own.
+    Escape,
+    /// Arrow up.
+    Up,
+    /// Arrow down.
+    Down,
+    /// Arrow left.
+    Left,
+    /// Arrow right.
+    Right,
+    /// Home.
+    Home,
+    /// End.
+    End,
+    /// Insert.
+    Insert,
+    /// Delete (forward).
+    Delete,
+    /// Page up.
+    PageUp,
+    /// Page down.
+    PageDown,
+    /// F1 to F12.
+    Function(u8),
+}
+
+/// Decodes terminal input into [`Key`]s, one byte at a time.
+///
+/// Arrow, editing and function keys arrive as `ESC [` (CSI) or `ESC O` (SS3) sequences,
+/// in the forms xterm and the VT100 send; modifier parameters are ignored. A lone ESC
+/// cannot be told from the start of a sequence until more input arrives or none does:
+/// [`KeyDecoder::flush`] resolves it once the caller has waited long enough.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{Key, KeyDecoder};
+///
+/// let mut decoder = KeyDecoder::new();
+/// let keys: Vec<Key> = b"\x1b[Ax\x1bOP".iter().filter_map(|&b| decoder.push(b)).collect();
+/// assert_eq!(keys, [Key::Up, Key::Char('x'), Key::Function(1)]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeyDecoder {
+    pending: Vec<u8>,
+}
+
+impl KeyDecoder {
+    /// Creates a decoder with nothing buffered.
+    pub const fn new() -> Self {
+        KeyDecoder { pending: Vec::new() }
+    }
+
+    /// Returns `true` if bytes of an unfinished key are buffered.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Feeds one byte of input, returning the key it completes, if any. Unknown escape
+    /// sequences are dropped.
+    pub fn push(&mut self, byte: u8) -> Option<Key> {
+        self.pending.push(byte);
+        let pending = self.pending.as_slice();
+        let key = match pending {
+            [ESC] => return None,
+            [ESC, b'[' | b'O'] => return None,
+            [ESC, b'[', body @ ..] => {
+                let (&last, params) = body.split_last()?;
+                if !(0x40..=0x7E).contains(&last) {
+                    if body.len() > 16 {
+                        self.pending.clear();
+                    }
+                    return None;
+                }
+                csi_key(params, last)
+            }
+            [ESC, b'O', last] => ss3_key(*last),
+            [ESC, ESC] => {
+                // The second ESC starts the next key, e.g. an Alt sequence.
+                self.pending.truncate(1);
+                return Some(Key::Escape);
+            }
+            [ESC, byte] => match control_key(*byte) {
+                Some(Key::Char(c)) => Some(Key::Alt(c)),
+                _ => Some(Key::Escape),
+            },
+            [first, ..] if *first >= 0x80 => {
+                let length = match first {
+                    0xC0..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF7 => 4,
+                    _ => 1,
+                };
+                if pending.len() < length {
+                    return None;
+                }
+                let c = std::str::from_utf8(pending).ok().and_then(|s| s.chars().next());
+                Some(Key::Char(c.unwrap_or(char::REPLACEMENT_CHARACTER)))
+            }
+            [byte] => control_key(*byte),
+            _ => None,
+        };
+        self.pending.clear();
+        key
+    }
+
+    /// Resolves buffered bytes once no more input is coming: a lone ESC is the Escape
+    /// key, ESC followed by `[` or `O` is Alt with that character, and anything else
+    /// unfinished is dropped.
+    pub fn flush(&mut self) -> Option<Key> {
+        let key = match self.pending.as_slice() {
+            [ESC] => Some(Key::Escape),
+            [ESC, byte] => Some(Key::Alt(*byte as char)),
+            _ => None,
+        };
+        self.pending.clear();
+        key
+    }
+}
+
+fn control_key(byte: u8) -> Option<Key> {
+    Some(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x08 | 0x7F => Key::Backspace,
+        ESC => Key::Escape,
+        0x00..=0x1F => Key::Ctrl((byte + 0x40) as char),
+        0x20..=0x7E => Key::Char(byte as char),
+        _ => return None,
+    })
+}
+
+fn csi_key(params: &[u8], last: u8) -> Option<Key> {
+    // Only the first parameter matters; a second one carries modifiers.
+    let first = params.split(|&b| b == b';').next().unwrap_or_default();
+    let number = std::str::from_utf8(first).ok().and_then(|s| s.parse::<u8>().ok());
+    match last {
+        b'~' => match number? {
+            1 | 7 => Some(Key::Home),
+            2 => Some(Key::Insert),
+            3 => Some(Key::Delete),
+            4 | 8 => Some(Key::End),
+            5 => Some(Key::PageUp),
+            6 => Some(Key::PageDown),
+            n @ 11..=15 => Some(Key::Function(n - 10)),
+            n @ 17..=21 => Some(Key::Function(n - 11)),
+            n @ 23..=24 => Some(Key::Function(n - 12)),
+            _ => None,
+        },
+        b'Z' => Some(Key::Tab),
+        _ => ss3_key(last),
+    }
+}
+
+fn ss3_key(last: u8) -> Option<Key> {
+    match last {
+        b'A' => Some(Key::Up),
+        b'B' => Some(Key::Down),
+        b'C' => Some(Key::Right),
+        b'D' => Some(Key::Left),
+        b'H' => Some(Key::Home),
+        b'F' => Some(Key::End),
+        b'P'..=b'S' => Some(Key::Function(last - b'P' + 1)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<AnsiEvent> {
+        let mut parser = AnsiParser::new();
+        bytes.iter().filter_map(|&b| parser.feed(b)).collect()
+    }
+
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        let mut decoder = KeyDecoder::new();
+        bytes.iter().filter_map(|&b| decoder.push(b)).collect()
+    }
+
+    #[test]
+    fn test_parse_output() {
+        assert_eq!(
+            parse(b"a\x1b[3;7H\x1b[2J\x1b[K\x1b[5A\x1b[?25l\x1b]0;title\x07\x1b[1;31;48;5;200m\xc3\xa9"),
+            vec![
+                AnsiEvent::Print('a'),
+                AnsiEvent::CursorPosition { row: 2, column: 6 },
+                AnsiEvent::EraseDisplay(EraseMode::All),
+                AnsiEvent::EraseLine(EraseMode::ToEnd),
+                AnsiEvent::CursorUp(5),
+                AnsiEvent::ShowCursor(false),
+                AnsiEvent::Graphics(vec![
+                    Sgr::Bold,
+                    Sgr::Foreground(Color::RED),
+                    Sgr::Background(Color::Indexed(200))
+                ]),
+                AnsiEvent::Print('é'),
+            ]
+        );
+        assert_eq!(
+            parse(b"\xff\x1b[m"),
+            [AnsiEvent::Print('\u{fffd}'), AnsiEvent::Graphics(vec![Sgr::Reset])]
+        );
+        // Device attribute queries, mouse reports and colon sub-parameters are skipped
+        // whole instead of leaking their tails as text.
+        assert_eq!(
+            parse(b"\x1b[>c\x1b[=1u\x1b[<0;3;4M\x1b[4:3mok"),
+            [AnsiEvent::Print('o'), AnsiEvent::Print('k')]
+        );
+    }
+
+    #[test]
+    fn test_events_round_trip() {
+        let events = vec![
+            AnsiEvent::CursorPosition { row: 0, column: 79 },
+            AnsiEvent::EraseLine(EraseMode::ToStart),
+            AnsiEvent::CursorBack(2),
+            AnsiEvent::Graphics(vec![
+                Sgr::Reset,
+                Sgr::Foreground(Color::Indexed(12)),
+                Sgr::Background(Color::Rgb(1, 2, 3)),
+                Sgr::Underline,
+            ]),
+            AnsiEvent::SaveCursor,
+            AnsiEvent::ShowCursor(true),
+            AnsiEvent::Print('x'),
+            AnsiEvent::Reset,
+        ];
+        let text: String = events.iter().map(ToString::to_string).collect();
+        assert_eq!(parse(text.as_bytes()), events);
+    }
+
+    #[test]
+    fn test_screen_draws_wraps_and_scrolls() {
+        let mut screen = Screen::new(3, 5);
+        let rows = |screen: &Screen| (0..3).map(|r| screen.row_text(r)).collect::<Vec<_>>();
+        screen.feed(b"hello world");
+        assert_eq!(rows(&screen), ["hello", " worl", "d"]);
+        screen.feed(b"\r\n\x1b[31mred");
+        assert_eq!(rows(&screen), [" worl", "d", "red"]);
+        assert_eq!(screen.cell(2, 1).style.foreground, Some(Color::RED));
+        assert_eq!(screen.cell(1, 0).style.foreground, None);
+
+        screen.feed(b"\x1b[2;3H\x1b[1K\x1b[1;5Hx\x1b[2;1H\x1b[0J");
+        assert_eq!(rows(&screen), [" worx", "", ""]);
+        assert_eq!(screen.cursor(), (1, 0));
+    }
+
+    #[test]
+    fn test_decode_keys() {
+        assert_eq!(
+            keys(
+                b"a\r\x7f\x03\x1b[A\x1b[1;5D\x1bOQ\x1b[15~\x1b[24~\x1b[3~\x1bx\xe2\x82\xac\x1b[99~"
+            ),
+            [
+                Key::Char('a'),
+                Key::Enter,
+                Key::Backspace,
+                Key::Ctrl('C'),
+                Key::Up,
+                Key::Left,
+                Key::Function(2),
+                Key::Function(5),
+                Key::Function(12),
+                Key::Delete,
+                Key::Alt('x'),
+                Key::Char('€'),
+            ]
+        );
+
+        let mut decoder = KeyDecoder::new();
+        assert_eq!(decoder.push(0x1B), None);
+        assert!(decoder.is_pending());
+        assert_eq!(decoder.flush(), Some(Key::Escape));
+        assert_eq!((decoder.push(0x1B), decoder.push(0x1B)), (None, Some(Key::Escape)));
+        assert_eq!(decoder.flush(), Some(Key::Escape));
+        assert_eq!(
+            keys(b"\x1b\x1bx\x1b\x1b[B"),
+            [Key::Escape, Key::Alt('x'), Key::Escape, Key::Down]
+        );
+    }
+}
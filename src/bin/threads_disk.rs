@@ -0,0 +1,217 @@
+// src/bin/threads_disk.rs
+
+//! Host-side tool for THREADS disk image files.
+//!
+//! ```text
+//! threads_disk info   <image>
+//! threads_disk dump   <image> <platter> <track> <sector> [count]
+//! threads_disk create <image> <tracks> [--mkfs] [--block-size N] [--journal N]
+//! threads_disk fsck   <image> [--repair]
+//! threads_disk overlay  <overlay> <base>
+//! threads_disk snapshot <overlay> <base> [name]
+//! threads_disk revert   <overlay> <base> <name>
+//! threads_disk flatten  <overlay> <base> <image>
+//! ```
+
+use std::process::ExitCode;
+
+use rusty_threads::{
+    CowImage, DiskAddress, DiskGeometry, DiskImage, FileSystem, MkfsOptions, PartitionTable,
+    Superblock, fsck, hex_dump, mkfs, repair,
+};
+
+const USAGE: &str = "usage:
+  threads_disk info   <image>
+  threads_disk dump   <image> <platter> <track> <sector> [count]
+  threads_disk create <image> <tracks> [--mkfs] [--block-size N] [--journal N]
+  threads_disk fsck   <image> [--repair]
+  threads_disk overlay  <overlay> <base>
+  threads_disk snapshot <overlay> <base> [name]
+  threads_disk revert   <overlay> <base> <name>
+  threads_disk flatten  <overlay> <base> <image>";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("threads_disk: {message}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<ExitCode, String> {
+    let (command, rest) = args.split_first().ok_or(USAGE)?;
+    let (path, options) = rest.split_first().ok_or(USAGE)?;
+    match command.as_str() {
+        "info" => info(path),
+        "dump" => dump(path, options),
+        "create" => create(path, options),
+        "fsck" => check(path, options),
+        "overlay" | "snapshot" | "revert" | "flatten" => overlay(command, path, options),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn number<T: std::str::FromStr>(text: Option<&String>, what: &str) -> Result<T, String> {
+    let text = text.ok_or_else(|| format!("missing {what}\n{USAGE}"))?;
+    text.parse().map_err(|_| format!("invalid {what}: {text}"))
+}
+
+/// Value of `--name N`, if present.
+fn option<T: std::str::FromStr>(options: &[String], name: &str) -> Result<Option<T>, String> {
+    match options.iter().position(|o| o == name) {
+        Some(i) => number(options.get(i + 1), name).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn open(path: &str, block_size: usize) -> Result<DiskImage, String> {
+    DiskImage::open(path, block_size).map_err(|e| format!("{path}: {e:?}"))
+}
+
+fn sector_size() -> usize {
+    DiskGeometry::with_tracks(1).sector_size as usize
+}
+
+fn info(path: &str) -> Result<ExitCode, String> {
+    let mut image = open(path, sector_size())?;
+    let g = image.geometry();
+    println!("{path}");
+    println!("  platters:          {}", g.platters);
+    println!("  tracks:            {}", g.tracks);
+    println!("  sectors per track: {}", g.sectors_per_track);
+    println!("  sector size:       {}", g.sector_size);
+    println!("  capacity:          {} bytes ({} sectors)", g.capacity(), g.total_sectors());
+
+    let mut sector = vec![0; sector_size()];
+    image.read_sector(DiskAddress::new(0, 0, 0), &mut sector).map_err(|e| format!("{e:?}"))?;
+    match Superblock::probe(&sector) {
+        Ok(block_size) => {
+            let mut fs = FileSystem::mount(open(path, block_size)?)
+                .map_err(|e| format!("file system: {e:?}"))?;
+            let sb = fs.superblock();
+            let stats = fs.statfs().map_err(|e| format!("file system: {e:?}"))?;
+            println!("  file system:       {} blocks of {} bytes", sb.block_count, sb.block_size);
+            println!("    free blocks:     {} of {}", stats.free_blocks, stats.data_blocks);
+            println!("    free inodes:     {} of {}", stats.free_inodes, stats.inodes);
+            println!("    journal:         {} blocks", sb.journal_blocks);
+        }
+        Err(_) => match PartitionTable::decode(&sector, g.total_sectors()) {
+            Ok(table) => {
+                println!("  partitions:        {}", table.entries().len());
+                for entry in table.entries() {
+                    println!(
+                        "    {:<20} sectors {}..{} ({} bytes)",
+                        entry.name,
+                        entry.start,
+                        entry.start + entry.sectors,
+                        u64::from(entry.sectors) * u64::from(g.sector_size)
+                    );
+                }
+            }
+            Err(_) => println!("  file system:       none"),
+        },
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn dump(path: &str, options: &[String]) -> Result<ExitCode, String> {
+    let mut image = open(path, sector_size())?;
+    let start = DiskAddress::new(
+        number(options.first(), "platter")?,
+        number(options.get(1), "track")?,
+        number(options.get(2), "sector")?,
+    );
+    let count: u32 = if options.len() > 3 { number(options.get(3), "count")? } else { 1 };
+    let geometry = image.geometry();
+    let first = geometry.block_of(start).map_err(|_| format!("{start:?} is not on the disk"))?;
+
+    let mut sector = vec![0; sector_size()];
+    for block in first..(first + u64::from(count)).min(geometry.total_sectors()) {
+        let address = geometry.address_of(block).map_err(|e| format!("{e:?}"))?;
+        image.read_sector(address, &mut sector).map_err(|e| format!("{e:?}"))?;
+        println!("platter {} track {} sector {}:", address.platter, address.track, address.sector);
+        print!("{}", hex_dump(&sector, block * sector.len() as u64));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn create(path: &str, options: &[String]) -> Result<ExitCode, String> {
+    let tracks = number(options.first(), "tracks")?;
+    let block_size = option(options, "--block-size")?.unwrap_or(1024);
+    let geometry = DiskGeometry::with_tracks(tracks);
+    let image =
+        DiskImage::create(path, geometry, block_size).map_err(|e| format!("{path}: {e:?}"))?;
+    println!("created {path}: {tracks} tracks, {} bytes", geometry.capacity());
+
+    if options.iter().any(|o| o == "--mkfs") {
+        let journal_blocks = option(options, "--journal")?.unwrap_or(0);
+        let fs = mkfs(image, MkfsOptions { journal_blocks, ..Default::default() })
+            .map_err(|e| format!("mkfs: {e:?}"))?;
+        let sb = fs.superblock();
+        fs.unmount().map_err(|e| format!("mkfs: {e:?}"))?;
+        println!(
+            "formatted: {} blocks of {} bytes, {} inodes, {} journal blocks",
+            sb.block_count, sb.block_size, sb.inode_count, sb.journal_blocks
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn check(path: &str, options: &[String]) -> Result<ExitCode, String> {
+    let mut image = open(path, sector_size())?;
+    let mut sector = vec![0; sector_size()];
+    image.read_sector(DiskAddress::new(0, 0, 0), &mut sector).map_err(|e| format!("{e:?}"))?;
+    let block_size =
+        Superblock::probe(&sector).map_err(|_| format!("{path}: no file system found"))?;
+    // Mounting replays the journal, so the check sees the volume as the kernel would.
+    let mut fs = FileSystem::mount(open(path, block_size)?).map_err(|e| format!("{e:?}"))?;
+
+    let repairing = options.iter().any(|o| o == "--repair");
+    let report = if repairing { repair(&mut fs) } else { fsck(&mut fs) };
+    let report = report.map_err(|e| format!("fsck: {e:?}"))?;
+    fs.unmount().map_err(|e| format!("{e:?}"))?;
+
+    for problem in &report.problems {
+        println!("{problem}");
+    }
+    match (report.is_clean(), repairing) {
+        (true, _) => println!("{path}: clean"),
+        (false, true) => println!("{path}: {} problems repaired", report.problems.len()),
+        (false, false) => {
+            println!("{path}: {} problems found", report.problems.len());
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn overlay(command: &str, path: &str, options: &[String]) -> Result<ExitCode, String> {
+    let base = options.first().ok_or(USAGE)?;
+    let argument = options.get(1);
+    let error = |e| format!("{path}: {e:?}");
+    if command == "overlay" {
+        CowImage::create(base, path, sector_size()).map_err(error)?;
+        println!("created overlay {path} over {base}");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut image = CowImage::open(base, path, sector_size()).map_err(error)?;
+    match (command, argument) {
+        ("snapshot", Some(name)) => image.snapshot(name).map_err(error)?,
+        ("snapshot", None) => {
+            for snapshot in image.snapshots() {
+                println!("{}", snapshot.name);
+            }
+            println!("{} sectors changed", image.changed_sectors());
+        }
+        ("revert", Some(name)) => image.revert(name).map_err(error)?,
+        ("flatten", Some(output)) => {
+            image.flatten(output).map_err(error)?;
+            println!("wrote {output}");
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,290 @@
+// src/block_device.rs
+
+use core::ops::Range;
+
+use crate::constants::THREADS_DISK_SECTOR_SIZE;
+use crate::device::DeviceId;
+use crate::disk::{self, DiskAddress, DiskError};
+use crate::disk_geometry::DiskGeometry;
+
+/// Errors returned by block devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// The block size is zero or not a multiple of `THREADS_DISK_SECTOR_SIZE`.
+    InvalidBlockSize,
+    /// The block number is past the end of the device.
+    OutOfRange,
+    /// The buffer length differs from the block size.
+    BufferSize,
+    /// The underlying disk reported an error.
+    Disk(DiskError),
+    /// The device was stopped by crash injection and accepts no more requests.
+    Crashed,
+    /// A host-side image file could not be read or written.
+    Io(std::io::ErrorKind),
+    /// No working copy of the block is left, e.g. a RAID array lost too many disks.
+    Unavailable,
+}
+
+impl From<std::io::Error> for BlockError {
+    fn from(error: std::io::Error) -> Self {
+        BlockError::Io(error.kind())
+    }
+}
+
+impl From<DiskError> for BlockError {
+    fn from(error: DiskError) -> Self {
+        BlockError::Disk(error)
+    }
+}
+
+/// A device addressed in fixed-size blocks numbered from zero.
+///
+/// Block sizes are whole multiples of the 512-byte THREADS sector, so a block always maps
+/// onto a run of complete sectors. Buffers passed to `read_block` and `write_block` must
+/// be exactly `block_size()` bytes long.
+pub trait BlockDevice {
+    /// Size of a block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// Number of blocks on the device.
+    fn block_count(&self) -> u64;
+
+    /// Reads block `block` into `buffer`.
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError>;
+
+    /// Writes `buffer` to block `block`.
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError>;
+
+    /// Makes every completed write durable. Devices without caching do nothing.
+    fn flush(&mut self) -> Result<(), BlockError> {
+        Ok(())
+    }
+}
+
+/// Checks that `block_size` is a non-zero multiple of the sector size.
+pub fn validate_block_size(block_size: usize) -> Result<(), BlockError> {
+    let sector_size = THREADS_DISK_SECTOR_SIZE as usize;
+    if block_size == 0 || !block_size.is_multiple_of(sector_size) {
+        return Err(BlockError::InvalidBlockSize);
+    }
+    Ok(())
+}
+
+/// Checks a block number and buffer length against `device`.
+pub(crate) fn check_request(
+    device: &impl BlockDevice,
+    block: u64,
+    length: usize,
+) -> Result<(), BlockError> {
+    if block >= device.block_count() {
+        return Err(BlockError::OutOfRange);
+    }
+    if length != device.block_size() {
+        return Err(BlockError::BufferSize);
+    }
+    Ok(())
+}
+
+/// A THREADS disk seen as a [`BlockDevice`], using the linear block numbering of
+/// [`DiskGeometry`].
+///
+/// Reads and writes block the calling process in the disk driver, so they must be made
+/// from a running process.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{BlockDevice, DeviceId, ThreadsDisk};
+///
+/// let mut disk = ThreadsDisk::open(DeviceId::disk(0), 1024)?;
+/// let mut block = vec![0u8; disk.block_size()];
+/// disk.read_block(3, &mut block)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadsDisk {
+    disk: DeviceId,
+    geometry: DiskGeometry,
+    block_size: usize,
+}
+
+impl ThreadsDisk {
+    /// Opens `disk` with blocks of `block_size` bytes, querying its geometry with
+    /// `DISK_INFO`. Sectors left over at the end of the disk are not addressable.
+    pub fn open(disk: DeviceId, block_size: usize) -> Result<Self, BlockError> {
+        validate_block_size(block_size)?;
+        let geometry = disk::disk_info(disk)?;
+        Ok(ThreadsDisk { disk, geometry, block_size })
+    }
+
+    /// The disk this device reads and writes.
+    pub fn disk(&self) -> DeviceId {
+        self.disk
+    }
+
+    /// Geometry reported by the disk when it was opened.
+    pub fn geometry(&self) -> DiskGeometry {
+        self.geometry
+    }
+
+    fn sectors_per_block(&self) -> u64 {
+        (self.block_size / THREADS_DISK_SECTOR_SIZE as usize) as u64
+    }
+
+    /// Splits block `block` into runs of sectors that do not cross a platter, calling
+    /// `transfer(address, byte range)` for each.
+    fn for_each_run(
+        &self,
+        block: u64,
+        mut transfer: impl FnMut(DiskAddress, Range<usize>) -> Result<(), DiskError>,
+    ) -> Result<(), BlockError> {
+        let sector_size = THREADS_DISK_SECTOR_SIZE as usize;
+        let mut sector = block * self.sectors_per_block();
+        let end = sector + self.sectors_per_block();
+        let mut offset = 0;
+        while sector < end {
+            let address = self.geometry.address_of(sector)?;
+            let left_on_platter =
+                self.geometry.sectors_per_platter() - sector % self.geometry.sectors_per_platter();
+            let run = (end - sector).min(left_on_platter) as usize;
+            transfer(address, offset..offset + run * sector_size)?;
+            sector += run as u64;
+            offset += run * sector_size;
+        }
+        Ok(())
+    }
+}
+
+impl BlockDevice for ThreadsDisk {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.geometry.total_sectors() / self.sectors_per_block()
+    }
+
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        self.for_each_run(block, |address, range| {
+            disk::read_sectors(self.disk, address, &mut buffer[range])
+        })
+    }
+
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        self.for_each_run(block, |address, range| {
+            disk::write_sectors(self.disk, address, &buffer[range])
+        })
+    }
+}
+
+/// A [`BlockDevice`] held in memory, for host-side tools and tests.
+///
+/// It counts the reads and writes it serves, which makes it easy to observe what a layer
+/// above it (such as [`crate::buffer_cache::BufferCache`]) actually sends to the device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemBlockDevice {
+    block_size: usize,
+    data: Vec<u8>,
+    reads: u64,
+    writes: u64,
+}
+
+impl MemBlockDevice {
+    /// Creates a zero-filled device of `block_count` blocks of `block_size` bytes.
+    pub fn new(block_size: usize, block_count: u64) -> Result<Self, BlockError> {
+        validate_block_size(block_size)?;
+        Ok(MemBlockDevice {
+            block_size,
+            data: vec![0; block_size * block_count as usize],
+            reads: 0,
+            writes: 0,
+        })
+    }
+
+    /// Wraps an existing image. Trailing bytes that do not fill a block are kept but not
+    /// addressable.
+    pub fn from_bytes(block_size: usize, data: Vec<u8>) -> Result<Self, BlockError> {
+        validate_block_size(block_size)?;
+        Ok(MemBlockDevice { block_size, data, reads: 0, writes: 0 })
+    }
+
+    /// The raw contents of the device.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Consumes the device, returning its raw contents.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Number of blocks read so far.
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Number of blocks written so far.
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    fn range(&self, block: u64) -> Range<usize> {
+        let start = block as usize * self.block_size;
+        start..start + self.block_size
+    }
+}
+
+impl BlockDevice for MemBlockDevice {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.len() / self.block_size) as u64
+    }
+
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        buffer.copy_from_slice(&self.data[self.range(block)]);
+        self.reads += 1;
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        let range = self.range(block);
+        self.data[range].copy_from_slice(buffer);
+        self.writes += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_size_validation() {
+        assert_eq!(validate_block_size(512), Ok(()));
+        assert_eq!(validate_block_size(4096), Ok(()));
+        assert_eq!(validate_block_size(0), Err(BlockError::InvalidBlockSize));
+        assert_eq!(validate_block_size(1000), Err(BlockError::InvalidBlockSize));
+    }
+
+    #[test]
+    fn test_mem_block_device() {
+        let mut device = MemBlockDevice::new(1024, 4).unwrap();
+        assert_eq!(device.block_count(), 4);
+        device.write_block(2, &[7; 1024]).unwrap();
+
+        let mut block = [0; 1024];
+        device.read_block(2, &mut block).unwrap();
+        assert_eq!(block, [7; 1024]);
+        assert_eq!((device.reads(), device.writes()), (1, 1));
+
+        assert_eq!(device.read_block(4, &mut block), Err(BlockError::OutOfRange));
+        assert_eq!(device.write_block(0, &[0; 512]), Err(BlockError::BufferSize));
+    }
+}
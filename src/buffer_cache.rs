@@ -0,0 +1,311 @@
+// src/buffer_cache.rs
+
+use std::collections::HashMap;
+
+use crate::block_device::{BlockDevice, BlockError, check_request};
+
+/// When a [`BufferCache`] writes modified blocks to its device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WritePolicy {
+    /// Writes stay in the cache, marked dirty, until the block is evicted or the cache is
+    /// synced.
+    #[default]
+    WriteBack,
+    /// Writes go to the device immediately; cached blocks are never dirty.
+    WriteThrough,
+}
+
+/// Counters describing how well a [`BufferCache`] has served its callers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    /// Block reads served from the cache.
+    pub hits: u64,
+    /// Block reads that had to go to the device.
+    pub misses: u64,
+    /// Blocks written to the device, by write-through, eviction or sync.
+    pub device_writes: u64,
+    /// Blocks dropped to make room for others.
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// Fraction of reads served from the cache, or `None` before the first read.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let reads = self.hits + self.misses;
+        (reads > 0).then(|| self.hits as f64 / reads as f64)
+    }
+}
+
+struct Buffer {
+    data: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+/// An LRU cache of blocks in front of a [`BlockDevice`].
+///
+/// The cache is itself a `BlockDevice`, so a file system can run on top of it unchanged.
+/// With [`WritePolicy::WriteBack`], dirty blocks are written when they are evicted or on
+/// [`BufferCache::sync`]. Like a machine halted by `stop`, dropping the cache discards
+/// dirty blocks; use [`BufferCache::into_inner`] to sync and keep the device. The cache
+/// does no locking of its own; share it between processes behind a kernel
+/// [`crate::sync::Mutex`].
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{BlockDevice, BufferCache, DeviceId, ThreadsDisk, WritePolicy};
+///
+/// let disk = ThreadsDisk::open(DeviceId::disk(0), 1024)?;
+/// let mut cache = BufferCache::new(disk, 64, WritePolicy::WriteBack);
+/// let mut block = vec![0u8; cache.block_size()];
+/// cache.read_block(0, &mut block)?; // miss
+/// cache.read_block(0, &mut block)?; // hit
+/// cache.sync()?;
+/// ```
+pub struct BufferCache<D: BlockDevice> {
+    device: D,
+    capacity: usize,
+    policy: WritePolicy,
+    buffers: HashMap<u64, Buffer>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl<D: BlockDevice> BufferCache<D> {
+    /// Creates a cache holding at most `capacity` blocks of `device`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(device: D, capacity: usize, policy: WritePolicy) -> Self {
+        assert!(capacity > 0, "buffer cache capacity must be at least one block");
+        BufferCache {
+            device,
+            capacity,
+            policy,
+            buffers: HashMap::with_capacity(capacity),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// The current write policy.
+    pub fn policy(&self) -> WritePolicy {
+        self.policy
+    }
+
+    /// Changes the write policy. Switching to write-through syncs the cache first, so
+    /// that no dirty blocks are left behind.
+    pub fn set_policy(&mut self, policy: WritePolicy) -> Result<(), BlockError> {
+        if policy == WritePolicy::WriteThrough {
+            self.sync()?;
+        }
+        self.policy = policy;
+        Ok(())
+    }
+
+    /// Hit, miss and write-back counters since the cache was created or last reset.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Clears the statistics.
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    /// Number of blocks currently cached.
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Returns `true` if nothing is cached.
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// Returns `true` if `block` is cached with changes not yet written to the device.
+    pub fn is_dirty(&self, block: u64) -> bool {
+        self.buffers.get(&block).is_some_and(|b| b.dirty)
+    }
+
+    /// Number of dirty blocks.
+    pub fn dirty_count(&self) -> usize {
+        self.buffers.values().filter(|b| b.dirty).count()
+    }
+
+    /// Writes every dirty block to the device, in block order, then flushes the device.
+    pub fn sync(&mut self) -> Result<(), BlockError> {
+        let mut dirty: Vec<u64> =
+            self.buffers.iter().filter(|(_, b)| b.dirty).map(|(&block, _)| block).collect();
+        dirty.sort_unstable();
+        for block in dirty {
+            self.write_back(block)?;
+        }
+        self.device.flush()
+    }
+
+    /// Drops `block` from the cache without writing it back, e.g. after the device was
+    /// changed behind the cache's back.
+    pub fn invalidate(&mut self, block: u64) {
+        self.buffers.remove(&block);
+    }
+
+    /// The underlying device.
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Syncs the cache and returns the underlying device.
+    pub fn into_inner(mut self) -> Result<D, BlockError> {
+        self.sync()?;
+        Ok(self.device)
+    }
+
+    fn touch(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn write_back(&mut self, block: u64) -> Result<(), BlockError> {
+        let Some(buffer) = self.buffers.get_mut(&block) else {
+            return Ok(());
+        };
+        self.device.write_block(block, &buffer.data)?;
+        buffer.dirty = false;
+        self.stats.device_writes += 1;
+        Ok(())
+    }
+
+    /// Makes room for one more block, writing the least recently used one back if dirty.
+    fn make_room(&mut self) -> Result<(), BlockError> {
+        if self.buffers.len() < self.capacity {
+            return Ok(());
+        }
+        let Some(victim) = self.buffers.iter().min_by_key(|(_, b)| b.last_used).map(|(&k, _)| k)
+        else {
+            return Ok(());
+        };
+        self.write_back(victim)?;
+        self.buffers.remove(&victim);
+        self.stats.evictions += 1;
+        Ok(())
+    }
+
+    fn insert(&mut self, block: u64, data: Vec<u8>, dirty: bool) -> Result<(), BlockError> {
+        self.make_room()?;
+        let last_used = self.touch();
+        self.buffers.insert(block, Buffer { data, dirty, last_used });
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for BufferCache<D> {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        let now = self.touch();
+        if let Some(cached) = self.buffers.get_mut(&block) {
+            cached.last_used = now;
+            buffer.copy_from_slice(&cached.data);
+            self.stats.hits += 1;
+            return Ok(());
+        }
+
+        self.stats.misses += 1;
+        self.device.read_block(block, buffer)?;
+        self.insert(block, buffer.to_vec(), false)
+    }
+
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        let write_through = self.policy == WritePolicy::WriteThrough;
+        if write_through {
+            self.device.write_block(block, buffer)?;
+            self.stats.device_writes += 1;
+        }
+
+        let now = self.touch();
+        if let Some(cached) = self.buffers.get_mut(&block) {
+            cached.data.copy_from_slice(buffer);
+            cached.dirty = !write_through;
+            cached.last_used = now;
+            return Ok(());
+        }
+        self.insert(block, buffer.to_vec(), !write_through)
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        self.sync()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_device::MemBlockDevice;
+
+    fn cache(capacity: usize, policy: WritePolicy) -> BufferCache<MemBlockDevice> {
+        BufferCache::new(MemBlockDevice::new(512, 8).unwrap(), capacity, policy)
+    }
+
+    #[test]
+    fn test_hits_misses_and_lru_eviction() {
+        let mut cache = cache(2, WritePolicy::WriteBack);
+        let mut block = [0; 512];
+        for b in [0, 1, 0, 2, 0, 1] {
+            cache.read_block(b, &mut block).unwrap();
+        }
+        // 0 miss, 1 miss, 0 hit, 2 miss (evicts 1), 0 hit, 1 miss (evicts 2).
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 4, 2));
+        assert_eq!(cache.device().reads(), 4);
+        assert_eq!(stats.hit_ratio(), Some(2.0 / 6.0));
+    }
+
+    #[test]
+    fn test_write_back_defers_writes_until_eviction_or_sync() {
+        let mut cache = cache(2, WritePolicy::WriteBack);
+        cache.write_block(0, &[1; 512]).unwrap();
+        cache.write_block(1, &[2; 512]).unwrap();
+        assert_eq!(cache.device().writes(), 0);
+        assert_eq!(cache.dirty_count(), 2);
+
+        // Block 0 is least recently used, so it is written back to make room.
+        cache.write_block(2, &[3; 512]).unwrap();
+        assert_eq!(cache.device().writes(), 1);
+        assert!(!cache.is_dirty(0) && cache.is_dirty(1) && cache.is_dirty(2));
+
+        let device = cache.into_inner().unwrap();
+        assert_eq!(device.writes(), 3);
+        assert_eq!(&device.as_bytes()[1024..1536], &[3; 512]);
+    }
+
+    #[test]
+    fn test_write_through_writes_immediately() {
+        let mut cache = cache(2, WritePolicy::WriteThrough);
+        cache.write_block(5, &[9; 512]).unwrap();
+        assert_eq!(cache.device().writes(), 1);
+        assert_eq!(cache.dirty_count(), 0);
+
+        let mut block = [0; 512];
+        cache.read_block(5, &mut block).unwrap();
+        assert_eq!((block, cache.stats().hits), ([9; 512], 1));
+    }
+
+    #[test]
+    fn test_switching_to_write_through_syncs() {
+        let mut cache = cache(4, WritePolicy::WriteBack);
+        cache.write_block(3, &[4; 512]).unwrap();
+        cache.set_policy(WritePolicy::WriteThrough).unwrap();
+        assert_eq!((cache.dirty_count(), cache.device().writes()), (0, 1));
+    }
+}
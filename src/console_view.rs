@@ -0,0 +1,659 @@
+// src/console_view.rs
+
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{LazyLock, Mutex};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+use crate::ansi::{AnsiEvent, Screen, Sgr, Style};
+use crate::constants::THREADS_MAX_TERMINALS;
+use crate::device::DeviceId;
+use crate::interrupts::InterruptGuard;
+use crate::scheduler::{KERNEL, Pid};
+use crate::terminal::{add_terminal_listener, inject_terminal_input};
+
+/// Key that starts a viewer command (Ctrl-A); it is followed by `0`–`3` to focus a
+/// terminal, `c` to focus the console, `n` for the next pane or `q` to detach. Typing it
+/// twice sends it to the focused terminal.
+pub const CONSOLE_VIEW_PREFIX: u8 = 0x01;
+
+/// How often the viewer redraws the host screen when something changed.
+pub const CONSOLE_VIEW_REFRESH: Duration = Duration::from_millis(50);
+
+const TERMINALS: usize = THREADS_MAX_TERMINALS as usize;
+
+/// Kernel state shown in the status bar, sampled on the clock interrupt.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusLine {
+    /// PSR of the interrupted code.
+    pub psr: u32,
+    /// `system_clock()` in microseconds.
+    pub clock: u32,
+    /// Pid and name of the running process.
+    pub running: Option<(Pid, String)>,
+}
+
+/// Pane with the keyboard focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    /// The pane of the terminal with this unit; keys typed here go to the terminal.
+    Terminal(u32),
+    /// The console pane; keys typed here go nowhere.
+    Console,
+}
+
+/// What a key typed on the host asks the viewer to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewAction {
+    /// The key was used by the viewer itself, or has nowhere to go.
+    Nothing,
+    /// Send the byte to the terminal.
+    Forward(DeviceId, u8),
+    /// Stop the viewer and give the host terminal back.
+    Detach,
+}
+
+/// A rectangle of the host screen, in 0-based rows and columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    row: usize,
+    column: usize,
+    rows: usize,
+    columns: usize,
+}
+
+/// A titled pane: a title bar over a [`Screen`].
+#[derive(Debug, Clone)]
+struct Pane {
+    title: String,
+    area: Rect,
+    screen: Screen,
+}
+
+impl Pane {
+    fn new(title: String, area: Rect) -> Self {
+        let screen = Screen::new(area.rows.saturating_sub(1), area.columns);
+        Pane { title, area, screen }
+    }
+}
+
+/// The multiplexed host display: one pane per terminal, a console pane and a status bar.
+///
+/// The view only models the display. [`start_console_view`] connects it to the
+/// terminal driver, the console and the host's own terminal.
+///
+/// ```text
+/// ┌ term0 ──────────┐ ┌ term1 ──────────┐
+/// │                 │ │                 │
+/// ├ term2 ──────────┤ ├ term3 ──────────┤
+/// │                 │ │                 │
+/// ├ console ────────────────────────────┤
+/// │                                     │
+/// └ status: PSR, clock, running process ┘
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConsoleView {
+    columns: usize,
+    rows: usize,
+    terminals: Vec<Pane>,
+    console: Pane,
+    focus: Focus,
+    status: StatusLine,
+    prefix: bool,
+    dirty: bool,
+}
+
+impl ConsoleView {
+    /// Lays out a view of a host screen `columns` wide and `rows` high.
+    pub fn new(columns: usize, rows: usize) -> Self {
+        let (columns, rows) = (columns.max(20), rows.max(10));
+        // The status bar takes the last row, the console a third of the rest.
+        let console_rows = ((rows - 1) / 3).max(3);
+        let terminal_rows = rows - 1 - console_rows;
+        let grid_columns = (TERMINALS as f64).sqrt().ceil() as usize;
+        let grid_rows = TERMINALS.div_ceil(grid_columns);
+        let terminals = (0..TERMINALS)
+            .map(|unit| {
+                let (r, c) = (unit / grid_columns, unit % grid_columns);
+                let top = terminal_rows * r / grid_rows;
+                let left = columns * c / grid_columns;
+                let area = Rect {
+                    row: top,
+                    column: left,
+                    rows: terminal_rows * (r + 1) / grid_rows - top,
+                    // One blank column separates panes side by side.
+                    columns: columns * (c + 1) / grid_columns - left - 1,
+                };
+                Pane::new(DeviceId::terminal(unit as u32).name(), area)
+            })
+            .collect();
+        let console_area = Rect { row: terminal_rows, column: 0, rows: console_rows, columns };
+        ConsoleView {
+            columns,
+            rows,
+            terminals,
+            console: Pane::new("console".to_string(), console_area),
+            focus: Focus::Terminal(0),
+            status: StatusLine::default(),
+            prefix: false,
+            dirty: true,
+        }
+    }
+
+    /// Pane with the keyboard focus.
+    pub fn focus(&self) -> Focus {
+        self.focus
+    }
+
+    /// Screen of terminal `unit`, as the view shows it.
+    pub fn terminal_screen(&self, unit: u32) -> Option<&Screen> {
+        self.terminals.get(unit as usize).map(|p| &p.screen)
+    }
+
+    /// Screen of the console pane.
+    pub fn console_screen(&self) -> &Screen {
+        &self.console.screen
+    }
+
+    /// Returns `true` if the view changed since the last [`ConsoleView::render`].
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Shows output sent to `terminal`.
+    pub fn terminal_output(&mut self, terminal: DeviceId, bytes: &[u8]) {
+        if let Some(pane) = self.terminals.get_mut(terminal.unit as usize) {
+            pane.screen.feed(bytes);
+            self.dirty = true;
+        }
+    }
+
+    /// Shows a `console_output` message; debug messages are dimmed.
+    pub fn console_output(&mut self, debug: bool, message: &str) {
+        let screen = &mut self.console.screen;
+        if debug {
+            screen.apply(&AnsiEvent::Graphics(vec![Sgr::Foreground(crate::ansi::Color::CYAN)]));
+        }
+        for line in message.split_inclusive('\n') {
+            match line.strip_suffix('\n') {
+                Some(text) => screen.feed(format!("{text}\r\n").as_bytes()),
+                None => screen.feed(line.as_bytes()),
+            }
+        }
+        if debug {
+            screen.apply(&AnsiEvent::Graphics(vec![Sgr::Reset]));
+        }
+        self.dirty = true;
+    }
+
+    /// Shows `status` in the status bar.
+    pub fn set_status(&mut self, status: StatusLine) {
+        if self.status != status {
+            self.status = status;
+            self.dirty = true;
+        }
+    }
+
+    /// Handles one byte typed on the host.
+    pub fn handle_input(&mut self, byte: u8) -> ViewAction {
+        if !self.prefix {
+            if byte == CONSOLE_VIEW_PREFIX {
+                self.prefix = true;
+                return ViewAction::Nothing;
+            }
+            return self.forward(byte);
+        }
+        self.prefix = false;
+        let focus = match byte {
+            CONSOLE_VIEW_PREFIX => return self.forward(byte),
+            b'q' => return ViewAction::Detach,
+            b'c' => Focus::Console,
+            b'n' => match self.focus {
+                Focus::Terminal(unit) if (unit as usize) + 1 < TERMINALS => {
+                    Focus::Terminal(unit + 1)
+                }
+                Focus::Terminal(_) => Focus::Console,
+                Focus::Console => Focus::Terminal(0),
+            },
+            b'0'..=b'9' if usize::from(byte - b'0') < TERMINALS => {
+                Focus::Terminal(u32::from(byte - b'0'))
+            }
+            _ => return ViewAction::Nothing,
+        };
+        self.focus = focus;
+        self.dirty = true;
+        ViewAction::Nothing
+    }
+
+    fn forward(&self, byte: u8) -> ViewAction {
+        match self.focus {
+            Focus::Terminal(unit) => ViewAction::Forward(DeviceId::terminal(unit), byte),
+            Focus::Console => ViewAction::Nothing,
+        }
+    }
+
+    /// Draws the whole view as ANSI output for the host terminal.
+    pub fn render(&mut self) -> String {
+        self.dirty = false;
+        let mut out = AnsiEvent::ShowCursor(false).to_string();
+        for (unit, pane) in self.terminals.iter().enumerate() {
+            let focused = self.focus == Focus::Terminal(unit as u32);
+            draw_pane(&mut out, pane, focused);
+        }
+        draw_pane(&mut out, &self.console, self.focus == Focus::Console);
+
+        out += &AnsiEvent::CursorPosition { row: (self.rows - 1) as u16, column: 0 }.to_string();
+        out += &AnsiEvent::Graphics(vec![Sgr::Reset, Sgr::Reverse]).to_string();
+        out += &fit(&self.status_text(), self.columns);
+        out += &AnsiEvent::Graphics(vec![Sgr::Reset]).to_string();
+
+        if let Focus::Terminal(unit) = self.focus {
+            let pane = &self.terminals[unit as usize];
+            let (row, column) = pane.screen.cursor();
+            out += &AnsiEvent::CursorPosition {
+                row: (pane.area.row + 1 + row) as u16,
+                column: (pane.area.column + column) as u16,
+            }
+            .to_string();
+            if pane.screen.cursor_visible() {
+                out += &AnsiEvent::ShowCursor(true).to_string();
+            }
+        }
+        out
+    }
+
+    fn status_text(&self) -> String {
+        let status = &self.status;
+        let running = match &status.running {
+            Some((pid, name)) => format!("{pid} {name}"),
+            None => "-".to_string(),
+        };
+        let focus = match self.focus {
+            Focus::Terminal(unit) => DeviceId::terminal(unit).name(),
+            Focus::Console => "console".to_string(),
+        };
+        format!(
+            " PSR {:#04x} | clock {}.{:06} s | running {running} | {focus} | Ctrl-A 0-{} c n q",
+            status.psr,
+            status.clock / 1_000_000,
+            status.clock % 1_000_000,
+            TERMINALS - 1
+        )
+    }
+}
+
+/// Pads or cuts `text` to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let length = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - length));
+    fitted
+}
+
+/// SGR attributes that select `style` from the default.
+fn style_attributes(style: Style) -> Vec<Sgr> {
+    let mut attributes = vec![Sgr::Reset];
+    attributes.extend(style.bold.then_some(Sgr::Bold));
+    attributes.extend(style.underline.then_some(Sgr::Underline));
+    attributes.extend(style.reverse.then_some(Sgr::Reverse));
+    attributes.extend(style.foreground.map(Sgr::Foreground));
+    attributes.extend(style.background.map(Sgr::Background));
+    attributes
+}
+
+fn draw_pane(out: &mut String, pane: &Pane, focused: bool) {
+    let Rect { row, column, columns, .. } = pane.area;
+    let title = if focused {
+        vec![Sgr::Reset, Sgr::Reverse, Sgr::Bold]
+    } else {
+        vec![Sgr::Reset, Sgr::Underline]
+    };
+    *out += &AnsiEvent::CursorPosition { row: row as u16, column: column as u16 }.to_string();
+    *out += &AnsiEvent::Graphics(title).to_string();
+    *out += &fit(&format!(" {} ", pane.title), columns);
+    *out += &AnsiEvent::Graphics(vec![Sgr::Reset]).to_string();
+
+    let screen = &pane.screen;
+    for r in 0..screen.rows() {
+        *out += &AnsiEvent::CursorPosition { row: (row + 1 + r) as u16, column: column as u16 }
+            .to_string();
+        let mut style = Style::default();
+        for c in 0..screen.columns() {
+            let cell = screen.cell(r, c);
+            if cell.style != style {
+                style = cell.style;
+                *out += &AnsiEvent::Graphics(style_attributes(style)).to_string();
+            }
+            out.push(cell.ch);
+        }
+        if style != Style::default() {
+            *out += &AnsiEvent::Graphics(vec![Sgr::Reset]).to_string();
+        }
+    }
+}
+
+/// Output produced in kernel context, queued for the host thread that draws the view.
+enum Update {
+    Terminal(DeviceId, u8),
+    Console(bool, String),
+}
+
+static VIEW: Mutex<Option<ConsoleView>> = Mutex::new(None);
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static LISTENING: AtomicBool = AtomicBool::new(false);
+static RAW_MODE: Mutex<Option<host_tty::RawMode>> = Mutex::new(None);
+// Kernel code only ever sends, which never blocks; the render thread applies the updates
+// under the view lock, which kernel code never takes.
+static UPDATES: LazyLock<(Sender<Update>, Mutex<Receiver<Update>>)> = LazyLock::new(|| {
+    let (sender, receiver) = mpsc::channel();
+    (sender, Mutex::new(receiver))
+});
+/// The host thread reading standard input, started with the first viewer.
+static READER: Mutex<Option<Thread>> = Mutex::new(None);
+
+fn view() -> std::sync::MutexGuard<'static, Option<ConsoleView>> {
+    VIEW.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Takes over the host terminal with a [`ConsoleView`] `columns` wide and `rows` high.
+///
+/// Puts the host terminal in raw mode on its alternate screen, shows everything sent to
+/// the THREADS terminals and every `console_output` message in their panes, and forwards
+/// keys typed on the host to the focused terminal (see [`CONSOLE_VIEW_PREFIX`]). The
+/// status bar is updated on every clock interrupt. Call it before starting the processes
+/// whose output should be shown; [`stop_console_view`], detaching with Ctrl-A `q`, or
+/// `stop` gives the host terminal back.
+///
+/// # Example
+///
+/// ```ignore
+/// rusty_threads::init_terminals();
+/// rusty_threads::start_console_view(120, 40)?;
+/// rusty_threads::spawn("shell", 3, 65536, shell_main)?;
+/// ```
+pub fn start_console_view(columns: usize, rows: usize) -> std::io::Result<()> {
+    if ACTIVE.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    let raw = match host_tty::RawMode::enable() {
+        Ok(raw) => raw,
+        Err(error) => {
+            ACTIVE.store(false, Ordering::SeqCst);
+            return Err(error);
+        }
+    };
+    *RAW_MODE.lock().unwrap_or_else(|e| e.into_inner()) = Some(raw);
+    // Output queued after the last viewer stopped was never shown and stays unshown.
+    updates().try_iter().for_each(drop);
+    *view() = Some(ConsoleView::new(columns, rows));
+    let _ = write_host("\x1b[?1049h");
+
+    if !LISTENING.swap(true, Ordering::SeqCst) {
+        add_terminal_listener(|terminal, byte| {
+            if ACTIVE.load(Ordering::SeqCst) {
+                let _ = UPDATES.0.send(Update::Terminal(terminal, byte));
+            }
+        });
+    }
+    // A read of standard input cannot be cancelled, so the reader outlives the viewer and
+    // waits for the next one.
+    let mut reader = READER.lock().unwrap_or_else(|e| e.into_inner());
+    match reader.as_ref() {
+        Some(reader) => reader.unpark(),
+        None => *reader = Some(thread::spawn(input_loop).thread().clone()),
+    }
+    thread::spawn(render_loop);
+    Ok(())
+}
+
+/// Gives the host terminal back after [`start_console_view`]; console output goes to the
+/// THREADS console again.
+///
+/// The host thread reading keys stays blocked in its read until the next key arrives,
+/// which is dropped, and then waits for the next viewer.
+pub fn stop_console_view() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    *view() = None;
+    let _ = write_host(&format!("{}\x1b[?1049l", AnsiEvent::ShowCursor(true)));
+    if let Some(raw) = RAW_MODE.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        raw.restore();
+    }
+}
+
+fn write_host(text: &str) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(text.as_bytes())?;
+    stdout.flush()
+}
+
+fn updates() -> std::sync::MutexGuard<'static, Receiver<Update>> {
+    UPDATES.1.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn input_loop() {
+    let mut buffer = [0; 64];
+    let mut stdin = std::io::stdin();
+    loop {
+        while !ACTIVE.load(Ordering::SeqCst) {
+            thread::park();
+        }
+        let Ok(count @ 1..) = stdin.read(&mut buffer) else {
+            // Standard input is closed; a later viewer starts without keys.
+            return;
+        };
+        for &byte in &buffer[..count] {
+            let action = match view().as_mut() {
+                Some(view) => view.handle_input(byte),
+                None => break,
+            };
+            match action {
+                ViewAction::Nothing => {}
+                ViewAction::Forward(terminal, byte) => {
+                    let _ = inject_terminal_input(terminal, &[byte]);
+                }
+                ViewAction::Detach => {
+                    stop_console_view();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn render_loop() {
+    while ACTIVE.load(Ordering::SeqCst) {
+        let frame = match view().as_mut() {
+            Some(view) => {
+                for update in updates().try_iter() {
+                    match update {
+                        Update::Terminal(terminal, byte) => view.terminal_output(terminal, &[byte]),
+                        Update::Console(debug, message) => view.console_output(debug, &message),
+                    }
+                }
+                view.is_dirty().then(|| view.render())
+            }
+            None => return,
+        };
+        if let Some(frame) = frame {
+            let _ = write_host(&frame);
+        }
+        std::thread::sleep(CONSOLE_VIEW_REFRESH);
+    }
+}
+
+/// Shows a console message in the viewer instead of the THREADS console.
+///
+/// # Returns
+///
+/// `true` if the viewer is running and took the message.
+pub(crate) fn capture_console(debug: bool, message: &str) -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+        && UPDATES.0.send(Update::Console(debug, message.to_string())).is_ok()
+}
+
+/// Samples the status bar; called on every clock interrupt.
+pub(crate) fn on_clock(guard: &InterruptGuard, now: u32) {
+    if !ACTIVE.load(Ordering::SeqCst) {
+        return;
+    }
+    let running = KERNEL.with(guard, |k| {
+        let pid = k.current()?;
+        k.process(pid).map(|p| (pid, p.name.clone()))
+    });
+    let status = StatusLine { psr: guard.saved_psr(), clock: now, running };
+    // Skip the tick rather than wait while the host thread draws.
+    if let Ok(mut view) = VIEW.try_lock()
+        && let Some(view) = view.as_mut()
+    {
+        view.set_status(status);
+    }
+}
+
+/// Raw mode for the host terminal the simulator runs in.
+#[cfg(unix)]
+mod host_tty {
+    use std::io;
+    use std::process::{Command, Stdio};
+
+    /// Saved `stty` settings.
+    pub(super) struct RawMode {
+        saved: String,
+    }
+
+    impl RawMode {
+        pub(super) fn enable() -> io::Result<Self> {
+            let saved = stty(&["-g"])?.trim().to_string();
+            stty(&["raw", "-echo"])?;
+            Ok(RawMode { saved })
+        }
+
+        pub(super) fn restore(self) {
+            let _ = stty(&[&self.saved]);
+        }
+    }
+
+    fn stty(args: &[&str]) -> io::Result<String> {
+        let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other("stty failed; is stdin a terminal?"));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// Raw mode for the host console the simulator runs in.
+#[cfg(windows)]
+mod host_tty {
+    use std::ffi::c_void;
+    use std::io;
+
+    const STD_INPUT_HANDLE: u32 = -10i32 as u32;
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_PROCESSED_INPUT: u32 = 0x0001;
+    const ENABLE_LINE_INPUT: u32 = 0x0002;
+    const ENABLE_ECHO_INPUT: u32 = 0x0004;
+    const ENABLE_VIRTUAL_TERMINAL_INPUT: u32 = 0x0200;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    /// Saved console modes of standard input and output.
+    pub(super) struct RawMode {
+        input: u32,
+        output: u32,
+    }
+
+    impl RawMode {
+        pub(super) fn enable() -> io::Result<Self> {
+            let input = mode(STD_INPUT_HANDLE)?;
+            let output = mode(STD_OUTPUT_HANDLE)?;
+            let raw_input = (input
+                & !(ENABLE_PROCESSED_INPUT | ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT))
+                | ENABLE_VIRTUAL_TERMINAL_INPUT;
+            set_mode(STD_INPUT_HANDLE, raw_input)?;
+            set_mode(STD_OUTPUT_HANDLE, output | ENABLE_VIRTUAL_TERMINAL_PROCESSING)?;
+            Ok(RawMode { input, output })
+        }
+
+        pub(super) fn restore(self) {
+            let _ = set_mode(STD_INPUT_HANDLE, self.input);
+            let _ = set_mode(STD_OUTPUT_HANDLE, self.output);
+        }
+    }
+
+    fn mode(handle: u32) -> io::Result<u32> {
+        let mut mode = 0;
+        // SAFETY: `GetStdHandle` returns a handle owned by the process; `mode` is a valid
+        // out-pointer.
+        match unsafe { GetConsoleMode(GetStdHandle(handle), &mut mode) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(mode),
+        }
+    }
+
+    fn set_mode(handle: u32, mode: u32) -> io::Result<()> {
+        // SAFETY: as in `mode`.
+        match unsafe { SetConsoleMode(GetStdHandle(handle), mode) } {
+            0 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_and_render() {
+        let mut view = ConsoleView::new(80, 31);
+        let pane = |unit: usize| view.terminals[unit].area;
+        assert_eq!(pane(0), Rect { row: 0, column: 0, rows: 10, columns: 39 });
+        assert_eq!(pane(3), Rect { row: 10, column: 40, rows: 10, columns: 39 });
+        assert_eq!(view.console.area, Rect { row: 20, column: 0, rows: 10, columns: 80 });
+
+        view.terminal_output(DeviceId::terminal(3), b"login: ");
+        view.console_output(true, "disk0 ready\nboot");
+        view.set_status(StatusLine { psr: 3, clock: 1_500_000, running: Some((2, "sh".into())) });
+        assert_eq!(view.terminal_screen(3).unwrap().row_text(0), "login:");
+        assert_eq!(view.console_screen().row_text(1), "boot");
+
+        let frame = view.render();
+        assert!(!view.is_dirty());
+        assert!(frame.contains(" term3 ") && frame.contains(" console "));
+        assert!(frame.contains("PSR 0x03 | clock 1.500000 s | running 2 sh | term0"));
+        // The cursor is left where term0's cursor is.
+        let cursor = AnsiEvent::CursorPosition { row: 1, column: 0 };
+        assert!(frame.ends_with(&format!("{cursor}{}", AnsiEvent::ShowCursor(true))));
+    }
+
+    #[test]
+    fn test_prefix_keys() {
+        let mut view = ConsoleView::new(80, 24);
+        let term = DeviceId::terminal;
+        assert_eq!(view.handle_input(b'x'), ViewAction::Forward(term(0), b'x'));
+        assert_eq!(view.handle_input(CONSOLE_VIEW_PREFIX), ViewAction::Nothing);
+        assert_eq!(view.handle_input(b'2'), ViewAction::Nothing);
+        assert_eq!(view.focus(), Focus::Terminal(2));
+        view.handle_input(CONSOLE_VIEW_PREFIX);
+        assert_eq!(view.handle_input(CONSOLE_VIEW_PREFIX), ViewAction::Forward(term(2), 1));
+
+        view.handle_input(CONSOLE_VIEW_PREFIX);
+        view.handle_input(b'c');
+        assert_eq!(view.handle_input(b'x'), ViewAction::Nothing);
+        view.handle_input(CONSOLE_VIEW_PREFIX);
+        view.handle_input(b'n');
+        assert_eq!(view.focus(), Focus::Terminal(0));
+        view.handle_input(CONSOLE_VIEW_PREFIX);
+        assert_eq!(view.handle_input(b'q'), ViewAction::Detach);
+    }
+}
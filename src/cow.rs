@@ -0,0 +1,434 @@
+// src/cow.rs
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::block_device::{BlockDevice, BlockError, check_request, validate_block_size};
+use crate::constants::THREADS_DISK_SECTOR_SIZE;
+use crate::disk_geometry::DiskGeometry;
+use crate::disk_image::geometry_for_length;
+use crate::fs::{get_u32, put_u32};
+
+/// Magic number at the start of an overlay file ("COWO").
+pub const COW_MAGIC: u32 = 0x434F_574F;
+
+/// Maximum length of a snapshot name in bytes.
+pub const MAX_SNAPSHOT_NAME: usize = 32;
+
+const VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 16;
+const RECORD_HEADER_SIZE: usize = 16 + MAX_SNAPSHOT_NAME;
+const SECTOR_SIZE: usize = THREADS_DISK_SECTOR_SIZE as usize;
+
+const RECORD_DATA: u32 = 1;
+const RECORD_SNAPSHOT: u32 = 2;
+
+/// Errors returned by [`CowImage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CowError {
+    /// Reading or writing the base image or the overlay failed.
+    Block(BlockError),
+    /// The overlay file does not start with [`COW_MAGIC`] or holds an unknown record.
+    NotOverlay,
+    /// The overlay was created over a base image of a different size.
+    BaseMismatch,
+    /// The name is empty, longer than [`MAX_SNAPSHOT_NAME`] or already taken.
+    InvalidName,
+    /// No snapshot has the requested name.
+    NoSuchSnapshot,
+}
+
+impl From<BlockError> for CowError {
+    fn from(error: BlockError) -> Self {
+        CowError::Block(error)
+    }
+}
+
+impl From<std::io::Error> for CowError {
+    fn from(error: std::io::Error) -> Self {
+        CowError::Block(error.into())
+    }
+}
+
+/// A named snapshot of an overlay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Name, unique within the overlay.
+    pub name: String,
+    /// Length of the overlay log when the snapshot was taken.
+    end: u64,
+}
+
+/// A read-only base image with a copy-on-write overlay file, seen as a [`BlockDevice`].
+///
+/// The base is a [`crate::DiskImage`] file that is never written. Every sector written
+/// through the device goes to the overlay, a log of sector copies after a 16-byte header
+/// (magic, version, base sector count). A snapshot appends a marker naming the log
+/// position; sectors copied before the newest snapshot are never overwritten in place, so
+/// [`CowImage::revert`] only has to cut the log back to a marker. Reverting discards the
+/// snapshots taken after the one reverted to.
+///
+/// A torn record at the end of the log, left by a crash while writing, is dropped when
+/// the overlay is opened.
+///
+/// The image is a host-side [`BlockDevice`] only: THREADS opens plain image files for
+/// its simulated disks, so a `CowImage` cannot back `disk0`/`disk1` directly. To run
+/// THREADS against an overlay, [`CowImage::flatten`] it to a plain image first.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{mkfs, CowImage, MkfsOptions};
+///
+/// let image = CowImage::create("base.img", "run.cow", 1024)?;
+/// let mut fs = mkfs(image, MkfsOptions::default())?;
+/// let mut image = fs.unmount()?;
+/// image.snapshot("formatted")?;
+/// // ... run a test case against the image ...
+/// image.revert("formatted")?;
+/// ```
+#[derive(Debug)]
+pub struct CowImage {
+    base: File,
+    overlay: File,
+    geometry: DiskGeometry,
+    block_size: usize,
+    /// Overlay offset of the newest copy of each sector in the overlay.
+    sectors: HashMap<u32, u64>,
+    snapshots: Vec<Snapshot>,
+    /// Length of the overlay log.
+    end: u64,
+}
+
+impl CowImage {
+    /// Creates an empty overlay at `overlay` over the image at `base`, replacing any
+    /// existing overlay file.
+    pub fn create(
+        base: impl AsRef<Path>,
+        overlay: impl AsRef<Path>,
+        block_size: usize,
+    ) -> Result<Self, CowError> {
+        let (base, geometry) = open_base(base.as_ref(), block_size)?;
+        let mut overlay =
+            OpenOptions::new().read(true).write(true).create(true).truncate(true).open(overlay)?;
+        let mut header = [0; HEADER_SIZE as usize];
+        put_u32(&mut header, 0, COW_MAGIC);
+        put_u32(&mut header, 4, VERSION);
+        put_u32(&mut header, 8, geometry.total_sectors() as u32);
+        overlay.write_all(&header)?;
+        Ok(CowImage {
+            base,
+            overlay,
+            geometry,
+            block_size,
+            sectors: HashMap::new(),
+            snapshots: Vec::new(),
+            end: HEADER_SIZE,
+        })
+    }
+
+    /// Opens an existing overlay at `overlay` over the image at `base`.
+    ///
+    /// # Returns
+    ///
+    /// `CowError::BaseMismatch` if `base` is not the size the overlay was created over.
+    pub fn open(
+        base: impl AsRef<Path>,
+        overlay: impl AsRef<Path>,
+        block_size: usize,
+    ) -> Result<Self, CowError> {
+        let (base, geometry) = open_base(base.as_ref(), block_size)?;
+        let mut overlay = OpenOptions::new().read(true).write(true).open(overlay)?;
+        let mut header = [0; HEADER_SIZE as usize];
+        overlay.read_exact(&mut header).map_err(|_| CowError::NotOverlay)?;
+        if get_u32(&header, 0) != COW_MAGIC || get_u32(&header, 4) != VERSION {
+            return Err(CowError::NotOverlay);
+        }
+        if u64::from(get_u32(&header, 8)) != geometry.total_sectors() {
+            return Err(CowError::BaseMismatch);
+        }
+        let mut image = CowImage {
+            base,
+            overlay,
+            geometry,
+            block_size,
+            sectors: HashMap::new(),
+            snapshots: Vec::new(),
+            end: HEADER_SIZE,
+        };
+        image.load()?;
+        Ok(image)
+    }
+
+    /// Geometry of the base image.
+    pub fn geometry(&self) -> DiskGeometry {
+        self.geometry
+    }
+
+    /// Snapshots, oldest first.
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    /// Number of sectors that differ from the base image.
+    pub fn changed_sectors(&self) -> usize {
+        self.sectors.len()
+    }
+
+    /// Records the current contents of the image as snapshot `name`.
+    pub fn snapshot(&mut self, name: &str) -> Result<(), CowError> {
+        if name.is_empty()
+            || name.len() > MAX_SNAPSHOT_NAME
+            || self.snapshots.iter().any(|s| s.name == name)
+        {
+            return Err(CowError::InvalidName);
+        }
+        let mut record = [0; RECORD_HEADER_SIZE];
+        put_u32(&mut record, 0, RECORD_SNAPSHOT);
+        put_u32(&mut record, 8, name.len() as u32);
+        record[16..16 + name.len()].copy_from_slice(name.as_bytes());
+        self.append(&record)?;
+        self.overlay.sync_data()?;
+        self.snapshots.push(Snapshot { name: name.to_string(), end: self.end });
+        Ok(())
+    }
+
+    /// Returns the image to snapshot `name`, discarding every later write and snapshot.
+    pub fn revert(&mut self, name: &str) -> Result<(), CowError> {
+        let index =
+            self.snapshots.iter().position(|s| s.name == name).ok_or(CowError::NoSuchSnapshot)?;
+        self.truncate(self.snapshots[index].end)
+    }
+
+    /// Returns the image to the base, discarding every write and snapshot.
+    pub fn reset(&mut self) -> Result<(), CowError> {
+        self.truncate(HEADER_SIZE)
+    }
+
+    /// Writes the current contents of the image to a plain disk image at `path`, for
+    /// THREADS to run on.
+    pub fn flatten(&mut self, path: impl AsRef<Path>) -> Result<(), CowError> {
+        let mut out =
+            OpenOptions::new().write(true).create(true).truncate(true).open(path.as_ref())?;
+        let mut sector = [0; SECTOR_SIZE];
+        for index in 0..self.geometry.total_sectors() as u32 {
+            self.read_sector(index, &mut sector)?;
+            out.write_all(&sector)?;
+        }
+        Ok(out.sync_data()?)
+    }
+
+    /// Rebuilds the sector map and snapshot list from the overlay log.
+    fn load(&mut self) -> Result<(), CowError> {
+        self.sectors.clear();
+        self.snapshots.clear();
+        let length = self.overlay.metadata()?.len();
+        let mut offset = HEADER_SIZE;
+        let mut record = [0; RECORD_HEADER_SIZE];
+        while offset + RECORD_HEADER_SIZE as u64 <= length {
+            self.overlay.seek(SeekFrom::Start(offset))?;
+            self.overlay.read_exact(&mut record)?;
+            let data = offset + RECORD_HEADER_SIZE as u64;
+            match get_u32(&record, 0) {
+                RECORD_DATA if data + SECTOR_SIZE as u64 <= length => {
+                    let sector = get_u32(&record, 4);
+                    if u64::from(sector) >= self.geometry.total_sectors() {
+                        return Err(CowError::NotOverlay);
+                    }
+                    self.sectors.insert(sector, data);
+                    offset = data + SECTOR_SIZE as u64;
+                }
+                // The sector copy was torn.
+                RECORD_DATA => break,
+                RECORD_SNAPSHOT => {
+                    let length = get_u32(&record, 8) as usize;
+                    let name = record.get(16..16 + length).ok_or(CowError::NotOverlay)?;
+                    let name =
+                        String::from_utf8(name.to_vec()).map_err(|_| CowError::NotOverlay)?;
+                    self.snapshots.push(Snapshot { name, end: data });
+                    offset = data;
+                }
+                _ => return Err(CowError::NotOverlay),
+            }
+        }
+        if offset < length {
+            self.overlay.set_len(offset)?;
+        }
+        self.end = offset;
+        Ok(())
+    }
+
+    fn truncate(&mut self, end: u64) -> Result<(), CowError> {
+        self.overlay.set_len(end)?;
+        self.overlay.sync_data()?;
+        self.load()
+    }
+
+    fn append(&mut self, bytes: &[u8]) -> Result<(), CowError> {
+        self.overlay.seek(SeekFrom::Start(self.end))?;
+        self.overlay.write_all(bytes)?;
+        self.end += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Log position below which sector copies belong to a snapshot.
+    fn frozen(&self) -> u64 {
+        self.snapshots.last().map_or(HEADER_SIZE, |s| s.end)
+    }
+
+    fn read_sector(&mut self, index: u32, buffer: &mut [u8]) -> Result<(), CowError> {
+        let (file, offset) = match self.sectors.get(&index) {
+            Some(&offset) => (&mut self.overlay, offset),
+            None => (&mut self.base, u64::from(index) * SECTOR_SIZE as u64),
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        Ok(file.read_exact(buffer)?)
+    }
+
+    fn write_sector(&mut self, index: u32, buffer: &[u8]) -> Result<(), CowError> {
+        match self.sectors.get(&index) {
+            Some(&offset) if offset >= self.frozen() => {
+                self.overlay.seek(SeekFrom::Start(offset))?;
+                self.overlay.write_all(buffer)?;
+            }
+            _ => {
+                let mut record = [0; RECORD_HEADER_SIZE];
+                put_u32(&mut record, 0, RECORD_DATA);
+                put_u32(&mut record, 4, index);
+                self.append(&record)?;
+                let data = self.end;
+                self.append(buffer)?;
+                self.sectors.insert(index, data);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn open_base(path: &Path, block_size: usize) -> Result<(File, DiskGeometry), CowError> {
+    validate_block_size(block_size)?;
+    let base = File::open(path)?;
+    let geometry = geometry_for_length(base.metadata()?.len()).map_err(BlockError::from)?;
+    Ok((base, geometry))
+}
+
+fn block_error(error: CowError) -> BlockError {
+    match error {
+        CowError::Block(error) => error,
+        _ => BlockError::Io(std::io::ErrorKind::InvalidData),
+    }
+}
+
+impl BlockDevice for CowImage {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.geometry.capacity() / self.block_size as u64
+    }
+
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        let first = block * (self.block_size / SECTOR_SIZE) as u64;
+        for (i, sector) in buffer.chunks_mut(SECTOR_SIZE).enumerate() {
+            self.read_sector((first + i as u64) as u32, sector).map_err(block_error)?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        let first = block * (self.block_size / SECTOR_SIZE) as u64;
+        for (i, sector) in buffer.chunks(SECTOR_SIZE).enumerate() {
+            self.write_sector((first + i as u64) as u32, sector).map_err(block_error)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        Ok(self.overlay.sync_data()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk_image::DiskImage;
+
+    /// Paths of a fresh base image and overlay in the temporary directory.
+    fn paths(test: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+        let dir = std::env::temp_dir();
+        let stem = format!("rusty_threads_{}_{test}", std::process::id());
+        let base = dir.join(format!("{stem}.img"));
+        let mut image = DiskImage::create(&base, DiskGeometry::with_tracks(1), 512).unwrap();
+        image.write_block(3, &[0xB5; 512]).unwrap();
+        (base, dir.join(format!("{stem}.cow")))
+    }
+
+    fn block(image: &mut CowImage, index: u64) -> u8 {
+        let mut buffer = vec![0; image.block_size()];
+        image.read_block(index, &mut buffer).unwrap();
+        assert!(buffer.iter().all(|&b| b == buffer[0]));
+        buffer[0]
+    }
+
+    #[test]
+    fn test_writes_go_to_overlay_and_revert_to_snapshots() {
+        let (base, overlay) = paths("revert");
+        let mut image = CowImage::create(&base, &overlay, 512).unwrap();
+        image.write_block(3, &[1; 512]).unwrap();
+        image.snapshot("one").unwrap();
+        image.write_block(3, &[2; 512]).unwrap();
+        image.write_block(4, &[2; 512]).unwrap();
+        image.snapshot("two").unwrap();
+        image.write_block(4, &[3; 512]).unwrap();
+        assert_eq!(image.snapshot("one"), Err(CowError::InvalidName));
+
+        image.revert("two").unwrap();
+        assert_eq!((block(&mut image, 3), block(&mut image, 4)), (2, 2));
+        image.revert("one").unwrap();
+        assert_eq!((block(&mut image, 3), block(&mut image, 4)), (1, 0));
+        assert_eq!(image.revert("two"), Err(CowError::NoSuchSnapshot));
+        image.reset().unwrap();
+        assert_eq!(block(&mut image, 3), 0xB5);
+        assert_eq!(image.changed_sectors(), 0);
+
+        let mut original = [0; 512];
+        DiskImage::open(&base, 512).unwrap().read_block(3, &mut original).unwrap();
+        assert_eq!(original, [0xB5; 512]);
+        let _ = std::fs::remove_file(&base);
+        let _ = std::fs::remove_file(&overlay);
+    }
+
+    #[test]
+    fn test_reopen_drops_torn_record() {
+        let (base, overlay) = paths("reopen");
+        let mut image = CowImage::create(&base, &overlay, 1024).unwrap();
+        image.write_block(0, &[7; 1024]).unwrap();
+        image.snapshot("written").unwrap();
+        drop(image);
+        let file = OpenOptions::new().append(true).open(&overlay).unwrap();
+        let mut torn = [0; RECORD_HEADER_SIZE + 100];
+        put_u32(&mut torn, 0, RECORD_DATA);
+        (&file).write_all(&torn).unwrap();
+
+        let mut image = CowImage::open(&base, &overlay, 1024).unwrap();
+        assert_eq!(image.snapshots()[0].name, "written");
+        assert_eq!(image.changed_sectors(), 2);
+        assert_eq!(block(&mut image, 0), 7);
+        let flat = overlay.with_extension("flat");
+        image.flatten(&flat).unwrap();
+        let mut flat_image = DiskImage::open(&flat, 512).unwrap();
+        let mut sector = [0; 512];
+        flat_image.read_block(1, &mut sector).unwrap();
+        assert_eq!(sector, [7; 512]);
+        flat_image.read_block(3, &mut sector).unwrap();
+        assert_eq!(sector, [0xB5; 512]);
+        for path in [&base, &overlay, &flat] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+// src/crash.rs
+
+use crate::block_device::{BlockDevice, BlockError, check_request};
+use crate::constants::THREADS_DISK_SECTOR_SIZE;
+use crate::rng::XorShift;
+use crate::rusty_wrapper;
+
+/// What a [`CrashingDevice`] does when it reaches its crash point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashAction {
+    /// Fail the write and every later request with `BlockError::Crashed`, leaving the
+    /// device exactly as a power cut would. Used by host-side tests.
+    DropWrites,
+    /// Halt the THREADS machine with `stop(code)`. The disk keeps whatever sectors were
+    /// written, and the next run mounts it and replays the journal.
+    Halt(i32),
+}
+
+/// A [`BlockDevice`] wrapper that crashes at a chosen sector write.
+///
+/// Writes are counted in 512-byte sectors, since that is the unit a THREADS disk writes
+/// atomically. When the crash point falls inside a multi-sector block, only the sectors
+/// before it reach the device, so the block is torn the way a real crash tears it.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{CrashAction, CrashingDevice, DeviceId, FileSystem, ThreadsDisk};
+///
+/// let disk = ThreadsDisk::open(DeviceId::disk(0), 1024)?;
+/// // Halt somewhere within the next 200 sector writes; the seed makes it reproducible.
+/// let device = CrashingDevice::new(disk, 42, 200, CrashAction::Halt(3));
+/// let mut fs = FileSystem::mount(device)?;
+/// ```
+pub struct CrashingDevice<D: BlockDevice> {
+    device: D,
+    crash_at: u64,
+    sectors_written: u64,
+    action: CrashAction,
+    crashed: bool,
+}
+
+impl<D: BlockDevice> CrashingDevice<D> {
+    /// Wraps `device` so that it crashes at a sector write picked from
+    /// `0..max_sectors` by `seed`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_sectors` is zero.
+    pub fn new(device: D, seed: u64, max_sectors: u64, action: CrashAction) -> Self {
+        assert!(max_sectors > 0, "crash window must include at least one sector write");
+        let crash_at = XorShift::new(seed).below(max_sectors);
+        Self::crash_after(device, crash_at, action)
+    }
+
+    /// Wraps `device` so that sector writes `0..sectors` succeed and sector write
+    /// `sectors` crashes.
+    pub fn crash_after(device: D, sectors: u64, action: CrashAction) -> Self {
+        CrashingDevice { device, crash_at: sectors, sectors_written: 0, action, crashed: false }
+    }
+
+    /// Index of the sector write that crashes.
+    pub fn crash_point(&self) -> u64 {
+        self.crash_at
+    }
+
+    /// Number of sectors written so far.
+    pub fn sectors_written(&self) -> u64 {
+        self.sectors_written
+    }
+
+    /// Returns `true` once the crash point has been reached.
+    pub fn has_crashed(&self) -> bool {
+        self.crashed
+    }
+
+    /// The underlying device.
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Returns the underlying device in whatever state the crash left it.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+
+    fn check_alive(&self) -> Result<(), BlockError> {
+        if self.crashed { Err(BlockError::Crashed) } else { Ok(()) }
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for CrashingDevice<D> {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        self.check_alive()?;
+        self.device.read_block(block, buffer)
+    }
+
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError> {
+        self.check_alive()?;
+        check_request(self, block, buffer.len())?;
+        let sector_size = THREADS_DISK_SECTOR_SIZE as usize;
+        let sectors = (buffer.len() / sector_size) as u64;
+        if self.sectors_written + sectors <= self.crash_at {
+            self.sectors_written += sectors;
+            return self.device.write_block(block, buffer);
+        }
+
+        // Tear the block: keep the sectors written before the crash point.
+        let torn = (self.crash_at - self.sectors_written) as usize * sector_size;
+        if torn > 0 {
+            let mut contents = vec![0; buffer.len()];
+            self.device.read_block(block, &mut contents)?;
+            contents[..torn].copy_from_slice(&buffer[..torn]);
+            self.device.write_block(block, &contents)?;
+            self.device.flush()?;
+        }
+        self.sectors_written = self.crash_at;
+        self.crashed = true;
+        match self.action {
+            CrashAction::DropWrites => Err(BlockError::Crashed),
+            CrashAction::Halt(code) => rusty_wrapper::stop(code),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        self.check_alive()?;
+        self.device.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_device::MemBlockDevice;
+
+    #[test]
+    fn test_crash_tears_block_and_stops_device() {
+        let memory = MemBlockDevice::new(1024, 4).unwrap();
+        let mut device = CrashingDevice::crash_after(memory, 3, CrashAction::DropWrites);
+        device.write_block(0, &[1; 1024]).unwrap();
+        assert_eq!(device.write_block(1, &[2; 1024]), Err(BlockError::Crashed));
+        assert!(device.has_crashed());
+        assert_eq!(device.read_block(0, &mut [0; 1024]), Err(BlockError::Crashed));
+
+        let bytes = device.into_inner().into_bytes();
+        assert_eq!(&bytes[..1024], &[1; 1024]);
+        assert_eq!(&bytes[1024..1536], &[2; 512]);
+        assert_eq!(&bytes[1536..2048], &[0; 512]);
+    }
+
+    #[test]
+    fn test_seeded_crash_point_is_reproducible() {
+        let point = |seed| {
+            let memory = MemBlockDevice::new(512, 1).unwrap();
+            CrashingDevice::new(memory, seed, 50, CrashAction::DropWrites).crash_point()
+        };
+        assert_eq!(point(9), point(9));
+        assert!((0..20).all(|seed| point(seed) < 50));
+    }
+}
@@ -0,0 +1,494 @@
+// src/custom_device.rs
+
+use crate::constants::{THREADS_MAX_DEVICE_NAME, THREADS_MAX_DEVICES, THREADS_MAX_IO_BUFFER_SIZE};
+use crate::device::{self, DeviceId, DeviceKind};
+use crate::dma::DmaRequest;
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::rusty_thread_bindings::device_control_block_t;
+
+/// Most devices that can be registered with [`register_device`].
+pub const MAX_CUSTOM_DEVICES: usize = 16;
+
+/// Errors returned when registering custom devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CustomDeviceError {
+    /// The name is empty, too long, not alphanumeric, or names a built-in device.
+    InvalidName,
+    /// Another custom device already has the name.
+    NameTaken,
+    /// [`MAX_CUSTOM_DEVICES`] devices are registered already.
+    TooManyDevices,
+    /// The device was not registered with [`register_device`].
+    NotCustom,
+}
+
+/// One `device_control` call on a custom device, with the buffers of its control block.
+#[derive(Debug)]
+pub struct DeviceRequest<'a> {
+    /// Device-defined command code.
+    pub command: u8,
+    /// First device-defined parameter, `control1` of the control block.
+    pub control1: u8,
+    /// Second device-defined parameter, `control2` of the control block.
+    pub control2: u8,
+    /// A copy of the `data_length` bytes at `input_data`, or empty if it is null.
+    pub input: &'a [u8],
+    /// The `data_length` bytes at `output_data`, or empty if it is null. Copied back to
+    /// `output_data` once [`Device::control`] returns, so `input_data` and `output_data`
+    /// may overlap.
+    pub output: &'a mut [u8],
+}
+
+/// A simulated device written in Rust, driven through the ordinary THREADS device calls.
+///
+/// Once registered, the device answers `device_initialize`, `device_handle` and
+/// `device_control` under its own name, with whatever command codes it defines. A command
+/// it accepts completes later: [`Device::poll`] is asked on every clock interrupt for
+/// finished commands, and each is delivered as a `THREADS_IO_INTERRUPT` carrying the
+/// device's name, through the installed interrupt vector like a built-in device.
+///
+/// Devices move data when a command is issued (the request's buffers are only borrowed
+/// for the call) and report completion when their simulated operation is over. The
+/// device is called outside the kernel's registry of custom devices, so it may make
+/// device calls itself; a call on the device it is serving fails as if the device were
+/// busy.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{Device, DeviceRequest};
+///
+/// /// Fills the output buffer with random bytes, 100 µs per request.
+/// struct RandomDevice { state: u64, due: Option<(u32, u8)> }
+///
+/// impl Device for RandomDevice {
+///     fn control(&mut self, request: DeviceRequest<'_>, now: u32) -> bool {
+///         if request.command != 1 || self.due.is_some() {
+///             return false;
+///         }
+///         for byte in request.output.iter_mut() {
+///             self.state ^= self.state << 13;
+///             self.state ^= self.state >> 7;
+///             self.state ^= self.state << 17;
+///             *byte = self.state as u8;
+///         }
+///         self.due = Some((now + 100, request.command));
+///         true
+///     }
+///
+///     fn poll(&mut self, now: u32) -> Option<(u8, u32)> {
+///         let (due, command) = self.due.filter(|&(due, _)| due <= now)?;
+///         self.due = None;
+///         Some((command, 0))
+///     }
+/// }
+///
+/// let rng = rusty_threads::register_device("rng0", RandomDevice { state: 7, due: None })?;
+/// ```
+pub trait Device: Send {
+    /// Called by `device_initialize`; returning `false` makes it fail.
+    fn initialize(&mut self) -> bool {
+        true
+    }
+
+    /// Starts `request` at simulated time `now`.
+    ///
+    /// # Returns
+    ///
+    /// `false` to reject the request, which makes `device_control` fail.
+    fn control(&mut self, request: DeviceRequest<'_>, now: u32) -> bool;
+
+    /// Starts a scatter-gather transfer issued with [`crate::dma::dma_transfer`]; like
+    /// [`Device::control`], but moving the whole list before returning and completing
+    /// with one interrupt. Devices without DMA keep the default, which rejects it.
+    fn dma(&mut self, _request: DmaRequest<'_, '_>, _now: u32) -> bool {
+        false
+    }
+
+    /// Returns the next command finished by `now` as `(command, status)`.
+    fn poll(&mut self, now: u32) -> Option<(u8, u32)>;
+}
+
+/// Driver entry point for the interrupts of a custom device, see
+/// [`set_device_interrupt_handler`].
+pub type DeviceInterruptHandler = fn(DeviceId, u8, u32);
+
+struct Registered {
+    name: String,
+    /// `None` while the device is out for a call, see [`call`].
+    device: Option<Box<dyn Device>>,
+    initialized: bool,
+    handler: Option<DeviceInterruptHandler>,
+}
+
+/// The registered custom devices; a device's unit number is its index.
+pub(crate) struct CustomDevices {
+    devices: Vec<Registered>,
+}
+
+impl CustomDevices {
+    pub(crate) const fn new() -> Self {
+        CustomDevices { devices: Vec::new() }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.devices.iter().position(|d| d.name == name)
+    }
+
+    fn register(
+        &mut self,
+        name: &str,
+        device: Box<dyn Device>,
+    ) -> Result<DeviceId, CustomDeviceError> {
+        let valid = !name.is_empty()
+            && name.len() < THREADS_MAX_DEVICE_NAME as usize
+            && name.bytes().all(|b| b.is_ascii_alphanumeric())
+            && name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && !is_builtin(name);
+        if !valid {
+            return Err(CustomDeviceError::InvalidName);
+        }
+        if self.find(name).is_some() {
+            return Err(CustomDeviceError::NameTaken);
+        }
+        if self.devices.len() == MAX_CUSTOM_DEVICES {
+            return Err(CustomDeviceError::TooManyDevices);
+        }
+        self.devices.push(Registered {
+            name: name.to_string(),
+            device: Some(device),
+            initialized: false,
+            handler: None,
+        });
+        Ok(custom_id(self.devices.len() - 1))
+    }
+
+    /// Unit number of the custom device called `name` and whether it is initialized.
+    fn lookup(&self, name: &str) -> Option<(usize, bool)> {
+        let unit = self.find(name)?;
+        Some((unit, self.devices[unit].initialized))
+    }
+
+    /// `device_handle`: `None` if `name` is not a custom device, otherwise the result of
+    /// the call.
+    fn handle(&self, name: &str) -> Option<Option<u32>> {
+        let (unit, initialized) = self.lookup(name)?;
+        Some(initialized.then(|| handle(unit)))
+    }
+
+    /// Takes the device of `unit` out for a call; `None` if it is out already.
+    fn take(&mut self, unit: usize) -> Option<Box<dyn Device>> {
+        self.devices.get_mut(unit)?.device.take()
+    }
+
+    /// Returns a device taken with [`CustomDevices::take`].
+    fn put_back(&mut self, unit: usize, device: Box<dyn Device>) {
+        self.devices[unit].device = Some(device);
+    }
+}
+
+static DEVICES: KernelCell<CustomDevices> = KernelCell::new(CustomDevices::new());
+
+fn custom_id(unit: usize) -> DeviceId {
+    DeviceId { kind: DeviceKind::Custom, unit: unit as u32 }
+}
+
+/// Handle of custom device `unit`; they follow the handles THREADS hands out.
+fn handle(unit: usize) -> u32 {
+    THREADS_MAX_DEVICES + unit as u32
+}
+
+/// Runs `f` on the device of custom device `unit` with [`DEVICES`] not borrowed, so the
+/// device may make device calls itself.
+///
+/// # Returns
+///
+/// `None` if there is no such device or it is already in a call further up the stack.
+fn call<R>(unit: usize, f: impl FnOnce(&mut dyn Device) -> R) -> Option<R> {
+    let guard = InterruptGuard::new();
+    let mut device = DEVICES.with(&guard, |d| d.take(unit))?;
+    let result = f(device.as_mut());
+    DEVICES.with(&guard, |d| d.put_back(unit, device));
+    Some(result)
+}
+
+/// Starts the `device_control` call described by `block` on `device`.
+///
+/// The buffers are copied in before the call and the output copied back after it, so
+/// the request's slices never alias each other or the caller's memory.
+///
+/// # Safety
+///
+/// As for the C `device_control`: non-null buffers of `block` must hold `data_length`
+/// bytes and stay valid for the call.
+unsafe fn start(device: &mut dyn Device, block: device_control_block_t, now: u32) -> bool {
+    let length = block.data_length as usize;
+    let copy_in = |data: *mut core::ffi::c_void| match data.is_null() {
+        true => Vec::new(),
+        // SAFETY: guaranteed by the caller.
+        false => unsafe { std::slice::from_raw_parts(data as *const u8, length) }.to_vec(),
+    };
+    let input = copy_in(block.input_data);
+    let mut output = copy_in(block.output_data);
+    let request = DeviceRequest {
+        command: block.command,
+        control1: block.control1,
+        control2: block.control2,
+        input: &input,
+        output: &mut output,
+    };
+    let accepted = device.control(request, now);
+    if !output.is_empty() {
+        // SAFETY: guaranteed by the caller; `output` is our own copy.
+        unsafe { std::ptr::copy_nonoverlapping(output.as_ptr(), block.output_data.cast(), length) };
+    }
+    accepted
+}
+
+/// Built-in names, including units beyond the configured limits, which THREADS might
+/// still claim.
+fn is_builtin(name: &str) -> bool {
+    let unit = name.strip_prefix("disk").or_else(|| name.strip_prefix("term"));
+    name == "clock" || unit.is_some_and(|u| u.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Adds a simulated device under `name`.
+///
+/// # Arguments
+///
+/// * `name` – Alphanumeric THREADS device name such as `"nic0"`, shorter than
+///   `THREADS_MAX_DEVICE_NAME` and different from the built-in disk, terminal and clock
+///   names.
+/// * `device` – The device model.
+///
+/// # Returns
+///
+/// The device's id, whose [`DeviceId::name`] is `name`.
+pub fn register_device(
+    name: &str,
+    device: impl Device + 'static,
+) -> Result<DeviceId, CustomDeviceError> {
+    let guard = InterruptGuard::new();
+    DEVICES.with(&guard, |d| d.register(name, Box::new(device)))
+}
+
+/// Makes `handler` the driver for interrupts of custom device `device`.
+///
+/// The crate's `THREADS_IO_INTERRUPT` handler calls it with each completion of the
+/// device, with interrupts disabled, and dispatches afterwards in case it readied a
+/// process.
+pub fn set_device_interrupt_handler(
+    device: DeviceId,
+    handler: DeviceInterruptHandler,
+) -> Result<(), CustomDeviceError> {
+    if device.kind != DeviceKind::Custom {
+        return Err(CustomDeviceError::NotCustom);
+    }
+    let guard = InterruptGuard::new();
+    DEVICES.with(&guard, |d| match d.devices.get_mut(device.unit as usize) {
+        Some(registered) => {
+            registered.handler = Some(handler);
+            Ok(())
+        }
+        None => Err(CustomDeviceError::NotCustom),
+    })
+}
+
+/// Name of custom device `unit`.
+pub(crate) fn device_name(unit: u32) -> Option<String> {
+    let guard = InterruptGuard::new();
+    DEVICES.with(&guard, |d| d.devices.get(unit as usize).map(|r| r.name.clone()))
+}
+
+/// Id of the custom device called `name`.
+pub(crate) fn device_id(name: &str) -> Option<DeviceId> {
+    let guard = InterruptGuard::new();
+    DEVICES.with(&guard, |d| d.find(name).map(custom_id))
+}
+
+/// `device_initialize` for custom devices.
+///
+/// # Returns
+///
+/// `None` if `name` is not a custom device, otherwise the result of the call.
+pub(crate) fn initialize(name: &str) -> Option<Option<u32>> {
+    let guard = InterruptGuard::new();
+    let (unit, mut initialized) = DEVICES.with(&guard, |d| d.lookup(name))?;
+    if !initialized {
+        initialized = call(unit, |device| device.initialize()).unwrap_or(false);
+        DEVICES.with(&guard, |d| d.devices[unit].initialized |= initialized);
+    }
+    Some(initialized.then(|| handle(unit)))
+}
+
+/// `device_handle` for custom devices, see [`initialize`].
+pub(crate) fn handle_of(name: &str) -> Option<Option<u32>> {
+    let guard = InterruptGuard::new();
+    DEVICES.with(&guard, |d| d.handle(name))
+}
+
+/// `device_control` for custom devices, see [`initialize`].
+pub(crate) fn control(name: &str, block: device_control_block_t, now: u32) -> Option<Option<u32>> {
+    let guard = InterruptGuard::new();
+    let (unit, initialized) = DEVICES.with(&guard, |d| d.lookup(name))?;
+    if !initialized || block.data_length > THREADS_MAX_IO_BUFFER_SIZE {
+        return Some(None);
+    }
+    // SAFETY: as for the C `device_control`, the caller guarantees that non-null
+    // buffers hold `data_length` bytes and stay valid for the call.
+    let accepted = call(unit, |device| unsafe { start(device, block, now) });
+    Some(accepted.unwrap_or(false).then_some(0))
+}
+
+/// Runs `f` on initialized custom device `device`.
+///
+/// # Returns
+///
+/// `None` if `device` is not a custom device, `Some(None)` if it is not initialized or
+/// is already in a call further up the stack.
+pub(crate) fn with_device<R>(
+    device: DeviceId,
+    f: impl FnOnce(&mut dyn Device) -> R,
+) -> Option<Option<R>> {
+    if device.kind != DeviceKind::Custom {
+        return None;
+    }
+    let unit = device.unit as usize;
+    let guard = InterruptGuard::new();
+    let initialized = DEVICES.with(&guard, |d| Some(d.devices.get(unit)?.initialized))?;
+    Some(initialized.then(|| call(unit, f)).flatten())
+}
+
+/// Raises the `THREADS_IO_INTERRUPT`s of custom devices whose commands finished; called
+/// on every clock interrupt, with interrupts enabled again.
+pub(crate) fn on_clock(now: u32) {
+    let mut due = Vec::new();
+    let units = DEVICES.with(&InterruptGuard::new(), |d| d.devices.len());
+    for unit in 0..units {
+        let guard = InterruptGuard::new();
+        let (name, initialized) = DEVICES.with(&guard, |d| {
+            let registered = &d.devices[unit];
+            (registered.name.clone(), registered.initialized)
+        });
+        if !initialized {
+            continue;
+        }
+        let finished: Option<Vec<_>> =
+            call(unit, |device| core::iter::from_fn(|| device.poll(now)).collect());
+        for (command, status) in finished.unwrap_or_default() {
+            due.push((name.clone(), command, status));
+        }
+    }
+    for (name, command, status) in due {
+        device::raise_io_interrupt(&name, command, status);
+    }
+}
+
+/// Handles a `THREADS_IO_INTERRUPT` from custom device `unit`.
+pub(crate) fn on_interrupt(unit: u32, command: u8, status: u32) {
+    let guard = InterruptGuard::new();
+    let handler = DEVICES.with(&guard, |d| d.devices.get(unit as usize)?.handler);
+    if let Some(handler) = handler {
+        handler(custom_id(unit as usize), command, status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reverses its input into the output, completing `delay` later.
+    struct Reverser {
+        delay: u32,
+        due: Vec<(u32, u8)>,
+    }
+
+    fn reverser(delay: u32) -> Box<dyn Device> {
+        Box::new(Reverser { delay, due: Vec::new() })
+    }
+
+    impl Device for Reverser {
+        fn control(&mut self, request: DeviceRequest<'_>, now: u32) -> bool {
+            if request.command != 7 {
+                return false;
+            }
+            for (dst, src) in request.output.iter_mut().zip(request.input.iter().rev()) {
+                *dst = *src;
+            }
+            self.due.push((now + self.delay, request.command));
+            true
+        }
+
+        fn poll(&mut self, now: u32) -> Option<(u8, u32)> {
+            let index = self.due.iter().position(|&(due, _)| due <= now)?;
+            Some((self.due.remove(index).1, 0))
+        }
+    }
+
+    fn block(command: u8, input: &mut [u8], output: &mut [u8]) -> device_control_block_t {
+        device_control_block_t {
+            command,
+            control1: 0,
+            control2: 0,
+            input_data: input.as_mut_ptr().cast(),
+            output_data: output.as_mut_ptr().cast(),
+            data_length: input.len() as u32,
+        }
+    }
+
+    #[test]
+    fn test_names_are_validated() {
+        let mut devices = CustomDevices::new();
+        for name in ["", "disk7", "term0", "clock", "my-dev", "9lives"] {
+            let result = devices.register(name, reverser(1));
+            assert_eq!(result.err(), Some(CustomDeviceError::InvalidName));
+        }
+        assert_eq!(devices.register("nic0", reverser(1)).ok(), Some(custom_id(0)));
+        let taken = devices.register("nic0", reverser(1));
+        assert_eq!(taken.err(), Some(CustomDeviceError::NameTaken));
+        for unit in 1..MAX_CUSTOM_DEVICES {
+            devices.register(&format!("rng{unit}"), reverser(1)).unwrap();
+        }
+        let full = devices.register("printer0", reverser(1));
+        assert_eq!(full.err(), Some(CustomDeviceError::TooManyDevices));
+    }
+
+    #[test]
+    fn test_requests_get_copies_of_their_buffers() {
+        let mut device = Reverser { delay: 50, due: Vec::new() };
+        let (mut input, mut output) = (*b"abc", [0; 3]);
+        let request = block(7, &mut input, &mut output);
+        assert!(unsafe { start(&mut device, request, 100) });
+        assert_eq!(&output, b"cba");
+        let unknown = block(8, &mut input, &mut output);
+        assert!(!unsafe { start(&mut device, unknown, 100) });
+
+        // The same buffer as input and output is reversed in place.
+        let mut buffer = *b"xyz";
+        let in_place = block(7, &mut buffer, &mut []);
+        let in_place = device_control_block_t { output_data: in_place.input_data, ..in_place };
+        assert!(unsafe { start(&mut device, in_place, 120) });
+        assert_eq!(&buffer, b"zyx");
+
+        assert_eq!(device.poll(149), None);
+        assert_eq!(device.poll(150), Some((7, 0)));
+        assert_eq!(device.poll(170), Some((7, 0)));
+    }
+
+    #[test]
+    fn test_a_device_is_out_of_the_registry_while_called() {
+        let mut devices = CustomDevices::new();
+        devices.register("reverse0", reverser(50)).unwrap();
+        assert_eq!(devices.lookup("reverse0"), Some((0, false)));
+        assert_eq!(devices.handle("reverse0"), Some(None));
+        assert_eq!(devices.handle("disk0"), None);
+
+        let device = devices.take(0).unwrap();
+        assert!(devices.take(0).is_none());
+        assert!(devices.take(1).is_none());
+        devices.put_back(0, device);
+        devices.devices[0].initialized = true;
+        assert_eq!(devices.handle("reverse0"), Some(Some(THREADS_MAX_DEVICES)));
+        assert!(devices.take(0).is_some());
+    }
+}
@@ -0,0 +1,211 @@
+// src/device.rs
+
+use core::fmt;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+use crate::console_view;
+use crate::constants::{
+    THREADS_IO_INTERRUPT, THREADS_MAX_DEVICE_NAME, THREADS_MAX_DISKS, THREADS_MAX_TERMINALS,
+};
+use crate::custom_device;
+use crate::disk;
+use crate::hotplug;
+use crate::interrupts::InterruptGuard;
+use crate::io_stats;
+use crate::rusty_wrapper::get_interrupt_handlers;
+use crate::scheduler;
+use crate::terminal;
+
+/// Kind of a THREADS device, mirroring the C `device_type_t` enumeration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceKind {
+    /// `DEVICE_TERMINAL`
+    Terminal,
+    /// `DEVICE_CLOCK`
+    Clock,
+    /// `DEVICE_DISK`
+    Disk,
+    /// A device registered with [`crate::custom_device::register_device`]; not part of
+    /// `device_type_t`.
+    Custom,
+}
+
+/// Identifies a THREADS device by kind and unit number.
+///
+/// THREADS addresses devices by name (`"disk0"`, `"term1"`, ...), both in the
+/// `device_*` calls and in the `deviceId` argument of interrupt handlers. `DeviceId`
+/// converts between the two forms.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::DeviceId;
+///
+/// let disk = DeviceId::disk(1);
+/// assert_eq!(disk.name(), "disk1");
+/// assert_eq!(DeviceId::from_name("disk1"), Some(disk));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceId {
+    /// The kind of device.
+    pub kind: DeviceKind,
+    /// Unit number within the kind, starting at zero.
+    pub unit: u32,
+}
+
+impl DeviceId {
+    /// The disk with the given unit number.
+    pub const fn disk(unit: u32) -> Self {
+        DeviceId { kind: DeviceKind::Disk, unit }
+    }
+
+    /// The terminal with the given unit number.
+    pub const fn terminal(unit: u32) -> Self {
+        DeviceId { kind: DeviceKind::Terminal, unit }
+    }
+
+    /// The system clock.
+    pub const fn clock() -> Self {
+        DeviceId { kind: DeviceKind::Clock, unit: 0 }
+    }
+
+    /// Returns the THREADS device name, e.g. `"disk0"` or `"term3"`.
+    pub fn name(&self) -> String {
+        match self.kind {
+            DeviceKind::Disk => format!("disk{}", self.unit),
+            DeviceKind::Terminal => format!("term{}", self.unit),
+            DeviceKind::Clock => "clock".to_string(),
+            DeviceKind::Custom => custom_device::device_name(self.unit).unwrap_or_default(),
+        }
+    }
+
+    /// Parses a THREADS device name, rejecting unit numbers beyond the configured limits.
+    /// Names of registered custom devices are recognized too.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name == "clock" {
+            return Some(Self::clock());
+        }
+        let (kind, unit, limit) = if let Some(unit) = name.strip_prefix("disk") {
+            (DeviceKind::Disk, unit, THREADS_MAX_DISKS)
+        } else if let Some(unit) = name.strip_prefix("term") {
+            (DeviceKind::Terminal, unit, THREADS_MAX_TERMINALS)
+        } else {
+            return custom_device::device_id(name);
+        };
+        let unit: u32 = unit.parse().ok()?;
+        (unit < limit).then_some(DeviceId { kind, unit })
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+/// Reads the `deviceId` argument of an interrupt handler, which THREADS passes as a
+/// NUL-terminated `char[THREADS_MAX_DEVICE_NAME]`.
+///
+/// # Safety
+///
+/// `raw` must be null or point to at least `THREADS_MAX_DEVICE_NAME` readable bytes.
+pub(crate) unsafe fn device_name_from_raw(raw: *const c_char) -> Option<String> {
+    if raw.is_null() {
+        return None;
+    }
+    let bytes =
+        unsafe { std::slice::from_raw_parts(raw as *const u8, THREADS_MAX_DEVICE_NAME as usize) };
+    let name = match CStr::from_bytes_until_nul(bytes) {
+        Ok(name) => name.to_bytes(),
+        Err(_) => bytes,
+    };
+    std::str::from_utf8(name).ok().map(str::to_string)
+}
+
+/// Installs the crate's I/O interrupt handler into the THREADS interrupt vector.
+///
+/// The handler routes each `THREADS_IO_INTERRUPT` to the driver that owns the device
+/// named in the interrupt. Drivers install it themselves from their `init` functions.
+pub fn install_io_handler() {
+    let handlers = get_interrupt_handlers();
+    handlers[THREADS_IO_INTERRUPT as usize] = Some(io_interrupt_handler);
+}
+
+/// Raises a `THREADS_IO_INTERRUPT` for device `name` through the interrupt vector, as
+/// THREADS does when one of its devices completes a command. Does nothing while no I/O
+/// handler is installed.
+pub(crate) fn raise_io_interrupt(name: &str, command: u8, status: u32) {
+    let Some(handler) = get_interrupt_handlers()[THREADS_IO_INTERRUPT as usize] else {
+        return;
+    };
+    let mut device_id = [0 as c_char; THREADS_MAX_DEVICE_NAME as usize];
+    for (dst, src) in device_id.iter_mut().zip(name.bytes()) {
+        *dst = src as c_char;
+    }
+    // SAFETY: the handler gets a NUL-terminated `char[THREADS_MAX_DEVICE_NAME]`, as
+    // THREADS passes for its own devices.
+    unsafe { handler(device_id.as_mut_ptr(), command, status) };
+}
+
+/// Runs the per-tick work of the device drivers; called by the scheduler on every clock
+/// interrupt.
+///
+/// Drivers that only update their own state run with interrupts disabled; those that
+/// raise `THREADS_IO_INTERRUPT`s run afterwards, with interrupts enabled again, so the
+/// handlers they trigger can take the guard themselves.
+pub(crate) fn on_clock(now: u32) {
+    {
+        let guard = InterruptGuard::new();
+        disk::on_clock(&guard, now);
+        console_view::on_clock(&guard, now);
+    }
+    custom_device::on_clock(now);
+    terminal::on_clock();
+    hotplug::on_clock();
+}
+
+unsafe extern "C" fn io_interrupt_handler(device_id: *mut c_char, command: u8, status: u32) {
+    let Some(device) =
+        unsafe { device_name_from_raw(device_id) }.and_then(|n| DeviceId::from_name(&n))
+    else {
+        return;
+    };
+
+    io_stats::on_interrupt(device, command, status);
+    match device.kind {
+        DeviceKind::Disk => disk::on_interrupt(device.unit, command, status),
+        DeviceKind::Terminal => terminal::on_interrupt(device.unit, command, status),
+        DeviceKind::Clock => {}
+        DeviceKind::Custom => custom_device::on_interrupt(device.unit, command, status),
+    }
+
+    // A completion may have readied a process with a higher priority than the one that
+    // was interrupted.
+    scheduler::dispatch();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_names_round_trip() {
+        for id in [DeviceId::disk(0), DeviceId::disk(3), DeviceId::terminal(2), DeviceId::clock()] {
+            assert_eq!(DeviceId::from_name(&id.name()), Some(id));
+        }
+        assert_eq!(DeviceId::from_name("disk4"), None);
+        assert_eq!(DeviceId::from_name("term"), None);
+        assert_eq!(DeviceId::from_name("printer0"), None);
+    }
+
+    #[test]
+    fn test_device_name_from_raw() {
+        let mut raw = [0 as c_char; THREADS_MAX_DEVICE_NAME as usize];
+        for (dst, src) in raw.iter_mut().zip(b"disk2") {
+            *dst = *src as c_char;
+        }
+        assert_eq!(unsafe { device_name_from_raw(raw.as_ptr()) }.as_deref(), Some("disk2"));
+        assert_eq!(unsafe { device_name_from_raw(core::ptr::null()) }, None);
+    }
+}
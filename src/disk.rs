@@ -0,0 +1,1134 @@
+// src/disk.rs
+
+use std::collections::VecDeque;
+
+use crate::constants::{
+    DISK_INFO, DISK_READ, DISK_SEEK, DISK_WRITE, THREADS_DISK_MAX_PLATTERS,
+    THREADS_DISK_SECTOR_COUNT, THREADS_DISK_SECTOR_SIZE, THREADS_MAX_DISKS,
+};
+use crate::device::{self, DeviceId, DeviceKind};
+use crate::disk_fault::{DISK_STATUS_TIMEOUT, Delivery, DiskFaults, FaultInjector, FaultStats};
+use crate::disk_geometry::{DISK_INFO_SIZE, DiskGeometry};
+use crate::disk_scheduler::{self, DiskSchedulingPolicy, DiskStats};
+use crate::hotplug::{self, DEVICE_STATUS_ATTACHED, DEVICE_STATUS_REMOVED, HOTPLUG_COMMAND};
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::io_stats;
+use crate::rusty_thread_bindings::device_control_block_t;
+use crate::rusty_wrapper::{device_control, device_initialize, system_clock};
+use crate::scheduler::{self, BlockReason, KERNEL, Pid};
+
+/// Interrupt status reported by THREADS for a disk command that completed successfully.
+/// Any other status is treated as a device error.
+pub const DISK_STATUS_OK: u32 = 0;
+
+const SECTOR_SIZE: usize = THREADS_DISK_SECTOR_SIZE as usize;
+
+/// Highest track number that fits in the 8-bit `control1` field of a seek command.
+pub(crate) const MAX_ADDRESSABLE_TRACK: u32 = u8::MAX as u32;
+
+/// Location of a sector on a THREADS disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DiskAddress {
+    /// Platter, below `THREADS_DISK_MAX_PLATTERS`.
+    pub platter: u32,
+    /// Track (cylinder) on the platter.
+    pub track: u32,
+    /// Sector within the track, below `THREADS_DISK_SECTOR_COUNT`.
+    pub sector: u32,
+}
+
+impl DiskAddress {
+    /// Creates an address from its parts.
+    pub const fn new(platter: u32, track: u32, sector: u32) -> Self {
+        DiskAddress { platter, track, sector }
+    }
+
+    /// Returns the address `offset` sectors further along the same platter, continuing
+    /// onto the following tracks.
+    pub fn offset(&self, offset: u32) -> Self {
+        let index = self.sector + offset;
+        DiskAddress {
+            platter: self.platter,
+            track: self.track + index / THREADS_DISK_SECTOR_COUNT,
+            sector: index % THREADS_DISK_SECTOR_COUNT,
+        }
+    }
+}
+
+/// Errors returned by the disk driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskError {
+    /// The call must be made from a running process.
+    NoProcess,
+    /// The device is not a disk, or its unit number is out of range.
+    NotADisk,
+    /// The disk did not initialize in [`init_disks`].
+    NotPresent,
+    /// The transfer would touch a sector outside the addressable range.
+    InvalidAddress,
+    /// The buffer is empty or not a whole number of sectors.
+    InvalidBuffer,
+    /// THREADS rejected the `device_control` call.
+    CommandRejected,
+    /// The completion interrupt reported the given non-zero status.
+    Device(u32),
+    /// The `DISK_INFO` reply could not be decoded.
+    InvalidGeometry,
+    /// The disk was unplugged while the request was queued or in flight, see
+    /// [`crate::hotplug::detach_device`].
+    Removed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Read,
+    Write,
+    /// `DISK_INFO`, answered without moving the head.
+    Info,
+}
+
+/// A queued multi-sector transfer on behalf of a blocked process.
+struct DiskRequest {
+    pid: Pid,
+    operation: Operation,
+    start: DiskAddress,
+    sectors: u32,
+    buffer: *mut u8,
+    submitted_at: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Seeking,
+    Transferring,
+}
+
+/// Why the active request ends with its outstanding command instead of going on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Abort {
+    /// The disk was unplugged; the owner fails with `DiskError::Removed`.
+    Removed,
+    /// The owner was killed and nobody waits for the result.
+    Killed,
+}
+
+struct ActiveRequest {
+    request: DiskRequest,
+    completed_sectors: u32,
+    phase: Phase,
+    /// Tracks the scheduling policy sends the head through before the request's own.
+    stops: VecDeque<u32>,
+    /// Track of the outstanding seek.
+    seek_target: u32,
+    /// Set when the request ends with its outstanding command, which still moves data
+    /// through the bounce buffer but no longer into the owner's.
+    abort: Option<Abort>,
+}
+
+impl ActiveRequest {
+    fn current_address(&self) -> DiskAddress {
+        self.request.start.offset(self.completed_sectors)
+    }
+
+    /// The command whose completion advances the request.
+    fn expected_command(&self) -> u8 {
+        match (self.phase, self.request.operation) {
+            (Phase::Seeking, _) => DISK_SEEK,
+            (Phase::Transferring, Operation::Read) => DISK_READ,
+            (Phase::Transferring, Operation::Write) => DISK_WRITE,
+            (Phase::Transferring, Operation::Info) => DISK_INFO,
+        }
+    }
+
+    /// Returns the part of the owner's buffer the current sector moves through.
+    ///
+    /// # Safety
+    ///
+    /// The owner must still be blocked in [`submit_and_wait`], which keeps the buffer
+    /// borrowed; aborted requests never reach here.
+    unsafe fn sector(&mut self) -> &mut [u8] {
+        let (offset, length) = match self.request.operation {
+            Operation::Info => (0, DISK_INFO_SIZE),
+            _ => ((self.completed_sectors * THREADS_DISK_SECTOR_SIZE) as usize, SECTOR_SIZE),
+        };
+        // The range stays within the `sectors * SECTOR_SIZE` byte buffer the request was
+        // created with.
+        unsafe { core::slice::from_raw_parts_mut(self.request.buffer.add(offset), length) }
+    }
+}
+
+/// A device command the driver needs issued through `device_control`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DiskCommand {
+    pub(crate) command: u8,
+    pub(crate) control1: u8,
+    pub(crate) control2: u8,
+    pub(crate) buffer: *mut u8,
+}
+
+impl DiskCommand {
+    /// Builds the control block for this command.
+    ///
+    /// Seeks carry the track in `control1` and the platter in `control2`. Reads and writes
+    /// carry the sector in `control1` and the platter in `control2`, and move exactly one
+    /// `THREADS_DISK_SECTOR_SIZE` sector through `output_data` (read) or `input_data`
+    /// (write). `DISK_INFO` takes no controls and fills `DISK_INFO_SIZE` bytes of
+    /// `output_data`.
+    fn control_block(&self) -> device_control_block_t {
+        let (input_data, output_data, data_length) = match self.command {
+            DISK_READ => (core::ptr::null_mut(), self.buffer.cast(), THREADS_DISK_SECTOR_SIZE),
+            DISK_WRITE => (self.buffer.cast(), core::ptr::null_mut(), THREADS_DISK_SECTOR_SIZE),
+            DISK_INFO => (core::ptr::null_mut(), self.buffer.cast(), DISK_INFO_SIZE as u32),
+            _ => (core::ptr::null_mut(), core::ptr::null_mut(), 0),
+        };
+        device_control_block_t {
+            command: self.command,
+            control1: self.control1,
+            control2: self.control2,
+            input_data,
+            output_data,
+            data_length,
+        }
+    }
+}
+
+/// Outcome of feeding a completion (or a rejected command) to a [`DiskQueue`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Progress {
+    /// A process whose request finished and should be readied.
+    pub(crate) finished: Option<Pid>,
+    /// The next command to issue on this disk.
+    pub(crate) next: Option<DiskCommand>,
+    /// The completion the queue took, as `(command, status)` once injected faults were
+    /// applied, for the I/O statistics.
+    pub(crate) completed: Option<(u8, u32)>,
+}
+
+/// Request queue and in-flight state of one disk.
+///
+/// The queue is a pure state machine: it never calls into THREADS, it only says which
+/// command to issue next and which process to wake. At most one command is outstanding
+/// per disk; the completion interrupt for it advances the machine. When the disk goes
+/// idle, the scheduling policy picks the next request from the pending queue.
+pub(crate) struct DiskQueue {
+    present: bool,
+    /// Track the head is known to be on, `None` after an error.
+    head_track: Option<u32>,
+    /// Last track the head reached, used for scheduling and head-movement accounting.
+    arm: u32,
+    ascending: bool,
+    /// Far edge of the disk for SCAN and C-SCAN sweeps.
+    last_track: u32,
+    /// Geometry from the last successful [`disk_info`], used to bounds-check transfers.
+    geometry: Option<DiskGeometry>,
+    policy: DiskSchedulingPolicy,
+    stats: DiskStats,
+    pending: VecDeque<DiskRequest>,
+    active: Option<ActiveRequest>,
+    results: Vec<(Pid, Result<(), DiskError>)>,
+    /// Faults injected into completions, see [`set_disk_faults`].
+    faults: Option<FaultInjector>,
+    /// Command waiting for its completion and when it was issued, for timeouts.
+    outstanding: Option<(u8, u32)>,
+    /// The sector every read, write and `DISK_INFO` moves through, so that THREADS only
+    /// ever holds a pointer into the driver and never into a process's stack.
+    bounce: [u8; SECTOR_SIZE],
+}
+
+impl DiskQueue {
+    const fn new() -> Self {
+        DiskQueue {
+            present: false,
+            head_track: None,
+            arm: 0,
+            ascending: true,
+            last_track: MAX_ADDRESSABLE_TRACK,
+            geometry: None,
+            policy: DiskSchedulingPolicy::Fcfs,
+            stats: DiskStats {
+                policy: DiskSchedulingPolicy::Fcfs,
+                requests: 0,
+                head_movement: 0,
+                total_wait: 0,
+            },
+            pending: VecDeque::new(),
+            active: None,
+            results: Vec::new(),
+            faults: None,
+            outstanding: None,
+            bounce: [0; SECTOR_SIZE],
+        }
+    }
+
+    /// Queues `request`, returning the command to issue if the disk was idle.
+    fn submit(&mut self, request: DiskRequest, now: u32) -> Option<DiskCommand> {
+        self.pending.push_back(request);
+        if self.active.is_some() {
+            return None;
+        }
+        self.start_next(now)
+    }
+
+    fn start_next(&mut self, now: u32) -> Option<DiskCommand> {
+        // `DISK_INFO` does not move the head, so it is "on the way" for every policy.
+        let tracks: Vec<u32> = self
+            .pending
+            .iter()
+            .map(|r| if r.operation == Operation::Info { self.arm } else { r.start.track })
+            .collect();
+        let choice = disk_scheduler::choose(
+            self.policy,
+            self.arm,
+            self.ascending,
+            self.last_track,
+            &tracks,
+        )?;
+        let request = self.pending.remove(choice.index)?;
+        self.ascending = choice.ascending;
+        self.stats.total_wait += u64::from(now.wrapping_sub(request.submitted_at));
+        self.active = Some(ActiveRequest {
+            request,
+            completed_sectors: 0,
+            phase: Phase::Seeking,
+            stops: choice.stops.into(),
+            seek_target: 0,
+            abort: None,
+        });
+        self.issue()
+    }
+
+    /// Produces the next command for the active request: a seek if the head is not yet on
+    /// the next stop or the request's track, otherwise the transfer of the next sector.
+    fn issue(&mut self) -> Option<DiskCommand> {
+        let active = self.active.as_mut()?;
+        let address = active.current_address();
+
+        if active.request.operation == Operation::Info {
+            active.phase = Phase::Transferring;
+            // A reply shorter than `DISK_INFO_SIZE` leaves some of these bytes behind,
+            // which makes `DiskGeometry::decode` see far more tracks than a seek can
+            // reach instead of a plausible count.
+            self.bounce[..DISK_INFO_SIZE].fill(0xFF);
+            return Some(DiskCommand {
+                command: DISK_INFO,
+                control1: 0,
+                control2: 0,
+                buffer: self.bounce.as_mut_ptr(),
+            });
+        }
+
+        while active.stops.front().is_some_and(|&stop| self.head_track == Some(stop)) {
+            active.stops.pop_front();
+        }
+        let target = active.stops.front().copied().unwrap_or(address.track);
+
+        if self.head_track != Some(target) {
+            active.phase = Phase::Seeking;
+            active.seek_target = target;
+            return Some(DiskCommand {
+                command: DISK_SEEK,
+                control1: target as u8,
+                control2: address.platter as u8,
+                buffer: core::ptr::null_mut(),
+            });
+        }
+
+        active.phase = Phase::Transferring;
+        if active.request.operation == Operation::Write {
+            // SAFETY: see `ActiveRequest::sector`.
+            self.bounce.copy_from_slice(unsafe { active.sector() });
+        }
+        Some(DiskCommand {
+            command: match active.request.operation {
+                Operation::Read => DISK_READ,
+                Operation::Write => DISK_WRITE,
+                Operation::Info => DISK_INFO,
+            },
+            control1: address.sector as u8,
+            control2: address.platter as u8,
+            buffer: self.bounce.as_mut_ptr(),
+        })
+    }
+
+    /// Advances the active request with a completion interrupt for `command`.
+    ///
+    /// The completion of a command that was in flight when the disk was unplugged is not
+    /// reported in [`Progress::completed`]: the statistics failed it on removal.
+    fn complete(&mut self, command: u8, status: u32, now: u32) -> Progress {
+        let counted = self
+            .active
+            .as_ref()
+            .is_some_and(|a| a.expected_command() == command && a.abort != Some(Abort::Removed));
+        let progress = self.advance(command, status, now);
+        Progress { completed: counted.then_some((command, status)), ..progress }
+    }
+
+    fn advance(&mut self, command: u8, status: u32, now: u32) -> Progress {
+        let Some(active) = self.active.as_mut() else {
+            return Progress::default();
+        };
+        if command != active.expected_command() {
+            return Progress::default();
+        }
+        self.outstanding = None;
+        match active.abort {
+            Some(Abort::Removed) => return self.finish(Err(DiskError::Removed), now),
+            Some(Abort::Killed) => return self.finish(Err(DiskError::Device(status)), now),
+            None => {}
+        }
+
+        if status != DISK_STATUS_OK {
+            // The head position is unknown after a failure; force a seek next time.
+            self.head_track = None;
+            if active.phase == Phase::Seeking && !active.stops.is_empty() {
+                // A sweep to an edge past the end of a smaller disk just ends the sweep.
+                active.stops.clear();
+                return Progress { next: self.issue(), ..Progress::default() };
+            }
+            return self.finish(Err(DiskError::Device(status)), now);
+        }
+
+        match active.phase {
+            Phase::Seeking => {
+                let target = active.seek_target;
+                self.stats.head_movement += u64::from(target.abs_diff(self.arm));
+                self.arm = target;
+                self.head_track = Some(target);
+            }
+            Phase::Transferring => {
+                if active.request.operation != Operation::Write {
+                    // SAFETY: see `ActiveRequest::sector`.
+                    let sector = unsafe { active.sector() };
+                    sector.copy_from_slice(&self.bounce[..sector.len()]);
+                }
+                active.completed_sectors += 1;
+                if active.completed_sectors == active.request.sectors {
+                    return self.finish(Ok(()), now);
+                }
+            }
+        }
+        Progress { next: self.issue(), ..Progress::default() }
+    }
+
+    /// Feeds a completion interrupt through the fault injector, if any, to
+    /// [`DiskQueue::complete`].
+    fn interrupt(&mut self, command: u8, status: u32, now: u32) -> Progress {
+        let Some(faults) = self.faults.as_mut() else {
+            return self.complete(command, status, now);
+        };
+        let address = self
+            .active
+            .as_ref()
+            .filter(|a| a.phase == Phase::Transferring)
+            .map(ActiveRequest::current_address);
+        match faults.intercept(command, address, status, now) {
+            Delivery::Now(status) => self.complete(command, status, now),
+            Delivery::Later | Delivery::Dropped => Progress::default(),
+        }
+    }
+
+    /// Records that `command` was handed to THREADS at `now`.
+    fn issued(&mut self, command: u8, now: u32) {
+        self.outstanding = Some((command, now));
+    }
+
+    /// Delivers a delayed completion that is due, or times out the outstanding command.
+    ///
+    /// # Returns
+    ///
+    /// `None` once there is nothing left to do at `now`.
+    fn tick(&mut self, now: u32) -> Option<Progress> {
+        let faults = self.faults.as_mut()?;
+        if let Some((command, status)) = faults.take_due(now) {
+            return Some(self.complete(command, status, now));
+        }
+        let (command, issued_at) = self.outstanding?;
+        if now.wrapping_sub(issued_at) < faults.command_timeout() {
+            return None;
+        }
+        // A completion held back past the timeout must not be mistaken for the next one.
+        faults.record_timeout();
+        Some(self.complete(command, DISK_STATUS_TIMEOUT, now))
+    }
+
+    /// Fails the active request because its command could not be issued: with
+    /// `DiskError::Removed` once the disk was unplugged, with
+    /// `DiskError::CommandRejected` otherwise.
+    fn reject(&mut self, now: u32) -> Progress {
+        self.head_track = None;
+        self.outstanding = None;
+        let removed = self.active.as_ref().is_some_and(|a| a.abort == Some(Abort::Removed));
+        let error = if removed { DiskError::Removed } else { DiskError::CommandRejected };
+        self.finish(Err(error), now)
+    }
+
+    fn finish(&mut self, result: Result<(), DiskError>, now: u32) -> Progress {
+        let Some(active) = self.active.take() else {
+            return Progress::default();
+        };
+        self.stats.requests += 1;
+        let owner = Some(active.request.pid).filter(|_| active.abort != Some(Abort::Killed));
+        self.results.extend(owner.map(|pid| (pid, result)));
+        Progress { finished: owner, next: self.start_next(now), completed: None }
+    }
+
+    /// Fails every request because the disk was unplugged, and forgets what the driver
+    /// knew about the medium.
+    ///
+    /// Queued requests fail at once. The request in flight fails only when its
+    /// outstanding command completes or times out: until then THREADS may still write
+    /// into the bounce buffer, and its owner stays blocked.
+    ///
+    /// # Returns
+    ///
+    /// The processes to wake.
+    fn remove(&mut self) -> Vec<Pid> {
+        self.present = false;
+        self.head_track = None;
+        self.geometry = None;
+        self.last_track = MAX_ADDRESSABLE_TRACK;
+        if let Some(active) = self.active.as_mut() {
+            active.abort.get_or_insert(Abort::Removed);
+        }
+        let pids: Vec<Pid> = self.pending.drain(..).map(|r| r.pid).collect();
+        self.results.extend(pids.iter().map(|&pid| (pid, Err(DiskError::Removed))));
+        pids
+    }
+
+    /// Forgets the requests and results of killed process `pid`. Its request in flight
+    /// ends with the outstanding command, which moves data through the bounce buffer, so
+    /// the owner's stack is never touched again.
+    fn cancel(&mut self, pid: Pid) {
+        self.pending.retain(|r| r.pid != pid);
+        self.results.retain(|(p, _)| *p != pid);
+        if let Some(active) = self.active.as_mut().filter(|a| a.request.pid == pid) {
+            active.abort = Some(Abort::Killed);
+        }
+    }
+
+    fn stats(&self) -> DiskStats {
+        DiskStats { policy: self.policy, ..self.stats }
+    }
+
+    fn take_result(&mut self, pid: Pid) -> Option<Result<(), DiskError>> {
+        let index = self.results.iter().position(|(p, _)| *p == pid)?;
+        Some(self.results.remove(index).1)
+    }
+}
+
+pub(crate) struct DiskDriver {
+    disks: [DiskQueue; THREADS_MAX_DISKS as usize],
+}
+
+static DRIVER: KernelCell<DiskDriver> =
+    KernelCell::new(DiskDriver { disks: [const { DiskQueue::new() }; THREADS_MAX_DISKS as usize] });
+
+/// Initializes every THREADS disk and installs the I/O interrupt handler.
+///
+/// # Returns
+///
+/// The disks that initialized successfully.
+///
+/// # Example
+///
+/// ```ignore
+/// let disks = rusty_threads::init_disks();
+/// assert!(disks.contains(&rusty_threads::DeviceId::disk(0)));
+/// ```
+pub fn init_disks() -> Vec<DeviceId> {
+    device::install_io_handler();
+
+    let present: Vec<DeviceId> = (0..THREADS_MAX_DISKS)
+        .map(DeviceId::disk)
+        .filter(|disk| device_initialize(&disk.name()).is_some())
+        .collect();
+
+    let guard = InterruptGuard::new();
+    DRIVER.with(&guard, |d| {
+        for disk in &present {
+            d.disks[disk.unit as usize].present = true;
+        }
+    });
+    present
+}
+
+/// Reads `buffer.len() / THREADS_DISK_SECTOR_SIZE` consecutive sectors starting at
+/// `address`, blocking the calling process until the transfer completes.
+///
+/// Sectors continue onto the next track of the same platter once a track is exhausted.
+///
+/// # Arguments
+///
+/// * `disk` – The disk to read, e.g. `DeviceId::disk(0)`.
+/// * `address` – The first sector to read.
+/// * `buffer` – Destination; its length must be a non-zero multiple of the sector size.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, DiskAddress, THREADS_DISK_SECTOR_SIZE};
+///
+/// let mut sector = [0u8; THREADS_DISK_SECTOR_SIZE as usize];
+/// rusty_threads::disk::read_sectors(DeviceId::disk(0), DiskAddress::new(0, 2, 5), &mut sector)?;
+/// ```
+pub fn read_sectors(
+    disk: DeviceId,
+    address: DiskAddress,
+    buffer: &mut [u8],
+) -> Result<(), DiskError> {
+    transfer(disk, Operation::Read, address, buffer.as_mut_ptr(), buffer.len())
+}
+
+/// Writes `buffer` to consecutive sectors starting at `address`, blocking the calling
+/// process until the transfer completes. See [`read_sectors`] for the addressing rules.
+pub fn write_sectors(disk: DeviceId, address: DiskAddress, buffer: &[u8]) -> Result<(), DiskError> {
+    // The buffer is only ever read through `input_data`.
+    transfer(disk, Operation::Write, address, buffer.as_ptr().cast_mut(), buffer.len())
+}
+
+/// Asks `disk` for its geometry with `DISK_INFO`, blocking the calling process until the
+/// reply arrives.
+///
+/// The geometry is remembered by the driver: later transfers past the last track are
+/// rejected with `DiskError::InvalidAddress`, and SCAN and C-SCAN sweep to the real last
+/// track.
+///
+/// # Example
+///
+/// ```ignore
+/// let geometry = rusty_threads::disk_info(rusty_threads::DeviceId::disk(0))?;
+/// let last = geometry.address_of(geometry.total_sectors() - 1)?;
+/// ```
+pub fn disk_info(disk: DeviceId) -> Result<DiskGeometry, DiskError> {
+    let unit = disk_unit(disk)?;
+    let mut info = [0u8; DISK_INFO_SIZE];
+    submit_and_wait(unit, Operation::Info, DiskAddress::new(0, 0, 0), 1, info.as_mut_ptr())?;
+    let geometry = DiskGeometry::decode(&info)?;
+
+    let guard = InterruptGuard::new();
+    DRIVER.with(&guard, |d| {
+        let queue = &mut d.disks[unit];
+        queue.geometry = Some(geometry);
+        queue.last_track = geometry.tracks - 1;
+    });
+    Ok(geometry)
+}
+
+/// Selects the scheduling policy `disk` uses to pick its next request.
+///
+/// The change takes effect the next time the disk goes idle; a request already being
+/// served runs to completion.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, DiskSchedulingPolicy};
+///
+/// rusty_threads::set_disk_policy(DeviceId::disk(0), DiskSchedulingPolicy::Look)?;
+/// ```
+pub fn set_disk_policy(disk: DeviceId, policy: DiskSchedulingPolicy) -> Result<(), DiskError> {
+    let unit = disk_unit(disk)?;
+    let guard = InterruptGuard::new();
+    DRIVER.with(&guard, |d| d.disks[unit].policy = policy);
+    Ok(())
+}
+
+/// Returns the head movement and wait statistics of `disk` since the last
+/// [`reset_disk_stats`].
+///
+/// # Example
+///
+/// ```ignore
+/// let stats = rusty_threads::disk_stats(rusty_threads::DeviceId::disk(0))?;
+/// rusty_threads::console_output(false, &format!("disk0: {stats}\n"));
+/// ```
+pub fn disk_stats(disk: DeviceId) -> Result<DiskStats, DiskError> {
+    let unit = disk_unit(disk)?;
+    let guard = InterruptGuard::new();
+    Ok(DRIVER.with(&guard, |d| d.disks[unit].stats()))
+}
+
+/// Injects `faults` into the completions of `disk`, or stops injecting with `None`.
+///
+/// Failures reach the driver through the status of the completion interrupt and fail
+/// the request with `DiskError::Device(status)`. While faults are injected, the driver
+/// also fails a command whose completion does not arrive within
+/// `DiskFaults::command_timeout`, with [`DISK_STATUS_TIMEOUT`].
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, DiskAddress, DiskError, DiskFaults, DISK_STATUS_BAD_SECTOR};
+///
+/// let disk = DeviceId::disk(0);
+/// let bad = DiskAddress::new(0, 2, 5);
+/// rusty_threads::set_disk_faults(disk, Some(DiskFaults { bad_sectors: vec![bad], ..Default::default() }))?;
+/// let mut sector = [0u8; 512];
+/// assert_eq!(
+///     rusty_threads::read_sectors(disk, bad, &mut sector),
+///     Err(DiskError::Device(DISK_STATUS_BAD_SECTOR))
+/// );
+/// ```
+pub fn set_disk_faults(disk: DeviceId, faults: Option<DiskFaults>) -> Result<(), DiskError> {
+    let unit = disk_unit(disk)?;
+    let guard = InterruptGuard::new();
+    DRIVER.with(&guard, |d| d.disks[unit].faults = faults.map(FaultInjector::new));
+    Ok(())
+}
+
+/// Returns the faults injected on `disk` since the last [`set_disk_faults`], or `None`
+/// if none are configured.
+pub fn disk_fault_stats(disk: DeviceId) -> Result<Option<FaultStats>, DiskError> {
+    let unit = disk_unit(disk)?;
+    let guard = InterruptGuard::new();
+    Ok(DRIVER.with(&guard, |d| d.disks[unit].faults.as_ref().map(FaultInjector::stats)))
+}
+
+/// Clears the statistics of `disk`, so that policies can be compared on the same workload.
+pub fn reset_disk_stats(disk: DeviceId) -> Result<(), DiskError> {
+    let unit = disk_unit(disk)?;
+    let guard = InterruptGuard::new();
+    DRIVER.with(&guard, |d| d.disks[unit].stats = DiskStats::default());
+    Ok(())
+}
+
+fn disk_unit(disk: DeviceId) -> Result<usize, DiskError> {
+    if disk.kind != DeviceKind::Disk || disk.unit >= THREADS_MAX_DISKS {
+        return Err(DiskError::NotADisk);
+    }
+    Ok(disk.unit as usize)
+}
+
+fn validate_range(address: DiskAddress, sectors: u32) -> Result<(), DiskError> {
+    let last = address.offset(sectors - 1);
+    if address.platter >= THREADS_DISK_MAX_PLATTERS
+        || address.sector >= THREADS_DISK_SECTOR_COUNT
+        || last.track > MAX_ADDRESSABLE_TRACK
+    {
+        return Err(DiskError::InvalidAddress);
+    }
+    Ok(())
+}
+
+fn transfer(
+    disk: DeviceId,
+    operation: Operation,
+    address: DiskAddress,
+    buffer: *mut u8,
+    length: usize,
+) -> Result<(), DiskError> {
+    let unit = disk_unit(disk)?;
+    let sector_size = THREADS_DISK_SECTOR_SIZE as usize;
+    if length == 0 || !length.is_multiple_of(sector_size) {
+        return Err(DiskError::InvalidBuffer);
+    }
+    let sectors = (length / sector_size) as u32;
+    validate_range(address, sectors)?;
+    submit_and_wait(unit, operation, address, sectors, buffer)
+}
+
+/// Queues a request on disk `unit` and blocks the calling process until it finishes.
+fn submit_and_wait(
+    unit: usize,
+    operation: Operation,
+    start: DiskAddress,
+    sectors: u32,
+    buffer: *mut u8,
+) -> Result<(), DiskError> {
+    let guard = InterruptGuard::new();
+    let pid = KERNEL.with(&guard, |k| k.current()).ok_or(DiskError::NoProcess)?;
+    let now = system_clock();
+    let request = DiskRequest { pid, operation, start, sectors, buffer, submitted_at: now };
+    let detached = hotplug::is_detached(&DeviceId::disk(unit as u32).name());
+    let command = DRIVER.with(&guard, |d| {
+        let queue = &mut d.disks[unit];
+        if !queue.present {
+            return Err(DiskError::NotPresent);
+        }
+        // The disk is gone even though its `DEVICE_STATUS_REMOVED` is not raised yet.
+        if detached {
+            return Err(DiskError::Removed);
+        }
+        let last = start.offset(sectors - 1);
+        if queue.geometry.is_some_and(|g| operation != Operation::Info && !g.contains(last)) {
+            return Err(DiskError::InvalidAddress);
+        }
+        Ok(queue.submit(request, now))
+    })?;
+    run(&guard, unit, command);
+
+    // The buffer stays borrowed, and so valid, until the request has finished.
+    loop {
+        if let Some(result) = DRIVER.with(&guard, |d| d.disks[unit].take_result(pid)) {
+            return result;
+        }
+        scheduler::block_current(&guard, BlockReason::DiskIo, None);
+    }
+}
+
+/// Issues `command` on disk `unit`, failing requests whose commands THREADS rejects
+/// until one is accepted or the queue is empty.
+///
+/// A disk detached with [`crate::hotplug::detach_device`] rejects every command until
+/// its `DEVICE_STATUS_REMOVED` interrupt arrives on the next tick; its requests fail with
+/// `DiskError::Removed` right away instead.
+fn run(guard: &InterruptGuard, unit: usize, mut command: Option<DiskCommand>) {
+    let name = DeviceId::disk(unit as u32).name();
+    while let Some(next) = command.take() {
+        if device_control(&name, next.control_block()).is_some() {
+            DRIVER.with(guard, |d| d.disks[unit].issued(next.command, system_clock()));
+            return;
+        }
+        if hotplug::is_detached(&name) {
+            for pid in DRIVER.with(guard, |d| d.disks[unit].remove()) {
+                scheduler::make_ready(guard, pid);
+            }
+        }
+        let progress = DRIVER.with(guard, |d| d.disks[unit].reject(system_clock()));
+        if let Some(pid) = progress.finished {
+            scheduler::make_ready(guard, pid);
+        }
+        command = progress.next;
+    }
+}
+
+/// Handles a `THREADS_IO_INTERRUPT` from disk `unit`.
+pub(crate) fn on_interrupt(unit: u32, command: u8, status: u32) {
+    let unit = unit as usize;
+    if unit >= THREADS_MAX_DISKS as usize {
+        return;
+    }
+    let guard = InterruptGuard::new();
+    if command == HOTPLUG_COMMAND {
+        return on_hotplug(&guard, unit, status);
+    }
+    let now = system_clock();
+    let progress = DRIVER.with(&guard, |d| d.disks[unit].interrupt(command, status, now));
+    apply(&guard, unit, progress);
+}
+
+/// Acts on the progress a completion made on disk `unit`: records it, wakes the owner of
+/// a finished request and issues the next command.
+fn apply(guard: &InterruptGuard, unit: usize, progress: Progress) {
+    if let Some((command, status)) = progress.completed {
+        io_stats::record_completion(guard, DeviceId::disk(unit as u32), command, status);
+    }
+    if let Some(pid) = progress.finished {
+        scheduler::make_ready(guard, pid);
+    }
+    run(guard, unit, progress.next);
+}
+
+/// Fails the requests of disk `unit` when it is unplugged, and initializes it again when
+/// it is plugged back in.
+fn on_hotplug(guard: &InterruptGuard, unit: usize, status: u32) {
+    match status {
+        DEVICE_STATUS_REMOVED => {
+            for pid in DRIVER.with(guard, |d| d.disks[unit].remove()) {
+                scheduler::make_ready(guard, pid);
+            }
+        }
+        DEVICE_STATUS_ATTACHED
+            if device_initialize(&DeviceId::disk(unit as u32).name()).is_some() =>
+        {
+            DRIVER.with(guard, |d| d.disks[unit].present = true);
+        }
+        _ => {}
+    }
+}
+
+/// Forgets the disk requests of killed process `pid`, see [`DiskQueue::cancel`].
+pub(crate) fn cancel_requests(guard: &InterruptGuard, pid: Pid) {
+    DRIVER.with(guard, |d| d.disks.iter_mut().for_each(|queue| queue.cancel(pid)));
+}
+
+/// Delivers delayed completions and times out lost ones; called on every clock interrupt.
+pub(crate) fn on_clock(guard: &InterruptGuard, now: u32) {
+    for unit in 0..THREADS_MAX_DISKS as usize {
+        while let Some(progress) = DRIVER.with(guard, |d| d.disks[unit].tick(now)) {
+            apply(guard, unit, progress);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk_fault::DISK_STATUS_BAD_SECTOR;
+    use crate::io_stats::IoStats;
+
+    fn request(pid: Pid, operation: Operation, start: DiskAddress, sectors: u32) -> DiskRequest {
+        DiskRequest {
+            pid,
+            operation,
+            start,
+            sectors,
+            // Tests hand out buffers that live for the rest of the run.
+            buffer: vec![0; sectors as usize * SECTOR_SIZE].leak().as_mut_ptr(),
+            submitted_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_address_offset_wraps_onto_next_track() {
+        let address = DiskAddress::new(1, 4, THREADS_DISK_SECTOR_COUNT - 1);
+        assert_eq!(address.offset(0), address);
+        assert_eq!(address.offset(1), DiskAddress::new(1, 5, 0));
+        assert_eq!(address.offset(THREADS_DISK_SECTOR_COUNT + 1), DiskAddress::new(1, 6, 0));
+    }
+
+    #[test]
+    fn test_validate_range() {
+        assert_eq!(validate_range(DiskAddress::new(0, 0, 0), 1), Ok(()));
+        assert_eq!(
+            validate_range(DiskAddress::new(THREADS_DISK_MAX_PLATTERS, 0, 0), 1),
+            Err(DiskError::InvalidAddress)
+        );
+        assert_eq!(
+            validate_range(
+                DiskAddress::new(0, MAX_ADDRESSABLE_TRACK, THREADS_DISK_SECTOR_COUNT - 1),
+                2
+            ),
+            Err(DiskError::InvalidAddress)
+        );
+    }
+
+    #[test]
+    fn test_request_seeks_then_transfers_each_sector() {
+        let mut queue = DiskQueue::new();
+        let start = DiskAddress::new(0, 3, THREADS_DISK_SECTOR_COUNT - 1);
+        let command = queue.submit(request(7, Operation::Read, start, 2), 0).unwrap();
+        assert_eq!((command.command, command.control1), (DISK_SEEK, 3));
+
+        let progress = queue.complete(DISK_SEEK, DISK_STATUS_OK, 0);
+        let command = progress.next.unwrap();
+        assert_eq!(
+            (command.command, command.control1),
+            (DISK_READ, THREADS_DISK_SECTOR_COUNT as u8 - 1)
+        );
+
+        // The second sector is on the next track, so the head has to move again.
+        let command = queue.complete(DISK_READ, DISK_STATUS_OK, 0).next.unwrap();
+        assert_eq!((command.command, command.control1), (DISK_SEEK, 4));
+        let command = queue.complete(DISK_SEEK, DISK_STATUS_OK, 0).next.unwrap();
+        assert_eq!((command.command, command.control1), (DISK_READ, 0));
+
+        let progress = queue.complete(DISK_READ, DISK_STATUS_OK, 0);
+        assert_eq!(progress.finished, Some(7));
+        assert_eq!(progress.next, None);
+        assert_eq!(queue.take_result(7), Some(Ok(())));
+    }
+
+    #[test]
+    fn test_requests_are_served_in_order_and_errors_reported() {
+        let mut queue = DiskQueue::new();
+        queue.submit(request(1, Operation::Write, DiskAddress::new(0, 0, 0), 1), 0);
+        assert_eq!(
+            queue.submit(request(2, Operation::Read, DiskAddress::new(0, 0, 1), 1), 0),
+            None
+        );
+
+        queue.complete(DISK_SEEK, DISK_STATUS_OK, 0);
+        // A completion for a command that is not outstanding is ignored.
+        assert_eq!(queue.complete(DISK_READ, DISK_STATUS_OK, 0), Progress::default());
+
+        let progress = queue.complete(DISK_WRITE, 9, 0);
+        assert_eq!(progress.finished, Some(1));
+        assert_eq!(queue.take_result(1), Some(Err(DiskError::Device(9))));
+        // After an error the head position is unknown, so the next request seeks first.
+        assert_eq!(progress.next.unwrap().command, DISK_SEEK);
+    }
+
+    #[test]
+    fn test_policy_drives_head_and_stats() {
+        let mut queue = DiskQueue::new();
+        queue.policy = DiskSchedulingPolicy::Scan;
+        queue.last_track = 10;
+        queue.submit(request(1, Operation::Read, DiskAddress::new(0, 5, 0), 1), 0);
+        let later = request(2, Operation::Read, DiskAddress::new(0, 2, 0), 1);
+        queue.submit(DiskRequest { submitted_at: 10, ..later }, 10);
+        queue.complete(DISK_SEEK, DISK_STATUS_OK, 20);
+
+        // Nothing is left above track 5, so SCAN travels to the edge before turning around.
+        let progress = queue.complete(DISK_READ, DISK_STATUS_OK, 50);
+        assert_eq!(progress.finished, Some(1));
+        assert_eq!(progress.next.unwrap().control1, 10);
+        assert_eq!(queue.complete(DISK_SEEK, DISK_STATUS_OK, 60).next.unwrap().control1, 2);
+        queue.complete(DISK_SEEK, DISK_STATUS_OK, 70);
+        queue.complete(DISK_READ, DISK_STATUS_OK, 80);
+
+        let stats = queue.stats();
+        assert_eq!(stats.policy, DiskSchedulingPolicy::Scan);
+        assert_eq!((stats.requests, stats.head_movement), (2, 5 + 5 + 8));
+        assert_eq!(stats.average_wait(), Some(20));
+    }
+
+    #[test]
+    fn test_info_does_not_move_the_head() {
+        let mut queue = DiskQueue::new();
+        queue.policy = DiskSchedulingPolicy::CScan;
+        queue.arm = 7;
+        let command = queue.submit(request(3, Operation::Info, DiskAddress::new(0, 0, 0), 1), 0);
+        assert_eq!(command.unwrap().command, DISK_INFO);
+        assert_eq!(queue.complete(DISK_INFO, DISK_STATUS_OK, 0).finished, Some(3));
+        assert_eq!(queue.stats().head_movement, 0);
+    }
+
+    #[test]
+    fn test_injected_faults_fail_requests() {
+        let start = DiskAddress::new(0, 0, THREADS_DISK_SECTOR_COUNT - 1);
+        let bad = start.offset(1);
+        let mut queue = DiskQueue::new();
+        queue.faults =
+            Some(FaultInjector::new(DiskFaults { bad_sectors: vec![bad], ..Default::default() }));
+        queue.submit(request(4, Operation::Read, start, 2), 0);
+        queue.interrupt(DISK_SEEK, DISK_STATUS_OK, 0);
+        assert_eq!(queue.interrupt(DISK_READ, DISK_STATUS_OK, 0).next.unwrap().command, DISK_SEEK);
+        queue.interrupt(DISK_SEEK, DISK_STATUS_OK, 0);
+        assert_eq!(queue.interrupt(DISK_READ, DISK_STATUS_OK, 0).finished, Some(4));
+        assert_eq!(queue.take_result(4), Some(Err(DiskError::Device(DISK_STATUS_BAD_SECTOR))));
+    }
+
+    #[test]
+    fn test_statistics_see_injected_failures() {
+        let disk = DeviceId::disk(0);
+        let start = DiskAddress::new(0, 0, 0);
+        let faults = DiskFaults { bad_sectors: vec![start.offset(1)], ..Default::default() };
+        let mut queue = DiskQueue::new();
+        queue.faults = Some(FaultInjector::new(faults));
+        let mut stats = IoStats::new();
+        let mut command = queue.submit(request(1, Operation::Read, start, 2), 0);
+        let mut now = 0;
+        while let Some(next) = command {
+            let bytes = if next.command == DISK_READ { SECTOR_SIZE as u32 } else { 0 };
+            stats.issued(disk, next.command, bytes, now);
+            now += 10;
+            let progress = queue.interrupt(next.command, DISK_STATUS_OK, now);
+            let (command_done, status) = progress.completed.unwrap();
+            stats.completed(disk, command_done, status, now);
+            command = progress.next;
+        }
+
+        assert_eq!(queue.take_result(1), Some(Err(DiskError::Device(DISK_STATUS_BAD_SECTOR))));
+        let stats = stats.stats(disk, now).unwrap();
+        assert_eq!(stats.errors(), 1);
+        let reads = stats.command(DISK_READ).unwrap();
+        assert_eq!((reads.completed, reads.failed), (2, 1));
+        assert_eq!(stats.bytes(), SECTOR_SIZE as u64);
+    }
+
+    #[test]
+    fn test_lost_completion_times_out() {
+        let mut queue = DiskQueue::new();
+        let faults = DiskFaults { drop_rate: 1.0, command_timeout: 100, ..Default::default() };
+        queue.faults = Some(FaultInjector::new(faults));
+        let command = queue.submit(request(5, Operation::Read, DiskAddress::new(0, 0, 0), 1), 0);
+        queue.issued(command.unwrap().command, 10);
+        assert_eq!(queue.interrupt(DISK_SEEK, DISK_STATUS_OK, 20), Progress::default());
+        assert_eq!(queue.tick(109), None);
+
+        assert_eq!(queue.tick(110).unwrap().finished, Some(5));
+        assert_eq!(queue.take_result(5), Some(Err(DiskError::Device(DISK_STATUS_TIMEOUT))));
+        assert_eq!(queue.tick(500), None);
+        assert_eq!(queue.faults.as_ref().unwrap().stats().timeouts, 1);
+    }
+
+    #[test]
+    fn test_delayed_completion_is_delivered_on_tick() {
+        let mut queue = DiskQueue::new();
+        let faults = DiskFaults { delay_rate: 1.0, delay: 50, ..Default::default() };
+        queue.faults = Some(FaultInjector::new(faults));
+        let command = queue.submit(request(6, Operation::Info, DiskAddress::new(0, 0, 0), 1), 0);
+        queue.issued(command.unwrap().command, 0);
+        assert_eq!(queue.interrupt(DISK_INFO, DISK_STATUS_OK, 0), Progress::default());
+        assert_eq!(queue.tick(49), None);
+        assert_eq!(queue.tick(50).unwrap().finished, Some(6));
+        assert_eq!(queue.take_result(6), Some(Ok(())));
+        assert_eq!(queue.tick(1_000_000), None);
+    }
+
+    #[test]
+    fn test_removal_fails_queued_requests_and_the_active_one_on_completion() {
+        let mut queue = DiskQueue::new();
+        queue.present = true;
+        let command = queue.submit(request(1, Operation::Read, DiskAddress::new(0, 3, 0), 1), 0);
+        queue.issued(command.unwrap().command, 0);
+        assert_eq!(
+            queue.submit(request(2, Operation::Write, DiskAddress::new(0, 1, 0), 2), 0),
+            None
+        );
+
+        assert_eq!(queue.remove(), [2]);
+        assert!(!queue.present && queue.pending.is_empty());
+        assert_eq!(queue.take_result(2), Some(Err(DiskError::Removed)));
+        // The command in flight when the disk went away still runs; its owner fails once
+        // the completion arrives.
+        assert_eq!(queue.take_result(1), None);
+        assert!(queue.outstanding.is_some());
+        let progress = queue.complete(DISK_SEEK, DISK_STATUS_OK, 10);
+        assert_eq!((progress.finished, progress.next, progress.completed), (Some(1), None, None));
+        assert_eq!(queue.take_result(1), Some(Err(DiskError::Removed)));
+        assert!(queue.active.is_none() && queue.outstanding.is_none());
+    }
+
+    #[test]
+    fn test_short_info_replies_are_rejected() {
+        let mut queue = DiskQueue::new();
+        let info = request(3, Operation::Info, DiskAddress::new(0, 0, 0), 1);
+        let buffer = info.buffer;
+        let command = queue.submit(info, 0).unwrap();
+        // THREADS writes only half of the track count.
+        let reply = 32u32.to_ne_bytes();
+        unsafe { core::ptr::copy_nonoverlapping(reply.as_ptr(), command.buffer, 2) };
+        assert_eq!(queue.complete(DISK_INFO, DISK_STATUS_OK, 0).finished, Some(3));
+
+        let received = unsafe { core::slice::from_raw_parts(buffer, DISK_INFO_SIZE) };
+        assert_eq!(DiskGeometry::decode(received), Err(DiskError::InvalidGeometry));
+    }
+
+    #[test]
+    fn test_commands_refused_after_detach_fail_as_removed() {
+        let mut queue = DiskQueue::new();
+        queue.present = true;
+        assert!(
+            queue.submit(request(1, Operation::Read, DiskAddress::new(0, 3, 0), 1), 0).is_some()
+        );
+        queue.submit(request(2, Operation::Write, DiskAddress::new(0, 1, 0), 1), 0);
+
+        // Detached, but `DEVICE_STATUS_REMOVED` is still to come: THREADS refuses the
+        // command, and the driver removes the disk itself.
+        assert_eq!(queue.remove(), [2]);
+        let progress = queue.reject(5);
+        assert_eq!((progress.finished, progress.next), (Some(1), None));
+        assert_eq!(queue.take_result(1), Some(Err(DiskError::Removed)));
+        assert_eq!(queue.take_result(2), Some(Err(DiskError::Removed)));
+
+        // The interrupt arriving later finds nothing left to fail.
+        assert!(queue.remove().is_empty());
+        assert!(queue.active.is_none() && queue.outstanding.is_none());
+    }
+
+    #[test]
+    fn test_killed_owner_is_forgotten_and_its_buffer_left_alone() {
+        let mut queue = DiskQueue::new();
+        let active = request(1, Operation::Read, DiskAddress::new(0, 0, 0), 1);
+        let buffer = active.buffer;
+        let command = queue.submit(active, 0).unwrap();
+        queue.complete(command.command, DISK_STATUS_OK, 0);
+        queue.submit(request(1, Operation::Write, DiskAddress::new(0, 0, 1), 1), 0);
+        queue.submit(request(2, Operation::Read, DiskAddress::new(0, 0, 2), 1), 0);
+        queue.results.push((1, Ok(())));
+
+        queue.cancel(1);
+        assert_eq!(queue.pending.len(), 1);
+        assert_eq!(queue.take_result(1), None);
+        // The sector in flight lands in the bounce buffer and nowhere else; nobody is woken
+        // and the next owner's request starts.
+        queue.bounce.fill(0xAA);
+        let progress = queue.complete(DISK_READ, DISK_STATUS_OK, 0);
+        assert_eq!(progress.finished, None);
+        assert_eq!(progress.next.unwrap().command, DISK_READ);
+        assert_eq!(unsafe { *buffer }, 0);
+        assert_eq!(queue.take_result(1), None);
+    }
+}
@@ -0,0 +1,233 @@
+// src/disk_fault.rs
+
+use std::collections::VecDeque;
+
+use crate::constants::{DISK_READ, DISK_WRITE};
+use crate::disk::{DISK_STATUS_OK, DiskAddress};
+use crate::rng::XorShift;
+
+/// Interrupt status injected for a read or write of a sector listed in
+/// [`DiskFaults::bad_sectors`].
+pub const DISK_STATUS_BAD_SECTOR: u32 = 0x100;
+
+/// Interrupt status injected for a random transient failure.
+pub const DISK_STATUS_TRANSIENT: u32 = 0x101;
+
+/// Status the driver completes a command with when its interrupt never arrives.
+pub const DISK_STATUS_TIMEOUT: u32 = 0x102;
+
+/// Default time the driver waits for a completion interrupt while faults are injected,
+/// in microseconds.
+pub const DEFAULT_COMMAND_TIMEOUT: u32 = 500_000;
+
+/// Faults to inject on one simulated disk, see [`crate::disk::set_disk_faults`].
+///
+/// Faults are applied to completion interrupts before the driver sees them: a failing
+/// command is reported through the `status` argument of the interrupt handler, exactly
+/// as a real device error would be. Random faults draw from a generator seeded with
+/// `seed`, so a run can be replayed.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, DiskAddress, DiskFaults};
+///
+/// rusty_threads::set_disk_faults(
+///     DeviceId::disk(1),
+///     Some(DiskFaults {
+///         bad_sectors: vec![DiskAddress::new(0, 3, 7)],
+///         failure_rate: 0.01,
+///         drop_rate: 0.001,
+///         seed: 42,
+///         ..Default::default()
+///     }),
+/// )?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskFaults {
+    /// Sectors whose reads and writes always fail with [`DISK_STATUS_BAD_SECTOR`].
+    pub bad_sectors: Vec<DiskAddress>,
+    /// Probability that a command fails with [`DISK_STATUS_TRANSIENT`].
+    pub failure_rate: f64,
+    /// Probability that a completion interrupt is held back for `delay`.
+    pub delay_rate: f64,
+    /// How long a delayed completion is held, in microseconds. It is delivered on the
+    /// first clock interrupt after that.
+    pub delay: u32,
+    /// Probability that a completion interrupt is lost.
+    pub drop_rate: f64,
+    /// How long the driver waits for a completion before failing the command with
+    /// [`DISK_STATUS_TIMEOUT`], in microseconds.
+    pub command_timeout: u32,
+    /// Seed of the random faults.
+    pub seed: u64,
+}
+
+impl Default for DiskFaults {
+    fn default() -> Self {
+        DiskFaults {
+            bad_sectors: Vec::new(),
+            failure_rate: 0.0,
+            delay_rate: 0.0,
+            delay: 0,
+            drop_rate: 0.0,
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            seed: 0,
+        }
+    }
+}
+
+/// Counts of the faults injected on a disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FaultStats {
+    /// Commands failed because they touched a bad sector.
+    pub bad_sectors: u64,
+    /// Commands failed at random.
+    pub transient_failures: u64,
+    /// Completions held back.
+    pub delayed: u64,
+    /// Completions lost.
+    pub dropped: u64,
+    /// Commands the driver gave up waiting for.
+    pub timeouts: u64,
+}
+
+/// What happens to one completion interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    /// Deliver it now with this status.
+    Now(u32),
+    /// Held back; it comes out of [`FaultInjector::take_due`] later.
+    Later,
+    /// Lost.
+    Dropped,
+}
+
+/// Applies a [`DiskFaults`] configuration to the completions of one disk.
+pub(crate) struct FaultInjector {
+    config: DiskFaults,
+    rng: XorShift,
+    /// Held completions as `(due time, command, status)`, in due order.
+    delayed: VecDeque<(u32, u8, u32)>,
+    stats: FaultStats,
+}
+
+impl FaultInjector {
+    pub(crate) fn new(config: DiskFaults) -> Self {
+        let rng = XorShift::new(config.seed);
+        FaultInjector { config, rng, delayed: VecDeque::new(), stats: FaultStats::default() }
+    }
+
+    pub(crate) fn stats(&self) -> FaultStats {
+        self.stats
+    }
+
+    pub(crate) fn command_timeout(&self) -> u32 {
+        self.config.command_timeout
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        // 53 random bits give a uniform value in [0, 1).
+        let sample = (self.rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        sample < probability
+    }
+
+    /// Decides the fate of a completion for `command`, which transferred the sector at
+    /// `address` if it was a read or write.
+    pub(crate) fn intercept(
+        &mut self,
+        command: u8,
+        address: Option<DiskAddress>,
+        status: u32,
+        now: u32,
+    ) -> Delivery {
+        let mut status = status;
+        if status == DISK_STATUS_OK {
+            let transfer = command == DISK_READ || command == DISK_WRITE;
+            if transfer && address.is_some_and(|a| self.config.bad_sectors.contains(&a)) {
+                status = DISK_STATUS_BAD_SECTOR;
+                self.stats.bad_sectors += 1;
+            } else if self.chance(self.config.failure_rate) {
+                status = DISK_STATUS_TRANSIENT;
+                self.stats.transient_failures += 1;
+            }
+        }
+        if self.chance(self.config.drop_rate) {
+            self.stats.dropped += 1;
+            return Delivery::Dropped;
+        }
+        if self.config.delay > 0 && self.chance(self.config.delay_rate) {
+            self.delayed.push_back((now.wrapping_add(self.config.delay), command, status));
+            self.stats.delayed += 1;
+            return Delivery::Later;
+        }
+        Delivery::Now(status)
+    }
+
+    /// Returns the next held completion whose delay has passed at `now`.
+    pub(crate) fn take_due(&mut self, now: u32) -> Option<(u8, u32)> {
+        let &(due, command, status) = self.delayed.front()?;
+        // Wrapping comparison, so the clock may roll over.
+        if (now.wrapping_sub(due) as i32) < 0 {
+            return None;
+        }
+        self.delayed.pop_front();
+        Some((command, status))
+    }
+
+    /// Forgets held completions after the driver timed their command out.
+    pub(crate) fn record_timeout(&mut self) {
+        self.delayed.clear();
+        self.stats.timeouts += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bad_sectors_fail_transfers_only() {
+        let bad = DiskAddress::new(0, 2, 3);
+        let mut faults =
+            FaultInjector::new(DiskFaults { bad_sectors: vec![bad], ..Default::default() });
+        assert_eq!(
+            faults.intercept(DISK_READ, Some(bad), 0, 0),
+            Delivery::Now(DISK_STATUS_BAD_SECTOR)
+        );
+        assert_eq!(faults.intercept(DISK_WRITE, Some(bad.offset(1)), 0, 0), Delivery::Now(0));
+        assert_eq!(faults.intercept(DISK_READ, Some(bad), 7, 0), Delivery::Now(7));
+        assert_eq!(faults.stats().bad_sectors, 1);
+    }
+
+    #[test]
+    fn test_seeded_failures_replay() {
+        let config = DiskFaults { failure_rate: 0.3, seed: 11, ..Default::default() };
+        let run = || {
+            let mut faults = FaultInjector::new(config.clone());
+            (0..200).map(|_| faults.intercept(DISK_READ, None, 0, 0)).collect::<Vec<_>>()
+        };
+        let first = run();
+        assert_eq!(first, run());
+        let failed = first.iter().filter(|d| **d == Delivery::Now(DISK_STATUS_TRANSIENT)).count();
+        assert!((30..90).contains(&failed), "{failed} failures");
+    }
+
+    #[test]
+    fn test_delayed_and_dropped_completions() {
+        let config = DiskFaults { delay_rate: 1.0, delay: 100, ..Default::default() };
+        let mut faults = FaultInjector::new(config);
+        assert_eq!(faults.intercept(DISK_WRITE, None, 0, u32::MAX - 10), Delivery::Later);
+        assert_eq!(faults.take_due(u32::MAX), None);
+        assert_eq!(faults.take_due(89), Some((DISK_WRITE, 0)));
+        assert_eq!(faults.take_due(89), None);
+
+        let mut faults = FaultInjector::new(DiskFaults { drop_rate: 1.0, ..Default::default() });
+        assert_eq!(faults.intercept(DISK_READ, None, 0, 0), Delivery::Dropped);
+        faults.record_timeout();
+        assert_eq!((faults.stats().dropped, faults.stats().timeouts), (1, 1));
+    }
+}
@@ -0,0 +1,176 @@
+// src/disk_geometry.rs
+
+use crate::constants::{
+    THREADS_DISK_MAX_PLATTERS, THREADS_DISK_SECTOR_COUNT, THREADS_DISK_SECTOR_SIZE,
+};
+use crate::disk::{DiskAddress, DiskError, MAX_ADDRESSABLE_TRACK};
+
+/// Size in bytes of the `DISK_INFO` reply.
+///
+/// No specification of the reply is available: `include/THREADSLib.h` only defines the
+/// command code, and the simulator is linked as a prebuilt library. This layout is an
+/// assumption of the driver, which reads the reply as the number of tracks on each
+/// platter, a 32-bit integer in the host's byte order. [`DiskGeometry::decode`] rejects
+/// replies that do not have exactly this size or whose track count could not be
+/// addressed by a seek, and the driver fills the reply buffer with `0xFF` before
+/// asking, so a shorter reply or a different layout is reported instead of misread. The
+/// platter count, sectors per track and sector size are fixed by
+/// `THREADS_DISK_MAX_PLATTERS`, `THREADS_DISK_SECTOR_COUNT` and
+/// `THREADS_DISK_SECTOR_SIZE`.
+pub const DISK_INFO_SIZE: usize = size_of::<u32>();
+
+/// Shape of a THREADS disk, as reported by `DISK_INFO`.
+///
+/// Linear block numbers count sectors of `sector_size` bytes, platter by platter and
+/// track by track within a platter, so that consecutive blocks map to the consecutive
+/// sectors that [`crate::disk::read_sectors`] transfers in one call.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, DiskAddress};
+///
+/// let geometry = rusty_threads::disk_info(DeviceId::disk(0))?;
+/// let address = geometry.address_of(20)?;
+/// assert_eq!(address, DiskAddress::new(0, 1, 4));
+/// assert_eq!(geometry.block_of(address)?, 20);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DiskGeometry {
+    /// Number of platters.
+    pub platters: u32,
+    /// Number of tracks on each platter.
+    pub tracks: u32,
+    /// Number of sectors on each track.
+    pub sectors_per_track: u32,
+    /// Size of a sector in bytes.
+    pub sector_size: u32,
+}
+
+impl DiskGeometry {
+    /// Geometry of a disk with `tracks` tracks per platter and the THREADS defaults for
+    /// everything else.
+    pub const fn with_tracks(tracks: u32) -> Self {
+        DiskGeometry {
+            platters: THREADS_DISK_MAX_PLATTERS,
+            tracks,
+            sectors_per_track: THREADS_DISK_SECTOR_COUNT,
+            sector_size: THREADS_DISK_SECTOR_SIZE,
+        }
+    }
+
+    /// Decodes a `DISK_INFO` reply (see [`DISK_INFO_SIZE`]).
+    ///
+    /// # Returns
+    ///
+    /// `DiskError::InvalidGeometry` if the reply is not `DISK_INFO_SIZE` bytes long, or
+    /// reports no tracks or more tracks than the 8-bit seek command can reach.
+    pub fn decode(info: &[u8]) -> Result<Self, DiskError> {
+        let bytes = info.try_into().map_err(|_| DiskError::InvalidGeometry)?;
+        let tracks = u32::from_ne_bytes(bytes);
+        if !(1..=MAX_ADDRESSABLE_TRACK + 1).contains(&tracks) {
+            return Err(DiskError::InvalidGeometry);
+        }
+        Ok(Self::with_tracks(tracks))
+    }
+
+    /// Number of sectors on one platter.
+    pub fn sectors_per_platter(&self) -> u64 {
+        u64::from(self.tracks) * u64::from(self.sectors_per_track)
+    }
+
+    /// Total number of sectors (blocks) on the disk.
+    pub fn total_sectors(&self) -> u64 {
+        self.sectors_per_platter() * u64::from(self.platters)
+    }
+
+    /// Capacity of the disk in bytes.
+    pub fn capacity(&self) -> u64 {
+        self.total_sectors() * u64::from(self.sector_size)
+    }
+
+    /// Returns `true` if `address` lies on the disk.
+    pub fn contains(&self, address: DiskAddress) -> bool {
+        address.platter < self.platters
+            && address.track < self.tracks
+            && address.sector < self.sectors_per_track
+    }
+
+    /// Converts a linear block number to a (platter, track, sector) address.
+    ///
+    /// # Returns
+    ///
+    /// `DiskError::InvalidAddress` if `block` is not below [`Self::total_sectors`].
+    pub fn address_of(&self, block: u64) -> Result<DiskAddress, DiskError> {
+        if block >= self.total_sectors() {
+            return Err(DiskError::InvalidAddress);
+        }
+        let platter = block / self.sectors_per_platter();
+        let within = block % self.sectors_per_platter();
+        let per_track = u64::from(self.sectors_per_track);
+        Ok(DiskAddress::new(
+            platter as u32,
+            (within / per_track) as u32,
+            (within % per_track) as u32,
+        ))
+    }
+
+    /// Converts a (platter, track, sector) address to its linear block number.
+    ///
+    /// # Returns
+    ///
+    /// `DiskError::InvalidAddress` if the address is not on the disk.
+    pub fn block_of(&self, address: DiskAddress) -> Result<u64, DiskError> {
+        if !self.contains(address) {
+            return Err(DiskError::InvalidAddress);
+        }
+        Ok(u64::from(address.platter) * self.sectors_per_platter()
+            + u64::from(address.track) * u64::from(self.sectors_per_track)
+            + u64::from(address.sector))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let geometry = DiskGeometry::decode(&32u32.to_ne_bytes()).unwrap();
+        assert_eq!(geometry, DiskGeometry::with_tracks(32));
+        assert_eq!(geometry.total_sectors(), 32 * 16 * 3);
+        assert_eq!(geometry.capacity(), 32 * 16 * 3 * 512);
+        assert_eq!(DiskGeometry::decode(&[1, 0]), Err(DiskError::InvalidGeometry));
+        assert_eq!(DiskGeometry::decode(&0u32.to_ne_bytes()), Err(DiskError::InvalidGeometry));
+        assert_eq!(DiskGeometry::decode(&[32, 0, 0, 0, 0]), Err(DiskError::InvalidGeometry));
+        // A reply in the other byte order reports far more tracks than a seek can reach.
+        let swapped = 32u32.swap_bytes().to_ne_bytes();
+        assert_eq!(DiskGeometry::decode(&swapped), Err(DiskError::InvalidGeometry));
+        assert!(DiskGeometry::decode(&256u32.to_ne_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_block_address_round_trip() {
+        let geometry = DiskGeometry::with_tracks(10);
+        for block in 0..geometry.total_sectors() {
+            let address = geometry.address_of(block).unwrap();
+            assert!(geometry.contains(address));
+            assert_eq!(geometry.block_of(address), Ok(block));
+        }
+        assert_eq!(geometry.address_of(20), Ok(DiskAddress::new(0, 1, 4)));
+        assert_eq!(geometry.address_of(160), Ok(DiskAddress::new(1, 0, 0)));
+    }
+
+    #[test]
+    fn test_bounds_checks() {
+        let geometry = DiskGeometry::with_tracks(10);
+        assert_eq!(geometry.address_of(geometry.total_sectors()), Err(DiskError::InvalidAddress));
+        for address in [
+            DiskAddress::new(THREADS_DISK_MAX_PLATTERS, 0, 0),
+            DiskAddress::new(0, 10, 0),
+            DiskAddress::new(0, 0, THREADS_DISK_SECTOR_COUNT),
+        ] {
+            assert_eq!(geometry.block_of(address), Err(DiskError::InvalidAddress));
+        }
+    }
+}
@@ -0,0 +1,170 @@
+// src/disk_image.rs
+
+use core::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::block_device::{BlockDevice, BlockError, check_request, validate_block_size};
+use crate::disk::{DiskAddress, DiskError};
+use crate::disk_geometry::DiskGeometry;
+
+/// A THREADS disk image file opened on the host, seen as a [`BlockDevice`].
+///
+/// An image is the raw contents of a disk, sector after sector in the linear order of
+/// [`DiskGeometry`] (platter by platter, track by track), with no header. Its geometry
+/// therefore follows from its length: the platter count, sectors per track and sector
+/// size are the THREADS constants, and the length must be a whole number of tracks on
+/// every platter.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DiskAddress, DiskGeometry, DiskImage};
+///
+/// let mut image = DiskImage::create("disk0.img", DiskGeometry::with_tracks(32), 512)?;
+/// let mut sector = [0u8; 512];
+/// image.read_sector(DiskAddress::new(0, 1, 4), &mut sector)?;
+/// ```
+#[derive(Debug)]
+pub struct DiskImage {
+    file: File,
+    geometry: DiskGeometry,
+    block_size: usize,
+}
+
+impl DiskImage {
+    /// Opens an existing image with blocks of `block_size` bytes.
+    ///
+    /// # Returns
+    ///
+    /// `BlockError::Disk(DiskError::InvalidGeometry)` if the file length is not a whole,
+    /// non-zero number of tracks on every platter.
+    pub fn open(path: impl AsRef<Path>, block_size: usize) -> Result<Self, BlockError> {
+        validate_block_size(block_size)?;
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let geometry = geometry_for_length(file.metadata()?.len())?;
+        Ok(DiskImage { file, geometry, block_size })
+    }
+
+    /// Creates a zero-filled image of `geometry`, replacing any existing file.
+    pub fn create(
+        path: impl AsRef<Path>,
+        geometry: DiskGeometry,
+        block_size: usize,
+    ) -> Result<Self, BlockError> {
+        validate_block_size(block_size)?;
+        if geometry != DiskGeometry::with_tracks(geometry.tracks) || geometry.tracks == 0 {
+            return Err(DiskError::InvalidGeometry.into());
+        }
+        let file =
+            OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(geometry.capacity())?;
+        Ok(DiskImage { file, geometry, block_size })
+    }
+
+    /// Geometry implied by the image length.
+    pub fn geometry(&self) -> DiskGeometry {
+        self.geometry
+    }
+
+    /// Reads the sector at `address` into `buffer`, which must be one sector long.
+    pub fn read_sector(
+        &mut self,
+        address: DiskAddress,
+        buffer: &mut [u8],
+    ) -> Result<(), BlockError> {
+        let sector = self.geometry.block_of(address)?;
+        let sector_size = self.geometry.sector_size as usize;
+        if buffer.len() != sector_size {
+            return Err(BlockError::BufferSize);
+        }
+        self.file.seek(SeekFrom::Start(sector * sector_size as u64))?;
+        Ok(self.file.read_exact(buffer)?)
+    }
+}
+
+/// Geometry of an image file `length` bytes long (see [`DiskImage`]).
+pub fn geometry_for_length(length: u64) -> Result<DiskGeometry, DiskError> {
+    let per_track = DiskGeometry::with_tracks(1).capacity();
+    if length == 0 || !length.is_multiple_of(per_track) {
+        return Err(DiskError::InvalidGeometry);
+    }
+    let tracks = u32::try_from(length / per_track).map_err(|_| DiskError::InvalidGeometry)?;
+    Ok(DiskGeometry::with_tracks(tracks))
+}
+
+/// Formats `data` as a hex dump, 16 bytes per line, with offsets counted from `base` and
+/// printable ASCII on the right.
+pub fn hex_dump(data: &[u8], base: u64) -> String {
+    let mut out = String::new();
+    for (i, line) in data.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", base + i as u64 * 16);
+        for column in 0..16 {
+            match line.get(column) {
+                Some(byte) => {
+                    let _ = write!(out, " {byte:02x}");
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        out.extend(
+            line.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }),
+        );
+        out.push_str("|\n");
+    }
+    out
+}
+
+impl BlockDevice for DiskImage {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        self.geometry.capacity() / self.block_size as u64
+    }
+
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        self.file.seek(SeekFrom::Start(block * self.block_size as u64))?;
+        Ok(self.file.read_exact(buffer)?)
+    }
+
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        self.file.seek(SeekFrom::Start(block * self.block_size as u64))?;
+        Ok(self.file.write_all(buffer)?)
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        Ok(self.file.sync_data()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geometry_for_length() {
+        let track = 3 * 16 * 512;
+        assert_eq!(geometry_for_length(32 * track), Ok(DiskGeometry::with_tracks(32)));
+        assert_eq!(geometry_for_length(0), Err(DiskError::InvalidGeometry));
+        assert_eq!(geometry_for_length(track + 512), Err(DiskError::InvalidGeometry));
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let dump = hex_dump(b"THREADS\x00\x01 disk image!", 0x200);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "00000200  54 48 52 45 41 44 53 00 01 20 64 69 73 6b 20 69  |THREADS.. disk i|"
+        );
+        assert!(lines[1].starts_with("00000210  6d 61 67 65 21   "));
+        assert!(lines[1].ends_with("|mage!|"));
+    }
+}
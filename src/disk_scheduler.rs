@@ -0,0 +1,211 @@
+// src/disk_scheduler.rs
+
+use core::fmt;
+
+/// Disk-arm scheduling policy, selecting which queued request a disk serves next.
+///
+/// Requests are ordered by the track of their first sector. Multi-sector requests are
+/// served to completion once started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiskSchedulingPolicy {
+    /// First come, first served: requests are served in arrival order.
+    #[default]
+    Fcfs,
+    /// Shortest seek time first: the request closest to the head, oldest first on ties.
+    Sstf,
+    /// Elevator: sweep in one direction serving requests on the way, travel to the last
+    /// track, then sweep back.
+    Scan,
+    /// Circular SCAN: sweep upwards only, then travel to the last track and return to
+    /// track 0 before starting the next sweep.
+    CScan,
+    /// Like SCAN, but reverse at the last request in the current direction instead of
+    /// travelling to the edge of the disk.
+    Look,
+}
+
+/// Counters describing how a disk has served its requests since the last reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiskStats {
+    /// Policy in effect when the stats were read.
+    pub policy: DiskSchedulingPolicy,
+    /// Requests that finished, successfully or not.
+    pub requests: u64,
+    /// Total number of tracks the head has moved across.
+    pub head_movement: u64,
+    /// Sum of the time each finished request spent queued before being served, in
+    /// microseconds.
+    pub total_wait: u64,
+}
+
+impl DiskStats {
+    /// Returns the average time a request waited in the queue, in microseconds, or
+    /// `None` if no request has finished.
+    pub fn average_wait(&self) -> Option<u64> {
+        self.total_wait.checked_div(self.requests)
+    }
+}
+
+impl fmt::Display for DiskStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}: {} requests, {} tracks of head movement, average wait ",
+            self.policy, self.requests, self.head_movement
+        )?;
+        match self.average_wait() {
+            Some(wait) => write!(f, "{wait} us"),
+            None => f.write_str("-"),
+        }
+    }
+}
+
+/// The request a policy picked, and how the head gets there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Choice {
+    /// Index of the chosen request in the pending queue.
+    pub(crate) index: usize,
+    /// Tracks the head must visit, in order, before seeking to the request.
+    pub(crate) stops: Vec<u32>,
+    /// Sweep direction after serving the request.
+    pub(crate) ascending: bool,
+}
+
+/// Picks the next request for `policy`.
+///
+/// # Arguments
+///
+/// * `head` – Current track of the disk arm.
+/// * `ascending` – Current sweep direction.
+/// * `last_track` – Highest track on the disk, the far edge for SCAN and C-SCAN.
+/// * `tracks` – Track of each pending request, oldest first.
+pub(crate) fn choose(
+    policy: DiskSchedulingPolicy,
+    head: u32,
+    ascending: bool,
+    last_track: u32,
+    tracks: &[u32],
+) -> Option<Choice> {
+    if tracks.is_empty() {
+        return None;
+    }
+    let choice =
+        |index: usize, stops: Vec<u32>, ascending: bool| Choice { index, stops, ascending };
+
+    match policy {
+        DiskSchedulingPolicy::Fcfs => Some(choice(0, Vec::new(), tracks[0] >= head)),
+        DiskSchedulingPolicy::Sstf => {
+            let index = nearest(tracks, |_| true, |t| t.abs_diff(head))?;
+            Some(choice(index, Vec::new(), tracks[index] >= head))
+        }
+        DiskSchedulingPolicy::Scan | DiskSchedulingPolicy::Look => {
+            let edge = if ascending { last_track } else { 0 };
+            if let Some(index) = ahead(tracks, head, ascending) {
+                return Some(choice(index, Vec::new(), ascending));
+            }
+            // Nothing left in this direction: turn around, after reaching the edge for SCAN.
+            let index = ahead(tracks, head, !ascending)?;
+            let stops = if policy == DiskSchedulingPolicy::Scan && head != edge {
+                vec![edge]
+            } else {
+                Vec::new()
+            };
+            Some(choice(index, stops, !ascending))
+        }
+        DiskSchedulingPolicy::CScan => {
+            if let Some(index) = ahead(tracks, head, true) {
+                return Some(choice(index, Vec::new(), true));
+            }
+            let index = nearest(tracks, |_| true, |t| t)?;
+            let mut stops = Vec::new();
+            if head != last_track {
+                stops.push(last_track);
+            }
+            if tracks[index] != 0 {
+                stops.push(0);
+            }
+            Some(choice(index, stops, true))
+        }
+    }
+}
+
+/// Index of the closest request at or beyond `head` in the given direction.
+fn ahead(tracks: &[u32], head: u32, ascending: bool) -> Option<usize> {
+    if ascending {
+        nearest(tracks, |t| t >= head, |t| t - head)
+    } else {
+        nearest(tracks, |t| t <= head, |t| head - t)
+    }
+}
+
+/// Index of the oldest track accepted by `filter` with the smallest `distance`.
+fn nearest(
+    tracks: &[u32],
+    filter: impl Fn(u32) -> bool,
+    distance: impl Fn(u32) -> u32,
+) -> Option<usize> {
+    tracks
+        .iter()
+        .enumerate()
+        .filter(|&(_, &t)| filter(t))
+        // `min_by_key` keeps the first of equal keys, so ties go to the oldest request.
+        .min_by_key(|&(_, &t)| distance(t))
+        .map(|(index, _)| index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves `tracks` to completion, returning the service order and the head movement.
+    fn run(policy: DiskSchedulingPolicy, head: u32, tracks: &[u32]) -> (Vec<u32>, u32) {
+        let (mut head, mut ascending, mut moved) = (head, true, 0);
+        let mut pending = tracks.to_vec();
+        let mut order = Vec::new();
+        while let Some(choice) = choose(policy, head, ascending, 199, &pending) {
+            let track = pending.remove(choice.index);
+            for stop in choice.stops.into_iter().chain([track]) {
+                moved += stop.abs_diff(head);
+                head = stop;
+            }
+            ascending = choice.ascending;
+            order.push(track);
+        }
+        (order, moved)
+    }
+
+    // The classic textbook workload: head at 53 on a 200-track disk.
+    const WORKLOAD: [u32; 8] = [98, 183, 37, 122, 14, 124, 65, 67];
+
+    #[test]
+    fn test_fcfs_and_sstf() {
+        assert_eq!(run(DiskSchedulingPolicy::Fcfs, 53, &WORKLOAD), (WORKLOAD.to_vec(), 640));
+        assert_eq!(
+            run(DiskSchedulingPolicy::Sstf, 53, &WORKLOAD),
+            (vec![65, 67, 37, 14, 98, 122, 124, 183], 236)
+        );
+    }
+
+    #[test]
+    fn test_scan_look_and_cscan() {
+        assert_eq!(
+            run(DiskSchedulingPolicy::Scan, 53, &WORKLOAD),
+            (vec![65, 67, 98, 122, 124, 183, 37, 14], 331)
+        );
+        assert_eq!(
+            run(DiskSchedulingPolicy::Look, 53, &WORKLOAD),
+            (vec![65, 67, 98, 122, 124, 183, 37, 14], 299)
+        );
+        assert_eq!(
+            run(DiskSchedulingPolicy::CScan, 53, &WORKLOAD),
+            (vec![65, 67, 98, 122, 124, 183, 14, 37], 382)
+        );
+    }
+
+    #[test]
+    fn test_average_wait() {
+        assert_eq!(DiskStats::default().average_wait(), None);
+        let stats = DiskStats { requests: 4, total_wait: 100, ..Default::default() };
+        assert_eq!(stats.average_wait(), Some(25));
+    }
+}
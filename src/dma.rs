@@ -0,0 +1,323 @@
+// src/dma.rs
+
+use std::io::{IoSlice, IoSliceMut};
+
+use crate::block_device::BlockDevice;
+use crate::custom_device::{self, Device, DeviceRequest};
+use crate::device::DeviceId;
+use crate::interrupts::InterruptGuard;
+use crate::io_stats;
+use crate::rusty_wrapper::system_clock;
+
+/// Most buffers in one scatter-gather list.
+pub const MAX_DMA_SEGMENTS: usize = 64;
+
+/// Most bytes moved by one DMA request.
+pub const MAX_DMA_TRANSFER: usize = 1 << 20;
+
+/// [`DmaDisk`] command: read consecutive blocks into the buffers.
+pub const DMA_DISK_READ: u8 = 0x21;
+
+/// [`DmaDisk`] command: write the buffers to consecutive blocks.
+pub const DMA_DISK_WRITE: u8 = 0x22;
+
+/// Completion status of a successful DMA transfer.
+pub const DMA_STATUS_OK: u32 = 0;
+
+/// Completion status of a [`DmaDisk`] transfer the backing device failed.
+pub const DMA_STATUS_ERROR: u32 = 0x200;
+
+/// Errors returned by [`dma_transfer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmaError {
+    /// The device is not a registered custom device.
+    NotCustom,
+    /// The device has not been initialized with `device_initialize`.
+    NotInitialized,
+    /// More than [`MAX_DMA_SEGMENTS`] buffers or [`MAX_DMA_TRANSFER`] bytes.
+    TooLarge,
+    /// The device does not do DMA, or refused the request.
+    Rejected,
+}
+
+/// The scatter-gather list of a DMA request, in transfer order.
+#[derive(Debug)]
+pub enum DmaBuffers<'a, 'b> {
+    /// The device fills these buffers.
+    FromDevice(&'a mut [IoSliceMut<'b>]),
+    /// The device takes its data from these buffers.
+    ToDevice(&'a [IoSlice<'b>]),
+}
+
+impl DmaBuffers<'_, '_> {
+    /// Total length of the buffers.
+    pub fn len(&self) -> usize {
+        match self {
+            DmaBuffers::FromDevice(buffers) => buffers.iter().map(|b| b.len()).sum(),
+            DmaBuffers::ToDevice(buffers) => buffers.iter().map(|b| b.len()).sum(),
+        }
+    }
+
+    /// Whether the buffers hold no bytes at all.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of buffers in the list, empty ones included.
+    pub fn segments(&self) -> usize {
+        match self {
+            DmaBuffers::FromDevice(buffers) => buffers.len(),
+            DmaBuffers::ToDevice(buffers) => buffers.len(),
+        }
+    }
+
+    /// Copies `data` across the buffers of a [`DmaBuffers::FromDevice`] list.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes copied: the smaller of `data.len()` and [`DmaBuffers::len`],
+    /// or 0 for a [`DmaBuffers::ToDevice`] list.
+    pub fn scatter(&mut self, data: &[u8]) -> usize {
+        let DmaBuffers::FromDevice(buffers) = self else {
+            return 0;
+        };
+        let mut copied = 0;
+        for buffer in buffers.iter_mut() {
+            let count = buffer.len().min(data.len() - copied);
+            buffer[..count].copy_from_slice(&data[copied..copied + count]);
+            copied += count;
+        }
+        copied
+    }
+
+    /// Concatenates the buffers of a [`DmaBuffers::ToDevice`] list; empty for
+    /// [`DmaBuffers::FromDevice`].
+    pub fn gather(&self) -> Vec<u8> {
+        match self {
+            DmaBuffers::ToDevice(buffers) => {
+                buffers.iter().flat_map(|b| b.iter().copied()).collect()
+            }
+            DmaBuffers::FromDevice(_) => Vec::new(),
+        }
+    }
+}
+
+/// A DMA request: one command moving a whole scatter-gather list.
+#[derive(Debug)]
+pub struct DmaRequest<'a, 'b> {
+    /// Device-defined command code.
+    pub command: u8,
+    /// Device-defined start address, e.g. the first block.
+    pub address: u64,
+    /// Where the data comes from or goes to, which also gives the transfer's length.
+    pub buffers: DmaBuffers<'a, 'b>,
+}
+
+/// Starts a DMA transfer on custom device `device`.
+///
+/// Unlike `device_control`, whose `data_length` is bounded by
+/// `THREADS_MAX_IO_BUFFER_SIZE`, a DMA request describes the whole transfer. The device
+/// copies the data while the call runs and raises a single `THREADS_IO_INTERRUPT` with
+/// the request's command when its simulated transfer time is over, so the buffers are
+/// free again as soon as this returns.
+///
+/// # Returns
+///
+/// `Ok` once the device accepted the request; its outcome arrives as the completion
+/// status.
+///
+/// # Example
+///
+/// ```ignore
+/// use std::io::IoSliceMut;
+/// use rusty_threads::{DMA_DISK_READ, DmaBuffers, DmaRequest};
+///
+/// let (mut header, mut body) = ([0; 512], [0; 4096]);
+/// let mut buffers = [IoSliceMut::new(&mut header), IoSliceMut::new(&mut body)];
+/// let buffers = DmaBuffers::FromDevice(&mut buffers);
+/// let request = DmaRequest { command: DMA_DISK_READ, address: 0, buffers };
+/// rusty_threads::dma_transfer(disk, request)?;
+/// ```
+pub fn dma_transfer(device: DeviceId, request: DmaRequest<'_, '_>) -> Result<(), DmaError> {
+    if request.buffers.segments() > MAX_DMA_SEGMENTS || request.buffers.len() > MAX_DMA_TRANSFER {
+        return Err(DmaError::TooLarge);
+    }
+    let (command, length) = (request.command, request.buffers.len() as u32);
+    let now = system_clock();
+    // Held across the transfer so the completion cannot be counted before the issue.
+    let guard = InterruptGuard::new();
+    let result = match custom_device::with_device(device, |d| d.dma(request, now)) {
+        None => return Err(DmaError::NotCustom),
+        Some(None) => Err(DmaError::NotInitialized),
+        Some(Some(true)) => Ok(()),
+        Some(Some(false)) => Err(DmaError::Rejected),
+    };
+    io_stats::record_request(&guard, device, command, length, result.is_ok());
+    result
+}
+
+/// Transfer timing of a [`DmaDisk`], in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaTiming {
+    /// Fixed cost of a request, such as the seek.
+    pub setup: u32,
+    /// Cost of each block moved.
+    pub per_block: u32,
+}
+
+impl Default for DmaTiming {
+    fn default() -> Self {
+        DmaTiming { setup: 1_000, per_block: 50 }
+    }
+}
+
+/// A disk that moves whole runs of blocks by DMA, as a custom [`Device`].
+///
+/// Register it with [`crate::custom_device::register_device`], backed by a
+/// [`BlockDevice`] that answers at once, such as a host image file or a
+/// [`crate::block_device::MemBlockDevice`]. The transfer runs inside [`dma_transfer`] with
+/// interrupts disabled, so the backing device must never block: a
+/// [`crate::block_device::ThreadsDisk`], or anything layered on one, would wait for a
+/// disk interrupt that can never arrive and hang the kernel.
+///
+/// [`DMA_DISK_READ`] and [`DMA_DISK_WRITE`] requests start at block
+/// [`DmaRequest::address`] and cover as many consecutive blocks as the buffers hold,
+/// which must be a whole number of blocks. Each completes with one interrupt after
+/// `setup + blocks * per_block`, capped at `i32::MAX` µs, with [`DMA_STATUS_OK`] or, if
+/// the backing device failed, [`DMA_STATUS_ERROR`]. It does not accept `device_control`
+/// commands.
+pub struct DmaDisk {
+    device: Box<dyn BlockDevice + Send>,
+    timing: DmaTiming,
+    /// Completions as `(due time, command, status)`.
+    completions: Vec<(u32, u8, u32)>,
+}
+
+impl DmaDisk {
+    /// Creates a DMA disk over `device`, which must not block (see [`DmaDisk`]), with
+    /// transfers taking `timing`.
+    pub fn new(device: impl BlockDevice + Send + 'static, timing: DmaTiming) -> Self {
+        DmaDisk { device: Box::new(device), timing, completions: Vec::new() }
+    }
+
+    fn transfer(&mut self, request: &mut DmaRequest<'_, '_>) -> bool {
+        let block_size = self.device.block_size();
+        let mut data = match &request.buffers {
+            DmaBuffers::FromDevice(_) => vec![0; request.buffers.len()],
+            DmaBuffers::ToDevice(_) => request.buffers.gather(),
+        };
+        let mut blocks = data.chunks_mut(block_size).zip(request.address..);
+        let ok = match request.command {
+            DMA_DISK_READ => blocks.all(|(b, n)| self.device.read_block(n, b).is_ok()),
+            _ => blocks.all(|(b, n)| self.device.write_block(n, b).is_ok()),
+        };
+        request.buffers.scatter(&data);
+        ok
+    }
+}
+
+impl Device for DmaDisk {
+    fn control(&mut self, _request: DeviceRequest<'_>, _now: u32) -> bool {
+        false
+    }
+
+    fn dma(&mut self, mut request: DmaRequest<'_, '_>, now: u32) -> bool {
+        let block_size = self.device.block_size();
+        let direction_ok = matches!(
+            (request.command, &request.buffers),
+            (DMA_DISK_READ, DmaBuffers::FromDevice(_)) | (DMA_DISK_WRITE, DmaBuffers::ToDevice(_))
+        );
+        let blocks = (request.buffers.len() / block_size) as u64;
+        let in_range =
+            request.address.checked_add(blocks).is_some_and(|end| end <= self.device.block_count());
+        if !direction_ok || !request.buffers.len().is_multiple_of(block_size) || !in_range {
+            return false;
+        }
+        let status = match self.transfer(&mut request) {
+            true => DMA_STATUS_OK,
+            false => DMA_STATUS_ERROR,
+        };
+        let blocks = u32::try_from(blocks).unwrap_or(u32::MAX);
+        let time = self.timing.setup.saturating_add(self.timing.per_block.saturating_mul(blocks));
+        // `poll` compares due times on the wrapping clock, which only looks half its range
+        // ahead.
+        let time = time.min(i32::MAX as u32);
+        self.completions.push((now.wrapping_add(time), request.command, status));
+        true
+    }
+
+    fn poll(&mut self, now: u32) -> Option<(u8, u32)> {
+        let index =
+            self.completions.iter().position(|&(due, ..)| (now.wrapping_sub(due) as i32) >= 0)?;
+        let (_, command, status) = self.completions.remove(index);
+        Some((command, status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_device::MemBlockDevice;
+
+    #[test]
+    fn test_scatter_and_gather() {
+        let (mut a, mut b) = ([0; 3], [0; 4]);
+        let mut slices = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        let mut buffers = DmaBuffers::FromDevice(&mut slices);
+        assert_eq!((buffers.len(), buffers.segments()), (7, 2));
+        assert_eq!(buffers.scatter(b"abcdefgh"), 7);
+        assert_eq!((&a, &b), (b"abc", b"defg"));
+
+        let slices = [IoSlice::new(b"xy"), IoSlice::new(b""), IoSlice::new(b"z")];
+        let mut buffers = DmaBuffers::ToDevice(&slices);
+        assert_eq!(buffers.gather(), b"xyz");
+        assert_eq!(buffers.scatter(b"ignored"), 0);
+    }
+
+    #[test]
+    fn test_dma_disk_moves_runs_with_one_completion() {
+        let timing = DmaTiming { setup: 100, per_block: 10 };
+        let mut disk = DmaDisk::new(MemBlockDevice::new(512, 16).unwrap(), timing);
+        let (first, second) = (vec![1; 512], vec![2; 1024]);
+        let slices = [IoSlice::new(&first), IoSlice::new(&second)];
+        let buffers = DmaBuffers::ToDevice(&slices);
+        assert!(disk.dma(DmaRequest { command: DMA_DISK_WRITE, address: 4, buffers }, 0));
+        assert_eq!(disk.poll(129), None);
+        assert_eq!(disk.poll(130), Some((DMA_DISK_WRITE, DMA_STATUS_OK)));
+        assert_eq!(disk.poll(130), None);
+
+        let (mut head, mut tail) = (vec![0; 1024], vec![0; 1536]);
+        let mut slices = [IoSliceMut::new(&mut head), IoSliceMut::new(&mut tail)];
+        let buffers = DmaBuffers::FromDevice(&mut slices);
+        assert!(disk.dma(DmaRequest { command: DMA_DISK_READ, address: 3, buffers }, 500));
+        assert_eq!(disk.poll(649), None);
+        assert_eq!(disk.poll(650), Some((DMA_DISK_READ, DMA_STATUS_OK)));
+        assert_eq!((head[511], head[512], tail[1023], tail[1024]), (0, 1, 2, 0));
+
+        // Misaligned lengths, runs past the end and mismatched directions are refused.
+        let mut odd = [IoSliceMut::new(&mut head[..100])];
+        let buffers = DmaBuffers::FromDevice(&mut odd);
+        assert!(!disk.dma(DmaRequest { command: DMA_DISK_READ, address: 0, buffers }, 0));
+        let mut slices = [IoSliceMut::new(&mut tail)];
+        let buffers = DmaBuffers::FromDevice(&mut slices);
+        assert!(!disk.dma(DmaRequest { command: DMA_DISK_READ, address: 15, buffers }, 0));
+        let slices = [IoSlice::new(&first)];
+        let buffers = DmaBuffers::ToDevice(&slices);
+        assert!(!disk.dma(DmaRequest { command: DMA_DISK_READ, address: 0, buffers }, 0));
+    }
+
+    #[test]
+    fn test_slow_transfers_saturate_their_time() {
+        let timing = DmaTiming { setup: 0, per_block: u32::MAX / 2 };
+        let mut disk = DmaDisk::new(MemBlockDevice::new(512, 4).unwrap(), timing);
+        let mut data = vec![0; 1536];
+        let mut slices = [IoSliceMut::new(&mut data)];
+        let buffers = DmaBuffers::FromDevice(&mut slices);
+        assert!(disk.dma(DmaRequest { command: DMA_DISK_READ, address: 0, buffers }, 0));
+        let due = i32::MAX as u32;
+        assert_eq!(disk.completions, [(due, DMA_DISK_READ, DMA_STATUS_OK)]);
+        assert_eq!(disk.poll(0), None);
+        assert_eq!(disk.poll(due - 1), None);
+        assert_eq!(disk.poll(due), Some((DMA_DISK_READ, DMA_STATUS_OK)));
+    }
+}
@@ -0,0 +1,1281 @@
+// src/fs.rs
+
+use core::ops::BitOr;
+use std::collections::BTreeMap;
+
+use crate::block_device::{BlockDevice, BlockError};
+use crate::journal;
+
+/// Magic number identifying a formatted volume (`"RTFS"`).
+pub const FS_MAGIC: u32 = 0x5254_4653;
+
+/// On-disk format version written by [`mkfs`].
+pub const FS_VERSION: u32 = 1;
+
+/// Inode number of the root directory. Inode numbers start at 1; 0 means "none".
+pub const ROOT_INODE: u32 = 1;
+
+/// Longest file name, in bytes, that fits in a directory entry.
+pub const MAX_NAME_LEN: usize = 28;
+
+/// Number of block pointers held directly in an inode.
+pub const DIRECT_BLOCKS: usize = 12;
+
+/// Smallest metadata journal, in blocks, that [`mkfs`] accepts.
+///
+/// Besides its header block, the journal must hold every metadata block one operation can
+/// change. The largest is a `mkdir` that grows its parent: up to two bitmap blocks, the
+/// inode table blocks of both directories, the new directory's block and the parent's new
+/// data and indirect blocks.
+pub const MIN_JOURNAL_BLOCKS: u32 = 8;
+
+const INODE_SIZE: usize = 64;
+const DIR_ENTRY_SIZE: usize = 32;
+
+/// Errors returned by the file system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// The underlying block device failed.
+    Block(BlockError),
+    /// The device does not hold a volume in this format.
+    NotFormatted,
+    /// On-disk structures are inconsistent.
+    Corrupt,
+    /// The device is too small for the requested layout, or the requested journal is
+    /// smaller than [`MIN_JOURNAL_BLOCKS`].
+    DeviceTooSmall,
+    /// The device has more blocks than a volume can address, `u32::MAX`.
+    DeviceTooLarge,
+    /// A path component does not exist.
+    NotFound,
+    /// A path component that must be a directory is a file.
+    NotADirectory,
+    /// The operation needs a file but the path names a directory.
+    IsADirectory,
+    /// The name is already taken in its directory.
+    AlreadyExists,
+    /// The directory still has entries.
+    DirectoryNotEmpty,
+    /// A path component is longer than [`MAX_NAME_LEN`].
+    NameTooLong,
+    /// The path is not absolute, or names `.`, `..` or the root where that is not allowed.
+    InvalidPath,
+    /// No free data blocks are left.
+    NoSpace,
+    /// No free inodes are left.
+    NoInodes,
+    /// The write would grow the file past its largest possible size.
+    FileTooLarge,
+    /// The file descriptor is not open.
+    BadDescriptor,
+    /// The descriptor was not opened for this kind of access.
+    AccessDenied,
+    /// The file is open and cannot be removed.
+    Busy,
+    /// The operation changes more metadata blocks than the journal can hold.
+    JournalFull,
+}
+
+impl From<BlockError> for FsError {
+    fn from(error: BlockError) -> Self {
+        FsError::Block(error)
+    }
+}
+
+/// Kind of object an inode describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+}
+
+impl FileKind {
+    fn encode(kind: Option<FileKind>) -> u16 {
+        match kind {
+            None => 0,
+            Some(FileKind::File) => 1,
+            Some(FileKind::Directory) => 2,
+        }
+    }
+
+    fn decode(raw: u16) -> Result<Option<FileKind>, FsError> {
+        match raw {
+            0 => Ok(None),
+            1 => Ok(Some(FileKind::File)),
+            2 => Ok(Some(FileKind::Directory)),
+            _ => Err(FsError::Corrupt),
+        }
+    }
+}
+
+/// Flags for [`FileSystem::open`], combined with `|`.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::OpenFlags;
+///
+/// let flags = OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE;
+/// assert!(flags.contains(OpenFlags::CREATE));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    /// Open for reading.
+    pub const READ: OpenFlags = OpenFlags(0x01);
+    /// Open for writing.
+    pub const WRITE: OpenFlags = OpenFlags(0x02);
+    /// Create the file if it does not exist.
+    pub const CREATE: OpenFlags = OpenFlags(0x04);
+    /// Discard the file's contents when opening it for writing.
+    pub const TRUNCATE: OpenFlags = OpenFlags(0x08);
+    /// Make every write go to the end of the file.
+    pub const APPEND: OpenFlags = OpenFlags(0x10);
+
+    /// Returns `true` if every flag in `other` is set.
+    pub const fn contains(self, other: OpenFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = OpenFlags;
+
+    fn bitor(self, rhs: OpenFlags) -> OpenFlags {
+        OpenFlags(self.0 | rhs.0)
+    }
+}
+
+/// A file descriptor returned by [`FileSystem::open`].
+pub type Fd = u32;
+
+/// Layout of a volume, stored in block 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Superblock {
+    /// Block size in bytes; equal to the device's block size.
+    pub block_size: u32,
+    /// Number of blocks in the volume.
+    pub block_count: u32,
+    /// Number of inodes in the inode table.
+    pub inode_count: u32,
+    /// First block of the free-block bitmap.
+    pub bitmap_start: u32,
+    /// Length of the free-block bitmap in blocks.
+    pub bitmap_blocks: u32,
+    /// First block of the inode table.
+    pub inode_start: u32,
+    /// Length of the inode table in blocks.
+    pub inode_blocks: u32,
+    /// First block available for file data.
+    pub data_start: u32,
+    /// First block of the metadata journal, or 0 without a journal.
+    pub journal_start: u32,
+    /// Length of the metadata journal in blocks, or 0 without a journal.
+    pub journal_blocks: u32,
+}
+
+impl Superblock {
+    /// Lays out a volume of `block_count` blocks with room for at least `inode_count`
+    /// inodes and a journal of `journal_blocks` blocks right after the superblock.
+    fn layout(
+        block_size: u32,
+        block_count: u32,
+        inode_count: u32,
+        journal_blocks: u32,
+    ) -> Result<Self, FsError> {
+        if journal_blocks > 0 && journal_blocks < MIN_JOURNAL_BLOCKS {
+            return Err(FsError::DeviceTooSmall);
+        }
+        let bitmap_blocks = block_count.div_ceil(block_size * 8);
+        let inodes_per_block = block_size / INODE_SIZE as u32;
+        let inode_blocks = inode_count.max(1).div_ceil(inodes_per_block);
+        let journal_start = if journal_blocks > 0 { 1 } else { 0 };
+        let bitmap_start = 1 + journal_blocks;
+        let inode_start = bitmap_start + bitmap_blocks;
+        let data_start = inode_start + inode_blocks;
+        if data_start >= block_count {
+            return Err(FsError::DeviceTooSmall);
+        }
+        Ok(Superblock {
+            block_size,
+            block_count,
+            inode_count: inode_blocks * inodes_per_block,
+            bitmap_start,
+            bitmap_blocks,
+            inode_start,
+            inode_blocks,
+            data_start,
+            journal_start,
+            journal_blocks,
+        })
+    }
+
+    fn fields(&self) -> [u32; 12] {
+        [
+            FS_MAGIC,
+            FS_VERSION,
+            self.block_size,
+            self.block_count,
+            self.inode_count,
+            self.bitmap_start,
+            self.bitmap_blocks,
+            self.inode_start,
+            self.inode_blocks,
+            self.data_start,
+            self.journal_start,
+            self.journal_blocks,
+        ]
+    }
+
+    fn encode(&self, block: &mut [u8]) {
+        block.fill(0);
+        for (i, field) in self.fields().into_iter().enumerate() {
+            put_u32(block, i * 4, field);
+        }
+    }
+
+    /// Reads the block size from the first sector of a volume, so that a tool can open the
+    /// device with the right block size before mounting it.
+    ///
+    /// # Returns
+    ///
+    /// `FsError::NotFormatted` if the sector does not start with a superblock.
+    pub fn probe(sector: &[u8]) -> Result<usize, FsError> {
+        if sector.len() < 12 || get_u32(sector, 0) != FS_MAGIC || get_u32(sector, 4) != FS_VERSION {
+            return Err(FsError::NotFormatted);
+        }
+        Ok(get_u32(sector, 8) as usize)
+    }
+
+    /// Decodes block 0 of a volume, checking the magic number and that the regions are
+    /// laid out the way [`mkfs`] lays them out.
+    pub fn decode(block: &[u8]) -> Result<Self, FsError> {
+        if block.len() < 48 || get_u32(block, 0) != FS_MAGIC || get_u32(block, 4) != FS_VERSION {
+            return Err(FsError::NotFormatted);
+        }
+        let superblock = Superblock {
+            block_size: get_u32(block, 8),
+            block_count: get_u32(block, 12),
+            inode_count: get_u32(block, 16),
+            bitmap_start: get_u32(block, 20),
+            bitmap_blocks: get_u32(block, 24),
+            inode_start: get_u32(block, 28),
+            inode_blocks: get_u32(block, 32),
+            data_start: get_u32(block, 36),
+            journal_start: get_u32(block, 40),
+            journal_blocks: get_u32(block, 44),
+        };
+        if superblock.block_size as usize != block.len() {
+            return Err(FsError::Corrupt);
+        }
+        let expected = Superblock::layout(
+            superblock.block_size,
+            superblock.block_count,
+            superblock.inode_count,
+            superblock.journal_blocks,
+        );
+        if expected != Ok(superblock) {
+            return Err(FsError::Corrupt);
+        }
+        Ok(superblock)
+    }
+
+    fn inodes_per_block(&self) -> u32 {
+        self.block_size / INODE_SIZE as u32
+    }
+
+    fn pointers_per_block(&self) -> usize {
+        self.block_size as usize / 4
+    }
+
+    /// Largest possible file, in bytes.
+    pub fn max_file_size(&self) -> u64 {
+        let blocks = (DIRECT_BLOCKS + self.pointers_per_block()) as u64;
+        // Inodes store the size in 32 bits.
+        (blocks * u64::from(self.block_size)).min(u64::from(u32::MAX))
+    }
+}
+
+/// An on-disk inode: 64 bytes holding the kind, link count, size, twelve direct block
+/// pointers and one single-indirect block pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct Inode {
+    pub(crate) kind: Option<FileKind>,
+    pub(crate) links: u16,
+    pub(crate) size: u32,
+    pub(crate) direct: [u32; DIRECT_BLOCKS],
+    pub(crate) indirect: u32,
+}
+
+impl Inode {
+    fn new(kind: FileKind) -> Self {
+        Inode { kind: Some(kind), links: 1, ..Default::default() }
+    }
+
+    fn encode(&self, bytes: &mut [u8]) {
+        bytes[..INODE_SIZE].fill(0);
+        bytes[0..2].copy_from_slice(&FileKind::encode(self.kind).to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.links.to_le_bytes());
+        put_u32(bytes, 4, self.size);
+        for (i, &block) in self.direct.iter().enumerate() {
+            put_u32(bytes, 8 + i * 4, block);
+        }
+        put_u32(bytes, 8 + DIRECT_BLOCKS * 4, self.indirect);
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, FsError> {
+        let mut direct = [0; DIRECT_BLOCKS];
+        for (i, block) in direct.iter_mut().enumerate() {
+            *block = get_u32(bytes, 8 + i * 4);
+        }
+        Ok(Inode {
+            kind: FileKind::decode(u16::from_le_bytes([bytes[0], bytes[1]]))?,
+            links: u16::from_le_bytes([bytes[2], bytes[3]]),
+            size: get_u32(bytes, 4),
+            direct,
+            indirect: get_u32(bytes, 8 + DIRECT_BLOCKS * 4),
+        })
+    }
+}
+
+/// Information about a file or directory, returned by [`FileSystem::stat`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    /// Inode number.
+    pub inode: u32,
+    /// File or directory.
+    pub kind: FileKind,
+    /// Size in bytes. Directories grow in 32-byte entries.
+    pub size: u32,
+    /// Number of directory entries referring to the inode.
+    pub links: u16,
+}
+
+/// An entry returned by [`FileSystem::read_dir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// Name within the directory.
+    pub name: String,
+    /// Inode number.
+    pub inode: u32,
+    /// File or directory.
+    pub kind: FileKind,
+}
+
+/// Space usage reported by [`FileSystem::statfs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsStats {
+    /// Block size in bytes.
+    pub block_size: u32,
+    /// Blocks available for data in total.
+    pub data_blocks: u32,
+    /// Data blocks not in use.
+    pub free_blocks: u32,
+    /// Inodes in total.
+    pub inodes: u32,
+    /// Inodes not in use.
+    pub free_inodes: u32,
+}
+
+/// Settings for [`mkfs`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MkfsOptions {
+    /// Number of inodes to create. `None` reserves one inode for every four blocks.
+    pub inode_count: Option<u32>,
+    /// Size of the write-ahead metadata journal in blocks, including its header block:
+    /// 0 formats the volume without a journal, otherwise at least [`MIN_JOURNAL_BLOCKS`].
+    pub journal_blocks: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OpenFile {
+    inode: u32,
+    offset: u64,
+    flags: OpenFlags,
+}
+
+/// A small Unix-like file system on a [`BlockDevice`].
+///
+/// The volume is laid out as a superblock (block 0), a free-block bitmap, an inode table
+/// and data blocks. Directories are files of 32-byte entries (a little-endian inode
+/// number followed by a NUL-padded name) and always contain `.` and `..`. Paths are
+/// absolute and `/`-separated.
+///
+/// Volumes formatted with a journal (see [`MkfsOptions::journal_blocks`]) update metadata
+/// atomically: each mutating call buffers the superblock, bitmap, inode, indirect and
+/// directory blocks it changes and commits them through the journal before writing them
+/// in place, while file contents are written directly. [`FileSystem::mount`] replays a
+/// committed transaction left behind by a crash, so the volume is consistent whenever
+/// the machine stopped.
+///
+/// The file system does no locking of its own. Kernels that share it between processes
+/// keep it behind a kernel [`crate::sync::Mutex`]; putting a
+/// [`crate::buffer_cache::BufferCache`] underneath avoids re-reading the bitmap and inode
+/// blocks for every call.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{BufferCache, DeviceId, MkfsOptions, OpenFlags, ThreadsDisk, WritePolicy};
+///
+/// let disk = ThreadsDisk::open(DeviceId::disk(0), 1024)?;
+/// let cache = BufferCache::new(disk, 32, WritePolicy::WriteBack);
+/// let mut fs = rusty_threads::mkfs(cache, MkfsOptions::default())?;
+///
+/// fs.mkdir("/etc")?;
+/// let fd = fs.open("/etc/motd", OpenFlags::WRITE | OpenFlags::CREATE)?;
+/// fs.write(fd, b"hello from THREADS\n")?;
+/// fs.close(fd)?;
+/// let disk = fs.unmount()?;
+/// ```
+pub struct FileSystem<D: BlockDevice> {
+    device: D,
+    superblock: Superblock,
+    open_files: Vec<Option<OpenFile>>,
+    /// Metadata blocks changed by the current journaled operation.
+    transaction: Option<BTreeMap<u32, Vec<u8>>>,
+}
+
+/// Formats `device` and mounts the new, empty volume.
+///
+/// The volume covers the whole device, so on a [`crate::block_device::ThreadsDisk`] its
+/// size follows the geometry reported by `DISK_INFO`.
+pub fn mkfs<D: BlockDevice>(mut device: D, options: MkfsOptions) -> Result<FileSystem<D>, FsError> {
+    let block_size = device.block_size() as u32;
+    let block_count = u32::try_from(device.block_count()).map_err(|_| FsError::DeviceTooLarge)?;
+    let inode_count = options.inode_count.unwrap_or(block_count / 4);
+    let superblock =
+        Superblock::layout(block_size, block_count, inode_count, options.journal_blocks)?;
+
+    let mut block = vec![0; block_size as usize];
+    for b in 1..superblock.data_start {
+        device.write_block(u64::from(b), &block)?;
+    }
+    superblock.encode(&mut block);
+    device.write_block(0, &block)?;
+
+    let mut fs = FileSystem { device, superblock, open_files: Vec::new(), transaction: None };
+    for b in 0..superblock.data_start {
+        fs.set_block_used(b, true)?;
+    }
+    let root = fs.allocate_inode(FileKind::Directory)?;
+    debug_assert_eq!(root, ROOT_INODE);
+    fs.init_directory(root, root)?;
+    fs.device.flush()?;
+    Ok(fs)
+}
+
+impl<D: BlockDevice> FileSystem<D> {
+    /// Mounts the volume on `device`, first replaying any transaction that was committed
+    /// to the journal but not yet written in place.
+    ///
+    /// # Returns
+    ///
+    /// `FsError::NotFormatted` if the device was never formatted, or `FsError::Corrupt` if
+    /// the superblock does not fit the device.
+    pub fn mount(mut device: D) -> Result<Self, FsError> {
+        let mut block = vec![0; device.block_size()];
+        device.read_block(0, &mut block)?;
+        let superblock = Superblock::decode(&block)?;
+        if u64::from(superblock.block_count) > device.block_count() {
+            return Err(FsError::Corrupt);
+        }
+        if superblock.journal_blocks > 0 {
+            journal::recover_journal(&mut device, &superblock)?;
+        }
+        Ok(FileSystem { device, superblock, open_files: Vec::new(), transaction: None })
+    }
+
+    /// Closes every open file, flushes the device and returns it.
+    pub fn unmount(mut self) -> Result<D, FsError> {
+        self.open_files.clear();
+        self.device.flush()?;
+        Ok(self.device)
+    }
+
+    /// Flushes the underlying device.
+    pub fn sync(&mut self) -> Result<(), FsError> {
+        Ok(self.device.flush()?)
+    }
+
+    /// The volume layout.
+    pub fn superblock(&self) -> Superblock {
+        self.superblock
+    }
+
+    /// Returns the device without flushing it or closing files, leaving it as a machine
+    /// halted at this point would. Useful to inspect a device after crash injection.
+    pub fn into_device(self) -> D {
+        self.device
+    }
+
+    /// The underlying device.
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Returns `true` if the volume has a metadata journal.
+    pub fn is_journaled(&self) -> bool {
+        self.superblock.journal_blocks > 0
+    }
+
+    /// Opens the file at `path`, returning the lowest free descriptor.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` – Absolute path of a regular file.
+    /// * `flags` – Access mode and creation flags, see [`OpenFlags`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let fd = fs.open("/log", OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::APPEND)?;
+    /// ```
+    pub fn open(&mut self, path: &str, flags: OpenFlags) -> Result<Fd, FsError> {
+        let inode = self.atomically(|fs| {
+            let inode = match fs.resolve(path) {
+                Ok(inode) => inode,
+                Err(FsError::NotFound) if flags.contains(OpenFlags::CREATE) => {
+                    let (parent, name) = fs.resolve_parent(path)?;
+                    let inode = fs.allocate_inode(FileKind::File)?;
+                    if let Err(error) = fs.add_entry(parent, name, inode) {
+                        fs.discard_inode(inode)?;
+                        return Err(error);
+                    }
+                    inode
+                }
+                Err(error) => return Err(error),
+            };
+
+            let mut node = fs.read_inode(inode)?;
+            if node.kind == Some(FileKind::Directory) {
+                return Err(FsError::IsADirectory);
+            }
+            if flags.contains(OpenFlags::WRITE | OpenFlags::TRUNCATE) {
+                fs.truncate(&mut node)?;
+                fs.write_inode(inode, &node)?;
+            }
+            Ok(inode)
+        })?;
+
+        let file = Some(OpenFile { inode, offset: 0, flags });
+        let fd = match self.open_files.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.open_files[fd] = file;
+                fd
+            }
+            None => {
+                self.open_files.push(file);
+                self.open_files.len() - 1
+            }
+        };
+        Ok(fd as Fd)
+    }
+
+    /// Reads from the current offset of `fd`, returning the number of bytes read (0 at
+    /// end of file).
+    pub fn read(&mut self, fd: Fd, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let file = self.open_file(fd, OpenFlags::READ)?;
+        let node = self.read_inode(file.inode)?;
+        let count = self.read_at(&node, file.offset, buffer)?;
+        self.advance(fd, count);
+        Ok(count)
+    }
+
+    /// Writes `data` at the current offset of `fd` (or at the end with
+    /// `OpenFlags::APPEND`), growing the file as needed.
+    ///
+    /// If the volume fills up part way, a volume without a journal keeps the bytes
+    /// written so far and grows the file to cover them. A journaled volume rolls the
+    /// allocation back: the file keeps its old size and blocks, but file contents are
+    /// written in place without the journal, so bytes that overwrote blocks the file
+    /// already had stay overwritten.
+    pub fn write(&mut self, fd: Fd, data: &[u8]) -> Result<usize, FsError> {
+        let file = self.open_file(fd, OpenFlags::WRITE)?;
+        let offset = self.atomically(|fs| {
+            let mut node = fs.read_inode(file.inode)?;
+            let append = file.flags.contains(OpenFlags::APPEND);
+            let offset = if append { u64::from(node.size) } else { file.offset };
+            let written = fs.write_at(&mut node, offset, data);
+            // Blocks allocated before a failure are only reachable through the inode.
+            fs.write_inode(file.inode, &node)?;
+            written?;
+            Ok(offset)
+        })?;
+        if let Some(Some(open)) = self.open_files.get_mut(fd as usize) {
+            open.offset = offset + data.len() as u64;
+        }
+        Ok(data.len())
+    }
+
+    /// Moves the offset of `fd` to `offset` bytes from the start of the file. Seeking past
+    /// the end is allowed; a later write leaves a zero-filled gap.
+    pub fn seek(&mut self, fd: Fd, offset: u64) -> Result<(), FsError> {
+        match self.open_files.get_mut(fd as usize) {
+            Some(Some(file)) => {
+                file.offset = offset;
+                Ok(())
+            }
+            _ => Err(FsError::BadDescriptor),
+        }
+    }
+
+    /// Closes `fd`.
+    pub fn close(&mut self, fd: Fd) -> Result<(), FsError> {
+        match self.open_files.get_mut(fd as usize) {
+            Some(slot @ Some(_)) => {
+                *slot = None;
+                Ok(())
+            }
+            _ => Err(FsError::BadDescriptor),
+        }
+    }
+
+    /// Removes the file at `path`, freeing its blocks once no entry refers to it.
+    pub fn unlink(&mut self, path: &str) -> Result<(), FsError> {
+        self.atomically(|fs| {
+            let (parent, name) = fs.resolve_parent(path)?;
+            let inode = fs.lookup(parent, name)?.ok_or(FsError::NotFound)?;
+            let mut node = fs.read_inode(inode)?;
+            if node.kind == Some(FileKind::Directory) {
+                return Err(FsError::IsADirectory);
+            }
+            if fs.open_files.iter().flatten().any(|f| f.inode == inode) {
+                return Err(FsError::Busy);
+            }
+
+            fs.remove_entry(parent, name)?;
+            node.links = node.links.saturating_sub(1);
+            if node.links == 0 {
+                fs.truncate(&mut node)?;
+                node = Inode::default();
+            }
+            fs.write_inode(inode, &node)
+        })
+    }
+
+    /// Creates an empty directory at `path`.
+    pub fn mkdir(&mut self, path: &str) -> Result<(), FsError> {
+        self.atomically(|fs| {
+            let (parent, name) = fs.resolve_parent(path)?;
+            if fs.lookup(parent, name)?.is_some() {
+                return Err(FsError::AlreadyExists);
+            }
+            let inode = fs.allocate_inode(FileKind::Directory)?;
+            let linked =
+                fs.init_directory(inode, parent).and_then(|()| fs.add_entry(parent, name, inode));
+            if let Err(error) = linked {
+                fs.discard_inode(inode)?;
+                return Err(error);
+            }
+
+            let mut parent_node = fs.read_inode(parent)?;
+            parent_node.links += 1;
+            fs.write_inode(parent, &parent_node)
+        })
+    }
+
+    /// Removes the empty directory at `path`.
+    pub fn rmdir(&mut self, path: &str) -> Result<(), FsError> {
+        self.atomically(|fs| {
+            let (parent, name) = fs.resolve_parent(path)?;
+            let inode = fs.lookup(parent, name)?.ok_or(FsError::NotFound)?;
+            let mut node = fs.read_inode(inode)?;
+            if node.kind != Some(FileKind::Directory) {
+                return Err(FsError::NotADirectory);
+            }
+            if fs.entries(&node)?.iter().any(|(_, _, name)| name != "." && name != "..") {
+                return Err(FsError::DirectoryNotEmpty);
+            }
+
+            fs.remove_entry(parent, name)?;
+            fs.truncate(&mut node)?;
+            fs.write_inode(inode, &Inode::default())?;
+            let mut parent_node = fs.read_inode(parent)?;
+            parent_node.links = parent_node.links.saturating_sub(1);
+            fs.write_inode(parent, &parent_node)
+        })
+    }
+
+    /// Returns information about the file or directory at `path`.
+    pub fn stat(&mut self, path: &str) -> Result<Metadata, FsError> {
+        let inode = self.resolve(path)?;
+        let node = self.read_inode(inode)?;
+        Ok(Metadata {
+            inode,
+            kind: node.kind.ok_or(FsError::Corrupt)?,
+            size: node.size,
+            links: node.links,
+        })
+    }
+
+    /// Lists the directory at `path`, without `.` and `..`.
+    pub fn read_dir(&mut self, path: &str) -> Result<Vec<DirEntry>, FsError> {
+        let inode = self.resolve(path)?;
+        let node = self.read_inode(inode)?;
+        if node.kind != Some(FileKind::Directory) {
+            return Err(FsError::NotADirectory);
+        }
+        let mut listing = Vec::new();
+        for (_, inode, name) in self.entries(&node)? {
+            if name == "." || name == ".." {
+                continue;
+            }
+            let kind = self.read_inode(inode)?.kind.ok_or(FsError::Corrupt)?;
+            listing.push(DirEntry { name, inode, kind });
+        }
+        Ok(listing)
+    }
+
+    /// Counts free data blocks and inodes.
+    pub fn statfs(&mut self) -> Result<FsStats, FsError> {
+        let sb = self.superblock;
+        let mut free_blocks = 0;
+        for b in sb.data_start..sb.block_count {
+            if !self.is_block_used(b)? {
+                free_blocks += 1;
+            }
+        }
+        let mut free_inodes = 0;
+        for inode in 1..=sb.inode_count {
+            if self.read_inode(inode)?.kind.is_none() {
+                free_inodes += 1;
+            }
+        }
+        Ok(FsStats {
+            block_size: sb.block_size,
+            data_blocks: sb.block_count - sb.data_start,
+            free_blocks,
+            inodes: sb.inode_count,
+            free_inodes,
+        })
+    }
+
+    fn open_file(&self, fd: Fd, access: OpenFlags) -> Result<OpenFile, FsError> {
+        let file = self.open_files.get(fd as usize).copied().flatten();
+        let file = file.ok_or(FsError::BadDescriptor)?;
+        if !file.flags.contains(access) {
+            return Err(FsError::AccessDenied);
+        }
+        Ok(file)
+    }
+
+    fn advance(&mut self, fd: Fd, count: usize) {
+        if let Some(Some(file)) = self.open_files.get_mut(fd as usize) {
+            file.offset += count as u64;
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        self.superblock.block_size as usize
+    }
+
+    /// Runs one mutating operation. On a journaled volume its metadata writes are
+    /// collected and committed together, or dropped if the operation fails.
+    fn atomically<R>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<R, FsError>,
+    ) -> Result<R, FsError> {
+        if !self.is_journaled() {
+            return operation(self);
+        }
+        self.transaction = Some(BTreeMap::new());
+        let result = operation(self);
+        let blocks = self.transaction.take().unwrap_or_default();
+        let value = result?;
+        journal::commit_transaction(&mut self.device, &self.superblock, &blocks)?;
+        Ok(value)
+    }
+
+    pub(crate) fn read_raw(&mut self, block: u32) -> Result<Vec<u8>, FsError> {
+        if block >= self.superblock.block_count {
+            return Err(FsError::Corrupt);
+        }
+        if let Some(data) = self.transaction.as_ref().and_then(|t| t.get(&block)) {
+            return Ok(data.clone());
+        }
+        let mut data = vec![0; self.block_size()];
+        self.device.read_block(u64::from(block), &mut data)?;
+        Ok(data)
+    }
+
+    /// Writes a metadata block, through the current transaction if there is one.
+    pub(crate) fn write_raw(&mut self, block: u32, data: &[u8]) -> Result<(), FsError> {
+        if let Some(transaction) = self.transaction.as_mut() {
+            if block >= self.superblock.block_count {
+                return Err(FsError::Corrupt);
+            }
+            transaction.insert(block, data.to_vec());
+            return Ok(());
+        }
+        self.write_data(block, data)
+    }
+
+    /// Writes a block in place, bypassing the journal. Used for file contents and for
+    /// zeroing blocks that are still free on disk.
+    fn write_data(&mut self, block: u32, data: &[u8]) -> Result<(), FsError> {
+        if block >= self.superblock.block_count {
+            return Err(FsError::Corrupt);
+        }
+        Ok(self.device.write_block(u64::from(block), data)?)
+    }
+
+    fn bitmap_position(&self, block: u32) -> (u32, usize, u8) {
+        let bits_per_block = self.superblock.block_size * 8;
+        let bit = block % bits_per_block;
+        (self.superblock.bitmap_start + block / bits_per_block, bit as usize / 8, 1 << (bit % 8))
+    }
+
+    pub(crate) fn is_block_used(&mut self, block: u32) -> Result<bool, FsError> {
+        let (bitmap_block, byte, mask) = self.bitmap_position(block);
+        Ok(self.read_raw(bitmap_block)?[byte] & mask != 0)
+    }
+
+    pub(crate) fn set_block_used(&mut self, block: u32, used: bool) -> Result<(), FsError> {
+        let (bitmap_block, byte, mask) = self.bitmap_position(block);
+        let mut bitmap = self.read_raw(bitmap_block)?;
+        if used {
+            bitmap[byte] |= mask;
+        } else {
+            bitmap[byte] &= !mask;
+        }
+        self.write_raw(bitmap_block, &bitmap)
+    }
+
+    /// Allocates a zero-filled data block.
+    fn allocate_block(&mut self) -> Result<u32, FsError> {
+        let sb = self.superblock;
+        let bits_per_block = sb.block_size * 8;
+        for i in 0..sb.bitmap_blocks {
+            let bitmap = self.read_raw(sb.bitmap_start + i)?;
+            let Some(byte) = bitmap.iter().position(|&b| b != 0xFF) else {
+                continue;
+            };
+            let block = i * bits_per_block + byte as u32 * 8 + bitmap[byte].trailing_ones();
+            if block >= sb.block_count {
+                break;
+            }
+            self.set_block_used(block, true)?;
+            self.write_data(block, &vec![0; self.block_size()])?;
+            return Ok(block);
+        }
+        Err(FsError::NoSpace)
+    }
+
+    fn inode_position(&self, inode: u32) -> Result<(u32, usize), FsError> {
+        if inode == 0 || inode > self.superblock.inode_count {
+            return Err(FsError::Corrupt);
+        }
+        let index = inode - 1;
+        let per_block = self.superblock.inodes_per_block();
+        Ok((
+            self.superblock.inode_start + index / per_block,
+            (index % per_block) as usize * INODE_SIZE,
+        ))
+    }
+
+    pub(crate) fn read_inode(&mut self, inode: u32) -> Result<Inode, FsError> {
+        let (block, offset) = self.inode_position(inode)?;
+        Inode::decode(&self.read_raw(block)?[offset..offset + INODE_SIZE])
+    }
+
+    pub(crate) fn write_inode(&mut self, inode: u32, node: &Inode) -> Result<(), FsError> {
+        let (block, offset) = self.inode_position(inode)?;
+        let mut data = self.read_raw(block)?;
+        node.encode(&mut data[offset..offset + INODE_SIZE]);
+        self.write_raw(block, &data)
+    }
+
+    fn allocate_inode(&mut self, kind: FileKind) -> Result<u32, FsError> {
+        for inode in 1..=self.superblock.inode_count {
+            if self.read_inode(inode)?.kind.is_none() {
+                self.write_inode(inode, &Inode::new(kind))?;
+                return Ok(inode);
+            }
+        }
+        Err(FsError::NoInodes)
+    }
+
+    /// Frees `inode` and its blocks after a create failed part way, so that a volume
+    /// without a journal does not leak them.
+    fn discard_inode(&mut self, inode: u32) -> Result<(), FsError> {
+        let mut node = self.read_inode(inode)?;
+        self.truncate(&mut node)?;
+        self.write_inode(inode, &Inode::default())
+    }
+
+    /// Returns the data block holding block `index` of the file, allocating it (and the
+    /// indirect block) if `allocate` is set. Unallocated blocks read as zeros.
+    fn block_map(
+        &mut self,
+        node: &mut Inode,
+        index: usize,
+        allocate: bool,
+    ) -> Result<Option<u32>, FsError> {
+        if index < DIRECT_BLOCKS {
+            if node.direct[index] == 0 && allocate {
+                node.direct[index] = self.allocate_block()?;
+            }
+            return Ok((node.direct[index] != 0).then_some(node.direct[index]));
+        }
+
+        let index = index - DIRECT_BLOCKS;
+        if index >= self.superblock.pointers_per_block() {
+            return if allocate { Err(FsError::FileTooLarge) } else { Ok(None) };
+        }
+        if node.indirect == 0 {
+            if !allocate {
+                return Ok(None);
+            }
+            node.indirect = self.allocate_block()?;
+        }
+        let mut pointers = self.read_raw(node.indirect)?;
+        let mut block = get_u32(&pointers, index * 4);
+        if block == 0 && allocate {
+            block = self.allocate_block()?;
+            put_u32(&mut pointers, index * 4, block);
+            self.write_raw(node.indirect, &pointers)?;
+        }
+        Ok((block != 0).then_some(block))
+    }
+
+    /// Data blocks of a file, including the indirect block, in file order.
+    pub(crate) fn file_blocks(&mut self, node: &Inode) -> Result<Vec<u32>, FsError> {
+        let mut blocks: Vec<u32> = node.direct.iter().copied().filter(|&b| b != 0).collect();
+        if node.indirect != 0 {
+            let pointers = self.read_raw(node.indirect)?;
+            blocks.extend(pointers.chunks_exact(4).map(|p| get_u32(p, 0)).filter(|&b| b != 0));
+            blocks.push(node.indirect);
+        }
+        Ok(blocks)
+    }
+
+    fn truncate(&mut self, node: &mut Inode) -> Result<(), FsError> {
+        for block in self.file_blocks(node)? {
+            self.set_block_used(block, false)?;
+        }
+        node.direct = [0; DIRECT_BLOCKS];
+        node.indirect = 0;
+        node.size = 0;
+        Ok(())
+    }
+
+    fn read_at(&mut self, node: &Inode, offset: u64, buffer: &mut [u8]) -> Result<usize, FsError> {
+        let size = u64::from(node.size);
+        if offset >= size {
+            return Ok(0);
+        }
+        let count = buffer.len().min((size - offset) as usize);
+        let block_size = self.block_size() as u64;
+        let mut node = *node;
+        let mut done = 0;
+        while done < count {
+            let position = offset + done as u64;
+            let within = (position % block_size) as usize;
+            let chunk = (count - done).min(block_size as usize - within);
+            let target = &mut buffer[done..done + chunk];
+            match self.block_map(&mut node, (position / block_size) as usize, false)? {
+                Some(block) => {
+                    target.copy_from_slice(&self.read_raw(block)?[within..within + chunk])
+                }
+                None => target.fill(0),
+            }
+            done += chunk;
+        }
+        Ok(count)
+    }
+
+    fn write_at(&mut self, node: &mut Inode, offset: u64, data: &[u8]) -> Result<(), FsError> {
+        let end = offset.checked_add(data.len() as u64);
+        if end.is_none_or(|end| end > self.superblock.max_file_size()) {
+            return Err(FsError::FileTooLarge);
+        }
+        let block_size = self.block_size() as u64;
+        let mut done = 0;
+        while done < data.len() {
+            let position = offset + done as u64;
+            let within = (position % block_size) as usize;
+            let chunk = (data.len() - done).min(block_size as usize - within);
+            let block = self
+                .block_map(node, (position / block_size) as usize, true)?
+                .ok_or(FsError::Corrupt)?;
+            let mut contents = self.read_raw(block)?;
+            contents[within..within + chunk].copy_from_slice(&data[done..done + chunk]);
+            if node.kind == Some(FileKind::File) {
+                self.write_data(block, &contents)?;
+            } else {
+                self.write_raw(block, &contents)?;
+            }
+            done += chunk;
+            // Grown chunk by chunk, so that a failure leaves the size covering what was
+            // written.
+            // Below `max_file_size`, so it fits.
+            node.size = node.size.max((position + chunk as u64) as u32);
+        }
+        Ok(())
+    }
+
+    /// Live entries of a directory as `(byte offset, inode, name)`.
+    pub(crate) fn entries(&mut self, node: &Inode) -> Result<Vec<(u64, u32, String)>, FsError> {
+        let mut contents = vec![0; node.size as usize];
+        self.read_at(node, 0, &mut contents)?;
+        let mut entries = Vec::new();
+        for (i, raw) in contents.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+            let inode = get_u32(raw, 0);
+            if inode == 0 {
+                continue;
+            }
+            let name = &raw[4..];
+            let length = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            let name = String::from_utf8(name[..length].to_vec()).map_err(|_| FsError::Corrupt)?;
+            entries.push(((i * DIR_ENTRY_SIZE) as u64, inode, name));
+        }
+        Ok(entries)
+    }
+
+    fn lookup(&mut self, directory: u32, name: &str) -> Result<Option<u32>, FsError> {
+        let node = self.read_inode(directory)?;
+        if node.kind != Some(FileKind::Directory) {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(self.entries(&node)?.into_iter().find(|(_, _, n)| n == name).map(|(_, inode, _)| inode))
+    }
+
+    pub(crate) fn add_entry(
+        &mut self,
+        directory: u32,
+        name: &str,
+        inode: u32,
+    ) -> Result<(), FsError> {
+        let mut node = self.read_inode(directory)?;
+        let mut contents = vec![0; node.size as usize];
+        self.read_at(&node, 0, &mut contents)?;
+        let slot = contents
+            .chunks_exact(DIR_ENTRY_SIZE)
+            .position(|raw| get_u32(raw, 0) == 0)
+            .unwrap_or(contents.len() / DIR_ENTRY_SIZE);
+
+        let mut entry = [0; DIR_ENTRY_SIZE];
+        put_u32(&mut entry, 0, inode);
+        entry[4..4 + name.len()].copy_from_slice(name.as_bytes());
+        let written = self.write_at(&mut node, (slot * DIR_ENTRY_SIZE) as u64, &entry);
+        self.write_inode(directory, &node)?;
+        written
+    }
+
+    pub(crate) fn remove_entry(&mut self, directory: u32, name: &str) -> Result<(), FsError> {
+        let mut node = self.read_inode(directory)?;
+        let (offset, _, _) = self
+            .entries(&node)?
+            .into_iter()
+            .find(|(_, _, n)| n == name)
+            .ok_or(FsError::NotFound)?;
+        self.write_at(&mut node, offset, &[0; DIR_ENTRY_SIZE])?;
+        self.write_inode(directory, &node)
+    }
+
+    /// Writes `.` and `..` into a freshly allocated directory.
+    fn init_directory(&mut self, inode: u32, parent: u32) -> Result<(), FsError> {
+        let mut node = self.read_inode(inode)?;
+        node.links = 2;
+        self.write_inode(inode, &node)?;
+        self.add_entry(inode, ".", inode)?;
+        self.add_entry(inode, "..", parent)
+    }
+
+    fn resolve(&mut self, path: &str) -> Result<u32, FsError> {
+        let rest = path.strip_prefix('/').ok_or(FsError::InvalidPath)?;
+        let mut inode = ROOT_INODE;
+        for name in rest.split('/').filter(|c| !c.is_empty()) {
+            inode = self.lookup(inode, name)?.ok_or(FsError::NotFound)?;
+        }
+        Ok(inode)
+    }
+
+    /// Splits `path` into its parent directory's inode and its final component.
+    fn resolve_parent<'p>(&mut self, path: &'p str) -> Result<(u32, &'p str), FsError> {
+        let trimmed = path.trim_end_matches('/');
+        let (parent, name) = trimmed.rsplit_once('/').ok_or(FsError::InvalidPath)?;
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(FsError::InvalidPath);
+        }
+        if name.len() > MAX_NAME_LEN {
+            return Err(FsError::NameTooLong);
+        }
+        let parent = self.resolve(if parent.is_empty() { "/" } else { parent })?;
+        Ok((parent, name))
+    }
+}
+
+pub(crate) fn get_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn put_u32(bytes: &mut [u8], offset: usize, value: u32) {
+    bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_device::MemBlockDevice;
+
+    fn fresh() -> FileSystem<MemBlockDevice> {
+        mkfs(MemBlockDevice::new(512, 256).unwrap(), MkfsOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_mkfs_layout_and_remount() {
+        let fs = fresh();
+        let sb = fs.superblock();
+        assert_eq!((sb.bitmap_start, sb.bitmap_blocks, sb.inode_count), (1, 1, 64));
+        assert_eq!(sb.data_start, 2 + 8);
+
+        let mut fs = FileSystem::mount(fs.unmount().unwrap()).unwrap();
+        assert_eq!(fs.superblock(), sb);
+        assert_eq!(fs.stat("/").unwrap().kind, FileKind::Directory);
+        assert_eq!(fs.read_dir("/").unwrap(), vec![]);
+
+        let blank = MemBlockDevice::new(512, 256).unwrap();
+        assert_eq!(FileSystem::mount(blank).err(), Some(FsError::NotFormatted));
+    }
+
+    #[test]
+    fn test_write_read_across_indirect_blocks() {
+        let mut fs = fresh();
+        let data: Vec<u8> = (0..20 * 512).map(|i| (i % 251) as u8).collect();
+        let fd = fs.open("/big", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        assert_eq!(fs.write(fd, &data), Ok(data.len()));
+        fs.close(fd).unwrap();
+
+        let fd = fs.open("/big", OpenFlags::READ).unwrap();
+        let mut back = vec![0; data.len() + 10];
+        assert_eq!(fs.read(fd, &mut back), Ok(data.len()));
+        assert_eq!(&back[..data.len()], &data[..]);
+        assert_eq!(fs.read(fd, &mut back), Ok(0));
+        assert_eq!(fs.write(fd, b"x"), Err(FsError::AccessDenied));
+
+        let before = fs.statfs().unwrap().free_blocks;
+        fs.close(fd).unwrap();
+        fs.unlink("/big").unwrap();
+        // 20 data blocks plus the indirect block come back.
+        assert_eq!(fs.statfs().unwrap().free_blocks, before + 21);
+    }
+
+    #[test]
+    fn test_directories_and_paths() {
+        let mut fs = fresh();
+        fs.mkdir("/a").unwrap();
+        fs.mkdir("/a/b").unwrap();
+        let fd = fs.open("/a/b/c.txt", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        fs.close(fd).unwrap();
+
+        assert_eq!(fs.stat("/a").unwrap().links, 3);
+        assert_eq!(fs.stat("/a/b/../b/./c.txt").unwrap().kind, FileKind::File);
+        assert_eq!(fs.mkdir("/a/b"), Err(FsError::AlreadyExists));
+        assert_eq!(fs.rmdir("/a/b"), Err(FsError::DirectoryNotEmpty));
+        assert_eq!(fs.open("/a", OpenFlags::READ), Err(FsError::IsADirectory));
+        assert_eq!(fs.open("/a/b/c.txt/d", OpenFlags::READ), Err(FsError::NotADirectory));
+        assert_eq!(fs.open("relative", OpenFlags::READ), Err(FsError::InvalidPath));
+        assert_eq!(
+            fs.mkdir(&format!("/{}", "n".repeat(MAX_NAME_LEN + 1))),
+            Err(FsError::NameTooLong)
+        );
+
+        fs.unlink("/a/b/c.txt").unwrap();
+        fs.rmdir("/a/b").unwrap();
+        assert_eq!(fs.stat("/a").unwrap().links, 2);
+        let names: Vec<String> = fs.read_dir("/").unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn test_open_flags() {
+        let mut fs = fresh();
+        assert_eq!(fs.open("/f", OpenFlags::READ), Err(FsError::NotFound));
+        let fd = fs.open("/f", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        fs.write(fd, b"hello").unwrap();
+        assert_eq!(fs.unlink("/f"), Err(FsError::Busy));
+        fs.close(fd).unwrap();
+        assert_eq!(fs.close(fd), Err(FsError::BadDescriptor));
+
+        let fd = fs.open("/f", OpenFlags::WRITE | OpenFlags::APPEND).unwrap();
+        fs.write(fd, b" world").unwrap();
+        fs.close(fd).unwrap();
+        assert_eq!(fs.stat("/f").unwrap().size, 11);
+
+        let fd = fs.open("/f", OpenFlags::WRITE | OpenFlags::TRUNCATE).unwrap();
+        assert_eq!(fs.stat("/f").unwrap().size, 0);
+        fs.seek(fd, 600).unwrap();
+        fs.write(fd, b"!").unwrap();
+        fs.close(fd).unwrap();
+
+        let fd = fs.open("/f", OpenFlags::READ).unwrap();
+        let mut buffer = [1; 601];
+        assert_eq!(fs.read(fd, &mut buffer), Ok(601));
+        assert!(buffer[..600].iter().all(|&b| b == 0));
+        assert_eq!(buffer[600], b'!');
+    }
+
+    #[test]
+    fn test_running_out_of_space_leaks_nothing() {
+        let device = MemBlockDevice::new(512, 64).unwrap();
+        let mut fs = mkfs(device, MkfsOptions::default()).unwrap();
+        let fd = fs.open("/big", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        assert_eq!(fs.write(fd, &vec![7; 100 * 512]), Err(FsError::NoSpace));
+        fs.close(fd).unwrap();
+        // The blocks filled before the volume ran out stay in the file.
+        let size = fs.stat("/big").unwrap().size;
+        assert!(size > 0 && size.is_multiple_of(512));
+        assert_eq!(fs.statfs().unwrap().free_blocks, 0);
+
+        // Creates that need a new block give their inode back.
+        let free_inodes = fs.statfs().unwrap().free_inodes;
+        assert_eq!(fs.mkdir("/d"), Err(FsError::NoSpace));
+        for i in 0..13 {
+            let fd = fs.open(&format!("/f{i}"), OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+            fs.close(fd).unwrap();
+        }
+        let free_inodes = free_inodes - 13;
+        assert_eq!(fs.open("/g", OpenFlags::WRITE | OpenFlags::CREATE), Err(FsError::NoSpace));
+        assert_eq!(fs.statfs().unwrap().free_inodes, free_inodes);
+        assert!(crate::fsck::fsck(&mut fs).unwrap().is_clean());
+    }
+
+    /// Claims more blocks than a volume can address; never read or written.
+    struct Huge;
+
+    impl BlockDevice for Huge {
+        fn block_size(&self) -> usize {
+            512
+        }
+
+        fn block_count(&self) -> u64 {
+            u64::from(u32::MAX) + 1
+        }
+
+        fn read_block(&mut self, _block: u64, _buffer: &mut [u8]) -> Result<(), BlockError> {
+            unreachable!()
+        }
+
+        fn write_block(&mut self, _block: u64, _buffer: &[u8]) -> Result<(), BlockError> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_sizes_beyond_32_bits_are_refused() {
+        assert_eq!(mkfs(Huge, MkfsOptions::default()).err(), Some(FsError::DeviceTooLarge));
+
+        let mut fs = mkfs(MemBlockDevice::new(512, 64).unwrap(), MkfsOptions::default()).unwrap();
+        let fd = fs.open("/f", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        for offset in [u64::from(u32::MAX), u64::MAX] {
+            fs.seek(fd, offset).unwrap();
+            assert_eq!(fs.write(fd, b"x"), Err(FsError::FileTooLarge));
+        }
+        assert_eq!(fs.stat("/f").unwrap().size, 0);
+    }
+}
@@ -0,0 +1,370 @@
+// src/fsck.rs
+
+use core::fmt;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::block_device::BlockDevice;
+use crate::fs::{FileKind, FileSystem, FsError, Inode, ROOT_INODE, get_u32, put_u32};
+
+/// An inconsistency found by [`fsck`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckProblem {
+    /// The inode's kind field holds an unknown value.
+    BadInode { inode: u32 },
+    /// A directory entry names an inode that is free or out of range.
+    DanglingEntry { directory: u32, name: String, inode: u32 },
+    /// A directory's `.` or `..` entry is missing or does not point at the directory and
+    /// `parent`, the directory it was reached from.
+    BadDotEntry { directory: u32, parent: u32 },
+    /// A file refers to a block outside the data region.
+    BlockOutOfRange { inode: u32, block: u32 },
+    /// A block is referred to more than once; `inode` is the second owner found.
+    DuplicateBlock { inode: u32, block: u32 },
+    /// A block in use is marked free in the bitmap.
+    UnmarkedBlock { block: u32 },
+    /// A block marked in use belongs to no file.
+    LeakedBlock { block: u32 },
+    /// An inode's link count differs from the number of entries naming it.
+    WrongLinkCount { inode: u32, recorded: u16, actual: u16 },
+    /// An allocated inode is not reachable from the root.
+    OrphanInode { inode: u32 },
+}
+
+impl fmt::Display for FsckProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsckProblem::BadInode { inode } => write!(f, "inode {inode}: unknown kind"),
+            FsckProblem::DanglingEntry { directory, name, inode } => {
+                write!(f, "directory {directory}: entry {name:?} names free inode {inode}")
+            }
+            FsckProblem::BadDotEntry { directory, .. } => {
+                write!(f, "directory {directory}: bad '.' or '..' entry")
+            }
+            FsckProblem::BlockOutOfRange { inode, block } => {
+                write!(f, "inode {inode}: block {block} out of range")
+            }
+            FsckProblem::DuplicateBlock { inode, block } => {
+                write!(f, "inode {inode}: block {block} already in use")
+            }
+            FsckProblem::UnmarkedBlock { block } => write!(f, "block {block}: in use but free"),
+            FsckProblem::LeakedBlock { block } => write!(f, "block {block}: marked but unused"),
+            FsckProblem::WrongLinkCount { inode, recorded, actual } => {
+                write!(f, "inode {inode}: link count {recorded}, should be {actual}")
+            }
+            FsckProblem::OrphanInode { inode } => write!(f, "inode {inode}: unreachable"),
+        }
+    }
+}
+
+/// Result of a consistency check.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FsckReport {
+    /// Every inconsistency found, in the order found.
+    pub problems: Vec<FsckProblem>,
+}
+
+impl FsckReport {
+    /// Returns `true` if no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Checks the metadata of a mounted volume without changing it.
+///
+/// The check walks the directory tree from the root and compares what it finds against
+/// the block bitmap and the inode table: every reachable block must lie in the data
+/// region, belong to one file and be marked in use; every marked block must belong to a
+/// file; link counts must match the entries naming each inode; and every allocated inode
+/// must be reachable.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut fs = rusty_threads::FileSystem::mount(disk)?;
+/// let report = rusty_threads::fsck(&mut fs)?;
+/// for problem in &report.problems {
+///     rusty_threads::console_output(false, &format!("fsck: {problem}\n"));
+/// }
+/// ```
+pub fn fsck<D: BlockDevice>(fs: &mut FileSystem<D>) -> Result<FsckReport, FsError> {
+    let sb = fs.superblock();
+    let mut problems = Vec::new();
+    let mut owners: BTreeMap<u32, u32> = BTreeMap::new();
+    let mut references: BTreeMap<u32, u16> = BTreeMap::new();
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![(ROOT_INODE, ROOT_INODE)];
+
+    while let Some((directory, parent)) = pending.pop() {
+        if !visited.insert(directory) {
+            continue;
+        }
+        let Some(node) = inode(fs, directory, &mut problems)? else {
+            continue;
+        };
+        if !claim_blocks(fs, directory, &node, &mut owners, &mut problems)? {
+            continue;
+        }
+        if node.kind != Some(FileKind::Directory) {
+            continue;
+        }
+
+        let entries = fs.entries(&node)?;
+        let dot = entries.iter().find(|(_, _, name)| name == ".").map(|e| e.1);
+        let dot_dot = entries.iter().find(|(_, _, name)| name == "..").map(|e| e.1);
+        if dot != Some(directory) || dot_dot != Some(parent) {
+            problems.push(FsckProblem::BadDotEntry { directory, parent });
+        }
+        for (_, child, name) in entries {
+            let live = child >= 1
+                && child <= sb.inode_count
+                && inode(fs, child, &mut problems)?.is_some_and(|n| n.kind.is_some());
+            if !live {
+                problems.push(FsckProblem::DanglingEntry { directory, name, inode: child });
+                continue;
+            }
+            *references.entry(child).or_default() += 1;
+            if name != "." && name != ".." {
+                pending.push((child, directory));
+            }
+        }
+    }
+
+    for number in 1..=sb.inode_count {
+        let Ok(node) = fs.read_inode(number) else {
+            continue;
+        };
+        if node.kind.is_none() {
+            continue;
+        }
+        if !visited.contains(&number) {
+            problems.push(FsckProblem::OrphanInode { inode: number });
+            continue;
+        }
+        let actual = references.get(&number).copied().unwrap_or(0);
+        if node.links != actual {
+            problems.push(FsckProblem::WrongLinkCount {
+                inode: number,
+                recorded: node.links,
+                actual,
+            });
+        }
+    }
+
+    for block in 0..sb.block_count {
+        let used = fs.is_block_used(block)?;
+        let owned = block < sb.data_start || owners.contains_key(&block);
+        if owned && !used {
+            problems.push(FsckProblem::UnmarkedBlock { block });
+        } else if used && !owned {
+            problems.push(FsckProblem::LeakedBlock { block });
+        }
+    }
+    Ok(FsckReport { problems })
+}
+
+/// Passes [`repair`] makes before giving up; each pass fixes what the last one uncovered.
+const REPAIR_PASSES: usize = 8;
+
+/// Checks a mounted volume and fixes what [`fsck`] finds, until a check comes back clean.
+///
+/// Repairs favour consistency over contents, the way a real fsck does after a crash:
+///
+/// * undecodable and unreachable inodes are released, and their blocks freed;
+/// * entries naming free inodes are removed, and `.` and `..` are rewritten;
+/// * pointers to out-of-range blocks, and the second claim on a shared block, are cleared;
+/// * link counts and the block bitmap are rewritten to match the directory tree.
+///
+/// # Returns
+///
+/// Every problem fixed, in the order found, or `FsError::Corrupt` if the volume is still
+/// inconsistent after repeated passes.
+pub fn repair<D: BlockDevice>(fs: &mut FileSystem<D>) -> Result<FsckReport, FsError> {
+    let mut fixed = Vec::new();
+    for _ in 0..REPAIR_PASSES {
+        let report = fsck(fs)?;
+        if report.is_clean() {
+            fs.sync()?;
+            return Ok(FsckReport { problems: fixed });
+        }
+        for problem in &report.problems {
+            fix(fs, problem)?;
+        }
+        fixed.extend(report.problems);
+    }
+    Err(FsError::Corrupt)
+}
+
+fn fix<D: BlockDevice>(fs: &mut FileSystem<D>, problem: &FsckProblem) -> Result<(), FsError> {
+    match *problem {
+        FsckProblem::BadInode { inode } | FsckProblem::OrphanInode { inode } => {
+            fs.write_inode(inode, &Inode::default())
+        }
+        FsckProblem::DanglingEntry { directory, ref name, .. } => {
+            if name == "." || name == ".." {
+                return Ok(());
+            }
+            fs.remove_entry(directory, name)
+        }
+        FsckProblem::BadDotEntry { directory, parent } => {
+            for (name, target) in [(".", directory), ("..", parent)] {
+                while fs.remove_entry(directory, name).is_ok() {}
+                fs.add_entry(directory, name, target)?;
+            }
+            Ok(())
+        }
+        FsckProblem::BlockOutOfRange { inode, block }
+        | FsckProblem::DuplicateBlock { inode, block } => clear_pointer(fs, inode, block),
+        FsckProblem::UnmarkedBlock { block } => fs.set_block_used(block, true),
+        FsckProblem::LeakedBlock { block } => fs.set_block_used(block, false),
+        FsckProblem::WrongLinkCount { inode, actual, .. } => {
+            let mut node = fs.read_inode(inode)?;
+            node.links = actual;
+            fs.write_inode(inode, &node)
+        }
+    }
+}
+
+/// Removes every pointer to `block` from inode `inode`, including its indirect block.
+fn clear_pointer<D: BlockDevice>(
+    fs: &mut FileSystem<D>,
+    inode: u32,
+    block: u32,
+) -> Result<(), FsError> {
+    let sb = fs.superblock();
+    let mut node = fs.read_inode(inode)?;
+    for pointer in node.direct.iter_mut().filter(|p| **p == block) {
+        *pointer = 0;
+    }
+    if node.indirect == block {
+        node.indirect = 0;
+    } else if node.indirect >= sb.data_start && node.indirect < sb.block_count {
+        let mut pointers = fs.read_raw(node.indirect)?;
+        for i in 0..pointers.len() / 4 {
+            if get_u32(&pointers, i * 4) == block {
+                put_u32(&mut pointers, i * 4, 0);
+            }
+        }
+        fs.write_raw(node.indirect, &pointers)?;
+    }
+    fs.write_inode(inode, &node)
+}
+
+/// Reads an inode, recording a problem instead of failing if it cannot be decoded.
+fn inode<D: BlockDevice>(
+    fs: &mut FileSystem<D>,
+    number: u32,
+    problems: &mut Vec<FsckProblem>,
+) -> Result<Option<Inode>, FsError> {
+    match fs.read_inode(number) {
+        Ok(node) => Ok(Some(node)),
+        Err(FsError::Corrupt) => {
+            let problem = FsckProblem::BadInode { inode: number };
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+            Ok(None)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Records the blocks of `node` as owned by `number`. Returns `false` if the inode's
+/// block pointers cannot be followed safely.
+fn claim_blocks<D: BlockDevice>(
+    fs: &mut FileSystem<D>,
+    number: u32,
+    node: &Inode,
+    owners: &mut BTreeMap<u32, u32>,
+    problems: &mut Vec<FsckProblem>,
+) -> Result<bool, FsError> {
+    let sb = fs.superblock();
+    let in_range = |block: u32| block >= sb.data_start && block < sb.block_count;
+    let mut blocks: Vec<u32> = node.direct.iter().copied().filter(|&b| b != 0).collect();
+    let mut sound = true;
+    if node.indirect != 0 {
+        if in_range(node.indirect) {
+            let pointers = fs.read_raw(node.indirect)?;
+            blocks.extend(pointers.chunks_exact(4).map(|p| get_u32(p, 0)).filter(|&b| b != 0));
+        }
+        blocks.push(node.indirect);
+    }
+    for block in blocks {
+        if !in_range(block) {
+            problems.push(FsckProblem::BlockOutOfRange { inode: number, block });
+            sound = false;
+        } else if owners.insert(block, number).is_some() {
+            problems.push(FsckProblem::DuplicateBlock { inode: number, block });
+        }
+    }
+    Ok(sound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_device::MemBlockDevice;
+    use crate::fs::{MkfsOptions, OpenFlags, mkfs};
+
+    #[test]
+    fn test_detects_leaks_orphans_and_link_counts() {
+        let mut fs = mkfs(MemBlockDevice::new(512, 128).unwrap(), MkfsOptions::default()).unwrap();
+        fs.mkdir("/d").unwrap();
+        let fd = fs.open("/d/f", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        fs.write(fd, &[5; 700]).unwrap();
+        fs.close(fd).unwrap();
+        assert!(fsck(&mut fs).unwrap().is_clean());
+
+        let file = fs.stat("/d/f").unwrap().inode;
+        let dir = fs.stat("/d").unwrap().inode;
+        let spare = fs.superblock().block_count - 1;
+        fs.set_block_used(spare, true).unwrap();
+        let mut node = fs.read_inode(dir).unwrap();
+        node.links = 7;
+        fs.write_inode(dir, &node).unwrap();
+        // Hide the file by blanking its directory entry's inode number.
+        let block = node.direct[0];
+        let mut contents = fs.read_raw(block).unwrap();
+        contents[64..68].fill(0);
+        fs.write_raw(block, &contents).unwrap();
+
+        let problems = fsck(&mut fs).unwrap().problems;
+        assert!(problems.contains(&FsckProblem::LeakedBlock { block: spare }));
+        assert!(problems.contains(&FsckProblem::OrphanInode { inode: file }));
+        assert!(problems.contains(&FsckProblem::WrongLinkCount {
+            inode: dir,
+            recorded: 7,
+            actual: 2,
+        }));
+    }
+
+    #[test]
+    fn test_repair_restores_consistency() {
+        let mut fs = mkfs(MemBlockDevice::new(512, 128).unwrap(), MkfsOptions::default()).unwrap();
+        fs.mkdir("/d").unwrap();
+        let fd = fs.open("/d/f", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        fs.write(fd, &[5; 1500]).unwrap();
+        fs.close(fd).unwrap();
+        let fd = fs.open("/g", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+        fs.close(fd).unwrap();
+
+        let f = fs.stat("/d/f").unwrap().inode;
+        let g = fs.stat("/g").unwrap().inode;
+        let mut node = fs.read_inode(f).unwrap();
+        let shared = node.direct[0];
+        node.direct[1] = 9999;
+        fs.write_inode(f, &node).unwrap();
+        let mut other = fs.read_inode(g).unwrap();
+        other.direct[0] = shared;
+        other.size = 512;
+        fs.write_inode(g, &other).unwrap();
+        fs.set_block_used(node.direct[2], false).unwrap();
+
+        let fixed = repair(&mut fs).unwrap().problems;
+        assert!(fixed.contains(&FsckProblem::BlockOutOfRange { inode: f, block: 9999 }));
+        assert!(fixed.iter().any(|p| matches!(p, FsckProblem::DuplicateBlock { .. })));
+        assert!(fixed.contains(&FsckProblem::UnmarkedBlock { block: node.direct[2] }));
+        assert!(fsck(&mut fs).unwrap().is_clean());
+        assert!(repair(&mut fs).unwrap().is_clean());
+    }
+}
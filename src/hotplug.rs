@@ -0,0 +1,239 @@
+// src/hotplug.rs
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use crate::constants::{THREADS_MAX_DISKS, THREADS_MAX_TERMINALS};
+use crate::device::{self, DeviceId, DeviceKind};
+use crate::rusty_wrapper::device_handle;
+
+/// Interrupt status a driver receives when its device is unplugged. Requests in flight
+/// on the device fail.
+pub const DEVICE_STATUS_REMOVED: u32 = 0x300;
+
+/// Interrupt status a driver receives when its device is plugged back in.
+pub const DEVICE_STATUS_ATTACHED: u32 = 0x301;
+
+/// Command code of hotplug interrupts; no device command uses it.
+pub const HOTPLUG_COMMAND: u8 = 0;
+
+const DISKS: usize = THREADS_MAX_DISKS as usize;
+const SLOTS: usize = DISKS + THREADS_MAX_TERMINALS as usize;
+
+/// Errors returned by hotplug calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotplugError {
+    /// Only disks and terminals can be plugged and unplugged.
+    NotHotpluggable,
+    /// The device is plugged in already.
+    Attached,
+    /// The device is unplugged already.
+    Detached,
+    /// The device was unplugged since the handle was opened.
+    Stale,
+}
+
+/// Which disks and terminals are plugged in, and the hotplug events not yet raised.
+///
+/// Kernel code reads it from `device_control`, possibly inside an interrupt handler, so
+/// the plug state is atomics rather than a lock; events are taken with `try_lock` on the
+/// clock.
+struct PlugTable {
+    detached: [AtomicBool; SLOTS],
+    /// Bumped every time the device is unplugged.
+    generation: [AtomicU32; SLOTS],
+    events: Mutex<VecDeque<(DeviceId, u32)>>,
+}
+
+impl PlugTable {
+    const fn new() -> Self {
+        PlugTable {
+            detached: [const { AtomicBool::new(false) }; SLOTS],
+            generation: [const { AtomicU32::new(0) }; SLOTS],
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn post(&self, device: DeviceId, status: u32) {
+        self.events.lock().unwrap_or_else(|e| e.into_inner()).push_back((device, status));
+    }
+
+    /// See [`detach_device`].
+    fn detach(&self, device: DeviceId) -> Result<(), HotplugError> {
+        let slot = slot(device)?;
+        if self.detached[slot].swap(true, Ordering::SeqCst) {
+            return Err(HotplugError::Detached);
+        }
+        self.generation[slot].fetch_add(1, Ordering::SeqCst);
+        self.post(device, DEVICE_STATUS_REMOVED);
+        Ok(())
+    }
+
+    /// See [`attach_device`].
+    fn attach(&self, device: DeviceId) -> Result<(), HotplugError> {
+        let slot = slot(device)?;
+        if !self.detached[slot].swap(false, Ordering::SeqCst) {
+            return Err(HotplugError::Attached);
+        }
+        self.post(device, DEVICE_STATUS_ATTACHED);
+        Ok(())
+    }
+
+    fn is_detached(&self, slot: usize) -> bool {
+        self.detached[slot].load(Ordering::SeqCst)
+    }
+
+    fn generation(&self, slot: usize) -> u32 {
+        self.generation[slot].load(Ordering::SeqCst)
+    }
+
+    /// See [`DeviceHandle::get`].
+    fn check(&self, handle: &DeviceHandle) -> Result<u32, HotplugError> {
+        let Ok(slot) = slot(handle.device) else {
+            return Ok(handle.handle);
+        };
+        if self.generation(slot) != handle.generation || self.is_detached(slot) {
+            return Err(HotplugError::Stale);
+        }
+        Ok(handle.handle)
+    }
+
+    /// Takes the posted events, or nothing while a poster holds the lock.
+    fn take_events(&self) -> Vec<(DeviceId, u32)> {
+        match self.events.try_lock() {
+            Ok(mut events) => events.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+static PLUGS: PlugTable = PlugTable::new();
+
+fn slot(device: DeviceId) -> Result<usize, HotplugError> {
+    match device.kind {
+        DeviceKind::Disk if device.unit < THREADS_MAX_DISKS => Ok(device.unit as usize),
+        DeviceKind::Terminal if device.unit < THREADS_MAX_TERMINALS => {
+            Ok(DISKS + device.unit as usize)
+        }
+        _ => Err(HotplugError::NotHotpluggable),
+    }
+}
+
+/// Unplugs `device`.
+///
+/// From now on `device_initialize`, `device_handle` and `device_control` fail for it,
+/// and every [`DeviceHandle`] opened on it goes stale. On the next clock interrupt its
+/// driver receives a `THREADS_IO_INTERRUPT` with command [`HOTPLUG_COMMAND`] and status
+/// [`DEVICE_STATUS_REMOVED`]; the crate's disk and terminal drivers then fail the
+/// requests queued on it, and a disk request whose command is already with THREADS once
+/// that command completes. May be called from a process or from a host thread.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, DiskError};
+///
+/// let disk = DeviceId::disk(1);
+/// rusty_threads::detach_device(disk)?;
+/// // A process blocked in read_sectors on disk1 now gets Err(DiskError::Removed).
+/// rusty_threads::attach_device(disk)?;
+/// ```
+pub fn detach_device(device: DeviceId) -> Result<(), HotplugError> {
+    PLUGS.detach(device)
+}
+
+/// Plugs `device` back in after [`detach_device`].
+///
+/// Its driver receives a [`DEVICE_STATUS_ATTACHED`] interrupt on the next clock
+/// interrupt and initializes the device again. Handles opened before the device was
+/// unplugged stay stale.
+pub fn attach_device(device: DeviceId) -> Result<(), HotplugError> {
+    PLUGS.attach(device)
+}
+
+/// Returns `true` unless `device` is an unplugged disk or terminal.
+pub fn is_attached(device: DeviceId) -> bool {
+    slot(device).is_ok_and(|slot| !PLUGS.is_detached(slot))
+}
+
+/// Returns `true` if `name` is an unplugged disk or terminal.
+pub(crate) fn is_detached(name: &str) -> bool {
+    let builtin = name.starts_with("disk") || name.starts_with("term");
+    let slot = DeviceId::from_name(name).and_then(|d| slot(d).ok());
+    builtin && slot.is_some_and(|slot| PLUGS.is_detached(slot))
+}
+
+/// A device handle that notices when its device is unplugged.
+///
+/// THREADS handles are plain numbers that stay the same across a hotplug cycle, so a
+/// driver that caches one cannot tell that the device it names went away. A
+/// `DeviceHandle` also remembers the device's plug generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceHandle {
+    device: DeviceId,
+    handle: u32,
+    generation: u32,
+}
+
+impl DeviceHandle {
+    /// Looks up the handle of `device` with `device_handle`.
+    ///
+    /// # Returns
+    ///
+    /// `None` if THREADS has no handle for the device or the device is unplugged.
+    pub fn open(device: DeviceId) -> Option<Self> {
+        let generation = slot(device).map_or(0, |slot| PLUGS.generation(slot));
+        let handle = device_handle(&device.name())?;
+        Some(DeviceHandle { device, handle, generation })
+    }
+
+    /// The device the handle was opened on.
+    pub fn device(&self) -> DeviceId {
+        self.device
+    }
+
+    /// Returns the THREADS handle if the device has stayed plugged in since
+    /// [`DeviceHandle::open`].
+    pub fn get(&self) -> Result<u32, HotplugError> {
+        PLUGS.check(self)
+    }
+}
+
+/// Raises the hotplug interrupts posted since the last tick; called on every clock
+/// interrupt, with interrupts enabled again.
+pub(crate) fn on_clock() {
+    // While a process or host thread is posting, the events wait for the next tick.
+    for (device, status) in PLUGS.take_events() {
+        device::raise_io_interrupt(&device.name(), HOTPLUG_COMMAND, status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handles_go_stale_across_a_hotplug_cycle() {
+        let plugs = PlugTable::new();
+        let disk = DeviceId::disk(3);
+        assert_eq!(slot(DeviceId::terminal(1)), Ok(DISKS + 1));
+        assert_eq!(plugs.detach(DeviceId::clock()), Err(HotplugError::NotHotpluggable));
+        assert_eq!(plugs.attach(disk), Err(HotplugError::Attached));
+
+        let handle = DeviceHandle { device: disk, handle: 7, generation: plugs.generation(3) };
+        assert_eq!(plugs.check(&handle), Ok(7));
+        plugs.detach(disk).unwrap();
+        assert_eq!(plugs.detach(disk), Err(HotplugError::Detached));
+        assert!(plugs.is_detached(3) && !plugs.is_detached(2));
+        assert_eq!(plugs.check(&handle), Err(HotplugError::Stale));
+        plugs.attach(disk).unwrap();
+        assert_eq!(plugs.check(&handle), Err(HotplugError::Stale));
+        let handle = DeviceHandle { generation: plugs.generation(3), ..handle };
+        assert_eq!(plugs.check(&handle), Ok(7));
+
+        let posted = [(disk, DEVICE_STATUS_REMOVED), (disk, DEVICE_STATUS_ATTACHED)];
+        assert_eq!(plugs.take_events(), posted);
+        assert!(plugs.take_events().is_empty());
+    }
+}
@@ -0,0 +1,87 @@
+// src/interrupts.rs
+
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+
+use crate::constants::PSR_INTERRUPTS;
+use crate::rusty_wrapper::{get_psr, set_psr};
+
+/// Disables interrupts for as long as the guard is alive.
+///
+/// The guard captures the PSR on creation, clears `PSR_INTERRUPTS`, and writes the
+/// captured value back when dropped. Because the saved PSR lives on the stack of the
+/// process that created the guard, a guard held across a `context_switch()` restores the
+/// *blocked process's* interrupt state when that process resumes, not the state of
+/// whichever process happened to switch back to it.
+///
+/// Guards nest: an inner guard saves a PSR with interrupts already disabled and restores
+/// exactly that, leaving the outer guard in charge of re-enabling them.
+///
+/// # Example
+///
+/// ```ignore
+/// {
+///     let _guard = rusty_threads::InterruptGuard::new();
+///     // ... touch kernel data structures ...
+/// } // interrupts are restored here
+/// ```
+pub struct InterruptGuard {
+    saved_psr: u32,
+    // The guard restores the PSR of the process that created it, so it must not be sent
+    // to (or shared with) anything else.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl InterruptGuard {
+    /// Disables interrupts and returns a guard that restores the previous PSR on drop.
+    pub fn new() -> Self {
+        let saved_psr = get_psr();
+        set_psr(saved_psr & !PSR_INTERRUPTS);
+        InterruptGuard { saved_psr, _not_send: PhantomData }
+    }
+
+    /// Returns the PSR value that will be restored when the guard is dropped.
+    pub fn saved_psr(&self) -> u32 {
+        self.saved_psr
+    }
+}
+
+impl Default for InterruptGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        set_psr(self.saved_psr);
+    }
+}
+
+/// Kernel-global state that may only be touched with interrupts disabled.
+///
+/// THREADS runs every process on a single simulated CPU, so disabling interrupts is the
+/// kernel's only form of mutual exclusion. `KernelCell::with` demands an
+/// [`InterruptGuard`] as proof of that, and hands out the `&mut T` for the duration of a
+/// closure so the borrow can never be held across a `context_switch()`.
+pub(crate) struct KernelCell<T> {
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: access is serialized by disabling interrupts on the single simulated CPU.
+unsafe impl<T> Sync for KernelCell<T> {}
+
+impl<T> KernelCell<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        KernelCell { value: UnsafeCell::new(value) }
+    }
+
+    /// Runs `f` with exclusive access to the contained value.
+    ///
+    /// Callers must not re-enter `with` on the same cell from inside `f`.
+    pub(crate) fn with<R>(&self, _guard: &InterruptGuard, f: impl FnOnce(&mut T) -> R) -> R {
+        // SAFETY: interrupts are disabled (witnessed by `_guard`) and the borrow does not
+        // escape `f`, so no other reference to the value can exist concurrently.
+        unsafe { f(&mut *self.value.get()) }
+    }
+}
@@ -0,0 +1,581 @@
+// src/io_stats.rs
+
+use core::fmt;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::constants::{
+    DISK_INFO, DISK_READ, DISK_SEEK, DISK_WRITE, TERMINAL_READ_CHAR, TERMINAL_WRITE_CHAR,
+};
+use crate::device::{DeviceId, DeviceKind};
+use crate::hotplug::{DEVICE_STATUS_REMOVED, HOTPLUG_COMMAND};
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::rusty_wrapper::{console_output, system_clock};
+use crate::terminal::TERMINAL_HOST_INPUT;
+
+/// Number of buckets in a [`ServiceTimes`] histogram.
+///
+/// Bucket 0 counts service times under 2 us, bucket `i` those in `[2^i, 2^(i+1))` us,
+/// and the last bucket everything from `2^(SERVICE_TIME_BUCKETS - 1)` us up.
+pub const SERVICE_TIME_BUCKETS: usize = 24;
+
+/// Most queue depth changes kept in [`DeviceStats::queue_depths`].
+pub const QUEUE_DEPTH_HISTORY: usize = 64;
+
+/// Histogram of the time from issuing a command to its completion interrupt, in
+/// microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServiceTimes {
+    /// Recorded times per bucket, see [`SERVICE_TIME_BUCKETS`].
+    pub buckets: [u64; SERVICE_TIME_BUCKETS],
+    /// Number of recorded times.
+    pub count: u64,
+    /// Sum of all recorded times.
+    pub total: u64,
+    /// Longest recorded time.
+    pub max: u32,
+}
+
+impl ServiceTimes {
+    /// An empty histogram.
+    pub const fn new() -> Self {
+        ServiceTimes { buckets: [0; SERVICE_TIME_BUCKETS], count: 0, total: 0, max: 0 }
+    }
+
+    /// Returns the bucket that counts service time `time`.
+    pub fn bucket(time: u32) -> usize {
+        (time.max(1).ilog2() as usize).min(SERVICE_TIME_BUCKETS - 1)
+    }
+
+    /// Adds service time `time`.
+    pub fn record(&mut self, time: u32) {
+        self.buckets[Self::bucket(time)] += 1;
+        self.count += 1;
+        self.total += u64::from(time);
+        self.max = self.max.max(time);
+    }
+
+    /// Returns the mean service time, or `None` if nothing was recorded.
+    pub fn mean(&self) -> Option<u64> {
+        self.total.checked_div(self.count)
+    }
+
+    /// Returns an upper bound on the service time of `percent` percent of the commands:
+    /// the end of the bucket the percentile falls in, or [`ServiceTimes::max`] if that
+    /// is lower.
+    ///
+    /// # Returns
+    ///
+    /// `None` if nothing was recorded.
+    pub fn percentile(&self, percent: u32) -> Option<u32> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (self.count * u64::from(percent.min(100))).div_ceil(100).max(1);
+        let mut seen = 0;
+        let index = self.buckets.iter().position(|&n| {
+            seen += n;
+            seen >= rank
+        })?;
+        let end = 2u32.checked_pow(index as u32 + 1).unwrap_or(u32::MAX);
+        Some(end.min(self.max))
+    }
+}
+
+impl Default for ServiceTimes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for ServiceTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.mean(), self.percentile(50), self.percentile(99)) {
+            (Some(mean), Some(p50), Some(p99)) => {
+                write!(f, "mean {mean} us, p50 <= {p50} us, p99 <= {p99} us, max {} us", self.max)
+            }
+            _ => f.write_str("-"),
+        }
+    }
+}
+
+/// Counters of one command code on one device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandStats {
+    /// The command code.
+    pub command: u8,
+    /// Commands the device accepted.
+    pub issued: u64,
+    /// Commands whose completion interrupt arrived, successful or not.
+    pub completed: u64,
+    /// Commands the device refused outright.
+    pub rejected: u64,
+    /// Commands that completed with a non-zero status, or were in flight when the device
+    /// was unplugged.
+    pub failed: u64,
+    /// Bytes moved by commands that completed successfully. Only reads and writes move
+    /// data: seeks, `DISK_INFO` and refused or failed commands count none.
+    pub bytes: u64,
+    /// Service times of the completed commands.
+    pub service_times: ServiceTimes,
+}
+
+impl CommandStats {
+    fn new(command: u8) -> Self {
+        CommandStats {
+            command,
+            issued: 0,
+            completed: 0,
+            rejected: 0,
+            failed: 0,
+            bytes: 0,
+            service_times: ServiceTimes::new(),
+        }
+    }
+
+    /// Returns the commands that were rejected or failed.
+    pub fn errors(&self) -> u64 {
+        self.rejected + self.failed
+    }
+}
+
+/// I/O counters of one device, see [`device_stats`].
+///
+/// The queue depth of a device is the number of its commands that were issued and have
+/// not completed yet.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceStats {
+    /// Per-command counters, in the order the commands were first seen.
+    pub commands: Vec<CommandStats>,
+    /// Completion interrupts that matched no outstanding command, such as a NIC's frame
+    /// arrivals.
+    pub unsolicited: u64,
+    /// Commands in flight when the counters were read.
+    pub queue_depth: u32,
+    /// Highest queue depth seen during the observation.
+    pub max_queue_depth: u32,
+    /// The last [`QUEUE_DEPTH_HISTORY`] queue depth changes, as `(time, depth)`.
+    pub queue_depths: VecDeque<(u32, u32)>,
+    /// Start of the observation, the first command or the last reset.
+    pub since: u32,
+    /// Time the counters were read.
+    pub until: u32,
+    /// Integral of the queue depth over the observation, in command-microseconds.
+    pub depth_time: u64,
+}
+
+impl DeviceStats {
+    fn new(now: u32) -> Self {
+        DeviceStats { since: now, until: now, ..Default::default() }
+    }
+
+    /// Returns the counters of `command`, if the device has seen it.
+    pub fn command(&self, command: u8) -> Option<&CommandStats> {
+        self.commands.iter().find(|c| c.command == command)
+    }
+
+    fn command_mut(&mut self, command: u8) -> &mut CommandStats {
+        let index = match self.commands.iter().position(|c| c.command == command) {
+            Some(index) => index,
+            None => {
+                self.commands.push(CommandStats::new(command));
+                self.commands.len() - 1
+            }
+        };
+        &mut self.commands[index]
+    }
+
+    /// Returns the commands the device accepted, over all command codes.
+    pub fn requests(&self) -> u64 {
+        self.commands.iter().map(|c| c.issued).sum()
+    }
+
+    /// Returns the bytes moved by successful commands, over all command codes.
+    pub fn bytes(&self) -> u64 {
+        self.commands.iter().map(|c| c.bytes).sum()
+    }
+
+    /// Returns the rejected and failed commands, over all command codes.
+    pub fn errors(&self) -> u64 {
+        self.commands.iter().map(CommandStats::errors).sum()
+    }
+
+    /// Returns the queue depth averaged over time, or `None` if no time has passed.
+    pub fn average_queue_depth(&self) -> Option<f64> {
+        let elapsed = self.until.wrapping_sub(self.since);
+        (elapsed > 0).then(|| self.depth_time as f64 / f64::from(elapsed))
+    }
+
+    /// Accounts the time since the last update at the current queue depth.
+    fn advance(&mut self, now: u32) {
+        let elapsed = now.wrapping_sub(self.until);
+        self.depth_time += u64::from(self.queue_depth) * u64::from(elapsed);
+        self.until = now;
+    }
+
+    fn set_queue_depth(&mut self, depth: u32, now: u32) {
+        self.advance(now);
+        self.queue_depth = depth;
+        self.max_queue_depth = self.max_queue_depth.max(depth);
+        if self.queue_depths.len() == QUEUE_DEPTH_HISTORY {
+            self.queue_depths.pop_front();
+        }
+        self.queue_depths.push_back((now, depth));
+    }
+}
+
+/// A command waiting for its completion interrupt.
+struct Outstanding {
+    command: u8,
+    bytes: u32,
+    issued_at: u32,
+}
+
+struct Tracked {
+    device: DeviceId,
+    stats: DeviceStats,
+    outstanding: VecDeque<Outstanding>,
+}
+
+/// The counters of every device that has been used.
+pub(crate) struct IoStats {
+    devices: Vec<Tracked>,
+}
+
+impl IoStats {
+    pub(crate) const fn new() -> Self {
+        IoStats { devices: Vec::new() }
+    }
+
+    fn tracked(&mut self, device: DeviceId, now: u32) -> &mut Tracked {
+        let index = match self.devices.iter().position(|t| t.device == device) {
+            Some(index) => index,
+            None => {
+                let stats = DeviceStats::new(now);
+                self.devices.push(Tracked { device, stats, outstanding: VecDeque::new() });
+                self.devices.len() - 1
+            }
+        };
+        &mut self.devices[index]
+    }
+
+    /// Records that `device` accepted `command`, which moves `bytes` if it succeeds.
+    pub(crate) fn issued(&mut self, device: DeviceId, command: u8, bytes: u32, now: u32) {
+        let tracked = self.tracked(device, now);
+        tracked.stats.command_mut(command).issued += 1;
+        tracked.outstanding.push_back(Outstanding { command, bytes, issued_at: now });
+        tracked.stats.set_queue_depth(tracked.outstanding.len() as u32, now);
+    }
+
+    /// Records that `device` refused `command`.
+    pub(crate) fn rejected(&mut self, device: DeviceId, command: u8, now: u32) {
+        self.tracked(device, now).stats.command_mut(command).rejected += 1;
+    }
+
+    /// Matches a completion interrupt to the oldest outstanding `command` of `device`.
+    pub(crate) fn completed(&mut self, device: DeviceId, command: u8, status: u32, now: u32) {
+        let tracked = self.tracked(device, now);
+        let Some(index) = tracked.outstanding.iter().position(|o| o.command == command) else {
+            tracked.stats.unsolicited += 1;
+            return;
+        };
+        let Some(done) = tracked.outstanding.remove(index) else {
+            return;
+        };
+        let counters = tracked.stats.command_mut(command);
+        counters.completed += 1;
+        counters.service_times.record(now.wrapping_sub(done.issued_at));
+        match status {
+            0 => counters.bytes += u64::from(done.bytes),
+            _ => counters.failed += 1,
+        }
+        tracked.stats.set_queue_depth(tracked.outstanding.len() as u32, now);
+    }
+
+    /// Fails every outstanding command of `device`, which was unplugged.
+    pub(crate) fn removed(&mut self, device: DeviceId, now: u32) {
+        let tracked = self.tracked(device, now);
+        for lost in core::mem::take(&mut tracked.outstanding) {
+            tracked.stats.command_mut(lost.command).failed += 1;
+        }
+        tracked.stats.set_queue_depth(0, now);
+    }
+
+    /// Returns the counters of `device` brought up to `now`.
+    pub(crate) fn stats(&self, device: DeviceId, now: u32) -> Option<DeviceStats> {
+        let tracked = self.devices.iter().find(|t| t.device == device)?;
+        let mut stats = tracked.stats.clone();
+        stats.advance(now);
+        Some(stats)
+    }
+
+    pub(crate) fn devices(&self) -> impl Iterator<Item = DeviceId> + '_ {
+        self.devices.iter().map(|t| t.device)
+    }
+
+    /// Clears the counters of `device`; commands in flight stay queued.
+    pub(crate) fn reset(&mut self, device: DeviceId, now: u32) {
+        if let Some(tracked) = self.devices.iter_mut().find(|t| t.device == device) {
+            let depth = tracked.outstanding.len() as u32;
+            let stats = DeviceStats::new(now);
+            tracked.stats = DeviceStats { queue_depth: depth, max_queue_depth: depth, ..stats };
+        }
+    }
+}
+
+static STATS: KernelCell<IoStats> = KernelCell::new(IoStats::new());
+static DUMP_AT_STOP: AtomicBool = AtomicBool::new(false);
+
+/// Bytes a `device_control` call with `data_length` moves if it succeeds. Custom devices
+/// define their own commands, so all of their buffer is counted.
+fn transfer_bytes(device: DeviceId, command: u8, data_length: u32) -> u32 {
+    match (device.kind, command) {
+        (DeviceKind::Disk, DISK_READ | DISK_WRITE) => data_length,
+        (DeviceKind::Terminal, TERMINAL_READ_CHAR | TERMINAL_WRITE_CHAR) => data_length.min(1),
+        (DeviceKind::Custom, _) => data_length,
+        _ => 0,
+    }
+}
+
+/// Records a `device_control` call on `name` that THREADS accepted or refused.
+///
+/// `guard` must have been taken before the call was made, so that the command's
+/// completion interrupt cannot be recorded ahead of it.
+pub(crate) fn record_control(
+    guard: &InterruptGuard,
+    name: &str,
+    command: u8,
+    data_length: u32,
+    accepted: bool,
+) {
+    let Some(device) = DeviceId::from_name(name) else {
+        return;
+    };
+    let bytes = transfer_bytes(device, command, data_length);
+    record_request(guard, device, command, bytes, accepted);
+}
+
+/// Records a request started on `device` without `device_control`, such as a DMA
+/// transfer; `guard` must predate the request as for [`record_control`].
+pub(crate) fn record_request(
+    guard: &InterruptGuard,
+    device: DeviceId,
+    command: u8,
+    bytes: u32,
+    accepted: bool,
+) {
+    let now = system_clock();
+    STATS.with(guard, |s| match accepted {
+        true => s.issued(device, command, bytes, now),
+        false => s.rejected(device, command, now),
+    });
+}
+
+/// Records a `THREADS_IO_INTERRUPT` from `device`; called by the crate's I/O interrupt
+/// handler before the driver sees it.
+pub(crate) fn on_interrupt(device: DeviceId, command: u8, status: u32) {
+    let guard = InterruptGuard::new();
+    let now = system_clock();
+    STATS.with(&guard, |s| match (command, status) {
+        (HOTPLUG_COMMAND, DEVICE_STATUS_REMOVED) => s.removed(device, now),
+        (HOTPLUG_COMMAND, _) => {}
+        // Host input was never issued as a command.
+        (TERMINAL_HOST_INPUT, _) if device.kind == DeviceKind::Terminal => {}
+        // Injected faults may still change, delay or drop the completion; the disk
+        // driver records the one it acts on with `record_completion`.
+        _ if device.kind == DeviceKind::Disk => {}
+        _ => s.completed(device, command, status, now),
+    });
+}
+
+/// Records the completion of `command` on `device` with the status a driver finally acted
+/// on, for drivers that may change or hold back the interrupt's own.
+pub(crate) fn record_completion(
+    guard: &InterruptGuard,
+    device: DeviceId,
+    command: u8,
+    status: u32,
+) {
+    let now = system_clock();
+    STATS.with(guard, |s| s.completed(device, command, status, now));
+}
+
+/// Returns the I/O counters of `device`, or `None` if it was never issued a command,
+/// raised an interrupt or was unplugged.
+///
+/// Commands are counted when issued through `device_control` or
+/// [`crate::dma::dma_transfer`], and completed when their `THREADS_IO_INTERRUPT`
+/// reaches the crate's I/O interrupt handler, so the counters cover the disk and terminal
+/// drivers, custom devices and direct `device_control` calls alike. Disk completions are
+/// counted with the status the disk driver acts on, after the faults injected with
+/// [`crate::disk::set_disk_faults`], and timed when they are delivered.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DISK_READ, DeviceId};
+///
+/// let stats = rusty_threads::device_stats(DeviceId::disk(0)).unwrap();
+/// let reads = stats.command(DISK_READ).unwrap();
+/// rusty_threads::console_output(false, &format!("reads: {}\n", reads.service_times));
+/// ```
+pub fn device_stats(device: DeviceId) -> Option<DeviceStats> {
+    let guard = InterruptGuard::new();
+    let now = system_clock();
+    STATS.with(&guard, |s| s.stats(device, now))
+}
+
+/// Returns the I/O counters of every device that has been used.
+pub fn all_device_stats() -> Vec<(DeviceId, DeviceStats)> {
+    let guard = InterruptGuard::new();
+    let now = system_clock();
+    STATS.with(&guard, |s| {
+        let devices: Vec<DeviceId> = s.devices().collect();
+        devices.into_iter().filter_map(|d| Some((d, s.stats(d, now)?))).collect()
+    })
+}
+
+/// Clears the I/O counters of `device`.
+pub fn reset_device_stats(device: DeviceId) {
+    let guard = InterruptGuard::new();
+    let now = system_clock();
+    STATS.with(&guard, |s| s.reset(device, now));
+}
+
+/// Makes `stop` print [`dump_device_stats`] before halting, or stops it.
+pub fn set_device_stats_dump(enabled: bool) {
+    DUMP_AT_STOP.store(enabled, Ordering::SeqCst);
+}
+
+fn command_name(kind: DeviceKind, command: u8) -> String {
+    let name = match (kind, command) {
+        (DeviceKind::Disk, DISK_INFO) => "DISK_INFO",
+        (DeviceKind::Disk, DISK_READ) => "DISK_READ",
+        (DeviceKind::Disk, DISK_WRITE) => "DISK_WRITE",
+        (DeviceKind::Disk, DISK_SEEK) => "DISK_SEEK",
+        (DeviceKind::Terminal, TERMINAL_READ_CHAR) => "TERMINAL_READ_CHAR",
+        (DeviceKind::Terminal, TERMINAL_WRITE_CHAR) => "TERMINAL_WRITE_CHAR",
+        _ => return format!("0x{command:02x}"),
+    };
+    name.to_string()
+}
+
+fn report(device: DeviceId, stats: &DeviceStats) -> String {
+    let depth = stats.average_queue_depth().map_or_else(|| "-".to_string(), |d| format!("{d:.2}"));
+    let mut report = format!(
+        "{}: {} requests, {} bytes, {} errors, queue depth {} now, {depth} average, {} max\n",
+        device.name(),
+        stats.requests(),
+        stats.bytes(),
+        stats.errors(),
+        stats.queue_depth,
+        stats.max_queue_depth
+    );
+    for c in &stats.commands {
+        report += &format!(
+            "  {}: {} issued, {} rejected, {} failed, {} bytes\n    service time {}\n",
+            command_name(device.kind, c.command),
+            c.issued,
+            c.rejected,
+            c.failed,
+            c.bytes,
+            c.service_times
+        );
+    }
+    report
+}
+
+/// Prints the I/O counters of every device that has been used to the console.
+///
+/// # Example
+///
+/// ```text
+/// disk0: 130 requests, 32768 bytes, 1 errors, queue depth 0 now, 0.61 average, 1 max
+///   DISK_SEEK: 66 issued, 0 rejected, 1 failed, 0 bytes
+///     service time mean 2140 us, p50 <= 2048 us, p99 <= 4096 us, max 4012 us
+///   DISK_READ: 64 issued, 0 rejected, 0 failed, 32768 bytes
+///     service time mean 510 us, p50 <= 512 us, p99 <= 530 us, max 530 us
+/// ```
+pub fn dump_device_stats() {
+    for (device, stats) in all_device_stats() {
+        console_output(false, &report(device, &stats));
+    }
+}
+
+/// Called by `stop` before halting.
+pub(crate) fn on_stop() {
+    if DUMP_AT_STOP.load(Ordering::SeqCst) {
+        dump_device_stats();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_time_histogram() {
+        assert_eq!((ServiceTimes::bucket(0), ServiceTimes::bucket(1)), (0, 0));
+        assert_eq!((ServiceTimes::bucket(2), ServiceTimes::bucket(1023)), (1, 9));
+        assert_eq!(ServiceTimes::bucket(u32::MAX), SERVICE_TIME_BUCKETS - 1);
+
+        let mut times = ServiceTimes::new();
+        assert_eq!((times.mean(), times.percentile(50)), (None, None));
+        for time in [100, 110, 120, 3000] {
+            times.record(time);
+        }
+        assert_eq!((times.count, times.mean(), times.max), (4, Some(832), 3000));
+        assert_eq!(times.percentile(50), Some(128));
+        assert_eq!(times.percentile(99), Some(3000));
+        assert_eq!(times.to_string(), "mean 832 us, p50 <= 128 us, p99 <= 3000 us, max 3000 us");
+    }
+
+    #[test]
+    fn test_only_reads_and_writes_move_bytes() {
+        let (disk, terminal) = (DeviceId::disk(0), DeviceId::terminal(0));
+        assert_eq!(transfer_bytes(disk, DISK_SEEK, 512), 0);
+        assert_eq!(transfer_bytes(disk, DISK_INFO, 4), 0);
+        assert_eq!(transfer_bytes(disk, DISK_WRITE, 512), 512);
+        assert_eq!(transfer_bytes(terminal, TERMINAL_WRITE_CHAR, 1), 1);
+        assert_eq!(transfer_bytes(terminal, DISK_READ, 512), 0);
+        assert_eq!(transfer_bytes(DeviceId::clock(), DISK_READ, 512), 0);
+    }
+
+    #[test]
+    fn test_commands_are_matched_to_their_completions() {
+        let disk = DeviceId::disk(0);
+        let mut io = IoStats::new();
+        io.issued(disk, DISK_SEEK, 0, 100);
+        io.rejected(disk, DISK_READ, 150);
+        io.completed(disk, DISK_SEEK, 0, 300);
+        io.issued(disk, DISK_READ, 512, 300);
+        io.issued(disk, DISK_WRITE, 512, 400);
+        io.completed(disk, DISK_WRITE, 7, 500);
+        io.completed(disk, DISK_READ, 0, 700);
+        io.completed(disk, DISK_INFO, 0, 700);
+
+        let stats = io.stats(disk, 1100).unwrap();
+        let read = stats.command(DISK_READ).unwrap();
+        assert_eq!((read.issued, read.rejected, read.bytes), (1, 1, 512));
+        assert_eq!(read.service_times.max, 400);
+        assert_eq!(stats.command(DISK_WRITE).unwrap().failed, 1);
+        assert_eq!((stats.requests(), stats.bytes(), stats.errors()), (3, 512, 2));
+        assert_eq!((stats.unsolicited, stats.queue_depth, stats.max_queue_depth), (1, 0, 2));
+        // Depth 1 for 200 us, 1 for 100 us, 2 for 100 us and 1 for 200 us, over 1000 us.
+        assert_eq!(stats.depth_time, 700);
+        assert_eq!(stats.average_queue_depth(), Some(0.7));
+        assert_eq!(stats.queue_depths.back(), Some(&(700, 0)));
+        assert_eq!(io.stats(DeviceId::disk(1), 0), None);
+        io.completed(DeviceId::disk(1), DISK_READ, 0, 1100);
+        assert_eq!(io.stats(DeviceId::disk(1), 1100).unwrap().unsolicited, 1);
+
+        io.issued(disk, DISK_READ, 512, 1200);
+        io.removed(disk, 1300);
+        let stats = io.stats(disk, 1300).unwrap();
+        assert_eq!((stats.command(DISK_READ).unwrap().failed, stats.queue_depth), (1, 0));
+        io.issued(disk, DISK_SEEK, 0, 1400);
+        io.reset(disk, 1500);
+        let stats = io.stats(disk, 1600).unwrap();
+        assert_eq!((stats.requests(), stats.queue_depth, stats.depth_time), (0, 1, 100));
+    }
+}
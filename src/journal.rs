@@ -0,0 +1,248 @@
+// src/journal.rs
+
+use std::collections::BTreeMap;
+
+use crate::block_device::{BlockDevice, BlockError};
+use crate::fs::{FsError, Superblock, get_u32, put_u32};
+
+/// Magic number of a journal header holding a committed transaction ("RJNL").
+pub const JOURNAL_MAGIC: u32 = 0x524A_4E4C;
+
+/// Bytes of the journal header before the list of target blocks: magic, block count
+/// and checksum.
+const HEADER_SIZE: usize = 12;
+
+/// Number of blocks one transaction can log on a volume described by `superblock`.
+///
+/// The first journal block is the header; every other block holds one logged copy, and
+/// the header must have room for the home location of each.
+pub fn journal_capacity(superblock: &Superblock) -> usize {
+    let slots = superblock.journal_blocks.saturating_sub(1) as usize;
+    slots.min((superblock.block_size as usize - HEADER_SIZE) / 4)
+}
+
+/// FNV-1a over the target list and the logged copies, so that a header whose copies were
+/// not all written is never replayed.
+fn checksum(targets: &[u32], blocks: &[&[u8]]) -> u32 {
+    let mut hash: u32 = 0x811C_9DC5;
+    let bytes = targets.iter().flat_map(|t| t.to_le_bytes());
+    for byte in bytes.chain(blocks.iter().flat_map(|b| b.iter().copied())) {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+fn write(device: &mut impl BlockDevice, block: u32, data: &[u8]) -> Result<(), BlockError> {
+    device.write_block(u64::from(block), data)
+}
+
+fn read(device: &mut impl BlockDevice, block: u32) -> Result<Vec<u8>, BlockError> {
+    let mut data = vec![0; device.block_size()];
+    device.read_block(u64::from(block), &mut data)?;
+    Ok(data)
+}
+
+/// Commits `blocks` (home block number to new contents) to the volume atomically.
+///
+/// The copies are written to the journal first and the header after them, so the
+/// transaction exists only once the header is on disk. The blocks are then written in
+/// place and the header cleared. A crash before the header is written loses the whole
+/// transaction; a crash after it is repaired by [`recover_journal`].
+///
+/// # Returns
+///
+/// `FsError::JournalFull` if the transaction has more blocks than
+/// [`journal_capacity`]. Nothing is written in that case.
+pub fn commit_transaction(
+    device: &mut impl BlockDevice,
+    superblock: &Superblock,
+    blocks: &BTreeMap<u32, Vec<u8>>,
+) -> Result<(), FsError> {
+    if blocks.is_empty() {
+        return Ok(());
+    }
+    if blocks.len() > journal_capacity(superblock) {
+        return Err(FsError::JournalFull);
+    }
+
+    let start = superblock.journal_start;
+    for (i, data) in blocks.values().enumerate() {
+        write(device, start + 1 + i as u32, data)?;
+    }
+    device.flush()?;
+
+    let targets: Vec<u32> = blocks.keys().copied().collect();
+    let copies: Vec<&[u8]> = blocks.values().map(Vec::as_slice).collect();
+    let mut header = vec![0; superblock.block_size as usize];
+    put_u32(&mut header, 0, JOURNAL_MAGIC);
+    put_u32(&mut header, 4, targets.len() as u32);
+    put_u32(&mut header, 8, checksum(&targets, &copies));
+    for (i, &target) in targets.iter().enumerate() {
+        put_u32(&mut header, HEADER_SIZE + i * 4, target);
+    }
+    write(device, start, &header)?;
+    device.flush()?;
+
+    for (&target, data) in blocks {
+        write(device, target, data)?;
+    }
+    device.flush()?;
+    write(device, start, &vec![0; header.len()])?;
+    Ok(device.flush()?)
+}
+
+/// Replays a transaction left in the journal by a crash.
+///
+/// A header with a valid checksum is replayed and then cleared; one whose copies do not
+/// match its checksum was never committed and is simply cleared. Replaying is idempotent,
+/// so a crash during recovery is repaired by the next mount.
+///
+/// # Returns
+///
+/// The number of blocks replayed, or `FsError::Corrupt` if a committed header names a
+/// block outside the volume or inside the journal.
+pub fn recover_journal(
+    device: &mut impl BlockDevice,
+    superblock: &Superblock,
+) -> Result<usize, FsError> {
+    let start = superblock.journal_start;
+    let header = read(device, start)?;
+    if get_u32(&header, 0) != JOURNAL_MAGIC {
+        return Ok(0);
+    }
+
+    let count = get_u32(&header, 4) as usize;
+    let mut replayed = 0;
+    if count <= journal_capacity(superblock) {
+        let targets: Vec<u32> = (0..count).map(|i| get_u32(&header, HEADER_SIZE + i * 4)).collect();
+        let copies = (0..count)
+            .map(|i| read(device, start + 1 + i as u32))
+            .collect::<Result<Vec<_>, _>>()?;
+        let slices: Vec<&[u8]> = copies.iter().map(Vec::as_slice).collect();
+        if checksum(&targets, &slices) == get_u32(&header, 8) {
+            let journal = start..start + superblock.journal_blocks;
+            if targets.iter().any(|t| *t >= superblock.block_count || journal.contains(t)) {
+                return Err(FsError::Corrupt);
+            }
+            for (&target, data) in targets.iter().zip(&copies) {
+                write(device, target, data)?;
+            }
+            device.flush()?;
+            replayed = count;
+        }
+    }
+
+    write(device, start, &vec![0; header.len()])?;
+    device.flush()?;
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_device::MemBlockDevice;
+    use crate::crash::{CrashAction, CrashingDevice};
+    use crate::fs::{FileSystem, MIN_JOURNAL_BLOCKS, MkfsOptions, OpenFlags, mkfs};
+    use crate::fsck::fsck;
+
+    fn image(journal_blocks: u32) -> MemBlockDevice {
+        let options = MkfsOptions { journal_blocks, ..Default::default() };
+        let fs = mkfs(MemBlockDevice::new(1024, 128).unwrap(), options).unwrap();
+        fs.unmount().unwrap()
+    }
+
+    fn workload(fs: &mut FileSystem<CrashingDevice<MemBlockDevice>>) -> Result<(), FsError> {
+        fs.mkdir("/logs")?;
+        let fd = fs.open("/logs/boot", OpenFlags::WRITE | OpenFlags::CREATE)?;
+        fs.write(fd, &[7; 14 * 1024])?;
+        fs.close(fd)?;
+        fs.mkdir("/tmp")?;
+        fs.unlink("/logs/boot")?;
+        fs.rmdir("/tmp")
+    }
+
+    /// Runs the workload, crashing at sector write `point`, then mounts the image again
+    /// and checks it. Returns `None` once `point` is past the end of the workload.
+    fn crash_at(base: &MemBlockDevice, point: u64) -> Option<bool> {
+        let device = CrashingDevice::crash_after(base.clone(), point, CrashAction::DropWrites);
+        let mut fs = FileSystem::mount(device).unwrap();
+        let _ = workload(&mut fs);
+        let device = fs.into_device();
+        if !device.has_crashed() {
+            return None;
+        }
+        let mut fs = FileSystem::mount(device.into_inner()).unwrap();
+        Some(fsck(&mut fs).unwrap().is_clean())
+    }
+
+    #[test]
+    fn test_commit_and_recover() {
+        let mut device = image(8);
+        let superblock = FileSystem::mount(device.clone()).unwrap().superblock();
+        assert_eq!((superblock.journal_start, superblock.bitmap_start), (1, 9));
+        assert_eq!(journal_capacity(&superblock), 7);
+
+        let blocks = BTreeMap::from([(100, vec![1; 1024]), (101, vec![2; 1024])]);
+        commit_transaction(&mut device, &superblock, &blocks).unwrap();
+        assert_eq!(&device.as_bytes()[100 * 1024..101 * 1024], &[1; 1024]);
+        assert_eq!(recover_journal(&mut device, &superblock), Ok(0));
+
+        let too_many = (100..108).map(|b| (b, vec![0; 1024])).collect();
+        assert_eq!(
+            commit_transaction(&mut device, &superblock, &too_many),
+            Err(FsError::JournalFull)
+        );
+    }
+
+    #[test]
+    fn test_every_crash_point_recovers_with_journal() {
+        let base = image(16);
+        let mut point = 0;
+        while let Some(clean) = crash_at(&base, point) {
+            assert!(clean, "inconsistent after crash at sector write {point}");
+            point += 1;
+        }
+        assert!(point > 20);
+    }
+
+    #[test]
+    fn test_crash_without_journal_can_corrupt() {
+        let base = image(0);
+        let mut unclean = 0;
+        let mut point = 0;
+        while let Some(clean) = crash_at(&base, point) {
+            unclean += usize::from(!clean);
+            point += 1;
+        }
+        assert!(unclean > 0);
+    }
+
+    #[test]
+    fn test_random_crash_replays_committed_transaction() {
+        let device = CrashingDevice::new(image(16), 2024, 200, CrashAction::DropWrites);
+        let mut fs = FileSystem::mount(device).unwrap();
+        let _ = workload(&mut fs);
+        let device = fs.into_device().into_inner();
+        let mut fs = FileSystem::mount(device).unwrap();
+        assert!(fsck(&mut fs).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_smallest_journal_logs_the_largest_operation() {
+        for journal_blocks in [1, 2, MIN_JOURNAL_BLOCKS - 1] {
+            let options = MkfsOptions { journal_blocks, ..Default::default() };
+            let device = MemBlockDevice::new(1024, 128).unwrap();
+            assert_eq!(mkfs(device, options).err(), Some(FsError::DeviceTooSmall));
+        }
+
+        // The last directory fills the inode table and puts the root's 33rd entry, after
+        // `.` and `..`, in a second block.
+        let mut fs = FileSystem::mount(image(MIN_JOURNAL_BLOCKS)).unwrap();
+        for i in 0..31 {
+            fs.mkdir(&format!("/d{i}")).unwrap();
+        }
+        assert_eq!(fs.stat("/").unwrap().size, 33 * 32);
+        assert!(fsck(&mut fs).unwrap().is_clean());
+    }
+}
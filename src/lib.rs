@@ -1,12 +1,75 @@
 // lib.rs
+pub mod ansi;
+pub mod block_device;
+pub mod buffer_cache;
+pub mod console_view;
 pub mod constants;
+pub mod cow;
+pub mod crash;
+pub mod custom_device;
+pub mod device;
+pub mod disk;
+pub mod disk_fault;
+pub mod disk_geometry;
+pub mod disk_image;
+pub mod disk_scheduler;
+pub mod dma;
+pub mod fs;
+pub mod fsck;
+pub mod hotplug;
+pub mod interrupts;
+pub mod io_stats;
+pub mod journal;
+pub mod mailbox;
+pub mod nic;
+pub mod partition;
+pub mod priority;
+pub mod process;
+pub mod raid;
+mod rng;
 pub mod rusty_wrapper;
 mod rusty_thread_bindings;
+pub mod scheduler;
+pub mod sync;
+pub mod terminal;
+pub mod terminal_socket;
+pub mod watchdog;
 
 #[allow(unused_imports)]
 mod exports {
+    pub use crate::ansi::*;
+    pub use crate::block_device::*;
+    pub use crate::buffer_cache::*;
+    pub use crate::console_view::*;
     pub use crate::constants::*;
+    pub use crate::cow::*;
+    pub use crate::crash::*;
+    pub use crate::custom_device::*;
+    pub use crate::device::*;
+    pub use crate::disk::*;
+    pub use crate::disk_fault::*;
+    pub use crate::disk_geometry::*;
+    pub use crate::disk_image::*;
+    pub use crate::disk_scheduler::*;
+    pub use crate::dma::*;
+    pub use crate::fs::*;
+    pub use crate::fsck::*;
+    pub use crate::hotplug::*;
+    pub use crate::interrupts::*;
+    pub use crate::io_stats::*;
+    pub use crate::journal::*;
+    pub use crate::mailbox::*;
+    pub use crate::nic::*;
+    pub use crate::partition::*;
+    pub use crate::priority::*;
+    pub use crate::process::*;
+    pub use crate::raid::*;
     pub use crate::rusty_wrapper::*;
+    pub use crate::scheduler::*;
+    pub use crate::sync::*;
+    pub use crate::terminal::*;
+    pub use crate::terminal_socket::*;
+    pub use crate::watchdog::*;
 }
 
 pub use exports::*;
@@ -0,0 +1,403 @@
+// src/mailbox.rs
+
+use std::collections::VecDeque;
+
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::scheduler::{self, BlockReason, KERNEL, Pid};
+use crate::sync::{WaitQueue, WakeOrder, pop_waiter, running_pid};
+
+/// Errors returned by mailbox operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MailboxError {
+    /// The call was made outside of a running process.
+    NoProcess,
+    /// The message is larger than the mailbox's maximum message size.
+    TooLarge,
+    /// The receive buffer is smaller than the message. The message is consumed.
+    BufferTooSmall,
+    /// A conditional operation would have had to block.
+    WouldBlock,
+    /// The mailbox was released, either before the call or while the caller was blocked.
+    Released,
+}
+
+/// Result of a send attempt against the mailbox state.
+#[derive(Debug, PartialEq, Eq)]
+enum SendOutcome {
+    /// The message was queued or handed over; `Some(pid)` is a receiver to wake.
+    Delivered(Option<Pid>),
+    /// The sender was queued and must block.
+    Blocked,
+}
+
+/// Result of a receive attempt against the mailbox state.
+#[derive(Debug, PartialEq, Eq)]
+enum ReceiveOutcome {
+    /// A message was taken; `Some(pid)` is a sender to wake.
+    Received(Vec<u8>, Option<Pid>),
+    /// The receiver was queued and must block.
+    Blocked,
+}
+
+struct MailboxState {
+    slots: usize,
+    max_message_size: usize,
+    messages: VecDeque<Vec<u8>>,
+    /// Blocked senders, with their messages held in `pending` until accepted.
+    senders: WaitQueue,
+    pending: Vec<(Pid, Vec<u8>)>,
+    /// Blocked receivers, with messages handed to them directly in `deliveries`.
+    receivers: WaitQueue,
+    deliveries: Vec<(Pid, Vec<u8>)>,
+    released: bool,
+}
+
+impl MailboxState {
+    const fn new(slots: usize, max_message_size: usize, order: WakeOrder) -> Self {
+        MailboxState {
+            slots,
+            max_message_size,
+            messages: VecDeque::new(),
+            senders: WaitQueue::new(order),
+            pending: Vec::new(),
+            receivers: WaitQueue::new(order),
+            deliveries: Vec::new(),
+            released: false,
+        }
+    }
+
+    /// Tries to deliver `message` from `pid`. `pick` chooses which blocked process to wake.
+    fn offer(
+        &mut self,
+        pid: Pid,
+        message: &[u8],
+        block: bool,
+        mut pick: impl FnMut(&mut WaitQueue) -> Option<Pid>,
+    ) -> Result<SendOutcome, MailboxError> {
+        if self.released {
+            return Err(MailboxError::Released);
+        }
+        if message.len() > self.max_message_size {
+            return Err(MailboxError::TooLarge);
+        }
+
+        // Receivers only block when no message is queued, so a waiting receiver can take
+        // the message directly without overtaking anything.
+        if let Some(receiver) = pick(&mut self.receivers) {
+            self.deliveries.push((receiver, message.to_vec()));
+            return Ok(SendOutcome::Delivered(Some(receiver)));
+        }
+        if self.messages.len() < self.slots {
+            self.messages.push_back(message.to_vec());
+            return Ok(SendOutcome::Delivered(None));
+        }
+        if !block {
+            return Err(MailboxError::WouldBlock);
+        }
+
+        self.senders.push(pid);
+        self.pending.push((pid, message.to_vec()));
+        Ok(SendOutcome::Blocked)
+    }
+
+    /// Tries to take a message for `pid`. `pick` chooses which blocked sender to wake.
+    fn take(
+        &mut self,
+        pid: Pid,
+        block: bool,
+        mut pick: impl FnMut(&mut WaitQueue) -> Option<Pid>,
+    ) -> Result<ReceiveOutcome, MailboxError> {
+        if self.released {
+            return Err(MailboxError::Released);
+        }
+
+        if let Some(message) = self.messages.pop_front() {
+            // A slot just opened up: admit the next blocked sender's message.
+            let sender = pick(&mut self.senders);
+            if let Some(sender) = sender {
+                let waiting = self.take_pending(sender);
+                self.messages.extend(waiting);
+            }
+            return Ok(ReceiveOutcome::Received(message, sender));
+        }
+
+        // No queued messages: rendezvous with a blocked sender (the zero-slot case).
+        if let Some(sender) = pick(&mut self.senders) {
+            let message = self.take_pending(sender).unwrap_or_default();
+            return Ok(ReceiveOutcome::Received(message, Some(sender)));
+        }
+        if !block {
+            return Err(MailboxError::WouldBlock);
+        }
+
+        self.receivers.push(pid);
+        Ok(ReceiveOutcome::Blocked)
+    }
+
+    fn take_pending(&mut self, sender: Pid) -> Option<Vec<u8>> {
+        let index = self.pending.iter().position(|(pid, _)| *pid == sender)?;
+        Some(self.pending.remove(index).1)
+    }
+
+    fn take_delivery(&mut self, receiver: Pid) -> Option<Vec<u8>> {
+        let index = self.deliveries.iter().position(|(pid, _)| *pid == receiver)?;
+        Some(self.deliveries.remove(index).1)
+    }
+
+    /// Drops everything held for `pid`: its place in either queue, its pending message
+    /// and any message delivered to it but not yet taken.
+    fn forget(&mut self, pid: Pid) {
+        self.senders.remove(pid);
+        self.receivers.remove(pid);
+        self.take_pending(pid);
+        self.take_delivery(pid);
+    }
+
+    /// Marks the mailbox released and returns every blocked process.
+    fn release(&mut self) -> Vec<Pid> {
+        self.released = true;
+        self.messages.clear();
+        self.senders.drain().chain(self.receivers.drain()).collect()
+    }
+}
+
+/// A message-passing mailbox for kernel processes.
+///
+/// A mailbox holds up to `slots` messages of at most `max_message_size` bytes each.
+/// `send` blocks while every slot is full and `receive` blocks while the mailbox is empty;
+/// the `try_` variants return [`MailboxError::WouldBlock`] instead. A mailbox with zero
+/// slots is a rendezvous: each `send` blocks until a `receive` takes its message directly.
+///
+/// [`Mailbox::release`] wakes every blocked sender and receiver with
+/// [`MailboxError::Released`], and every later operation fails the same way.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{Mailbox, WakeOrder};
+///
+/// static REQUESTS: Mailbox = Mailbox::new(8, 64, WakeOrder::Fifo);
+///
+/// // client process
+/// REQUESTS.send(b"ping")?;
+///
+/// // server process
+/// let mut buffer = [0u8; 64];
+/// let length = REQUESTS.receive(&mut buffer)?;
+/// assert_eq!(&buffer[..length], b"ping");
+/// ```
+pub struct Mailbox {
+    state: KernelCell<MailboxState>,
+}
+
+impl Mailbox {
+    /// Creates an empty mailbox.
+    ///
+    /// # Arguments
+    ///
+    /// * `slots` – Number of messages buffered before senders block; `0` for a rendezvous.
+    /// * `max_message_size` – Largest message accepted, in bytes.
+    /// * `order` – Order in which blocked senders and receivers are woken.
+    pub const fn new(slots: usize, max_message_size: usize, order: WakeOrder) -> Self {
+        Mailbox { state: KernelCell::new(MailboxState::new(slots, max_message_size, order)) }
+    }
+
+    /// Sends `message`, blocking while the mailbox is full.
+    pub fn send(&self, message: &[u8]) -> Result<(), MailboxError> {
+        self.send_inner(message, true)
+    }
+
+    /// Sends `message` only if it can be accepted without blocking.
+    pub fn try_send(&self, message: &[u8]) -> Result<(), MailboxError> {
+        self.send_inner(message, false)
+    }
+
+    /// Receives the next message into `buffer`, blocking while the mailbox is empty.
+    ///
+    /// # Returns
+    ///
+    /// The length of the message copied into `buffer`.
+    pub fn receive(&self, buffer: &mut [u8]) -> Result<usize, MailboxError> {
+        self.receive_inner(buffer, true)
+    }
+
+    /// Receives the next message into `buffer` only if one is available without blocking.
+    pub fn try_receive(&self, buffer: &mut [u8]) -> Result<usize, MailboxError> {
+        self.receive_inner(buffer, false)
+    }
+
+    /// Releases the mailbox, waking every blocked sender and receiver with
+    /// [`MailboxError::Released`].
+    pub fn release(&self) {
+        let guard = InterruptGuard::new();
+        let blocked = self.state.with(&guard, |s| s.release());
+        if blocked.is_empty() {
+            return;
+        }
+        for pid in blocked {
+            scheduler::make_ready(&guard, pid);
+        }
+        drop(guard);
+        scheduler::dispatch();
+    }
+
+    /// Returns `true` once [`Mailbox::release`] has been called.
+    pub fn is_released(&self) -> bool {
+        let guard = InterruptGuard::new();
+        self.state.with(&guard, |s| s.released)
+    }
+
+    /// Returns the number of messages currently buffered in slots.
+    pub fn len(&self) -> usize {
+        let guard = InterruptGuard::new();
+        self.state.with(&guard, |s| s.messages.len())
+    }
+
+    /// Returns `true` if no messages are buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the state held for a killed process.
+    pub(crate) fn forget(&self, guard: &InterruptGuard, pid: Pid) {
+        self.state.with(guard, |s| s.forget(pid));
+    }
+
+    /// Records that the running process blocks in this mailbox, so that killing it
+    /// drops its pending message; `None` once it has returned.
+    fn enter(&self, guard: &InterruptGuard, pid: Pid, mailbox: Option<*const Mailbox>) {
+        KERNEL.with(guard, |k| {
+            if let Some(process) = k.process_mut(pid) {
+                process.in_mailbox = mailbox;
+            }
+        });
+    }
+
+    fn send_inner(&self, message: &[u8], block: bool) -> Result<(), MailboxError> {
+        let guard = InterruptGuard::new();
+        let pid = running_pid(&guard).map_err(|_| MailboxError::NoProcess)?;
+        let outcome =
+            self.state.with(&guard, |s| s.offer(pid, message, block, |q| pop_waiter(&guard, q)))?;
+
+        match outcome {
+            SendOutcome::Delivered(None) => Ok(()),
+            SendOutcome::Delivered(Some(receiver)) => {
+                scheduler::make_ready(&guard, receiver);
+                drop(guard);
+                scheduler::dispatch();
+                Ok(())
+            }
+            SendOutcome::Blocked => {
+                self.enter(&guard, pid, Some(self));
+                scheduler::block_current(&guard, BlockReason::MailboxSend, None);
+                self.enter(&guard, pid, None);
+                // A sender whose message is still pending was woken by a release.
+                match self.state.with(&guard, |s| s.take_pending(pid)) {
+                    Some(_) => Err(MailboxError::Released),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn receive_inner(&self, buffer: &mut [u8], block: bool) -> Result<usize, MailboxError> {
+        let guard = InterruptGuard::new();
+        let pid = running_pid(&guard).map_err(|_| MailboxError::NoProcess)?;
+        let outcome = self.state.with(&guard, |s| s.take(pid, block, |q| pop_waiter(&guard, q)))?;
+
+        let message = match outcome {
+            ReceiveOutcome::Received(message, sender) => {
+                if let Some(sender) = sender {
+                    scheduler::make_ready(&guard, sender);
+                }
+                message
+            }
+            ReceiveOutcome::Blocked => {
+                self.enter(&guard, pid, Some(self));
+                scheduler::block_current(&guard, BlockReason::MailboxReceive, None);
+                self.enter(&guard, pid, None);
+                self.state.with(&guard, |s| s.take_delivery(pid)).ok_or(MailboxError::Released)?
+            }
+        };
+        drop(guard);
+        scheduler::dispatch();
+
+        let destination = buffer.get_mut(..message.len()).ok_or(MailboxError::BufferTooSmall)?;
+        destination.copy_from_slice(&message);
+        Ok(message.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fifo(queue: &mut WaitQueue) -> Option<Pid> {
+        queue.pop(|_| 0)
+    }
+
+    #[test]
+    fn test_slots_fill_then_block() {
+        let mut mailbox = MailboxState::new(2, 8, WakeOrder::Fifo);
+        assert_eq!(mailbox.offer(1, b"a", true, fifo), Ok(SendOutcome::Delivered(None)));
+        assert_eq!(mailbox.offer(1, b"b", true, fifo), Ok(SendOutcome::Delivered(None)));
+        assert_eq!(mailbox.offer(1, b"c", false, fifo), Err(MailboxError::WouldBlock));
+        assert_eq!(mailbox.offer(2, b"c", true, fifo), Ok(SendOutcome::Blocked));
+
+        // Taking a message admits the blocked sender's message behind the others.
+        assert_eq!(
+            mailbox.take(3, true, fifo),
+            Ok(ReceiveOutcome::Received(b"a".to_vec(), Some(2)))
+        );
+        assert_eq!(mailbox.messages, [b"b".to_vec(), b"c".to_vec()]);
+    }
+
+    #[test]
+    fn test_oversized_message_rejected() {
+        let mut mailbox = MailboxState::new(1, 2, WakeOrder::Fifo);
+        assert_eq!(mailbox.offer(1, b"abc", true, fifo), Err(MailboxError::TooLarge));
+    }
+
+    #[test]
+    fn test_zero_slot_rendezvous() {
+        let mut mailbox = MailboxState::new(0, 8, WakeOrder::Fifo);
+        assert_eq!(mailbox.offer(1, b"hi", false, fifo), Err(MailboxError::WouldBlock));
+        assert_eq!(mailbox.offer(1, b"hi", true, fifo), Ok(SendOutcome::Blocked));
+        assert_eq!(
+            mailbox.take(2, true, fifo),
+            Ok(ReceiveOutcome::Received(b"hi".to_vec(), Some(1)))
+        );
+
+        // A receiver that arrives first gets the next message handed to it directly.
+        assert_eq!(mailbox.take(2, true, fifo), Ok(ReceiveOutcome::Blocked));
+        assert_eq!(mailbox.offer(1, b"yo", true, fifo), Ok(SendOutcome::Delivered(Some(2))));
+        assert_eq!(mailbox.take_delivery(2), Some(b"yo".to_vec()));
+    }
+
+    #[test]
+    fn test_release_wakes_everyone() {
+        let mut mailbox = MailboxState::new(0, 8, WakeOrder::Fifo);
+        mailbox.offer(1, b"x", true, fifo).unwrap();
+        mailbox.offer(2, b"y", true, fifo).unwrap();
+
+        assert_eq!(mailbox.release(), vec![1, 2]);
+        assert_eq!(mailbox.offer(3, b"z", true, fifo), Err(MailboxError::Released));
+        assert_eq!(mailbox.take(3, true, fifo), Err(MailboxError::Released));
+        // Released senders still find their message pending, which is how they tell a
+        // release apart from a successful hand-off.
+        assert!(mailbox.take_pending(1).is_some());
+    }
+
+    #[test]
+    fn test_forget_drops_a_killed_process_messages() {
+        let mut mailbox = MailboxState::new(0, 8, WakeOrder::Fifo);
+        assert_eq!(mailbox.offer(1, b"x", true, fifo), Ok(SendOutcome::Blocked));
+        mailbox.forget(1);
+        assert!(mailbox.pending.is_empty());
+        // The next receiver blocks instead of taking the killed sender's message.
+        assert_eq!(mailbox.take(2, true, fifo), Ok(ReceiveOutcome::Blocked));
+        assert_eq!(mailbox.offer(3, b"y", true, fifo), Ok(SendOutcome::Delivered(Some(2))));
+        mailbox.forget(2);
+        assert!(mailbox.deliveries.is_empty());
+    }
+}
@@ -0,0 +1,575 @@
+// src/nic.rs
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use crate::constants::THREADS_MAX_IO_BUFFER_SIZE;
+use crate::custom_device::{CustomDeviceError, Device, DeviceRequest, register_device};
+use crate::device::DeviceId;
+
+/// NIC command: transmit the frame in the input buffer.
+pub const NIC_SEND: u8 = 0x01;
+
+/// NIC command: copy the oldest received frame into the output buffer. The completion
+/// status is the number of bytes copied, or 0 if no frame was waiting, with
+/// [`NIC_RECEIVE_TRUNCATED`] set if the frame did not fit.
+pub const NIC_RECEIVE: u8 = 0x02;
+
+/// Bit set in the [`NIC_RECEIVE`] status when the output buffer was shorter than the
+/// frame; the rest of the frame is lost.
+pub const NIC_RECEIVE_TRUNCATED: u32 = 1 << 31;
+
+/// NIC command: write the MAC address and the MTU (little-endian `u16`) into the output
+/// buffer, [`NIC_INFO_SIZE`] bytes.
+pub const NIC_INFO: u8 = 0x03;
+
+/// Command code of the interrupt a NIC raises when frames arrive; the status is the
+/// number of frames waiting to be received.
+pub const NIC_FRAME_ARRIVED: u8 = 0x80;
+
+/// Size of the [`NIC_INFO`] output.
+pub const NIC_INFO_SIZE: usize = 8;
+
+/// Length of the frame header: destination and source MAC addresses.
+pub const NIC_HEADER_SIZE: usize = 12;
+
+/// Frames a NIC holds for the driver; more are dropped.
+pub const NIC_RECEIVE_QUEUE: usize = 32;
+
+/// Destination address that reaches every NIC on the switch.
+pub const BROADCAST_MAC: MacAddress = [0xFF; 6];
+
+/// A 6-byte hardware address.
+pub type MacAddress = [u8; 6];
+
+/// Errors returned when creating NICs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NicError {
+    /// The MTU is shorter than a frame header or longer than
+    /// `THREADS_MAX_IO_BUFFER_SIZE`.
+    InvalidMtu,
+    /// The NIC could not be registered as a custom device.
+    Device(CustomDeviceError),
+}
+
+impl From<CustomDeviceError> for NicError {
+    fn from(error: CustomDeviceError) -> Self {
+        NicError::Device(error)
+    }
+}
+
+/// Settings of a simulated NIC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NicConfig {
+    /// Hardware address; frames carry it as their source.
+    pub mac: MacAddress,
+    /// Largest frame, header included; at most `THREADS_MAX_IO_BUFFER_SIZE`.
+    pub mtu: usize,
+    /// Time to transmit one frame, in microseconds.
+    pub transmit_time: u32,
+}
+
+impl NicConfig {
+    /// A NIC with address `mac`, the largest MTU and a 100 µs transmit time.
+    pub const fn new(mac: MacAddress) -> Self {
+        NicConfig { mac, mtu: THREADS_MAX_IO_BUFFER_SIZE as usize, transmit_time: 100 }
+    }
+}
+
+/// Counters of a [`VirtualSwitch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SwitchStats {
+    /// Frames sent to the one port their destination was learned on.
+    pub forwarded: u64,
+    /// Frames sent to every other port: broadcasts and unknown destinations.
+    pub flooded: u64,
+    /// Frame copies dropped because a NIC's receive queue was full, whether on the way
+    /// in or when the NIC moved them on to its driver.
+    pub dropped: u64,
+}
+
+/// Where a switch accepts or makes trunk connections, see [`VirtualSwitch::listen`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwitchEndpoint {
+    /// A loopback TCP port. Port 0 picks a free port when listening.
+    Tcp(u16),
+    /// A Unix domain socket at this path.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+enum Port {
+    /// A NIC's receive queue.
+    Nic(Arc<Mutex<VecDeque<Vec<u8>>>>),
+    /// A connection to another switch, served by a writer thread.
+    Trunk(Sender<Vec<u8>>),
+}
+
+#[derive(Default)]
+struct SwitchState {
+    ports: Vec<Option<Port>>,
+    /// Port each source address was last seen on.
+    table: HashMap<MacAddress, usize>,
+    stats: SwitchStats,
+}
+
+impl SwitchState {
+    /// Plugs `port` into the first free slot.
+    fn attach(&mut self, port: Port) -> usize {
+        match self.ports.iter().position(Option::is_none) {
+            Some(index) => {
+                self.ports[index] = Some(port);
+                index
+            }
+            None => {
+                self.ports.push(Some(port));
+                self.ports.len() - 1
+            }
+        }
+    }
+
+    fn detach(&mut self, port: usize) {
+        self.ports[port] = None;
+        self.table.retain(|_, p| *p != port);
+    }
+
+    /// Learns the frame's source and passes it on towards its destination.
+    fn forward(&mut self, from: usize, frame: &[u8]) {
+        let destination: MacAddress = frame[..6].try_into().unwrap();
+        let source: MacAddress = frame[6..NIC_HEADER_SIZE].try_into().unwrap();
+        if source != BROADCAST_MAC {
+            self.table.insert(source, from);
+        }
+        let known = self.table.get(&destination).copied();
+        let targets: Vec<usize> = match known {
+            Some(port) if destination != BROADCAST_MAC => {
+                self.stats.forwarded += 1;
+                vec![port]
+            }
+            _ => {
+                self.stats.flooded += 1;
+                (0..self.ports.len()).collect()
+            }
+        };
+        for port in targets.into_iter().filter(|&p| p != from) {
+            match &self.ports[port] {
+                Some(Port::Nic(queue)) => {
+                    let mut queue = lock(queue);
+                    if queue.len() < NIC_RECEIVE_QUEUE {
+                        queue.push_back(frame.to_vec());
+                    } else {
+                        self.stats.dropped += 1;
+                    }
+                }
+                Some(Port::Trunk(sender)) => {
+                    let _ = sender.send(frame.to_vec());
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// An Ethernet-style learning switch connecting simulated NICs.
+///
+/// NICs attached to the same switch reach each other; a switch shared between kernels in
+/// one host process connects them, and trunk connections over local sockets
+/// ([`VirtualSwitch::listen`], [`VirtualSwitch::connect`]) join switches in different
+/// processes into one network. Frames start with the destination and source MAC
+/// addresses; the switch learns where each source lives and floods broadcasts and
+/// frames for unknown destinations. Cloning the switch gives another handle to it.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{NicConfig, SwitchEndpoint, VirtualSwitch};
+///
+/// let switch = VirtualSwitch::new();
+/// switch.connect(&SwitchEndpoint::Tcp(7000))?; // the lab's other kernel listens here
+/// let nic = rusty_threads::attach_nic("nic0", &switch, NicConfig::new([2, 0, 0, 0, 0, 1]))?;
+/// rusty_threads::device_initialize("nic0");
+/// ```
+#[derive(Clone, Default)]
+pub struct VirtualSwitch {
+    state: Arc<Mutex<SwitchState>>,
+}
+
+impl VirtualSwitch {
+    /// Creates a switch with no ports.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the switch's counters.
+    pub fn stats(&self) -> SwitchStats {
+        lock(&self.state).stats
+    }
+
+    /// Accepts trunk connections from other switches at `endpoint`, on a host thread.
+    ///
+    /// # Returns
+    ///
+    /// The endpoint listened on, with the port filled in if port 0 was asked for.
+    pub fn listen(&self, endpoint: &SwitchEndpoint) -> io::Result<SwitchEndpoint> {
+        match endpoint {
+            SwitchEndpoint::Tcp(port) => {
+                let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, *port))?;
+                let port = listener.local_addr()?.port();
+                let switch = self.clone();
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        if let Ok(writer) = stream.try_clone() {
+                            switch.trunk(stream, writer);
+                        }
+                    }
+                });
+                Ok(SwitchEndpoint::Tcp(port))
+            }
+            #[cfg(unix)]
+            SwitchEndpoint::Unix(path) => {
+                let listener = UnixListener::bind(path)?;
+                let switch = self.clone();
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        if let Ok(writer) = stream.try_clone() {
+                            switch.trunk(stream, writer);
+                        }
+                    }
+                });
+                Ok(endpoint.clone())
+            }
+        }
+    }
+
+    /// Opens a trunk connection to the switch listening at `endpoint`.
+    pub fn connect(&self, endpoint: &SwitchEndpoint) -> io::Result<()> {
+        match endpoint {
+            SwitchEndpoint::Tcp(port) => {
+                let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, *port))?;
+                let writer = stream.try_clone()?;
+                self.trunk(stream, writer);
+            }
+            #[cfg(unix)]
+            SwitchEndpoint::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                let writer = stream.try_clone()?;
+                self.trunk(stream, writer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Attaches a trunk port carrying frames over a stream, each behind its big-endian
+    /// `u16` length.
+    fn trunk(&self, mut reader: impl Read + Send + 'static, writer: impl Write + Send + 'static) {
+        let (sender, frames) = mpsc::channel();
+        let port = lock(&self.state).attach(Port::Trunk(sender));
+        thread::spawn(move || write_trunk(writer, frames));
+        let state = Arc::clone(&self.state);
+        thread::spawn(move || {
+            let mut length = [0; 2];
+            while reader.read_exact(&mut length).is_ok() {
+                let mut frame = vec![0; u16::from_be_bytes(length) as usize];
+                if reader.read_exact(&mut frame).is_err() {
+                    break;
+                }
+                if frame.len() >= NIC_HEADER_SIZE {
+                    lock(&state).forward(port, &frame);
+                }
+            }
+            // Dropping the sender ends the writer thread.
+            lock(&state).detach(port);
+        });
+    }
+}
+
+fn write_trunk(mut writer: impl Write, frames: Receiver<Vec<u8>>) {
+    for frame in frames {
+        let length = (frame.len() as u16).to_be_bytes();
+        if writer.write_all(&length).and_then(|_| writer.write_all(&frame)).is_err() {
+            return;
+        }
+    }
+}
+
+/// A simulated network interface on a [`VirtualSwitch`], as a custom [`Device`].
+///
+/// The driver transmits with [`NIC_SEND`], whose completion interrupt arrives after the
+/// configured transmit time, and learns about incoming frames from
+/// [`NIC_FRAME_ARRIVED`] interrupts, after which it fetches them with [`NIC_RECEIVE`].
+/// Frames longer than the MTU or shorter than [`NIC_HEADER_SIZE`] are rejected by
+/// `device_control`.
+pub struct Nic {
+    config: NicConfig,
+    switch: VirtualSwitch,
+    port: usize,
+    inbox: Arc<Mutex<VecDeque<Vec<u8>>>>,
+    /// Frames waiting for [`NIC_RECEIVE`].
+    received: VecDeque<Vec<u8>>,
+    /// Completions as `(due time, command, status)`, in due order.
+    completions: VecDeque<(u32, u8, u32)>,
+}
+
+impl Nic {
+    /// Attaches a NIC to `switch`.
+    ///
+    /// # Returns
+    ///
+    /// The NIC, or [`NicError::InvalidMtu`] if the MTU is shorter than a frame header or
+    /// longer than `THREADS_MAX_IO_BUFFER_SIZE`.
+    pub fn new(switch: &VirtualSwitch, config: NicConfig) -> Result<Self, NicError> {
+        if !(NIC_HEADER_SIZE..=THREADS_MAX_IO_BUFFER_SIZE as usize).contains(&config.mtu) {
+            return Err(NicError::InvalidMtu);
+        }
+        let inbox = Arc::new(Mutex::new(VecDeque::new()));
+        let port = lock(&switch.state).attach(Port::Nic(Arc::clone(&inbox)));
+        let completions = VecDeque::new();
+        let (switch, received) = (switch.clone(), VecDeque::new());
+        Ok(Nic { config, switch, port, inbox, received, completions })
+    }
+
+    /// Returns the NIC's settings.
+    pub fn config(&self) -> NicConfig {
+        self.config
+    }
+
+    fn complete(&mut self, due: u32, command: u8, status: u32) {
+        // Keep due order; a receive completes before a send still being transmitted.
+        let index = self.completions.partition_point(|&(d, ..)| (due.wrapping_sub(d) as i32) >= 0);
+        self.completions.insert(index, (due, command, status));
+    }
+}
+
+impl Drop for Nic {
+    fn drop(&mut self) {
+        lock(&self.switch.state).detach(self.port);
+    }
+}
+
+impl Device for Nic {
+    fn control(&mut self, request: DeviceRequest<'_>, now: u32) -> bool {
+        match request.command {
+            NIC_SEND => {
+                let frame = request.input;
+                if !(NIC_HEADER_SIZE..=self.config.mtu).contains(&frame.len()) {
+                    return false;
+                }
+                lock(&self.switch.state).forward(self.port, frame);
+                let due = now.wrapping_add(self.config.transmit_time);
+                self.complete(due, NIC_SEND, 0);
+            }
+            NIC_RECEIVE => {
+                let status = match self.received.pop_front() {
+                    Some(frame) => {
+                        let count = frame.len().min(request.output.len());
+                        request.output[..count].copy_from_slice(&frame[..count]);
+                        let truncated = count < frame.len();
+                        count as u32 | if truncated { NIC_RECEIVE_TRUNCATED } else { 0 }
+                    }
+                    None => 0,
+                };
+                self.complete(now, NIC_RECEIVE, status);
+            }
+            NIC_INFO => {
+                if request.output.len() < NIC_INFO_SIZE {
+                    return false;
+                }
+                request.output[..6].copy_from_slice(&self.config.mac);
+                request.output[6..8].copy_from_slice(&(self.config.mtu as u16).to_le_bytes());
+                self.complete(now, NIC_INFO, 0);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn poll(&mut self, now: u32) -> Option<(u8, u32)> {
+        if let Some(&(due, command, status)) = self.completions.front()
+            && (now.wrapping_sub(due) as i32) >= 0
+        {
+            self.completions.pop_front();
+            return Some((command, status));
+        }
+        let mut inbox = lock(&self.inbox);
+        if inbox.is_empty() {
+            return None;
+        }
+        // Frames arrive in the switch's order; the driver hears about them once a tick.
+        let mut dropped = 0;
+        while let Some(frame) = inbox.pop_front() {
+            if self.received.len() < NIC_RECEIVE_QUEUE {
+                self.received.push_back(frame);
+            } else {
+                dropped += 1;
+            }
+        }
+        // The switch locks inboxes while holding its state, so count after letting go.
+        drop(inbox);
+        lock(&self.switch.state).stats.dropped += dropped;
+        Some((NIC_FRAME_ARRIVED, self.received.len() as u32))
+    }
+}
+
+/// Registers a [`Nic`] on `switch` as custom device `name`.
+///
+/// # Returns
+///
+/// The NIC's device id; initialize it with `device_initialize(name)` before use. Fails
+/// if the MTU is out of range or the name cannot be registered.
+pub fn attach_nic(
+    name: &str,
+    switch: &VirtualSwitch,
+    config: NicConfig,
+) -> Result<DeviceId, NicError> {
+    Ok(register_device(name, Nic::new(switch, config)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn frame(to: MacAddress, from: MacAddress, payload: &[u8]) -> Vec<u8> {
+        [&to[..], &from[..], payload].concat()
+    }
+
+    fn send(nic: &mut Nic, frame: &[u8], now: u32) -> bool {
+        nic.control(
+            DeviceRequest {
+                command: NIC_SEND,
+                control1: 0,
+                control2: 0,
+                input: frame,
+                output: &mut [],
+            },
+            now,
+        )
+    }
+
+    fn receive(nic: &mut Nic, now: u32) -> Option<Vec<u8>> {
+        let mut buffer = vec![0; nic.config().mtu];
+        let request = DeviceRequest {
+            command: NIC_RECEIVE,
+            control1: 0,
+            control2: 0,
+            input: &[],
+            output: &mut buffer,
+        };
+        assert!(nic.control(request, now));
+        let (command, length) = nic.poll(now).unwrap();
+        assert_eq!(command, NIC_RECEIVE);
+        buffer.truncate(length as usize);
+        (length > 0).then_some(buffer)
+    }
+
+    #[test]
+    fn test_switch_floods_then_forwards() {
+        let switch = VirtualSwitch::new();
+        let (a, b, c) = ([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2], [2, 0, 0, 0, 0, 3]);
+        let mut nic_a =
+            Nic::new(&switch, NicConfig { transmit_time: 40, ..NicConfig::new(a) }).unwrap();
+        let mut nic_b = Nic::new(&switch, NicConfig::new(b)).unwrap();
+        let mut nic_c = Nic::new(&switch, NicConfig::new(c)).unwrap();
+
+        // B is unknown yet, so the frame is flooded to B and C.
+        assert!(send(&mut nic_a, &frame(b, a, b"hello"), 0));
+        assert_eq!(nic_a.poll(39), None);
+        assert_eq!(nic_a.poll(40), Some((NIC_SEND, 0)));
+        assert_eq!(nic_b.poll(0), Some((NIC_FRAME_ARRIVED, 1)));
+        assert_eq!(nic_b.poll(0), None);
+        assert_eq!(receive(&mut nic_b, 0), Some(frame(b, a, b"hello")));
+        assert_eq!(receive(&mut nic_b, 0), None);
+
+        // A has been learned, so the reply only reaches A.
+        assert!(send(&mut nic_b, &frame(a, b, b"hi"), 0));
+        assert_eq!(nic_a.poll(50), Some((NIC_FRAME_ARRIVED, 1)));
+        assert_eq!(nic_c.poll(0), Some((NIC_FRAME_ARRIVED, 1)));
+        assert_eq!(switch.stats(), SwitchStats { forwarded: 1, flooded: 1, dropped: 0 });
+
+        let too_long = frame(BROADCAST_MAC, a, &[0; THREADS_MAX_IO_BUFFER_SIZE as usize]);
+        assert!(!send(&mut nic_a, &too_long, 0));
+        assert!(!send(&mut nic_a, &a, 0));
+    }
+
+    #[test]
+    fn test_frames_beyond_the_receive_queue_are_counted() {
+        let switch = VirtualSwitch::new();
+        let (a, b) = ([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2]);
+        let mut nic_a = Nic::new(&switch, NicConfig::new(a)).unwrap();
+        let mut nic_b = Nic::new(&switch, NicConfig::new(b)).unwrap();
+        let queue = NIC_RECEIVE_QUEUE as u32;
+        for _ in 0..queue {
+            assert!(send(&mut nic_a, &frame(b, a, b"x"), 0));
+        }
+        assert_eq!(nic_b.poll(0), Some((NIC_FRAME_ARRIVED, queue)));
+        for _ in 0..3 {
+            assert!(send(&mut nic_a, &frame(b, a, b"y"), 0));
+        }
+        assert_eq!(nic_b.poll(0), Some((NIC_FRAME_ARRIVED, queue)));
+        assert_eq!(switch.stats().dropped, 3);
+
+        let tiny = NicConfig { mtu: NIC_HEADER_SIZE - 1, ..NicConfig::new(a) };
+        assert_eq!(Nic::new(&switch, tiny).err(), Some(NicError::InvalidMtu));
+        let huge = NicConfig { mtu: THREADS_MAX_IO_BUFFER_SIZE as usize + 1, ..tiny };
+        assert_eq!(Nic::new(&switch, huge).err(), Some(NicError::InvalidMtu));
+    }
+
+    #[test]
+    fn test_short_buffers_truncate_and_ports_are_reused() {
+        let switch = VirtualSwitch::new();
+        let (a, b) = ([2, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, 2]);
+        let mut nic_a = Nic::new(&switch, NicConfig::new(a)).unwrap();
+        let mut nic_b = Nic::new(&switch, NicConfig::new(b)).unwrap();
+        assert!(send(&mut nic_a, &frame(b, a, b"payload"), 0));
+        assert_eq!(nic_b.poll(0), Some((NIC_FRAME_ARRIVED, 1)));
+        let mut buffer = [0; NIC_HEADER_SIZE];
+        let output = &mut buffer;
+        let request =
+            DeviceRequest { command: NIC_RECEIVE, control1: 0, control2: 0, input: &[], output };
+        assert!(nic_b.control(request, 0));
+        let status = NIC_HEADER_SIZE as u32 | NIC_RECEIVE_TRUNCATED;
+        assert_eq!(nic_b.poll(0), Some((NIC_RECEIVE, status)));
+        assert_eq!(buffer[..], frame(b, a, b"")[..]);
+
+        let port = nic_a.port;
+        drop(nic_a);
+        let nic_c = Nic::new(&switch, NicConfig::new(a)).unwrap();
+        assert_eq!(nic_c.port, port);
+        assert_eq!(lock(&switch.state).ports.len(), 2);
+    }
+
+    #[test]
+    fn test_trunk_joins_switches() {
+        let (left, right) = (VirtualSwitch::new(), VirtualSwitch::new());
+        let endpoint = left.listen(&SwitchEndpoint::Tcp(0)).unwrap();
+        right.connect(&endpoint).unwrap();
+        let (a, b) = ([2, 0, 0, 0, 1, 1], [2, 0, 0, 0, 2, 2]);
+        let mut nic_a = Nic::new(&left, NicConfig::new(a)).unwrap();
+        let mut nic_b = Nic::new(&right, NicConfig::new(b)).unwrap();
+
+        // The listener attaches its end of the trunk on its own thread.
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while lock(&left.state).ports.len() < 2 {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(send(&mut nic_b, &frame(BROADCAST_MAC, b, b"ping"), 0));
+        while nic_a.poll(0).is_none() {
+            assert!(Instant::now() < deadline, "frame did not cross the trunk");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(receive(&mut nic_a, 0), Some(frame(BROADCAST_MAC, b, b"ping")));
+    }
+}
@@ -0,0 +1,378 @@
+// src/partition.rs
+
+use crate::block_device::{BlockDevice, BlockError, check_request, validate_block_size};
+use crate::constants::THREADS_DISK_SECTOR_SIZE;
+use crate::fs::{get_u32, put_u32};
+
+/// Magic number at the start of a sector holding a partition table ("RPRT").
+pub const PARTITION_MAGIC: u32 = 0x5250_5254;
+
+/// Maximum number of partitions in a table.
+pub const MAX_PARTITIONS: usize = 8;
+
+/// Maximum length of a partition name in bytes.
+pub const MAX_PARTITION_NAME: usize = 20;
+
+const HEADER_SIZE: usize = 16;
+const ENTRY_SIZE: usize = 32;
+const SECTOR_SIZE: usize = THREADS_DISK_SECTOR_SIZE as usize;
+
+/// Errors returned while reading, writing or opening partitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionError {
+    /// The underlying device reported an error.
+    Block(BlockError),
+    /// Sector 0 does not hold a partition table.
+    NoTable,
+    /// The table already has [`MAX_PARTITIONS`] entries.
+    TableFull,
+    /// A partition has no sectors.
+    Empty,
+    /// A partition covers sector 0 or runs past the end of the disk.
+    OutOfRange,
+    /// Two partitions share a sector.
+    Overlap,
+    /// A partition does not start and end on a block boundary of the device.
+    Misaligned,
+    /// The name is empty, longer than [`MAX_PARTITION_NAME`] or used twice.
+    InvalidName,
+    /// No partition has the requested name.
+    NotFound,
+}
+
+impl From<BlockError> for PartitionError {
+    fn from(error: BlockError) -> Self {
+        PartitionError::Block(error)
+    }
+}
+
+/// One partition: a named run of sectors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionEntry {
+    /// Name, unique within the table.
+    pub name: String,
+    /// First sector of the partition.
+    pub start: u32,
+    /// Length of the partition in sectors.
+    pub sectors: u32,
+}
+
+impl PartitionEntry {
+    fn end(&self) -> u64 {
+        u64::from(self.start) + u64::from(self.sectors)
+    }
+
+    fn overlaps(&self, other: &PartitionEntry) -> bool {
+        u64::from(self.start) < other.end() && u64::from(other.start) < self.end()
+    }
+}
+
+/// A partition table, stored in the first sector of a disk.
+///
+/// The sector holds the magic number, the number of entries and, for each entry, its
+/// start sector, length and name. Sector 0 itself belongs to no partition. Partitions are
+/// counted in 512-byte sectors so that a table does not depend on the block size a disk
+/// is later opened with; [`PartitionDevice::open`] checks that a partition lines up with
+/// the blocks of the device it is opened on.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, PartitionDevice, PartitionTable, ThreadsDisk};
+///
+/// let mut disk = ThreadsDisk::open(DeviceId::disk(0), 1024)?;
+/// let mut table = PartitionTable::new();
+/// table.add("boot", 2, 256)?;
+/// table.add("home", 258, 1200)?;
+/// table.write(&mut disk)?;
+///
+/// let home = PartitionDevice::open(disk, table.find("home")?)?;
+/// let fs = rusty_threads::mkfs(home, Default::default())?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PartitionTable {
+    entries: Vec<PartitionEntry>,
+}
+
+impl PartitionTable {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The partitions, in the order they were added.
+    pub fn entries(&self) -> &[PartitionEntry] {
+        &self.entries
+    }
+
+    /// Looks a partition up by name.
+    pub fn find(&self, name: &str) -> Result<&PartitionEntry, PartitionError> {
+        self.entries.iter().find(|e| e.name == name).ok_or(PartitionError::NotFound)
+    }
+
+    /// Adds a partition of `sectors` sectors starting at sector `start`.
+    ///
+    /// The bounds of the disk are checked by [`PartitionTable::validate`] when the table
+    /// is written.
+    pub fn add(&mut self, name: &str, start: u32, sectors: u32) -> Result<(), PartitionError> {
+        if self.entries.len() == MAX_PARTITIONS {
+            return Err(PartitionError::TableFull);
+        }
+        if name.is_empty() || name.len() > MAX_PARTITION_NAME || self.find(name).is_ok() {
+            return Err(PartitionError::InvalidName);
+        }
+        let entry = PartitionEntry { name: name.to_string(), start, sectors };
+        Self::check_entry(&entry, u64::MAX)?;
+        if self.entries.iter().any(|e| e.overlaps(&entry)) {
+            return Err(PartitionError::Overlap);
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Removes the partition called `name`.
+    pub fn remove(&mut self, name: &str) -> Result<PartitionEntry, PartitionError> {
+        let index =
+            self.entries.iter().position(|e| e.name == name).ok_or(PartitionError::NotFound)?;
+        Ok(self.entries.remove(index))
+    }
+
+    fn check_entry(entry: &PartitionEntry, total_sectors: u64) -> Result<(), PartitionError> {
+        if entry.sectors == 0 {
+            return Err(PartitionError::Empty);
+        }
+        if entry.start == 0 || entry.end() > total_sectors.min(u64::from(u32::MAX)) {
+            return Err(PartitionError::OutOfRange);
+        }
+        Ok(())
+    }
+
+    /// Checks that every partition fits a disk of `total_sectors` sectors, misses sector
+    /// 0 and overlaps no other partition.
+    pub fn validate(&self, total_sectors: u64) -> Result<(), PartitionError> {
+        for (i, entry) in self.entries.iter().enumerate() {
+            Self::check_entry(entry, total_sectors)?;
+            let name_ok = !entry.name.is_empty() && entry.name.len() <= MAX_PARTITION_NAME;
+            if !name_ok || self.entries[..i].iter().any(|e| e.name == entry.name) {
+                return Err(PartitionError::InvalidName);
+            }
+            if self.entries[..i].iter().any(|e| e.overlaps(entry)) {
+                return Err(PartitionError::Overlap);
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes the table into one sector.
+    pub fn encode(&self) -> [u8; SECTOR_SIZE] {
+        let mut sector = [0; SECTOR_SIZE];
+        put_u32(&mut sector, 0, PARTITION_MAGIC);
+        put_u32(&mut sector, 4, self.entries.len() as u32);
+        for (i, entry) in self.entries.iter().enumerate() {
+            let offset = HEADER_SIZE + i * ENTRY_SIZE;
+            put_u32(&mut sector, offset, entry.start);
+            put_u32(&mut sector, offset + 4, entry.sectors);
+            let name = &mut sector[offset + 8..offset + 8 + MAX_PARTITION_NAME];
+            name[..entry.name.len()].copy_from_slice(entry.name.as_bytes());
+        }
+        sector
+    }
+
+    /// Decodes a table from the first sector of a disk with `total_sectors` sectors.
+    ///
+    /// # Returns
+    ///
+    /// `PartitionError::NoTable` if the sector does not start with [`PARTITION_MAGIC`], or
+    /// the [`PartitionTable::validate`] error if the entries are inconsistent.
+    pub fn decode(sector: &[u8], total_sectors: u64) -> Result<Self, PartitionError> {
+        if sector.len() < SECTOR_SIZE || get_u32(sector, 0) != PARTITION_MAGIC {
+            return Err(PartitionError::NoTable);
+        }
+        let count = get_u32(sector, 4) as usize;
+        if count > MAX_PARTITIONS {
+            return Err(PartitionError::TableFull);
+        }
+        let mut entries = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = HEADER_SIZE + i * ENTRY_SIZE;
+            let name = &sector[offset + 8..offset + 8 + MAX_PARTITION_NAME];
+            let length = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            let name = String::from_utf8(name[..length].to_vec())
+                .map_err(|_| PartitionError::InvalidName)?;
+            entries.push(PartitionEntry {
+                name,
+                start: get_u32(sector, offset),
+                sectors: get_u32(sector, offset + 4),
+            });
+        }
+        let table = PartitionTable { entries };
+        table.validate(total_sectors)?;
+        Ok(table)
+    }
+
+    /// Reads the table from block 0 of `device`.
+    pub fn read(device: &mut impl BlockDevice) -> Result<Self, PartitionError> {
+        validate_block_size(device.block_size())?;
+        let mut block = vec![0; device.block_size()];
+        device.read_block(0, &mut block)?;
+        Self::decode(&block, total_sectors(device))
+    }
+
+    /// Validates the table and writes it to the first sector of `device`, leaving the
+    /// rest of block 0 as it was.
+    pub fn write(&self, device: &mut impl BlockDevice) -> Result<(), PartitionError> {
+        validate_block_size(device.block_size())?;
+        self.validate(total_sectors(device))?;
+        let mut block = vec![0; device.block_size()];
+        device.read_block(0, &mut block)?;
+        block[..SECTOR_SIZE].copy_from_slice(&self.encode());
+        device.write_block(0, &block)?;
+        Ok(device.flush()?)
+    }
+}
+
+fn total_sectors(device: &impl BlockDevice) -> u64 {
+    device.block_count() * (device.block_size() / SECTOR_SIZE) as u64
+}
+
+/// One partition of a device, seen as a [`BlockDevice`] of its own.
+///
+/// Block 0 of the view is the first block of the partition, and requests past its end
+/// fail with `BlockError::OutOfRange` instead of reaching the next partition.
+#[derive(Debug, Clone)]
+pub struct PartitionDevice<D: BlockDevice> {
+    device: D,
+    entry: PartitionEntry,
+    first_block: u64,
+    block_count: u64,
+}
+
+impl<D: BlockDevice> PartitionDevice<D> {
+    /// Opens the partition described by `entry` on `device`.
+    ///
+    /// # Returns
+    ///
+    /// `BlockError::InvalidBlockSize` if the blocks of `device` are not whole sectors,
+    /// `PartitionError::Misaligned` if the partition does not start and end on block
+    /// boundaries of `device`, or `PartitionError::OutOfRange` if it runs past the end.
+    pub fn open(device: D, entry: &PartitionEntry) -> Result<Self, PartitionError> {
+        validate_block_size(device.block_size())?;
+        let per_block = (device.block_size() / SECTOR_SIZE) as u64;
+        let (start, sectors) = (u64::from(entry.start), u64::from(entry.sectors));
+        if !start.is_multiple_of(per_block) || !sectors.is_multiple_of(per_block) {
+            return Err(PartitionError::Misaligned);
+        }
+        PartitionTable::check_entry(entry, total_sectors(&device))?;
+        Ok(PartitionDevice {
+            device,
+            entry: entry.clone(),
+            first_block: start / per_block,
+            block_count: sectors / per_block,
+        })
+    }
+
+    /// The partition this view covers.
+    pub fn entry(&self) -> &PartitionEntry {
+        &self.entry
+    }
+
+    /// Returns the whole underlying device.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for PartitionDevice<D> {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        self.device.read_block(self.first_block + block, buffer)
+    }
+
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        self.device.write_block(self.first_block + block, buffer)
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        self.device.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_device::MemBlockDevice;
+
+    #[test]
+    fn test_table_round_trip_and_validation() {
+        let mut table = PartitionTable::new();
+        table.add("boot", 2, 62).unwrap();
+        table.add("data", 64, 192).unwrap();
+        assert_eq!(table.add("over", 60, 8), Err(PartitionError::Overlap));
+        assert_eq!(table.add("mbr", 0, 1), Err(PartitionError::OutOfRange));
+        assert_eq!(table.add("data", 300, 1), Err(PartitionError::InvalidName));
+        assert_eq!(table.add("none", 300, 0), Err(PartitionError::Empty));
+
+        assert_eq!(PartitionTable::decode(&table.encode(), 256), Ok(table.clone()));
+        assert_eq!(PartitionTable::decode(&table.encode(), 200), Err(PartitionError::OutOfRange));
+        assert_eq!(PartitionTable::decode(&[0; 512], 256), Err(PartitionError::NoTable));
+    }
+
+    #[test]
+    fn test_partition_device_is_bounded() {
+        let mut disk = MemBlockDevice::new(1024, 32).unwrap();
+        let mut table = PartitionTable::new();
+        table.add("a", 2, 10).unwrap();
+        table.add("b", 12, 52).unwrap();
+        table.write(&mut disk).unwrap();
+        let table = PartitionTable::read(&mut disk).unwrap();
+
+        let mut a = PartitionDevice::open(disk, table.find("a").unwrap()).unwrap();
+        assert_eq!(a.block_count(), 5);
+        a.write_block(4, &[9; 1024]).unwrap();
+        assert_eq!(a.write_block(5, &[9; 1024]), Err(BlockError::OutOfRange));
+
+        let disk = a.into_inner();
+        assert_eq!(&disk.as_bytes()[5 * 1024..6 * 1024], &[9; 1024]);
+        assert_eq!(&disk.as_bytes()[6 * 1024..7 * 1024], &[0; 1024]);
+        let odd = PartitionEntry { name: "odd".into(), start: 3, sectors: 4 };
+        assert_eq!(PartitionDevice::open(disk, &odd).err(), Some(PartitionError::Misaligned));
+    }
+
+    struct Small;
+
+    impl BlockDevice for Small {
+        fn block_size(&self) -> usize {
+            256
+        }
+
+        fn block_count(&self) -> u64 {
+            64
+        }
+
+        fn read_block(&mut self, _block: u64, _buffer: &mut [u8]) -> Result<(), BlockError> {
+            unreachable!()
+        }
+
+        fn write_block(&mut self, _block: u64, _buffer: &[u8]) -> Result<(), BlockError> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_blocks_smaller_than_a_sector_are_refused() {
+        let error = Some(PartitionError::Block(BlockError::InvalidBlockSize));
+        let entry = PartitionEntry { name: "a".into(), start: 0, sectors: 4 };
+        assert_eq!(PartitionDevice::open(Small, &entry).err(), error);
+        assert_eq!(PartitionTable::read(&mut Small).err(), error);
+        assert_eq!(PartitionTable::new().write(&mut Small).err(), error);
+    }
+}
@@ -0,0 +1,267 @@
+// src/priority.rs
+
+use std::collections::VecDeque;
+
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::rusty_wrapper::system_clock;
+use crate::scheduler::{KERNEL, Pid};
+use crate::sync::MutexProtocol;
+
+/// Maximum number of priority changes kept in the trace; older entries are discarded.
+pub const PRIORITY_TRACE_CAPACITY: usize = 256;
+
+/// Why a process's effective priority changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriorityCause {
+    /// Boosted by a higher-priority process blocking on a priority-inheritance mutex it
+    /// holds, directly or through a chain of owners.
+    Inherited,
+    /// Raised to the ceiling of a priority-ceiling mutex it locked.
+    Ceiling,
+    /// Lowered after releasing a mutex or after a waiter gave up.
+    Restored,
+}
+
+/// One entry in the priority trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriorityChange {
+    /// System clock at the time of the change, in microseconds.
+    pub time: u32,
+    /// The process whose priority changed.
+    pub pid: Pid,
+    /// Effective priority before the change.
+    pub from: u32,
+    /// Effective priority after the change.
+    pub to: u32,
+    /// What caused the change.
+    pub cause: PriorityCause,
+}
+
+static TRACE: KernelCell<VecDeque<PriorityChange>> = KernelCell::new(VecDeque::new());
+
+/// Returns a copy of the recorded priority boosts and restores, oldest first.
+///
+/// Tests can use the trace to check that a priority inversion was bounded, for example
+/// by asserting that every `Inherited` boost of a pid is followed by a `Restored` entry
+/// once the contended mutex is released.
+///
+/// # Example
+///
+/// ```ignore
+/// let boosts = rusty_threads::priority_trace()
+///     .into_iter()
+///     .filter(|c| c.cause == rusty_threads::PriorityCause::Inherited)
+///     .count();
+/// assert_eq!(boosts, 1);
+/// ```
+pub fn priority_trace() -> Vec<PriorityChange> {
+    let guard = InterruptGuard::new();
+    TRACE.with(&guard, |t| t.iter().copied().collect())
+}
+
+/// Discards every recorded priority change.
+pub fn clear_priority_trace() {
+    let guard = InterruptGuard::new();
+    TRACE.with(&guard, |t| t.clear());
+}
+
+fn record(guard: &InterruptGuard, change: PriorityChange) {
+    TRACE.with(guard, |t| {
+        if t.len() == PRIORITY_TRACE_CAPACITY {
+            t.pop_front();
+        }
+        t.push_back(change);
+    });
+}
+
+/// What a priority protocol mutex demands of its owner and why, given the effective
+/// priorities of the processes waiting for it.
+pub(crate) fn protocol_demand(
+    protocol: MutexProtocol,
+    waiters: impl Iterator<Item = u32>,
+) -> (u32, PriorityCause) {
+    match protocol {
+        MutexProtocol::None => (0, PriorityCause::Restored),
+        MutexProtocol::Ceiling(ceiling) => (ceiling, PriorityCause::Ceiling),
+        MutexProtocol::Inheritance => (waiters.max().unwrap_or(0), PriorityCause::Inherited),
+    }
+}
+
+/// The processes and protocol mutexes [`propagate`] walks.
+pub(crate) trait PriorityGraph {
+    /// Returns the base priority of `pid` and the demands of the protocol mutexes it
+    /// holds.
+    fn demands(&self, pid: Pid) -> Option<(u32, Vec<(u32, PriorityCause)>)>;
+    /// Sets the effective priority of `pid`, returning the previous one.
+    fn set_priority(&mut self, pid: Pid, priority: u32) -> Option<u32>;
+    /// Returns the owner of the inheritance mutex `pid` is blocked on.
+    fn blocked_on_owner(&self, pid: Pid) -> Option<Pid>;
+}
+
+/// Recomputes the effective priority of `pid` from its base priority and the protocol
+/// mutexes it holds, then of each owner down the chain of inheritance mutexes it is
+/// blocked on.
+///
+/// # Returns
+///
+/// The changes made, for the trace.
+pub(crate) fn propagate(graph: &mut impl PriorityGraph, pid: Pid, now: u32) -> Vec<PriorityChange> {
+    let mut changes = Vec::new();
+    let mut next = Some(pid);
+
+    while let Some(pid) = next.take() {
+        let Some((base, demands)) = graph.demands(pid) else {
+            break;
+        };
+        let mut target = base;
+        let mut cause = PriorityCause::Restored;
+        for (demand, demand_cause) in demands {
+            if demand > target {
+                target = demand;
+                cause = demand_cause;
+            }
+        }
+
+        // Read before the change: the owner's demand depends on this process's priority.
+        let owner = graph.blocked_on_owner(pid);
+        let Some(from) = graph.set_priority(pid, target) else {
+            break;
+        };
+        if target < from {
+            cause = PriorityCause::Restored;
+        }
+        changes.push(PriorityChange { time: now, pid, from, to: target, cause });
+        // A changed waiter priority changes what it lends to the owner it is waiting on.
+        next = owner;
+    }
+    changes
+}
+
+/// The kernel's process table and the mutexes its processes hold.
+struct Kernel<'g>(&'g InterruptGuard);
+
+impl PriorityGraph for Kernel<'_> {
+    fn demands(&self, pid: Pid) -> Option<(u32, Vec<(u32, PriorityCause)>)> {
+        let (base, held) = KERNEL
+            .with(self.0, |k| k.process(pid).map(|p| (p.base_priority, p.held_mutexes.clone())))?;
+        // SAFETY: protocol mutexes are tracked only while locked, and must not be moved or
+        // dropped while locked.
+        let demands = held.into_iter().map(|m| unsafe { &*m }.priority_demand(self.0)).collect();
+        Some((base, demands))
+    }
+
+    fn set_priority(&mut self, pid: Pid, priority: u32) -> Option<u32> {
+        KERNEL.with(self.0, |k| k.set_priority(pid, priority))
+    }
+
+    fn blocked_on_owner(&self, pid: Pid) -> Option<Pid> {
+        let mutex = KERNEL.with(self.0, |k| k.process(pid).and_then(|p| p.blocked_on))?;
+        // SAFETY: a blocked process keeps a borrow of the mutex it is blocked on.
+        unsafe { &*mutex }.inheritance_owner(self.0)
+    }
+}
+
+/// Recomputes the effective priority of `pid` from its base priority and the protocol
+/// mutexes it holds, then propagates the change along the chain of inheritance mutexes
+/// the process is blocked on.
+///
+/// Must not be called while any mutex's state is borrowed.
+pub(crate) fn refresh_priority(guard: &InterruptGuard, pid: Pid) {
+    for change in propagate(&mut Kernel(guard), pid, system_clock()) {
+        record(guard, change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Processes `0..n` with `(base, priority, blocked_on)`, and mutexes with
+    /// `(protocol, owner, waiters)`.
+    struct Graph {
+        processes: Vec<(u32, u32, Option<usize>)>,
+        mutexes: Vec<(MutexProtocol, Pid, Vec<Pid>)>,
+    }
+
+    impl PriorityGraph for Graph {
+        fn demands(&self, pid: Pid) -> Option<(u32, Vec<(u32, PriorityCause)>)> {
+            let (base, ..) = self.processes.get(pid as usize)?;
+            let demands = self
+                .mutexes
+                .iter()
+                .filter(|(_, owner, _)| *owner == pid)
+                .map(|(protocol, _, waiters)| {
+                    let priorities = waiters.iter().map(|&w| self.processes[w as usize].1);
+                    protocol_demand(*protocol, priorities)
+                })
+                .collect();
+            Some((*base, demands))
+        }
+
+        fn set_priority(&mut self, pid: Pid, priority: u32) -> Option<u32> {
+            let process = self.processes.get_mut(pid as usize)?;
+            Some(core::mem::replace(&mut process.1, priority))
+        }
+
+        fn blocked_on_owner(&self, pid: Pid) -> Option<Pid> {
+            let (protocol, owner, _) = &self.mutexes[self.processes[pid as usize].2?];
+            (*protocol == MutexProtocol::Inheritance).then_some(*owner)
+        }
+    }
+
+    fn change(pid: Pid, from: u32, to: u32, cause: PriorityCause) -> PriorityChange {
+        PriorityChange { time: 7, pid, from, to, cause }
+    }
+
+    #[test]
+    fn test_inheritance_boosts_owner_until_the_waiter_leaves() {
+        let mut graph = Graph {
+            processes: vec![(1, 1, None), (5, 5, Some(0)), (3, 3, Some(0))],
+            mutexes: vec![(MutexProtocol::Inheritance, 0, vec![1, 2])],
+        };
+        assert_eq!(propagate(&mut graph, 0, 7), [change(0, 1, 5, PriorityCause::Inherited)]);
+
+        graph.mutexes[0].2.retain(|&w| w != 1);
+        assert_eq!(propagate(&mut graph, 0, 7), [change(0, 5, 3, PriorityCause::Restored)]);
+        graph.mutexes[0].2.clear();
+        assert_eq!(propagate(&mut graph, 0, 7), [change(0, 3, 1, PriorityCause::Restored)]);
+    }
+
+    #[test]
+    fn test_boosts_follow_a_chain_of_owners() {
+        // 2 waits for mutex 1, held by 1, which waits for mutex 0, held by 0.
+        let mut graph = Graph {
+            processes: vec![(1, 1, None), (2, 2, Some(0)), (6, 6, Some(1))],
+            mutexes: vec![
+                (MutexProtocol::Inheritance, 0, vec![1]),
+                (MutexProtocol::Inheritance, 1, vec![2]),
+            ],
+        };
+        assert_eq!(
+            propagate(&mut graph, 1, 7),
+            [change(1, 2, 6, PriorityCause::Inherited), change(0, 1, 6, PriorityCause::Inherited)]
+        );
+
+        // A ceiling mutex does not pass boosts on to its owner's owner.
+        graph.mutexes[0].0 = MutexProtocol::Ceiling(3);
+        graph.processes[2].1 = 2;
+        assert_eq!(propagate(&mut graph, 1, 7), [change(1, 6, 2, PriorityCause::Restored)]);
+    }
+
+    #[test]
+    fn test_ceiling_raises_owner_unless_a_waiter_demands_more() {
+        let mut graph = Graph {
+            processes: vec![(1, 1, None), (3, 3, Some(1))],
+            mutexes: vec![
+                (MutexProtocol::Ceiling(4), 0, vec![]),
+                (MutexProtocol::Inheritance, 0, vec![1]),
+            ],
+        };
+        assert_eq!(propagate(&mut graph, 0, 7), [change(0, 1, 4, PriorityCause::Ceiling)]);
+        graph.processes[1].1 = 6;
+        assert_eq!(propagate(&mut graph, 0, 7), [change(0, 4, 6, PriorityCause::Inherited)]);
+
+        assert_eq!(protocol_demand(MutexProtocol::None, [9].into_iter()).0, 0);
+        assert_eq!(protocol_demand(MutexProtocol::Inheritance, [].into_iter()).0, 0);
+    }
+}
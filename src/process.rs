@@ -0,0 +1,350 @@
+// src/process.rs
+
+use std::ffi::c_void;
+
+use crate::constants::{PSR_INTERRUPTS, THREADS_MAX_NAME, THREADS_MIN_STACK_SIZE};
+use crate::interrupts::InterruptGuard;
+use crate::mailbox::Mailbox;
+use crate::rusty_wrapper::{context_initialize, context_stop, get_psr, set_psr, stop};
+use crate::scheduler::{
+    self, BlockReason, HIGHEST_PRIORITY, KERNEL, LOWEST_PRIORITY, Pid, ProcessEntry, ProcessState,
+    Scheduler,
+};
+use crate::sync::Mutex;
+use crate::{disk, priority, terminal};
+
+/// Exit code reported to `join` for a process ended by [`kill`].
+pub const KILLED_EXIT_CODE: i32 = -1;
+
+/// Errors returned by the process lifecycle calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessError {
+    /// The call must be made from a running process.
+    NoProcess,
+    /// The priority is outside `LOWEST_PRIORITY..=HIGHEST_PRIORITY`.
+    InvalidPriority,
+    /// The stack is smaller than `THREADS_MIN_STACK_SIZE`.
+    StackTooSmall,
+    /// The name is longer than `THREADS_MAX_NAME` bytes.
+    NameTooLong,
+    /// All `MAX_PROCESSES` process table slots are in use.
+    TooManyProcesses,
+    /// THREADS could not create the process context.
+    ContextFailed,
+    /// No live process has the given pid.
+    NoSuchProcess,
+    /// The given pid is not a child of the calling process.
+    NotChild,
+    /// The calling process has no children to join.
+    NoChildren,
+}
+
+/// Creates a child of the running process and places it on the ready queue.
+///
+/// The child begins executing `entry` with a null argument and interrupts enabled.
+/// Returning from `entry` is the same as calling [`exit`] with the returned value.
+/// Processes spawned from `bootstrap()` have no parent and are reaped as soon as they quit.
+///
+/// # Arguments
+///
+/// * `name` – A human readable name, at most `THREADS_MAX_NAME` bytes.
+/// * `priority` – Scheduling priority, between [`LOWEST_PRIORITY`] and [`HIGHEST_PRIORITY`].
+/// * `stack_size` – Stack size in bytes; must be at least `THREADS_MIN_STACK_SIZE`.
+/// * `entry` – The function the process starts in.
+///
+/// # Returns
+///
+/// The pid of the new process.
+///
+/// # Example
+///
+/// ```ignore
+/// extern "C" fn worker(_args: *mut core::ffi::c_void) -> i32 {
+///     42
+/// }
+///
+/// let pid = rusty_threads::spawn("worker", 3, rusty_threads::THREADS_MIN_STACK_SIZE, worker)?;
+/// assert_eq!(rusty_threads::join_pid(pid)?, 42);
+/// ```
+pub fn spawn(
+    name: &str,
+    priority: u32,
+    stack_size: u32,
+    entry: ProcessEntry,
+) -> Result<Pid, ProcessError> {
+    spawn_with_args(name, priority, stack_size, entry, core::ptr::null_mut())
+}
+
+/// Like [`spawn`], but passes `args` through to `entry` untouched.
+pub fn spawn_with_args(
+    name: &str,
+    priority: u32,
+    stack_size: u32,
+    entry: ProcessEntry,
+    args: *mut c_void,
+) -> Result<Pid, ProcessError> {
+    if !(LOWEST_PRIORITY..=HIGHEST_PRIORITY).contains(&priority) {
+        return Err(ProcessError::InvalidPriority);
+    }
+    spawn_kernel_process(name, priority, stack_size, entry, args)
+}
+
+/// Spawns a process without checking that `priority` is in the user range, for kernel
+/// processes such as the watchdog that run below [`LOWEST_PRIORITY`].
+pub(crate) fn spawn_kernel_process(
+    name: &str,
+    priority: u32,
+    stack_size: u32,
+    entry: ProcessEntry,
+    args: *mut c_void,
+) -> Result<Pid, ProcessError> {
+    if priority > HIGHEST_PRIORITY {
+        return Err(ProcessError::InvalidPriority);
+    }
+    if stack_size < THREADS_MIN_STACK_SIZE {
+        return Err(ProcessError::StackTooSmall);
+    }
+    if name.len() > THREADS_MAX_NAME as usize {
+        return Err(ProcessError::NameTooLong);
+    }
+
+    let guard = InterruptGuard::new();
+    let pid = KERNEL
+        .with(&guard, |k| {
+            let parent = k.current();
+            k.allocate(name, priority, parent, entry, args)
+        })
+        .ok_or(ProcessError::TooManyProcesses)?;
+
+    // The pid, rather than `args`, is handed to the trampoline so it can look up the
+    // real entry point before interrupts are enabled.
+    let context =
+        unsafe { context_initialize(launch, stack_size as i32, pid as usize as *mut c_void) };
+    if context.is_null() {
+        KERNEL.with(&guard, |k| {
+            let parent = k.release(pid).and_then(|p| p.parent);
+            if let Some(parent) = parent.and_then(|p| k.process_mut(p)) {
+                parent.children.retain(|&c| c != pid);
+            }
+        });
+        return Err(ProcessError::ContextFailed);
+    }
+
+    KERNEL.with(&guard, |k| {
+        if let Some(process) = k.process_mut(pid) {
+            process.context = context;
+        }
+        k.enqueue(pid);
+    });
+    drop(guard);
+
+    scheduler::dispatch();
+    Ok(pid)
+}
+
+/// Blocks until any child of the running process quits, then reaps it.
+///
+/// # Returns
+///
+/// The pid and exit code of the child that was reaped.
+///
+/// # Errors
+///
+/// Returns [`ProcessError::NoChildren`] if the caller has no living or zombie children.
+pub fn join() -> Result<(Pid, i32), ProcessError> {
+    join_inner(None)
+}
+
+/// Blocks until the child `pid` quits, then reaps it and returns its exit code.
+///
+/// # Errors
+///
+/// Returns [`ProcessError::NotChild`] if `pid` is not a child of the caller.
+pub fn join_pid(pid: Pid) -> Result<i32, ProcessError> {
+    join_inner(Some(pid)).map(|(_, code)| code)
+}
+
+/// Ends the running process with `code`.
+///
+/// The process becomes a zombie until its parent joins it; its own children are
+/// orphaned and reaped automatically once they quit. Called outside of a process, this
+/// halts THREADS with `code`.
+pub fn exit(code: i32) -> ! {
+    {
+        let guard = InterruptGuard::new();
+        let finished = KERNEL.with(&guard, |k| {
+            let pid = k.current()?;
+            k.finish(pid, code);
+            Some(pid)
+        });
+        if finished.is_none() {
+            stop(code);
+        }
+        scheduler::dispatch();
+    }
+    unreachable!("a process that exited was resumed")
+}
+
+/// Ends the process `pid` as if it had called [`exit`] with [`KILLED_EXIT_CODE`].
+///
+/// A ready or blocked target never runs again. Mutexes it holds stay locked, so kill
+/// processes only at points where that is acceptable (or have them release their locks).
+/// Killing the running process does not return.
+///
+/// # Errors
+///
+/// Returns [`ProcessError::NoSuchProcess`] if `pid` does not exist or has already quit.
+pub fn kill(pid: Pid) -> Result<(), ProcessError> {
+    let guard = InterruptGuard::new();
+    if KERNEL.with(&guard, |k| k.current()) == Some(pid) {
+        exit(KILLED_EXIT_CODE);
+    }
+
+    let (blocked_on, in_mailbox) = KERNEL.with(&guard, |k| finish_killed(k, pid))?;
+
+    // The victim's stack is freed when it is reaped, so no driver may keep a request that
+    // points into it.
+    disk::cancel_requests(&guard, pid);
+    terminal::cancel_requests(&guard, pid);
+
+    // SAFETY: the victim borrows the mailbox until it returns, and it has not returned.
+    if let Some(mailbox) = in_mailbox {
+        unsafe { &*mailbox }.forget(&guard, pid);
+    }
+
+    // A killed waiter no longer lends its priority to the mutex owner.
+    // SAFETY: the mutex cannot be dropped while it still lists the killed waiter.
+    if let Some(owner) = blocked_on.and_then(|mutex| unsafe { &*mutex }.inheritance_owner(&guard)) {
+        priority::refresh_priority(&guard, owner);
+    }
+    drop(guard);
+
+    scheduler::dispatch();
+    Ok(())
+}
+
+/// Marks `pid` quit with [`KILLED_EXIT_CODE`].
+///
+/// # Returns
+///
+/// The mutex and the mailbox the victim was blocked in, which still list it.
+fn finish_killed(
+    k: &mut Scheduler,
+    pid: Pid,
+) -> Result<(Option<*const Mutex>, Option<*const Mailbox>), ProcessError> {
+    let process = k
+        .process(pid)
+        .filter(|p| p.state != ProcessState::Quit)
+        .ok_or(ProcessError::NoSuchProcess)?;
+    let blocked = (process.blocked_on, process.in_mailbox);
+    k.finish(pid, KILLED_EXIT_CODE);
+    Ok(blocked)
+}
+
+/// Returns the parent of `pid`, or `None` for orphans and processes spawned at bootstrap.
+pub fn parent_pid(pid: Pid) -> Option<Pid> {
+    let guard = InterruptGuard::new();
+    KERNEL.with(&guard, |k| k.process(pid).and_then(|p| p.parent))
+}
+
+/// Returns the number of occupied process table slots, zombies included. At most
+/// `MAX_PROCESSES` processes can exist at once.
+pub fn process_count() -> usize {
+    let guard = InterruptGuard::new();
+    KERNEL.with(&guard, |k| k.processes().count())
+}
+
+fn join_inner(target: Option<Pid>) -> Result<(Pid, i32), ProcessError> {
+    let guard = InterruptGuard::new();
+    let me = KERNEL.with(&guard, |k| k.current()).ok_or(ProcessError::NoProcess)?;
+
+    loop {
+        let reaped = KERNEL.with(&guard, |k| {
+            let children = k.process(me).map(|p| p.children.clone()).unwrap_or_default();
+            match target {
+                Some(pid) if !children.contains(&pid) => return Err(ProcessError::NotChild),
+                None if children.is_empty() => return Err(ProcessError::NoChildren),
+                _ => {}
+            }
+
+            let zombie = children.into_iter().find(|&child| {
+                target.is_none_or(|pid| pid == child)
+                    && k.process(child).is_some_and(|p| p.state == ProcessState::Quit)
+            });
+            let Some(zombie) = zombie else {
+                return Ok(None);
+            };
+
+            if let Some(process) = k.process_mut(me) {
+                process.children.retain(|&c| c != zombie);
+            }
+            let child = k.release(zombie).expect("zombie child is in the process table");
+            Ok(Some((zombie, child.exit_code.unwrap_or(0), child.context)))
+        })?;
+
+        match reaped {
+            Some((pid, code, context)) => {
+                if !context.is_null() {
+                    unsafe { context_stop(context) };
+                }
+                return Ok((pid, code));
+            }
+            None => {
+                scheduler::block_current(&guard, BlockReason::Join, None);
+            }
+        }
+    }
+}
+
+extern "C" fn launch(arg: *mut c_void) -> i32 {
+    let pid = arg as usize as Pid;
+    let (entry, args) = {
+        let guard = InterruptGuard::new();
+        KERNEL.with(&guard, |k| {
+            let process = k.process(pid).expect("launched pid is in the process table");
+            (process.entry, process.args)
+        })
+    };
+
+    set_psr(get_psr() | PSR_INTERRUPTS);
+    let code = entry(args);
+    exit(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn noop(_: *mut c_void) -> i32 {
+        0
+    }
+
+    #[test]
+    fn test_spawn_validates_arguments() {
+        let stack = THREADS_MIN_STACK_SIZE;
+        let spawn = |name, priority, stack_size| spawn(name, priority, stack_size, noop);
+        assert_eq!(spawn("low", LOWEST_PRIORITY - 1, stack), Err(ProcessError::InvalidPriority));
+        assert_eq!(spawn("high", HIGHEST_PRIORITY + 1, stack), Err(ProcessError::InvalidPriority));
+        assert_eq!(spawn("small", LOWEST_PRIORITY, stack - 1), Err(ProcessError::StackTooSmall));
+        let long = "x".repeat(THREADS_MAX_NAME as usize + 1);
+        assert_eq!(spawn(&long, LOWEST_PRIORITY, stack), Err(ProcessError::NameTooLong));
+    }
+
+    #[test]
+    fn test_killed_process_quits_once_and_reports_what_it_was_blocked_in() {
+        let mut k = Scheduler::new();
+        let parent = k.allocate("parent", 3, None, noop, core::ptr::null_mut()).unwrap();
+        let pid = k.allocate("victim", 2, Some(parent), noop, core::ptr::null_mut()).unwrap();
+        let mutex: *const Mutex = core::ptr::dangling();
+        let victim = k.process_mut(pid).unwrap();
+        victim.state = ProcessState::Blocked(BlockReason::Mutex);
+        victim.blocked_on = Some(mutex);
+
+        assert_eq!(finish_killed(&mut k, pid), Ok((Some(mutex), None)));
+        let victim = k.process(pid).unwrap();
+        assert_eq!((victim.state, victim.exit_code), (ProcessState::Quit, Some(KILLED_EXIT_CODE)));
+        // The zombie waits for its parent, and cannot be killed a second time.
+        assert_eq!(finish_killed(&mut k, pid), Err(ProcessError::NoSuchProcess));
+        assert_eq!(finish_killed(&mut k, pid + 100), Err(ProcessError::NoSuchProcess));
+    }
+}
@@ -0,0 +1,622 @@
+// src/raid.rs
+
+use core::cell::UnsafeCell;
+use std::ffi::c_void;
+
+use crate::block_device::{BlockDevice, BlockError, check_request};
+use crate::constants::{THREADS_MAX_DISKS, THREADS_MIN_STACK_SIZE};
+use crate::process::{ProcessError, spawn_kernel_process};
+use crate::scheduler::Pid;
+use crate::sync::{Mutex, SyncError, WakeOrder};
+
+/// Rows a rebuild process copies before releasing the array to other processes.
+pub const REBUILD_ROWS_PER_STEP: u64 = 16;
+
+/// How a [`Raid`] spreads blocks over its disks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaidLevel {
+    /// Striping without redundancy: block `b` lives on disk `b % n`.
+    Raid0,
+    /// Mirroring: every disk holds every block.
+    Raid1,
+    /// Striping with one rotating parity block per row, so any one disk can be lost.
+    Raid5,
+}
+
+impl RaidLevel {
+    fn min_disks(self) -> usize {
+        match self {
+            RaidLevel::Raid0 | RaidLevel::Raid1 => 2,
+            RaidLevel::Raid5 => 3,
+        }
+    }
+}
+
+/// State of one disk in a [`Raid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemberState {
+    /// The disk holds current data.
+    Online,
+    /// The disk is not used; its blocks are reconstructed from the others.
+    Failed,
+    /// The disk is being rebuilt; rows below `next_row` are current.
+    Rebuilding {
+        /// First row not yet rebuilt.
+        next_row: u64,
+    },
+}
+
+/// Errors returned when building or managing a [`Raid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaidError {
+    /// A member device reported an error.
+    Block(BlockError),
+    /// The level needs more disks than were given.
+    TooFewDisks,
+    /// More than `THREADS_MAX_DISKS` disks were given.
+    TooManyDisks,
+    /// The disks do not share a block size, or a replacement disk is too small.
+    MismatchedDisks,
+    /// RAID-0 has no redundancy, so a disk cannot be failed or rebuilt.
+    NoRedundancy,
+    /// There is no disk with the given index.
+    NoSuchDisk,
+    /// The disk is not failed, so it cannot be replaced or rebuilt.
+    NotFailed,
+    /// Locking a [`SharedRaid`] failed.
+    Sync(SyncError),
+    /// A [`SharedRaid`] has no array installed.
+    NotInstalled,
+}
+
+impl From<BlockError> for RaidError {
+    fn from(error: BlockError) -> Self {
+        RaidError::Block(error)
+    }
+}
+
+struct Member<D> {
+    device: D,
+    state: MemberState,
+}
+
+impl<D> Member<D> {
+    /// Returns `true` if block `row` of this disk holds current data.
+    fn readable(&self, row: u64) -> bool {
+        match self.state {
+            MemberState::Online => true,
+            MemberState::Failed => false,
+            MemberState::Rebuilding { next_row } => row < next_row,
+        }
+    }
+
+    fn writable(&self) -> bool {
+        self.state != MemberState::Failed
+    }
+}
+
+/// A member that failed during a request, so the request can be retried around it.
+struct Fault(usize, BlockError);
+
+/// A software RAID array built from several block devices, itself a [`BlockDevice`].
+///
+/// Blocks are striped one device block at a time. Row `r` of the array is block `r` of
+/// every disk; RAID-5 puts the parity of row `r` on disk `r % n` and the row's data on
+/// the disks after it.
+///
+/// A disk that returns an I/O error is marked [`MemberState::Failed`] and the request is
+/// retried without it, so RAID-1 and RAID-5 keep serving reads and writes in degraded
+/// mode. A failed disk is brought back with [`Raid::replace_disk`] or
+/// [`Raid::readd_disk`] and then rebuilt row by row with [`Raid::rebuild_step`], normally
+/// from the kernel process started by [`spawn_rebuild`].
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, Raid, RaidLevel, ThreadsDisk};
+///
+/// let disks = (0..3)
+///     .map(|unit| ThreadsDisk::open(DeviceId::disk(unit), 1024))
+///     .collect::<Result<Vec<_>, _>>()?;
+/// let mut array = Raid::new(RaidLevel::Raid5, disks)?;
+/// array.write_block(7, &[0xAB; 1024])?;
+/// array.fail_disk(1)?;
+/// let mut block = [0u8; 1024];
+/// array.read_block(7, &mut block)?; // reconstructed from parity if needed
+/// ```
+pub struct Raid<D: BlockDevice> {
+    level: RaidLevel,
+    members: Vec<Member<D>>,
+    rows: u64,
+}
+
+impl<D: BlockDevice> Raid<D> {
+    /// Builds an array of `level` from `disks`. Every disk must have the same block
+    /// size; the array uses as many rows as the smallest disk has blocks.
+    pub fn new(level: RaidLevel, disks: Vec<D>) -> Result<Self, RaidError> {
+        if disks.len() < level.min_disks() {
+            return Err(RaidError::TooFewDisks);
+        }
+        if disks.len() > THREADS_MAX_DISKS as usize {
+            return Err(RaidError::TooManyDisks);
+        }
+        let block_size = disks[0].block_size();
+        if disks.iter().any(|d| d.block_size() != block_size) {
+            return Err(RaidError::MismatchedDisks);
+        }
+        let rows = disks.iter().map(|d| d.block_count()).min().unwrap_or(0);
+        let members =
+            disks.into_iter().map(|device| Member { device, state: MemberState::Online }).collect();
+        Ok(Raid { level, members, rows })
+    }
+
+    /// The RAID level.
+    pub fn level(&self) -> RaidLevel {
+        self.level
+    }
+
+    /// Number of disks in the array.
+    pub fn disk_count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// State of disk `index`.
+    pub fn disk_state(&self, index: usize) -> Option<MemberState> {
+        self.members.get(index).map(|m| m.state)
+    }
+
+    /// The device behind disk `index`.
+    pub fn disk(&self, index: usize) -> Option<&D> {
+        self.members.get(index).map(|m| &m.device)
+    }
+
+    /// Returns `true` if some disk is failed or rebuilding.
+    pub fn is_degraded(&self) -> bool {
+        self.members.iter().any(|m| m.state != MemberState::Online)
+    }
+
+    /// Number of rows: blocks per disk used by the array.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Returns the member devices.
+    pub fn into_disks(self) -> Vec<D> {
+        self.members.into_iter().map(|m| m.device).collect()
+    }
+
+    fn member(&mut self, index: usize) -> Result<&mut Member<D>, RaidError> {
+        self.members.get_mut(index).ok_or(RaidError::NoSuchDisk)
+    }
+
+    /// Marks disk `index` failed. Reads of its blocks are served from the other disks.
+    pub fn fail_disk(&mut self, index: usize) -> Result<(), RaidError> {
+        if self.level == RaidLevel::Raid0 {
+            return Err(RaidError::NoRedundancy);
+        }
+        self.member(index)?.state = MemberState::Failed;
+        Ok(())
+    }
+
+    /// Swaps a failed disk for `device` and starts rebuilding onto it.
+    ///
+    /// # Returns
+    ///
+    /// The old device.
+    pub fn replace_disk(&mut self, index: usize, device: D) -> Result<D, RaidError> {
+        let block_size = self.block_size();
+        if device.block_size() != block_size || device.block_count() < self.rows {
+            return Err(RaidError::MismatchedDisks);
+        }
+        self.readd_disk(index)?;
+        Ok(core::mem::replace(&mut self.member(index)?.device, device))
+    }
+
+    /// Starts rebuilding a failed disk in place, e.g. after a transient fault.
+    pub fn readd_disk(&mut self, index: usize) -> Result<(), RaidError> {
+        if self.level == RaidLevel::Raid0 {
+            return Err(RaidError::NoRedundancy);
+        }
+        let member = self.member(index)?;
+        if member.state != MemberState::Failed {
+            return Err(RaidError::NotFailed);
+        }
+        member.state = MemberState::Rebuilding { next_row: 0 };
+        Ok(())
+    }
+
+    /// Rebuilds up to `max_rows` rows of the first disk being rebuilt, from the other
+    /// disks. A disk becomes [`MemberState::Online`] once its last row is written.
+    ///
+    /// # Returns
+    ///
+    /// `true` once no disk is left to rebuild.
+    pub fn rebuild_step(&mut self, max_rows: u64) -> Result<bool, RaidError> {
+        let Some((index, next_row)) =
+            self.members.iter().enumerate().find_map(|(i, m)| match m.state {
+                MemberState::Rebuilding { next_row } => Some((i, next_row)),
+                _ => None,
+            })
+        else {
+            return Ok(true);
+        };
+
+        let end = (next_row + max_rows).min(self.rows);
+        let mut block = vec![0; self.block_size()];
+        for row in next_row..end {
+            loop {
+                match self.reconstruct(index, row, &mut block) {
+                    Ok(()) => break,
+                    // A source disk broke: drop it and try the remaining ones.
+                    Err(Fault(member, error))
+                        if error != BlockError::Unavailable && self.members[member].writable() =>
+                    {
+                        self.members[member].state = MemberState::Failed;
+                    }
+                    Err(Fault(_, error)) => return Err(error.into()),
+                }
+            }
+            if let Err(error) = self.members[index].device.write_block(row, &block) {
+                self.members[index].state = MemberState::Failed;
+                return Err(error.into());
+            }
+            self.members[index].state = MemberState::Rebuilding { next_row: row + 1 };
+        }
+        if end == self.rows {
+            self.members[index].device.flush()?;
+            self.members[index].state = MemberState::Online;
+        }
+        Ok(!self.members.iter().any(|m| matches!(m.state, MemberState::Rebuilding { .. })))
+    }
+
+    /// Computes block `row` of disk `index` from the other disks.
+    fn reconstruct(&mut self, index: usize, row: u64, buffer: &mut [u8]) -> Result<(), Fault> {
+        match self.level {
+            RaidLevel::Raid0 => Err(Fault(index, BlockError::Unavailable)),
+            RaidLevel::Raid1 => {
+                let source = (0..self.members.len())
+                    .find(|&i| i != index && self.members[i].readable(row))
+                    .ok_or(Fault(index, BlockError::Unavailable))?;
+                self.read_member(source, row, buffer)
+            }
+            RaidLevel::Raid5 => {
+                let others: Vec<usize> = (0..self.members.len()).filter(|&i| i != index).collect();
+                self.xor_members(&others, row, buffer)
+            }
+        }
+    }
+
+    fn read_member(&mut self, index: usize, row: u64, buffer: &mut [u8]) -> Result<(), Fault> {
+        self.members[index].device.read_block(row, buffer).map_err(|e| Fault(index, e))
+    }
+
+    fn write_member(&mut self, index: usize, row: u64, buffer: &[u8]) -> Result<(), Fault> {
+        if !self.members[index].writable() {
+            return Ok(());
+        }
+        self.members[index].device.write_block(row, buffer).map_err(|e| Fault(index, e))
+    }
+
+    /// XORs block `row` of every disk in `members` into `buffer`. Every one must be
+    /// readable.
+    fn xor_members(&mut self, members: &[usize], row: u64, buffer: &mut [u8]) -> Result<(), Fault> {
+        buffer.fill(0);
+        let mut scratch = vec![0; buffer.len()];
+        for &member in members {
+            if !self.members[member].readable(row) {
+                return Err(Fault(member, BlockError::Unavailable));
+            }
+            self.read_member(member, row, &mut scratch)?;
+            xor_into(buffer, &scratch);
+        }
+        Ok(())
+    }
+
+    /// Disk and row holding array block `block`, plus the parity disk for RAID-5.
+    fn locate(&self, block: u64) -> (usize, u64, Option<usize>) {
+        let n = self.members.len() as u64;
+        match self.level {
+            RaidLevel::Raid0 => ((block % n) as usize, block / n, None),
+            RaidLevel::Raid1 => (0, block, None),
+            RaidLevel::Raid5 => {
+                let row = block / (n - 1);
+                let parity = row % n;
+                let disk = (parity + 1 + block % (n - 1)) % n;
+                (disk as usize, row, Some(parity as usize))
+            }
+        }
+    }
+
+    fn try_read(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), Fault> {
+        let (disk, row, _) = self.locate(block);
+        match self.level {
+            RaidLevel::Raid0 => self.read_member(disk, row, buffer),
+            RaidLevel::Raid1 => {
+                let source = (0..self.members.len())
+                    .find(|&i| self.members[i].readable(row))
+                    .ok_or(Fault(disk, BlockError::Unavailable))?;
+                self.read_member(source, row, buffer)
+            }
+            RaidLevel::Raid5 if self.members[disk].readable(row) => {
+                self.read_member(disk, row, buffer)
+            }
+            RaidLevel::Raid5 => self.reconstruct(disk, row, buffer),
+        }
+    }
+
+    fn try_write(&mut self, block: u64, buffer: &[u8]) -> Result<(), Fault> {
+        let (disk, row, parity) = self.locate(block);
+        let Some(parity) = parity else {
+            let targets: Vec<usize> = match self.level {
+                RaidLevel::Raid0 => vec![disk],
+                _ => (0..self.members.len()).collect(),
+            };
+            if !targets.iter().any(|&i| self.members[i].writable()) {
+                return Err(Fault(disk, BlockError::Unavailable));
+            }
+            for i in targets {
+                self.write_member(i, row, buffer)?;
+            }
+            return Ok(());
+        };
+
+        let mut new_parity = vec![0; buffer.len()];
+        if self.members[disk].readable(row) && self.members[parity].readable(row) {
+            // Read-modify-write: parity ^= old data ^ new data.
+            let mut old = vec![0; buffer.len()];
+            self.read_member(disk, row, &mut old)?;
+            self.read_member(parity, row, &mut new_parity)?;
+            xor_into(&mut new_parity, &old);
+        } else {
+            // Reconstruct-write from the rest of the row.
+            let others: Vec<usize> =
+                (0..self.members.len()).filter(|&i| i != disk && i != parity).collect();
+            self.xor_members(&others, row, &mut new_parity)?;
+        }
+        xor_into(&mut new_parity, buffer);
+        if !self.members[disk].writable() && !self.members[parity].writable() {
+            return Err(Fault(disk, BlockError::Unavailable));
+        }
+        self.write_member(disk, row, buffer)?;
+        self.write_member(parity, row, &new_parity)
+    }
+
+    /// Runs `attempt`, failing any disk that reports an error and retrying without it
+    /// while the array still has redundancy.
+    fn retry<R>(
+        &mut self,
+        mut attempt: impl FnMut(&mut Self) -> Result<R, Fault>,
+    ) -> Result<R, BlockError> {
+        loop {
+            match attempt(self) {
+                Ok(value) => return Ok(value),
+                Err(Fault(_, error @ (BlockError::Unavailable | BlockError::BufferSize))) => {
+                    return Err(error);
+                }
+                Err(Fault(index, error)) => {
+                    if self.level == RaidLevel::Raid0 || !self.members[index].writable() {
+                        return Err(error);
+                    }
+                    self.members[index].state = MemberState::Failed;
+                }
+            }
+        }
+    }
+}
+
+fn xor_into(target: &mut [u8], source: &[u8]) {
+    for (t, s) in target.iter_mut().zip(source) {
+        *t ^= s;
+    }
+}
+
+impl<D: BlockDevice> BlockDevice for Raid<D> {
+    fn block_size(&self) -> usize {
+        self.members[0].device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        let n = self.members.len() as u64;
+        match self.level {
+            RaidLevel::Raid0 => self.rows * n,
+            RaidLevel::Raid1 => self.rows,
+            RaidLevel::Raid5 => self.rows * (n - 1),
+        }
+    }
+
+    fn read_block(&mut self, block: u64, buffer: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        self.retry(|raid| raid.try_read(block, buffer))
+    }
+
+    fn write_block(&mut self, block: u64, buffer: &[u8]) -> Result<(), BlockError> {
+        check_request(self, block, buffer.len())?;
+        self.retry(|raid| raid.try_write(block, buffer))
+    }
+
+    fn flush(&mut self) -> Result<(), BlockError> {
+        for member in self.members.iter_mut().filter(|m| m.writable()) {
+            member.device.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// A [`Raid`] shared between kernel processes behind a kernel [`Mutex`].
+///
+/// Declare it as a `static`, [`SharedRaid::install`] the array from `bootstrap()` or the
+/// first process, and reach it with [`SharedRaid::with`]. The rebuild process started by
+/// [`spawn_rebuild`] takes the lock for [`REBUILD_ROWS_PER_STEP`] rows at a time, so
+/// other processes keep using the array while it is rebuilt.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{SharedRaid, ThreadsDisk};
+///
+/// static ARRAY: SharedRaid<ThreadsDisk> = SharedRaid::new();
+///
+/// ARRAY.install(array)?;
+/// ARRAY.with(|raid| raid.replace_disk(1, spare))??;
+/// rusty_threads::spawn_rebuild(&ARRAY, 2)?;
+/// ```
+pub struct SharedRaid<D: BlockDevice> {
+    lock: Mutex,
+    raid: UnsafeCell<Option<Raid<D>>>,
+}
+
+// SAFETY: the array is only touched by the process holding `lock`.
+unsafe impl<D: BlockDevice> Sync for SharedRaid<D> {}
+
+impl<D: BlockDevice> SharedRaid<D> {
+    /// Creates an empty slot.
+    pub const fn new() -> Self {
+        SharedRaid { lock: Mutex::new(WakeOrder::Priority), raid: UnsafeCell::new(None) }
+    }
+
+    /// Stores `raid`, replacing any array installed before.
+    pub fn install(&self, raid: Raid<D>) -> Result<(), RaidError> {
+        self.with_slot(|slot| *slot = Some(raid))
+    }
+
+    /// Removes and returns the array.
+    pub fn take(&self) -> Result<Option<Raid<D>>, RaidError> {
+        self.with_slot(Option::take)
+    }
+
+    /// Runs `f` on the array while holding its lock. `f` may block on disk I/O.
+    pub fn with<R>(&self, f: impl FnOnce(&mut Raid<D>) -> R) -> Result<R, RaidError> {
+        self.with_slot(|slot| slot.as_mut().map(f))?.ok_or(RaidError::NotInstalled)
+    }
+
+    fn with_slot<R>(&self, f: impl FnOnce(&mut Option<Raid<D>>) -> R) -> Result<R, RaidError> {
+        self.lock.lock().map_err(RaidError::Sync)?;
+        // SAFETY: the caller owns `lock`, so no other reference to the slot exists.
+        let result = f(unsafe { &mut *self.raid.get() });
+        self.lock.unlock().map_err(RaidError::Sync)?;
+        Ok(result)
+    }
+}
+
+impl<D: BlockDevice> Default for SharedRaid<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Spawns a kernel process, `raid-rebuild`, that rebuilds every disk of `array` that is
+/// being rebuilt and then quits.
+///
+/// The process runs at `priority`, which may be below the user range so the rebuild only
+/// uses idle time. It exits with 0 once the array is fully online, or 1 if the rebuild
+/// failed, for instance because a second disk was lost.
+pub fn spawn_rebuild<D: BlockDevice + 'static>(
+    array: &'static SharedRaid<D>,
+    priority: u32,
+) -> Result<Pid, ProcessError> {
+    let args = array as *const SharedRaid<D> as *mut c_void;
+    spawn_kernel_process("raid-rebuild", priority, THREADS_MIN_STACK_SIZE, rebuild_main::<D>, args)
+}
+
+extern "C" fn rebuild_main<D: BlockDevice + 'static>(args: *mut c_void) -> i32 {
+    // SAFETY: `spawn_rebuild` passes a `&'static SharedRaid<D>`.
+    let array = unsafe { &*(args as *const SharedRaid<D>) };
+    loop {
+        match array.with(|raid| raid.rebuild_step(REBUILD_ROWS_PER_STEP)) {
+            Ok(Ok(true)) => return 0,
+            Ok(Ok(false)) => {}
+            _ => return 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_device::MemBlockDevice;
+
+    fn array(level: RaidLevel, disks: usize) -> Raid<MemBlockDevice> {
+        let disks = (0..disks).map(|_| MemBlockDevice::new(512, 8).unwrap()).collect();
+        Raid::new(level, disks).unwrap()
+    }
+
+    fn fill(raid: &mut Raid<MemBlockDevice>) {
+        for block in 0..raid.block_count() {
+            raid.write_block(block, &[block as u8 + 1; 512]).unwrap();
+        }
+    }
+
+    fn verify(raid: &mut Raid<MemBlockDevice>) {
+        let mut buffer = [0; 512];
+        for block in 0..raid.block_count() {
+            raid.read_block(block, &mut buffer).unwrap();
+            assert_eq!(buffer, [block as u8 + 1; 512], "block {block}");
+        }
+    }
+
+    #[test]
+    fn test_layout_and_capacity() {
+        assert_eq!(array(RaidLevel::Raid0, 3).block_count(), 24);
+        assert_eq!(array(RaidLevel::Raid1, 2).block_count(), 8);
+        assert_eq!(array(RaidLevel::Raid5, 4).block_count(), 24);
+        let one = vec![MemBlockDevice::new(512, 8).unwrap()];
+        assert_eq!(Raid::new(RaidLevel::Raid5, one).err(), Some(RaidError::TooFewDisks));
+
+        let mut raid = array(RaidLevel::Raid0, 2);
+        fill(&mut raid);
+        let disks = raid.into_disks();
+        assert_eq!(&disks[1].as_bytes()[..512], &[2; 512]);
+        assert_eq!(&disks[0].as_bytes()[512..1024], &[3; 512]);
+    }
+
+    #[test]
+    fn test_raid5_parity_and_degraded_reads() {
+        let mut raid = array(RaidLevel::Raid5, 3);
+        fill(&mut raid);
+        // Row 0 holds blocks 0 and 1 on disks 1 and 2, parity on disk 0.
+        assert_eq!(&raid.disk(0).unwrap().as_bytes()[..512], &[1 ^ 2; 512]);
+
+        for failed in 0..3 {
+            raid.fail_disk(failed).unwrap();
+            assert!(raid.is_degraded());
+            verify(&mut raid);
+            raid.write_block(4, &[5; 512]).unwrap();
+            raid.readd_disk(failed).unwrap();
+            assert!(raid.rebuild_step(u64::MAX).unwrap());
+        }
+        raid.fail_disk(0).unwrap();
+        raid.fail_disk(1).unwrap();
+        assert_eq!(raid.read_block(0, &mut [0; 512]), Err(BlockError::Unavailable));
+    }
+
+    #[test]
+    fn test_rebuild_onto_replacement() {
+        for (level, disks) in [(RaidLevel::Raid1, 2), (RaidLevel::Raid5, 4)] {
+            let mut raid = array(level, disks);
+            fill(&mut raid);
+            raid.fail_disk(1).unwrap();
+            assert_eq!(
+                raid.replace_disk(0, MemBlockDevice::new(512, 8).unwrap()).err(),
+                Some(RaidError::NotFailed)
+            );
+            raid.replace_disk(1, MemBlockDevice::new(512, 8).unwrap()).unwrap();
+
+            assert!(!raid.rebuild_step(3).unwrap());
+            assert_eq!(raid.disk_state(1), Some(MemberState::Rebuilding { next_row: 3 }));
+            verify(&mut raid);
+            while !raid.rebuild_step(3).unwrap() {}
+            assert!(!raid.is_degraded());
+
+            // The rebuilt disk alone must now be enough to serve its share.
+            raid.fail_disk(0).unwrap();
+            verify(&mut raid);
+        }
+    }
+
+    #[test]
+    fn test_raid0_has_no_redundancy() {
+        let mut raid = array(RaidLevel::Raid0, 2);
+        assert_eq!(raid.fail_disk(0), Err(RaidError::NoRedundancy));
+        assert_eq!(raid.rebuild_step(1), Ok(true));
+    }
+}
@@ -193,6 +193,12 @@ pub fn system_clock() -> u32 {
 ///
 /// Returns `None` if the device name is invalid or the device could not be initialized.
 pub fn device_initialize(device_name: &str) -> Option<u32> {
+    if crate::hotplug::is_detached(device_name) {
+        return None;
+    }
+    if let Some(result) = crate::custom_device::initialize(device_name) {
+        return result;
+    }
     // Convert the device name to a C-compatible string, ensuring it doesn't contain null bytes.
     let c_str = CString::new(device_name).expect("Device name contains null byte");
     // Call the C function to initialize the device.
@@ -227,6 +233,12 @@ pub fn device_initialize(device_name: &str) -> Option<u32> {
 /// let handle = rusty_threads::device_handle("disk0").expect("Device not found");
 /// ```
 pub fn device_handle(device_name: &str) -> Option<u32> {
+    if crate::hotplug::is_detached(device_name) {
+        return None;
+    }
+    if let Some(result) = crate::custom_device::handle_of(device_name) {
+        return result;
+    }
     let c_str = CString::new(device_name).expect("Device name contains null byte");
     let handle = unsafe { c_device_handle(c_str.as_ptr() as *mut _) };
     if handle == u32::MAX {
@@ -278,6 +290,22 @@ pub fn device_handle(device_name: &str) -> Option<u32> {
 /// assert!(result.is_some());
 /// ```
 pub fn device_control(device_name: &str, control_block: device_control_block_t) -> Option<u32> {
+    let (command, length) = (control_block.command, control_block.data_length);
+    // Held across the call so the completion cannot be counted before the issue.
+    let guard = crate::interrupts::InterruptGuard::new();
+    let result = control_device(device_name, control_block);
+    crate::io_stats::record_control(&guard, device_name, command, length, result.is_some());
+    result
+}
+
+fn control_device(device_name: &str, control_block: device_control_block_t) -> Option<u32> {
+    if crate::hotplug::is_detached(device_name) {
+        return None;
+    }
+    let now = system_clock();
+    if let Some(result) = crate::custom_device::control(device_name, control_block, now) {
+        return result;
+    }
     let c_str = CString::new(device_name).expect("Device name contains null byte");
     let result = unsafe {
         c_device_control(c_str.as_ptr() as *mut _, control_block)
@@ -330,6 +358,9 @@ pub fn set_debug_level(level: i32) {
 /// rusty_threads::console_output(true, "Debug: System initialized.");
 /// ```
 pub fn console_output(debug: bool, message: &str) {
+    if crate::console_view::capture_console(debug, message) {
+        return;
+    }
     if let Ok(c_str) = CString::new(message) {
         unsafe {
             crate::rusty_thread_bindings::c_console_output(
@@ -372,6 +403,8 @@ pub fn console_output(debug: bool, message: &str) {
 /// rusty_threads::stop(1);
 /// ```
 pub fn stop(code: i32) -> ! {
+    crate::console_view::stop_console_view();
+    crate::io_stats::on_stop();
     unsafe {
         crate::rusty_thread_bindings::c_stop(code);
     }
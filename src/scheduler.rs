@@ -0,0 +1,700 @@
+// src/scheduler.rs
+
+use std::collections::VecDeque;
+use std::ffi::c_void;
+use std::os::raw::c_char;
+
+use crate::constants::{MAX_PROCESSES, PSR_INTERRUPTS, THREADS_TIMER_INTERRUPT};
+use crate::device;
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::mailbox::Mailbox;
+use crate::rusty_wrapper::{
+    console_output, context_stop, context_switch, get_interrupt_handlers, get_psr, set_psr, stop,
+    system_clock,
+};
+use crate::sync::Mutex;
+use crate::watchdog;
+
+/// Process identifier handed out by [`crate::process::spawn`].
+pub type Pid = u32;
+
+/// Entry point of a kernel process, following the THREADS `process_entrypoint_t` signature.
+pub type ProcessEntry = extern "C" fn(*mut c_void) -> i32;
+
+/// Highest priority a process may be spawned with.
+pub const HIGHEST_PRIORITY: u32 = 5;
+
+/// Lowest priority a process may be spawned with.
+pub const LOWEST_PRIORITY: u32 = 1;
+
+/// Length of a round-robin time slice between processes of equal priority, in microseconds.
+pub const TIME_SLICE: u32 = 80_000;
+
+const PRIORITY_LEVELS: usize = HIGHEST_PRIORITY as usize + 1;
+
+/// Why a process is blocked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockReason {
+    /// Waiting in [`crate::sync::Semaphore::wait`].
+    Semaphore,
+    /// Waiting in [`crate::sync::Mutex::lock`].
+    Mutex,
+    /// Waiting in [`crate::sync::Condvar::wait`].
+    Condvar,
+    /// Waiting in [`crate::mailbox::Mailbox::send`] for a free slot or a receiver.
+    MailboxSend,
+    /// Waiting in [`crate::mailbox::Mailbox::receive`] for a message.
+    MailboxReceive,
+    /// Waiting in [`crate::process::join`] for a child to quit.
+    Join,
+    /// Waiting for a disk transfer started by [`crate::disk::read_sectors`] or
+    /// [`crate::disk::write_sectors`].
+    DiskIo,
+    /// Waiting in [`crate::terminal::read_terminal`] for input or in
+    /// [`crate::terminal::write_terminal`] for room in the output buffer.
+    TerminalIo,
+}
+
+/// Scheduling state of a process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    /// Runnable and waiting in a ready queue.
+    Ready,
+    /// Currently executing on the CPU.
+    Running,
+    /// Waiting for an event; not eligible to run.
+    Blocked(BlockReason),
+    /// Finished executing. Until its parent joins it, the process is a zombie that keeps
+    /// its exit code and process table slot.
+    Quit,
+}
+
+/// How a blocked process was woken up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WakeReason {
+    /// Another process made it ready.
+    Woken,
+    /// Its timeout expired first.
+    TimedOut,
+}
+
+pub(crate) struct Process {
+    pub(crate) pid: Pid,
+    pub(crate) name: String,
+    /// Effective priority used for scheduling; may be boosted above `base_priority`.
+    pub(crate) priority: u32,
+    /// Priority the process was spawned with.
+    pub(crate) base_priority: u32,
+    pub(crate) state: ProcessState,
+    pub(crate) parent: Option<Pid>,
+    pub(crate) children: Vec<Pid>,
+    pub(crate) exit_code: Option<i32>,
+    /// Protocol mutexes (inheritance or ceiling) currently held.
+    pub(crate) held_mutexes: Vec<*const Mutex>,
+    /// The priority-inheritance mutex this process is blocked on, if any.
+    pub(crate) blocked_on: Option<*const Mutex>,
+    /// The mailbox this process is blocked in, or was woken from but has not returned from.
+    pub(crate) in_mailbox: Option<*const Mailbox>,
+    pub(crate) context: *mut c_void,
+    pub(crate) entry: ProcessEntry,
+    pub(crate) args: *mut c_void,
+    deadline: Option<u32>,
+    timed_out: bool,
+    /// Total time spent on the CPU before the current burst, in microseconds.
+    pub(crate) cpu_time: u32,
+    /// Clock value when the process was last switched in.
+    pub(crate) running_since: u32,
+}
+
+impl Process {
+    /// Returns `true` if something other than another process is going to wake this
+    /// process, such as an expiring timeout or an I/O completion.
+    pub(crate) fn awaits_event(&self) -> bool {
+        self.deadline.is_some()
+            || matches!(
+                self.state,
+                ProcessState::Blocked(BlockReason::DiskIo | BlockReason::TerminalIo)
+            )
+    }
+}
+
+/// What the dispatcher decided to do.
+enum Dispatch {
+    Stay,
+    Switch(*mut c_void),
+    Idle,
+}
+
+pub(crate) struct Scheduler {
+    processes: Vec<Option<Process>>,
+    ready: [VecDeque<Pid>; PRIORITY_LEVELS],
+    current: Option<Pid>,
+    next_pid: Pid,
+    slice_start: u32,
+    started: bool,
+    /// A process's [`dispatch`] is waiting for an interrupt because nothing was runnable.
+    idling: bool,
+}
+
+pub(crate) static KERNEL: KernelCell<Scheduler> = KernelCell::new(Scheduler::new());
+
+/// Returns `true` once the clock has reached `deadline`, tolerating wrap-around of the
+/// 32-bit microsecond counter.
+pub(crate) fn deadline_passed(now: u32, deadline: u32) -> bool {
+    (now.wrapping_sub(deadline) as i32) >= 0
+}
+
+impl Scheduler {
+    pub(crate) const fn new() -> Self {
+        Scheduler {
+            processes: Vec::new(),
+            ready: [const { VecDeque::new() }; PRIORITY_LEVELS],
+            current: None,
+            next_pid: 1,
+            slice_start: 0,
+            started: false,
+            idling: false,
+        }
+    }
+
+    fn slot(pid: Pid) -> usize {
+        pid as usize % MAX_PROCESSES as usize
+    }
+
+    pub(crate) fn process(&self, pid: Pid) -> Option<&Process> {
+        self.processes.get(Self::slot(pid)).and_then(|p| p.as_ref()).filter(|p| p.pid == pid)
+    }
+
+    pub(crate) fn process_mut(&mut self, pid: Pid) -> Option<&mut Process> {
+        self.processes.get_mut(Self::slot(pid)).and_then(|p| p.as_mut()).filter(|p| p.pid == pid)
+    }
+
+    /// Iterates over every process in the table, zombies included.
+    pub(crate) fn processes(&self) -> impl Iterator<Item = &Process> {
+        self.processes.iter().flatten()
+    }
+
+    pub(crate) fn current(&self) -> Option<Pid> {
+        self.current
+    }
+
+    /// Returns `true` if an interrupt may still wake a blocked process.
+    fn awaits_event(&self) -> bool {
+        self.processes().any(|p| matches!(p.state, ProcessState::Blocked(_)) && p.awaits_event())
+    }
+
+    /// Reserves a process table slot for a child of `parent` and returns the new pid.
+    pub(crate) fn allocate(
+        &mut self,
+        name: &str,
+        priority: u32,
+        parent: Option<Pid>,
+        entry: ProcessEntry,
+        args: *mut c_void,
+    ) -> Option<Pid> {
+        if self.processes.is_empty() {
+            self.processes.resize_with(MAX_PROCESSES as usize, || None);
+        }
+
+        // Pids keep increasing so stale pids never alias a new process; skip over any
+        // whose slot is still occupied.
+        for _ in 0..MAX_PROCESSES {
+            let pid = self.next_pid;
+            self.next_pid = self.next_pid.wrapping_add(1).max(1);
+            let slot = &mut self.processes[Self::slot(pid)];
+            if slot.is_none() {
+                *slot = Some(Process {
+                    pid,
+                    name: name.to_string(),
+                    priority,
+                    base_priority: priority,
+                    state: ProcessState::Ready,
+                    parent,
+                    children: Vec::new(),
+                    exit_code: None,
+                    held_mutexes: Vec::new(),
+                    blocked_on: None,
+                    in_mailbox: None,
+                    context: core::ptr::null_mut(),
+                    entry,
+                    args,
+                    deadline: None,
+                    timed_out: false,
+                    cpu_time: 0,
+                    running_since: 0,
+                });
+                if let Some(parent) = parent.and_then(|p| self.process_mut(p)) {
+                    parent.children.push(pid);
+                }
+                return Some(pid);
+            }
+        }
+        None
+    }
+
+    pub(crate) fn release(&mut self, pid: Pid) -> Option<Process> {
+        let slot = self.processes.get_mut(Self::slot(pid))?;
+        if slot.as_ref().is_some_and(|p| p.pid == pid) { slot.take() } else { None }
+    }
+
+    /// Moves a blocked process onto the back of its ready queue.
+    pub(crate) fn make_ready(&mut self, pid: Pid) {
+        if self.process(pid).is_some_and(|p| matches!(p.state, ProcessState::Blocked(_))) {
+            self.enqueue(pid);
+        }
+    }
+
+    /// Puts `pid` at the back of its ready queue regardless of its current state.
+    pub(crate) fn enqueue(&mut self, pid: Pid) {
+        let Some(process) = self.process_mut(pid) else {
+            return;
+        };
+        process.state = ProcessState::Ready;
+        process.deadline = None;
+        let priority = process.priority as usize;
+        self.ready[priority].push_back(pid);
+    }
+
+    /// Changes the effective priority of `pid`, moving it between ready queues if needed.
+    ///
+    /// Returns the previous priority if it changed.
+    pub(crate) fn set_priority(&mut self, pid: Pid, priority: u32) -> Option<u32> {
+        let process = self.process_mut(pid)?;
+        let previous = process.priority;
+        if previous == priority {
+            return None;
+        }
+        process.priority = priority;
+
+        if process.state == ProcessState::Ready {
+            let queue = &mut self.ready[previous as usize];
+            if let Some(index) = queue.iter().position(|&p| p == pid) {
+                queue.remove(index);
+                self.ready[priority as usize].push_back(pid);
+            }
+        }
+        Some(previous)
+    }
+
+    /// Turns `pid` into a zombie holding `code`, orphans its children, and wakes its parent
+    /// if the parent is waiting in `join`.
+    pub(crate) fn finish(&mut self, pid: Pid, code: i32) {
+        let Some(process) = self.process_mut(pid) else {
+            return;
+        };
+        let was_ready = process.state == ProcessState::Ready;
+        let priority = process.priority as usize;
+        process.state = ProcessState::Quit;
+        process.exit_code = Some(code);
+        process.deadline = None;
+        let children = core::mem::take(&mut process.children);
+        let parent = process.parent;
+
+        if was_ready {
+            self.ready[priority].retain(|&p| p != pid);
+        }
+        // Orphans have nobody to join them; the dispatcher reaps them once they quit.
+        for child in children {
+            if let Some(child) = self.process_mut(child) {
+                child.parent = None;
+            }
+        }
+        if let Some(parent) = parent
+            && self
+                .process(parent)
+                .is_some_and(|p| p.state == ProcessState::Blocked(BlockReason::Join))
+        {
+            self.make_ready(parent);
+        }
+    }
+
+    fn highest_ready_priority(&self) -> Option<usize> {
+        (0..PRIORITY_LEVELS).rev().find(|&p| !self.ready[p].is_empty())
+    }
+
+    /// Wakes every blocked process whose deadline has passed, flagging it as timed out.
+    fn expire_deadlines(&mut self, now: u32) {
+        let expired: Vec<Pid> = self
+            .processes
+            .iter()
+            .flatten()
+            .filter(|p| matches!(p.state, ProcessState::Blocked(_)))
+            .filter(|p| p.deadline.is_some_and(|d| deadline_passed(now, d)))
+            .map(|p| p.pid)
+            .collect();
+
+        for pid in expired {
+            if let Some(process) = self.process_mut(pid) {
+                process.timed_out = true;
+            }
+            self.make_ready(pid);
+        }
+    }
+
+    /// Collects the contexts of finished orphans, other than the running process, so they
+    /// can be stopped outside of the kernel borrow. Zombies with a parent wait for `join`.
+    fn reap(&mut self) -> Vec<*mut c_void> {
+        let finished: Vec<Pid> = self
+            .processes
+            .iter()
+            .flatten()
+            .filter(|p| p.state == ProcessState::Quit && p.parent.is_none())
+            .filter(|p| Some(p.pid) != self.current)
+            .map(|p| p.pid)
+            .collect();
+
+        finished
+            .into_iter()
+            .filter_map(|pid| self.release(pid))
+            .map(|p| p.context)
+            .filter(|ctx| !ctx.is_null())
+            .collect()
+    }
+
+    /// Picks the process that should run next.
+    fn select_next(&mut self, now: u32) -> Dispatch {
+        if !self.started {
+            return Dispatch::Stay;
+        }
+
+        let running = self
+            .current
+            .and_then(|pid| self.process(pid))
+            .filter(|p| p.state == ProcessState::Running)
+            .map(|p| (p.pid, p.priority as usize));
+
+        let Some(best) = self.highest_ready_priority() else {
+            return match running {
+                Some(_) => Dispatch::Stay,
+                None => Dispatch::Idle,
+            };
+        };
+
+        if let Some((pid, priority)) = running {
+            let slice_expired = now.wrapping_sub(self.slice_start) >= TIME_SLICE;
+            if best < priority || (best == priority && !slice_expired) {
+                return Dispatch::Stay;
+            }
+            // Preempted: back of the line for its priority.
+            if let Some(process) = self.process_mut(pid) {
+                process.state = ProcessState::Ready;
+            }
+            self.ready[priority].push_back(pid);
+        }
+
+        let next = self.ready[best].pop_front().expect("ready queue is non-empty");
+        let previous = self.current.replace(next);
+        self.slice_start = now;
+
+        if previous != Some(next)
+            && let Some(process) = previous.and_then(|pid| self.process_mut(pid))
+        {
+            process.cpu_time =
+                process.cpu_time.wrapping_add(now.wrapping_sub(process.running_since));
+        }
+
+        let process = self.process_mut(next).expect("ready pid is in the process table");
+        process.state = ProcessState::Running;
+        if previous == Some(next) {
+            return Dispatch::Stay;
+        }
+        process.running_since = now;
+        Dispatch::Switch(process.context)
+    }
+}
+
+/// Installs the scheduler's clock interrupt handler into the THREADS interrupt vector.
+///
+/// Call this from `bootstrap()` before spawning any processes. The handler wakes processes
+/// whose timeouts have expired and preempts the running process when a higher-priority
+/// process is ready or its time slice has run out.
+///
+/// # Example
+///
+/// ```ignore
+/// #[unsafe(no_mangle)]
+/// extern "C" fn bootstrap(_args: *mut core::ffi::c_void) -> i32 {
+///     rusty_threads::scheduler::init();
+///     rusty_threads::spawn("startup", 3, rusty_threads::THREADS_MIN_STACK_SIZE, startup)
+///         .expect("startup process");
+///     rusty_threads::scheduler::start();
+/// }
+/// ```
+pub fn init() {
+    let handlers = get_interrupt_handlers();
+    handlers[THREADS_TIMER_INTERRUPT as usize] = Some(clock_interrupt_handler);
+}
+
+/// Starts scheduling by switching to the highest-priority ready process.
+///
+/// Called once at the end of `bootstrap()`; the bootstrap context is never resumed.
+pub fn start() -> ! {
+    {
+        let guard = InterruptGuard::new();
+        KERNEL.with(&guard, |k| k.started = true);
+    }
+    dispatch();
+    console_output(false, "scheduler: bootstrap context resumed unexpectedly\n");
+    stop(1)
+}
+
+/// Returns the pid of the running process, or `None` before scheduling has started.
+pub fn current_pid() -> Option<Pid> {
+    let guard = InterruptGuard::new();
+    KERNEL.with(&guard, |k| k.current)
+}
+
+/// Returns the state of `pid`, or `None` if no such process exists.
+pub fn process_state(pid: Pid) -> Option<ProcessState> {
+    let guard = InterruptGuard::new();
+    KERNEL.with(&guard, |k| k.process(pid).map(|p| p.state))
+}
+
+/// Returns the name `pid` was spawned with.
+pub fn process_name(pid: Pid) -> Option<String> {
+    let guard = InterruptGuard::new();
+    KERNEL.with(&guard, |k| k.process(pid).map(|p| p.name.clone()))
+}
+
+/// Returns the effective scheduling priority of `pid`, including any boost from a
+/// priority-inheritance or priority-ceiling mutex.
+pub fn process_priority(pid: Pid) -> Option<u32> {
+    let guard = InterruptGuard::new();
+    KERNEL.with(&guard, |k| k.process(pid).map(|p| p.priority))
+}
+
+/// Returns the priority `pid` was spawned with, ignoring any boost.
+pub fn process_base_priority(pid: Pid) -> Option<u32> {
+    let guard = InterruptGuard::new();
+    KERNEL.with(&guard, |k| k.process(pid).map(|p| p.base_priority))
+}
+
+/// Gives up the CPU to the next runnable process, if one should run.
+///
+/// The running process keeps the CPU unless a higher-priority process is ready or its
+/// time slice has expired and another process of equal priority is waiting.
+pub fn dispatch() {
+    let guard = InterruptGuard::new();
+    let now = system_clock();
+    // Reap before selecting so the caller, which may be quitting, is never stopped while
+    // still running on its own stack.
+    let (finished, decision, idling, awaits_event) = KERNEL.with(&guard, |k| {
+        let finished = k.reap();
+        let decision = k.select_next(now);
+        let idling = k.idling;
+        if !matches!(decision, Dispatch::Idle) {
+            k.idling = false;
+        }
+        (finished, decision, idling, k.awaits_event())
+    });
+
+    for context in finished {
+        unsafe { context_stop(context) };
+    }
+
+    match decision {
+        Dispatch::Stay => {}
+        Dispatch::Switch(context) => {
+            unsafe { context_switch(context) };
+        }
+        // An interrupt taken by the idle loop below woke nobody; keep waiting there.
+        Dispatch::Idle if idling => {}
+        Dispatch::Idle if awaits_event => idle(&guard),
+        Dispatch::Idle => {
+            console_output(false, "scheduler: no runnable processes, halting\n");
+            stop(1);
+        }
+    }
+}
+
+/// Waits with interrupts enabled until an interrupt readies a process.
+///
+/// Runs on the stack of the process that blocked last. The interrupt handler's own
+/// [`dispatch`] switches to the readied process; this loop returns once that dispatch
+/// has picked the blocked process itself, or halts if nothing can wake a process any more.
+fn idle(guard: &InterruptGuard) {
+    KERNEL.with(guard, |k| k.idling = true);
+    loop {
+        let psr = get_psr();
+        set_psr(psr | PSR_INTERRUPTS);
+        core::hint::spin_loop();
+        set_psr(psr);
+        let (idling, awaits_event) = KERNEL.with(guard, |k| (k.idling, k.awaits_event()));
+        if !idling {
+            return;
+        }
+        if !awaits_event {
+            console_output(false, "scheduler: no runnable processes, halting\n");
+            stop(1);
+        }
+    }
+}
+
+/// Blocks the running process until another process readies it or `timeout`
+/// microseconds elapse.
+///
+/// The caller's guard keeps interrupts disabled from the moment the process is queued on
+/// a wait list until the switch away, and is what restores this process's PSR once it
+/// runs again.
+pub(crate) fn block_current(
+    guard: &InterruptGuard,
+    reason: BlockReason,
+    timeout: Option<u32>,
+) -> WakeReason {
+    let deadline = timeout.map(|t| system_clock().wrapping_add(t));
+    KERNEL.with(guard, |k| {
+        let pid = k.current.expect("block_current called outside of a process");
+        let process = k.process_mut(pid).expect("running pid is in the process table");
+        process.state = ProcessState::Blocked(reason);
+        process.deadline = deadline;
+        process.timed_out = false;
+    });
+
+    dispatch();
+
+    KERNEL.with(guard, |k| {
+        let timed_out = k
+            .current
+            .and_then(|pid| k.process_mut(pid))
+            .is_some_and(|p| core::mem::take(&mut p.timed_out));
+        if timed_out { WakeReason::TimedOut } else { WakeReason::Woken }
+    })
+}
+
+/// Makes a blocked process runnable again. Does not switch processes by itself.
+pub(crate) fn make_ready(guard: &InterruptGuard, pid: Pid) {
+    KERNEL.with(guard, |k| k.make_ready(pid));
+}
+
+unsafe extern "C" fn clock_interrupt_handler(_device_id: *mut c_char, _command: u8, _status: u32) {
+    let now = system_clock();
+    {
+        let guard = InterruptGuard::new();
+        KERNEL.with(&guard, |k| k.expire_deadlines(now));
+        watchdog::check_cpu_hog(&guard, now);
+    }
+    device::on_clock(now);
+    dispatch();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn noop(_: *mut c_void) -> i32 {
+        0
+    }
+
+    fn ready(k: &mut Scheduler, name: &str, priority: u32) -> Pid {
+        let pid = k.allocate(name, priority, None, noop, core::ptr::null_mut()).unwrap();
+        k.process_mut(pid).unwrap().context = pid as usize as *mut c_void;
+        k.enqueue(pid);
+        pid
+    }
+
+    #[test]
+    fn test_highest_priority_runs_first() {
+        let mut k = Scheduler::new();
+        k.started = true;
+        let low = ready(&mut k, "low", 1);
+        let high = ready(&mut k, "high", 4);
+
+        assert!(matches!(k.select_next(0), Dispatch::Switch(_)));
+        assert_eq!(k.current(), Some(high));
+
+        // The running high-priority process is not preempted by a lower one.
+        assert!(matches!(k.select_next(TIME_SLICE * 2), Dispatch::Stay));
+        k.process_mut(high).unwrap().state = ProcessState::Quit;
+        assert!(matches!(k.select_next(0), Dispatch::Switch(_)));
+        assert_eq!(k.current(), Some(low));
+    }
+
+    #[test]
+    fn test_equal_priority_round_robin() {
+        let mut k = Scheduler::new();
+        k.started = true;
+        let a = ready(&mut k, "a", 3);
+        let b = ready(&mut k, "b", 3);
+
+        k.select_next(0);
+        assert_eq!(k.current(), Some(a));
+        assert!(matches!(k.select_next(TIME_SLICE - 1), Dispatch::Stay));
+        k.select_next(TIME_SLICE);
+        assert_eq!(k.current(), Some(b));
+        k.select_next(TIME_SLICE * 2);
+        assert_eq!(k.current(), Some(a));
+    }
+
+    #[test]
+    fn test_set_priority_requeues_ready_process() {
+        let mut k = Scheduler::new();
+        k.started = true;
+        let boosted = ready(&mut k, "boosted", 1);
+        let other = ready(&mut k, "other", 3);
+
+        assert_eq!(k.set_priority(boosted, 4), Some(1));
+        assert_eq!(k.set_priority(boosted, 4), None);
+        k.select_next(0);
+        assert_eq!(k.current(), Some(boosted));
+        assert_eq!(k.process(other).unwrap().state, ProcessState::Ready);
+    }
+
+    #[test]
+    fn test_finish_wakes_joining_parent_and_orphans_children() {
+        let mut k = Scheduler::new();
+        let parent = ready(&mut k, "parent", 3);
+        let child = k.allocate("child", 2, Some(parent), noop, core::ptr::null_mut()).unwrap();
+        let grandchild =
+            k.allocate("grandchild", 2, Some(child), noop, core::ptr::null_mut()).unwrap();
+        k.ready[3].clear();
+        k.process_mut(parent).unwrap().state = ProcessState::Blocked(BlockReason::Join);
+
+        k.finish(child, 7);
+        let zombie = k.process(child).unwrap();
+        assert_eq!((zombie.state, zombie.exit_code), (ProcessState::Quit, Some(7)));
+        assert_eq!(k.process(parent).unwrap().state, ProcessState::Ready);
+        assert_eq!(k.process(grandchild).unwrap().parent, None);
+
+        // The zombie waits for its parent; the orphaned grandchild is reaped on its own.
+        k.finish(grandchild, 0);
+        k.reap();
+        assert!(k.process(child).is_some());
+        assert!(k.process(grandchild).is_none());
+    }
+
+    #[test]
+    fn test_expired_deadline_wakes_with_timeout() {
+        let mut k = Scheduler::new();
+        let pid = ready(&mut k, "sleeper", 2);
+        k.ready[2].clear();
+        let process = k.process_mut(pid).unwrap();
+        process.state = ProcessState::Blocked(BlockReason::Semaphore);
+        process.deadline = Some(u32::MAX - 5);
+
+        k.expire_deadlines(u32::MAX - 10);
+        assert!(matches!(k.process(pid).unwrap().state, ProcessState::Blocked(_)));
+
+        // The deadline is reached even though the clock wrapped around.
+        k.expire_deadlines(3);
+        let process = k.process(pid).unwrap();
+        assert_eq!(process.state, ProcessState::Ready);
+        assert!(process.timed_out);
+    }
+
+    #[test]
+    fn test_idle_only_while_an_interrupt_can_wake_someone() {
+        let mut k = Scheduler::new();
+        k.started = true;
+        let pid = ready(&mut k, "reader", 2);
+        k.ready[2].clear();
+        k.process_mut(pid).unwrap().state = ProcessState::Blocked(BlockReason::Mutex);
+        assert!(matches!(k.select_next(0), Dispatch::Idle));
+        assert!(!k.awaits_event());
+
+        k.process_mut(pid).unwrap().state = ProcessState::Blocked(BlockReason::DiskIo);
+        assert!(k.awaits_event());
+        k.process_mut(pid).unwrap().state = ProcessState::Blocked(BlockReason::Semaphore);
+        k.process_mut(pid).unwrap().deadline = Some(100);
+        assert!(k.awaits_event());
+    }
+}
@@ -0,0 +1,635 @@
+// src/sync.rs
+
+use std::collections::VecDeque;
+
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::priority::{self, PriorityCause};
+use crate::scheduler::{
+    self, BlockReason, HIGHEST_PRIORITY, KERNEL, LOWEST_PRIORITY, Pid, ProcessState, WakeReason,
+};
+
+/// Order in which blocked processes are woken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeOrder {
+    /// First blocked, first woken.
+    Fifo,
+    /// Highest priority first; FIFO among equal priorities.
+    Priority,
+}
+
+/// Errors returned by the blocking synchronization primitives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncError {
+    /// The call was made outside of a running process (e.g. from `bootstrap()`).
+    NoProcess,
+    /// The timeout expired before the primitive became available.
+    TimedOut,
+    /// The calling process does not own the mutex.
+    NotOwner,
+    /// The calling process already owns the mutex; locking it again would deadlock.
+    AlreadyOwned,
+    /// The caller's base priority is above the mutex's priority ceiling.
+    CeilingViolated,
+}
+
+/// Processes blocked on a synchronization primitive.
+pub(crate) struct WaitQueue {
+    order: WakeOrder,
+    waiters: VecDeque<Pid>,
+}
+
+impl WaitQueue {
+    pub(crate) const fn new(order: WakeOrder) -> Self {
+        WaitQueue { order, waiters: VecDeque::new() }
+    }
+
+    pub(crate) fn push(&mut self, pid: Pid) {
+        self.waiters.push_back(pid);
+    }
+
+    /// Removes `pid` from the queue, returning `false` if it was not waiting.
+    pub(crate) fn remove(&mut self, pid: Pid) -> bool {
+        match self.waiters.iter().position(|&p| p == pid) {
+            Some(index) => {
+                self.waiters.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes and returns the next process to wake, using `priority_of` to rank
+    /// waiters when the queue is priority ordered.
+    pub(crate) fn pop(&mut self, priority_of: impl Fn(Pid) -> u32) -> Option<Pid> {
+        let index = match self.order {
+            WakeOrder::Fifo => 0,
+            WakeOrder::Priority => {
+                let mut best: Option<(usize, u32)> = None;
+                for (index, &pid) in self.waiters.iter().enumerate() {
+                    let priority = priority_of(pid);
+                    if best.is_none_or(|(_, p)| priority > p) {
+                        best = Some((index, priority));
+                    }
+                }
+                best?.0
+            }
+        };
+        self.waiters.remove(index)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Pid> + '_ {
+        self.waiters.iter().copied()
+    }
+
+    pub(crate) fn retain(&mut self, keep: impl Fn(Pid) -> bool) {
+        self.waiters.retain(|&pid| keep(pid));
+    }
+
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = Pid> + '_ {
+        self.waiters.drain(..)
+    }
+}
+
+/// Pops the next waiter according to the queue's order and the current priorities,
+/// discarding waiters that were killed while blocked.
+pub(crate) fn pop_waiter(guard: &InterruptGuard, queue: &mut WaitQueue) -> Option<Pid> {
+    KERNEL.with(guard, |k| {
+        queue.retain(|pid| k.process(pid).is_some_and(|p| p.state != ProcessState::Quit));
+        queue.pop(|pid| k.process(pid).map_or(0, |p| p.priority))
+    })
+}
+
+pub(crate) fn running_pid(guard: &InterruptGuard) -> Result<Pid, SyncError> {
+    KERNEL.with(guard, |k| k.current()).ok_or(SyncError::NoProcess)
+}
+
+/// Resolves a timed-out wait. If the waiter is still queued it really timed out; if it was
+/// already dequeued, a wake-up raced with the timeout and the wait succeeded.
+fn resolve_timeout(dequeued_by_waker: bool) -> Result<(), SyncError> {
+    if dequeued_by_waker { Ok(()) } else { Err(SyncError::TimedOut) }
+}
+
+struct SemaphoreState {
+    count: u32,
+    waiters: WaitQueue,
+}
+
+/// A kernel counting semaphore.
+///
+/// `wait` takes a unit or blocks the calling process; `signal` hands a unit directly to the
+/// next waiter (so a woken process never has to race for it) or increments the count.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{Semaphore, WakeOrder};
+///
+/// static ITEMS: Semaphore = Semaphore::new(0, WakeOrder::Fifo);
+///
+/// // consumer process
+/// ITEMS.wait().expect("called from a process");
+///
+/// // producer process
+/// ITEMS.signal();
+/// ```
+pub struct Semaphore {
+    state: KernelCell<SemaphoreState>,
+}
+
+impl Semaphore {
+    /// Creates a semaphore holding `initial` units.
+    pub const fn new(initial: u32, order: WakeOrder) -> Self {
+        Semaphore {
+            state: KernelCell::new(SemaphoreState {
+                count: initial,
+                waiters: WaitQueue::new(order),
+            }),
+        }
+    }
+
+    /// Takes a unit, blocking until one is available.
+    pub fn wait(&self) -> Result<(), SyncError> {
+        self.acquire(None)
+    }
+
+    /// Takes a unit, blocking for at most `timeout` microseconds.
+    pub fn wait_timeout(&self, timeout: u32) -> Result<(), SyncError> {
+        self.acquire(Some(timeout))
+    }
+
+    /// Takes a unit if one is available without blocking.
+    pub fn try_wait(&self) -> bool {
+        let guard = InterruptGuard::new();
+        self.state.with(&guard, |s| {
+            if s.count > 0 {
+                s.count -= 1;
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Releases a unit, waking the next waiter if there is one.
+    pub fn signal(&self) {
+        let guard = InterruptGuard::new();
+        let woken = self.state.with(&guard, |s| {
+            let next = pop_waiter(&guard, &mut s.waiters);
+            if next.is_none() {
+                s.count += 1;
+            }
+            next
+        });
+        if let Some(pid) = woken {
+            scheduler::make_ready(&guard, pid);
+            drop(guard);
+            scheduler::dispatch();
+        }
+    }
+
+    /// Returns the number of available units.
+    pub fn count(&self) -> u32 {
+        let guard = InterruptGuard::new();
+        self.state.with(&guard, |s| s.count)
+    }
+
+    /// Returns the number of processes blocked on the semaphore.
+    pub fn waiting(&self) -> usize {
+        let guard = InterruptGuard::new();
+        self.state.with(&guard, |s| s.waiters.len())
+    }
+
+    fn acquire(&self, timeout: Option<u32>) -> Result<(), SyncError> {
+        let guard = InterruptGuard::new();
+        let pid = running_pid(&guard)?;
+        let acquired = self.state.with(&guard, |s| {
+            if s.count > 0 {
+                s.count -= 1;
+                true
+            } else {
+                s.waiters.push(pid);
+                false
+            }
+        });
+        if acquired {
+            return Ok(());
+        }
+
+        match scheduler::block_current(&guard, BlockReason::Semaphore, timeout) {
+            WakeReason::Woken => Ok(()),
+            WakeReason::TimedOut => {
+                resolve_timeout(!self.state.with(&guard, |s| s.waiters.remove(pid)))
+            }
+        }
+    }
+}
+
+/// Priority protocol applied by a [`Mutex`] to its owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MutexProtocol {
+    /// No priority adjustment.
+    None,
+    /// The owner runs at the highest effective priority of the processes blocked on the
+    /// mutex. Boosts propagate transitively when the owner is itself blocked on another
+    /// inheritance mutex.
+    Inheritance,
+    /// Immediate priority ceiling: the owner runs at the given priority for as long as it
+    /// holds the mutex. Processes with a base priority above the ceiling may not lock it.
+    Ceiling(u32),
+}
+
+struct MutexState {
+    owner: Option<Pid>,
+    waiters: WaitQueue,
+    protocol: MutexProtocol,
+}
+
+/// A kernel mutex owned by the process that locked it.
+///
+/// Only the owner may unlock it, and locking a mutex the caller already owns is reported
+/// instead of deadlocking. On unlock, ownership passes directly to the next waiter.
+///
+/// A mutex created with [`Mutex::with_protocol`] adjusts its owner's priority to bound
+/// priority inversion; every boost and restore is recorded in
+/// [`crate::priority::priority_trace`]. Such a mutex is tracked by address while it is
+/// locked, so it must not be moved or dropped while locked; declare it as a `static`.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{Mutex, WakeOrder};
+///
+/// static TABLE_LOCK: Mutex = Mutex::new(WakeOrder::Priority);
+///
+/// TABLE_LOCK.lock()?;
+/// // ... critical section ...
+/// TABLE_LOCK.unlock()?;
+/// ```
+pub struct Mutex {
+    state: KernelCell<MutexState>,
+}
+
+impl Mutex {
+    /// Creates an unlocked mutex without a priority protocol.
+    pub const fn new(order: WakeOrder) -> Self {
+        Self::with_protocol(order, MutexProtocol::None)
+    }
+
+    /// Creates an unlocked mutex that applies `protocol` to its owner.
+    ///
+    /// # Panics
+    ///
+    /// Panics (at compile time when used in a `static`) if a ceiling is outside
+    /// [`LOWEST_PRIORITY`]..=[`HIGHEST_PRIORITY`].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// use rusty_threads::{Mutex, MutexProtocol, WakeOrder};
+    ///
+    /// static DISK_LOCK: Mutex = Mutex::with_protocol(WakeOrder::Priority, MutexProtocol::Inheritance);
+    /// static LOG_LOCK: Mutex = Mutex::with_protocol(WakeOrder::Fifo, MutexProtocol::Ceiling(4));
+    /// ```
+    pub const fn with_protocol(order: WakeOrder, protocol: MutexProtocol) -> Self {
+        if let MutexProtocol::Ceiling(ceiling) = protocol {
+            assert!(
+                ceiling >= LOWEST_PRIORITY && ceiling <= HIGHEST_PRIORITY,
+                "priority ceiling out of range"
+            );
+        }
+        Mutex {
+            state: KernelCell::new(MutexState {
+                owner: None,
+                waiters: WaitQueue::new(order),
+                protocol,
+            }),
+        }
+    }
+
+    /// Locks the mutex, blocking until it is available.
+    pub fn lock(&self) -> Result<(), SyncError> {
+        self.acquire(None)
+    }
+
+    /// Locks the mutex, blocking for at most `timeout` microseconds.
+    pub fn lock_timeout(&self, timeout: u32) -> Result<(), SyncError> {
+        self.acquire(Some(timeout))
+    }
+
+    /// Locks the mutex if it is free, without blocking.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(true)` – The caller now owns the mutex.
+    /// * `Ok(false)` – Another process owns it.
+    pub fn try_lock(&self) -> Result<bool, SyncError> {
+        let guard = InterruptGuard::new();
+        let pid = running_pid(&guard)?;
+        self.check_ceiling(&guard, pid)?;
+        let acquired = self.state.with(&guard, |s| match s.owner {
+            None => {
+                s.owner = Some(pid);
+                Ok(true)
+            }
+            Some(owner) if owner == pid => Err(SyncError::AlreadyOwned),
+            Some(_) => Ok(false),
+        })?;
+        if acquired {
+            self.take_ownership(&guard, pid);
+        }
+        Ok(acquired)
+    }
+
+    /// Unlocks the mutex, handing it to the next waiter if there is one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SyncError::NotOwner`] if the calling process does not hold the mutex.
+    pub fn unlock(&self) -> Result<(), SyncError> {
+        let guard = InterruptGuard::new();
+        let pid = running_pid(&guard)?;
+        self.release(&guard, pid)?;
+        drop(guard);
+        // Either a waiter was readied or the caller's priority dropped; both may call for
+        // a different process to run.
+        scheduler::dispatch();
+        Ok(())
+    }
+
+    /// Returns the pid of the owning process, if the mutex is locked.
+    pub fn owner(&self) -> Option<Pid> {
+        let guard = InterruptGuard::new();
+        self.state.with(&guard, |s| s.owner)
+    }
+
+    /// Returns the priority protocol the mutex was created with.
+    pub fn protocol(&self) -> MutexProtocol {
+        let guard = InterruptGuard::new();
+        self.state.with(&guard, |s| s.protocol)
+    }
+
+    fn acquire(&self, timeout: Option<u32>) -> Result<(), SyncError> {
+        let guard = InterruptGuard::new();
+        let pid = running_pid(&guard)?;
+        self.check_ceiling(&guard, pid)?;
+        let (acquired, protocol, owner) = self.state.with(&guard, |s| match s.owner {
+            None => {
+                s.owner = Some(pid);
+                Ok((true, s.protocol, None))
+            }
+            Some(owner) if owner == pid => Err(SyncError::AlreadyOwned),
+            Some(owner) => {
+                s.waiters.push(pid);
+                Ok((false, s.protocol, Some(owner)))
+            }
+        })?;
+        if acquired {
+            self.take_ownership(&guard, pid);
+            return Ok(());
+        }
+
+        if protocol == MutexProtocol::Inheritance {
+            KERNEL.with(&guard, |k| {
+                if let Some(process) = k.process_mut(pid) {
+                    process.blocked_on = Some(self as *const Mutex);
+                }
+            });
+            if let Some(owner) = owner {
+                priority::refresh_priority(&guard, owner);
+            }
+        }
+
+        let wake = scheduler::block_current(&guard, BlockReason::Mutex, timeout);
+        KERNEL.with(&guard, |k| {
+            if let Some(process) = k.process_mut(pid) {
+                process.blocked_on = None;
+            }
+        });
+
+        match wake {
+            WakeReason::Woken => Ok(()),
+            WakeReason::TimedOut => {
+                let still_queued = self.state.with(&guard, |s| s.waiters.remove(pid));
+                if still_queued && protocol == MutexProtocol::Inheritance {
+                    // The owner no longer inherits this waiter's priority.
+                    if let Some(owner) = self.inheritance_owner(&guard) {
+                        priority::refresh_priority(&guard, owner);
+                    }
+                }
+                resolve_timeout(!still_queued)
+            }
+        }
+    }
+
+    fn check_ceiling(&self, guard: &InterruptGuard, pid: Pid) -> Result<(), SyncError> {
+        let MutexProtocol::Ceiling(ceiling) = self.state.with(guard, |s| s.protocol) else {
+            return Ok(());
+        };
+        let base = KERNEL.with(guard, |k| k.process(pid).map_or(0, |p| p.base_priority));
+        if base > ceiling { Err(SyncError::CeilingViolated) } else { Ok(()) }
+    }
+
+    /// Records `pid` as holding this mutex and applies the protocol to it.
+    fn take_ownership(&self, guard: &InterruptGuard, pid: Pid) {
+        if self.state.with(guard, |s| s.protocol) == MutexProtocol::None {
+            return;
+        }
+        KERNEL.with(guard, |k| {
+            if let Some(process) = k.process_mut(pid) {
+                process.held_mutexes.push(self as *const Mutex);
+            }
+        });
+        priority::refresh_priority(guard, pid);
+    }
+
+    /// Gives up ownership and readies the next waiter, without dispatching.
+    fn release(&self, guard: &InterruptGuard, pid: Pid) -> Result<Option<Pid>, SyncError> {
+        let (woken, protocol) = self.state.with(guard, |s| {
+            if s.owner != Some(pid) {
+                return Err(SyncError::NotOwner);
+            }
+            s.owner = pop_waiter(guard, &mut s.waiters);
+            Ok((s.owner, s.protocol))
+        })?;
+
+        if protocol != MutexProtocol::None {
+            let this = self as *const Mutex;
+            KERNEL.with(guard, |k| {
+                if let Some(process) = k.process_mut(pid) {
+                    process.held_mutexes.retain(|&m| m != this);
+                }
+                if let Some(process) = woken.and_then(|next| k.process_mut(next)) {
+                    process.blocked_on = None;
+                }
+            });
+            priority::refresh_priority(guard, pid);
+        }
+
+        if let Some(next) = woken {
+            scheduler::make_ready(guard, next);
+            self.take_ownership(guard, next);
+        }
+        Ok(woken)
+    }
+
+    /// The priority this mutex imposes on its owner, and why.
+    pub(crate) fn priority_demand(&self, guard: &InterruptGuard) -> (u32, PriorityCause) {
+        self.state.with(guard, |s| {
+            let priorities = KERNEL.with(guard, |k| {
+                s.waiters
+                    .iter()
+                    .filter_map(|pid| k.process(pid))
+                    .filter(|p| p.state != ProcessState::Quit)
+                    .map(|p| p.priority)
+                    .collect::<Vec<u32>>()
+            });
+            priority::protocol_demand(s.protocol, priorities.into_iter())
+        })
+    }
+
+    /// The owner of this mutex if it uses priority inheritance.
+    pub(crate) fn inheritance_owner(&self, guard: &InterruptGuard) -> Option<Pid> {
+        self.state.with(guard, |s| match s.protocol {
+            MutexProtocol::Inheritance => s.owner,
+            _ => None,
+        })
+    }
+}
+
+/// A kernel condition variable used together with a [`Mutex`].
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{Condvar, Mutex, WakeOrder};
+///
+/// static LOCK: Mutex = Mutex::new(WakeOrder::Fifo);
+/// static NOT_EMPTY: Condvar = Condvar::new(WakeOrder::Fifo);
+///
+/// LOCK.lock()?;
+/// while queue_is_empty() {
+///     NOT_EMPTY.wait(&LOCK)?;
+/// }
+/// LOCK.unlock()?;
+/// ```
+pub struct Condvar {
+    waiters: KernelCell<WaitQueue>,
+}
+
+impl Condvar {
+    /// Creates a condition variable with no waiters.
+    pub const fn new(order: WakeOrder) -> Self {
+        Condvar { waiters: KernelCell::new(WaitQueue::new(order)) }
+    }
+
+    /// Atomically releases `mutex` and blocks until signalled, then re-locks `mutex`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SyncError::NotOwner`] if the caller does not hold `mutex`.
+    pub fn wait(&self, mutex: &Mutex) -> Result<(), SyncError> {
+        self.wait_inner(mutex, None)
+    }
+
+    /// Like [`Condvar::wait`], but gives up after `timeout` microseconds.
+    ///
+    /// The mutex is re-locked before returning in both cases; a timeout is reported as
+    /// [`SyncError::TimedOut`].
+    pub fn wait_timeout(&self, mutex: &Mutex, timeout: u32) -> Result<(), SyncError> {
+        self.wait_inner(mutex, Some(timeout))
+    }
+
+    /// Wakes one waiting process.
+    pub fn signal(&self) {
+        let guard = InterruptGuard::new();
+        let woken = self.waiters.with(&guard, |q| pop_waiter(&guard, q));
+        if let Some(pid) = woken {
+            scheduler::make_ready(&guard, pid);
+            drop(guard);
+            scheduler::dispatch();
+        }
+    }
+
+    /// Wakes every waiting process.
+    pub fn broadcast(&self) {
+        let guard = InterruptGuard::new();
+        let woken: Vec<Pid> = self.waiters.with(&guard, |q| q.drain().collect());
+        if woken.is_empty() {
+            return;
+        }
+        for pid in woken {
+            scheduler::make_ready(&guard, pid);
+        }
+        drop(guard);
+        scheduler::dispatch();
+    }
+
+    fn wait_inner(&self, mutex: &Mutex, timeout: Option<u32>) -> Result<(), SyncError> {
+        let guard = InterruptGuard::new();
+        let pid = running_pid(&guard)?;
+        if mutex.owner() != Some(pid) {
+            return Err(SyncError::NotOwner);
+        }
+
+        // Queue on the condition before releasing the mutex so a signal issued by the
+        // next owner cannot be missed.
+        self.waiters.with(&guard, |q| q.push(pid));
+        mutex.release(&guard, pid)?;
+
+        let outcome = match scheduler::block_current(&guard, BlockReason::Condvar, timeout) {
+            WakeReason::Woken => Ok(()),
+            WakeReason::TimedOut => resolve_timeout(!self.waiters.with(&guard, |q| q.remove(pid))),
+        };
+
+        mutex.lock()?;
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fifo_wait_queue() {
+        let mut queue = WaitQueue::new(WakeOrder::Fifo);
+        queue.push(3);
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.pop(|pid| pid), Some(3));
+        assert_eq!(queue.pop(|pid| pid), Some(1));
+        assert_eq!(queue.pop(|pid| pid), Some(2));
+        assert_eq!(queue.pop(|pid| pid), None);
+    }
+
+    #[test]
+    fn test_priority_wait_queue_breaks_ties_fifo() {
+        let mut queue = WaitQueue::new(WakeOrder::Priority);
+        let priority = |pid: Pid| match pid {
+            10 | 11 => 4,
+            _ => 1,
+        };
+        queue.push(5);
+        queue.push(10);
+        queue.push(6);
+        queue.push(11);
+        assert_eq!(queue.pop(priority), Some(10));
+        assert_eq!(queue.pop(priority), Some(11));
+        assert_eq!(queue.pop(priority), Some(5));
+        assert_eq!(queue.pop(priority), Some(6));
+    }
+
+    #[test]
+    fn test_wait_queue_remove() {
+        let mut queue = WaitQueue::new(WakeOrder::Fifo);
+        queue.push(1);
+        queue.push(2);
+        assert!(queue.remove(1));
+        assert!(!queue.remove(1));
+        assert_eq!(queue.len(), 1);
+    }
+}
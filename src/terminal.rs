@@ -0,0 +1,933 @@
+// src/terminal.rs
+
+use std::collections::VecDeque;
+
+use crate::ansi::{Key, KeyDecoder};
+use crate::constants::{TERMINAL_READ_CHAR, TERMINAL_WRITE_CHAR, THREADS_MAX_TERMINALS};
+use crate::device::{self, DeviceId, DeviceKind};
+use crate::hotplug::{DEVICE_STATUS_ATTACHED, DEVICE_STATUS_REMOVED, HOTPLUG_COMMAND};
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::rusty_thread_bindings::device_control_block_t;
+use crate::rusty_wrapper::{device_control, device_initialize};
+use crate::scheduler::{self, BlockReason, KERNEL, Pid, WakeReason};
+
+/// Capacity of each terminal's input and output ring buffers in bytes.
+pub const TERMINAL_BUFFER_SIZE: usize = 256;
+
+/// How long [`read_key`] waits after an ESC for the rest of an escape sequence before
+/// reporting the Escape key, in microseconds.
+pub const KEY_ESCAPE_TIMEOUT: u32 = 50_000;
+
+/// Interrupt status reported by THREADS for a terminal command that completed
+/// successfully.
+pub const TERMINAL_STATUS_OK: u32 = 0;
+
+/// Command code of the `THREADS_IO_INTERRUPT` that delivers one byte of host input
+/// queued by [`inject_terminal_input`]; the status is the byte. No device command uses
+/// it.
+pub const TERMINAL_HOST_INPUT: u8 = 0x80;
+
+const BACKSPACE: u8 = 0x08;
+const BELL: u8 = 0x07;
+/// Erases the character left of the cursor on the screen.
+const RUBOUT: &[u8] = b"\x08 \x08";
+
+/// Errors returned by the terminal driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalError {
+    /// The call must be made from a running process.
+    NoProcess,
+    /// The device is not a terminal, or its unit number is out of range.
+    NotATerminal,
+    /// The terminal did not initialize in [`init_terminals`], or was unplugged, see
+    /// [`crate::hotplug::detach_device`].
+    NotPresent,
+}
+
+/// How input is handed to readers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalMode {
+    /// Input is collected into lines that can be edited before they are read.
+    Canonical,
+    /// Every byte is readable as soon as it arrives, without interpretation.
+    Raw,
+}
+
+/// Line discipline settings of a terminal, see [`set_terminal_settings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalSettings {
+    /// Canonical or raw input.
+    pub mode: TerminalMode,
+    /// Echo input back to the terminal.
+    pub echo: bool,
+    /// Erases the last character of the line (canonical mode). Backspace always does.
+    pub erase: u8,
+    /// Erases the whole line (canonical mode).
+    pub kill: u8,
+    /// Ends the line without a newline; at the start of a line, reads see end of file
+    /// (canonical mode).
+    pub eof: u8,
+    /// Send `"\r\n"` for every `'\n'` written or echoed, and read `'\r'` as `'\n'`.
+    pub crlf: bool,
+}
+
+impl TerminalSettings {
+    /// Canonical mode with echo, DEL to erase, Ctrl-U to kill and Ctrl-D for end of file.
+    pub const fn new() -> Self {
+        TerminalSettings {
+            mode: TerminalMode::Canonical,
+            echo: true,
+            erase: 0x7F,
+            kill: 0x15,
+            eof: 0x04,
+            crlf: true,
+        }
+    }
+}
+
+impl Default for TerminalSettings {
+    fn default() -> Self {
+        TerminalSettings::new()
+    }
+}
+
+/// A fixed-capacity FIFO of bytes.
+pub(crate) struct RingBuffer {
+    data: [u8; TERMINAL_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RingBuffer {
+    pub(crate) const fn new() -> Self {
+        RingBuffer { data: [0; TERMINAL_BUFFER_SIZE], head: 0, len: 0 }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn free(&self) -> usize {
+        TERMINAL_BUFFER_SIZE - self.len
+    }
+
+    /// Appends `byte`, returning `false` if the buffer is full.
+    pub(crate) fn push(&mut self, byte: u8) -> bool {
+        if self.len == TERMINAL_BUFFER_SIZE {
+            return false;
+        }
+        self.data[(self.head + self.len) % TERMINAL_BUFFER_SIZE] = byte;
+        self.len += 1;
+        true
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.data[self.head];
+        self.head = (self.head + 1) % TERMINAL_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+/// Turns received bytes into readable input and the bytes to echo.
+///
+/// In canonical mode the line being typed is kept apart and only moves to the input
+/// buffer when it is ended by a newline or the EOF character; `lines` remembers where
+/// each complete line ends, so a read never returns more than one line. An empty line
+/// ended by EOF is how end of file reaches a reader.
+pub(crate) struct LineDiscipline {
+    settings: TerminalSettings,
+    line: Vec<u8>,
+    input: RingBuffer,
+    /// Lengths of the complete lines in `input`, oldest first (canonical mode).
+    lines: VecDeque<usize>,
+}
+
+impl LineDiscipline {
+    pub(crate) const fn new() -> Self {
+        LineDiscipline {
+            settings: TerminalSettings::new(),
+            line: Vec::new(),
+            input: RingBuffer::new(),
+            lines: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn settings(&self) -> TerminalSettings {
+        self.settings
+    }
+
+    /// Changes the settings. Leaving canonical mode makes the partial line readable;
+    /// entering it turns the unread input into one line.
+    pub(crate) fn set_settings(&mut self, settings: TerminalSettings) {
+        match (self.settings.mode, settings.mode) {
+            (TerminalMode::Canonical, TerminalMode::Raw) => {
+                for byte in self.line.drain(..) {
+                    self.input.push(byte);
+                }
+                self.lines.clear();
+            }
+            (TerminalMode::Raw, TerminalMode::Canonical) if !self.input.is_empty() => {
+                self.lines.push_back(self.input.len());
+            }
+            _ => {}
+        }
+        self.settings = settings;
+    }
+
+    /// Handles one received byte, appending what should be echoed to `echo`.
+    pub(crate) fn receive(&mut self, byte: u8, echo: &mut Vec<u8>) {
+        let settings = self.settings;
+        let mut shown = Vec::new();
+        let byte = if settings.crlf && byte == b'\r' { b'\n' } else { byte };
+
+        if settings.mode == TerminalMode::Raw {
+            if self.input.push(byte) {
+                shown.push(byte);
+            }
+        } else if byte == settings.erase || byte == BACKSPACE {
+            if self.line.pop().is_some() {
+                shown.extend_from_slice(RUBOUT);
+            }
+        } else if byte == settings.kill {
+            for _ in self.line.drain(..) {
+                shown.extend_from_slice(RUBOUT);
+            }
+        } else if byte == settings.eof || byte == b'\n' {
+            let newline = byte == b'\n';
+            // Unread lines are kept until readers catch up; meanwhile the line stays open.
+            if self.line.len() + usize::from(newline) > self.input.free()
+                || self.lines.len() == TERMINAL_BUFFER_SIZE
+            {
+                shown.push(BELL);
+            } else {
+                if newline {
+                    self.line.push(b'\n');
+                    shown.push(b'\n');
+                }
+                self.end_line();
+            }
+        } else if self.line.len() + 1 < self.input.free() {
+            self.line.push(byte);
+            shown.push(byte);
+        } else {
+            shown.push(BELL);
+        }
+
+        if settings.echo {
+            echo.extend_from_slice(&shown);
+        }
+    }
+
+    fn end_line(&mut self) {
+        self.lines.push_back(self.line.len());
+        for byte in self.line.drain(..) {
+            self.input.push(byte);
+        }
+    }
+
+    /// Returns `true` if a read would return without waiting.
+    pub(crate) fn readable(&self) -> bool {
+        match self.settings.mode {
+            TerminalMode::Canonical => !self.lines.is_empty(),
+            TerminalMode::Raw => !self.input.is_empty(),
+        }
+    }
+
+    /// Moves readable input into `buffer`: at most one line in canonical mode, where a
+    /// line that does not fit is returned over several reads.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes read, `Some(0)` at end of file, or `None` if nothing is
+    /// readable yet.
+    pub(crate) fn read(&mut self, buffer: &mut [u8]) -> Option<usize> {
+        self.read_part(buffer).map(|(count, _)| count)
+    }
+
+    /// Like [`LineDiscipline::read`], but also returns `true` once the read took the
+    /// rest of a canonical line, which tells a line ended by EOF apart from part of a
+    /// longer line.
+    pub(crate) fn read_part(&mut self, buffer: &mut [u8]) -> Option<(usize, bool)> {
+        if !self.readable() {
+            return None;
+        }
+        let available = match self.settings.mode {
+            TerminalMode::Canonical => self.lines[0],
+            TerminalMode::Raw => self.input.len(),
+        };
+        let count = available.min(buffer.len());
+        for slot in &mut buffer[..count] {
+            *slot = self.input.pop().unwrap_or_default();
+        }
+        let line_ended = self.settings.mode == TerminalMode::Canonical && count == available;
+        if line_ended {
+            self.lines.pop_front();
+        } else if self.settings.mode == TerminalMode::Canonical {
+            self.lines[0] -= count;
+        }
+        Some((count, line_ended))
+    }
+}
+
+/// Driver state of one terminal.
+///
+/// Like the disk queue, this is a pure state machine: it holds the line discipline and
+/// the output ring, and says which commands to issue. One read and one write may be
+/// outstanding at a time; `in_byte` and `out_byte` are the one-byte buffers those
+/// commands transfer through.
+pub(crate) struct Terminal {
+    present: bool,
+    discipline: LineDiscipline,
+    output: RingBuffer,
+    reading: bool,
+    writing: bool,
+    in_byte: u8,
+    out_byte: u8,
+    /// Decodes input for [`read_key`].
+    keys: KeyDecoder,
+    /// Processes blocked in a read or write, woken when either side makes progress.
+    waiting: Vec<Pid>,
+}
+
+impl Terminal {
+    pub(crate) const fn new() -> Self {
+        Terminal {
+            present: false,
+            discipline: LineDiscipline::new(),
+            output: RingBuffer::new(),
+            reading: false,
+            writing: false,
+            in_byte: 0,
+            out_byte: 0,
+            keys: KeyDecoder::new(),
+            waiting: Vec::new(),
+        }
+    }
+
+    /// Queues as much of `bytes` as fits in the output ring.
+    ///
+    /// # Returns
+    ///
+    /// The number of bytes of `bytes` queued.
+    pub(crate) fn queue_output(&mut self, bytes: &[u8]) -> usize {
+        let crlf = self.discipline.settings.crlf;
+        for (i, &byte) in bytes.iter().enumerate() {
+            let expanded = crlf && byte == b'\n';
+            if self.output.free() < if expanded { 2 } else { 1 } {
+                return i;
+            }
+            if expanded {
+                self.output.push(b'\r');
+            }
+            self.output.push(byte);
+        }
+        bytes.len()
+    }
+
+    /// Handles a completed `TERMINAL_READ_CHAR`, returning `true` if input became
+    /// readable or was echoed.
+    pub(crate) fn complete_read(&mut self, status: u32) -> bool {
+        self.reading = false;
+        // A byte typed on an unplugged terminal goes nowhere.
+        if status != TERMINAL_STATUS_OK || !self.present {
+            return false;
+        }
+        self.receive(self.in_byte)
+    }
+
+    /// Passes a received byte through the line discipline and queues its echo,
+    /// returning `true` if input is readable.
+    pub(crate) fn receive(&mut self, byte: u8) -> bool {
+        let mut echo = Vec::new();
+        self.discipline.receive(byte, &mut echo);
+        self.queue_output(&echo);
+        self.discipline.readable()
+    }
+
+    /// Handles a completed `TERMINAL_WRITE_CHAR`. A failed byte is not retried.
+    pub(crate) fn complete_write(&mut self) {
+        self.writing = false;
+    }
+
+    /// Marks the terminal unplugged, dropping its buffered input and output.
+    ///
+    /// The commands in flight stay marked as such until their completions arrive, so
+    /// none is issued twice once the terminal is back. Settings are kept.
+    ///
+    /// # Returns
+    ///
+    /// The processes blocked on the terminal, which then fail with `NotPresent`.
+    pub(crate) fn remove(&mut self) -> Vec<Pid> {
+        self.present = false;
+        let settings = self.discipline.settings();
+        self.discipline = LineDiscipline::new();
+        self.discipline.set_settings(settings);
+        self.output = RingBuffer::new();
+        self.keys = KeyDecoder::new();
+        core::mem::take(&mut self.waiting)
+    }
+
+    /// Forgets a command THREADS refused; a refused byte of output is lost.
+    fn reject(&mut self, command: u8) {
+        match command {
+            TERMINAL_WRITE_CHAR => self.writing = false,
+            _ => self.reading = false,
+        }
+    }
+
+    /// Returns the next command to issue, if the terminal has one to start.
+    fn next_command(&mut self) -> Option<TerminalCommand> {
+        if !self.present {
+            return None;
+        }
+        if !self.writing
+            && let Some(byte) = self.output.pop()
+        {
+            self.writing = true;
+            self.out_byte = byte;
+            return Some(TerminalCommand {
+                command: TERMINAL_WRITE_CHAR,
+                buffer: &mut self.out_byte,
+            });
+        }
+        if !self.reading {
+            self.reading = true;
+            return Some(TerminalCommand {
+                command: TERMINAL_READ_CHAR,
+                buffer: &mut self.in_byte,
+            });
+        }
+        None
+    }
+}
+
+/// A terminal command and the one-byte buffer it transfers through.
+struct TerminalCommand {
+    command: u8,
+    buffer: *mut u8,
+}
+
+impl TerminalCommand {
+    /// Builds the control block for this command.
+    ///
+    /// `TERMINAL_READ_CHAR` stores the received character in the one byte of
+    /// `output_data` before its completion interrupt; `TERMINAL_WRITE_CHAR` sends the one
+    /// byte of `input_data`.
+    fn control_block(&self) -> device_control_block_t {
+        let (input_data, output_data) = match self.command {
+            TERMINAL_WRITE_CHAR => (self.buffer.cast(), core::ptr::null_mut()),
+            _ => (core::ptr::null_mut(), self.buffer.cast()),
+        };
+        device_control_block_t {
+            command: self.command,
+            control1: 0,
+            control2: 0,
+            input_data,
+            output_data,
+            data_length: 1,
+        }
+    }
+}
+
+pub(crate) struct TerminalDriver {
+    terminals: [Terminal; THREADS_MAX_TERMINALS as usize],
+}
+
+static DRIVER: KernelCell<TerminalDriver> = KernelCell::new(TerminalDriver {
+    terminals: [const { Terminal::new() }; THREADS_MAX_TERMINALS as usize],
+});
+
+/// Initializes every THREADS terminal, installs the I/O interrupt handler and starts
+/// receiving input.
+///
+/// # Returns
+///
+/// The terminals that initialized successfully.
+///
+/// # Example
+///
+/// ```ignore
+/// let terminals = rusty_threads::init_terminals();
+/// assert!(terminals.contains(&rusty_threads::DeviceId::terminal(0)));
+/// ```
+pub fn init_terminals() -> Vec<DeviceId> {
+    device::install_io_handler();
+
+    let present: Vec<DeviceId> = (0..THREADS_MAX_TERMINALS)
+        .map(DeviceId::terminal)
+        .filter(|terminal| device_initialize(&terminal.name()).is_some())
+        .collect();
+
+    let guard = InterruptGuard::new();
+    for terminal in &present {
+        let unit = terminal.unit as usize;
+        DRIVER.with(&guard, |d| d.terminals[unit].present = true);
+        run(&guard, unit);
+    }
+    present
+}
+
+/// Reads input from `terminal` into `buffer`, blocking the calling process until some
+/// is available.
+///
+/// In canonical mode a read returns at most one line, including its `'\n'`; a line
+/// longer than `buffer` is returned over several reads. In raw mode it returns whatever
+/// has arrived.
+///
+/// # Returns
+///
+/// The number of bytes read; 0 means end of file (the EOF character at the start of a
+/// line) or an empty `buffer`.
+pub fn read_terminal(terminal: DeviceId, buffer: &mut [u8]) -> Result<usize, TerminalError> {
+    read_part(terminal, buffer).map(|(count, _)| count)
+}
+
+/// Reads like [`read_terminal`], also returning whether the read completed a line, see
+/// [`LineDiscipline::read_part`].
+fn read_part(terminal: DeviceId, buffer: &mut [u8]) -> Result<(usize, bool), TerminalError> {
+    let unit = terminal_unit(terminal)?;
+    if buffer.is_empty() {
+        return Ok((0, false));
+    }
+    let guard = InterruptGuard::new();
+    let pid = KERNEL.with(&guard, |k| k.current()).ok_or(TerminalError::NoProcess)?;
+    loop {
+        let read = DRIVER.with(&guard, |d| {
+            let t = &mut d.terminals[unit];
+            if !t.present {
+                return Err(TerminalError::NotPresent);
+            }
+            let read = t.discipline.read_part(buffer);
+            if read.is_none() {
+                t.waiting.push(pid);
+            }
+            Ok(read)
+        })?;
+        if let Some(read) = read {
+            return Ok(read);
+        }
+        scheduler::block_current(&guard, BlockReason::TerminalIo, None);
+    }
+}
+
+/// Reads one line from `terminal`, blocking the calling process until it is complete.
+///
+/// # Returns
+///
+/// The line including its `'\n'`, or without one if it was ended by EOF; an empty string
+/// means end of file. Bytes that are not UTF-8 are replaced with U+FFFD.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::DeviceId;
+///
+/// let term = DeviceId::terminal(0);
+/// rusty_threads::write_terminal(term, b"login: ")?;
+/// let name = rusty_threads::read_line(term)?;
+/// ```
+pub fn read_line(terminal: DeviceId) -> Result<String, TerminalError> {
+    let mut line = Vec::new();
+    let mut buffer = [0; TERMINAL_BUFFER_SIZE];
+    loop {
+        let (count, line_ended) = read_part(terminal, &mut buffer)?;
+        line.extend_from_slice(&buffer[..count]);
+        // In raw mode there are no lines, so the newline ends one.
+        if count == 0 || line_ended || line.last() == Some(&b'\n') {
+            return Ok(String::from_utf8_lossy(&line).into_owned());
+        }
+    }
+}
+
+/// Reads one key from `terminal`, blocking the calling process until it is complete.
+///
+/// Arrow, editing and function keys are decoded from their escape sequences (see
+/// [`KeyDecoder`]). The terminal should be in raw mode, usually without echo; in
+/// canonical mode keys only arrive once a line is ended. An ESC not followed by the rest
+/// of a sequence within [`KEY_ESCAPE_TIMEOUT`] is reported as [`Key::Escape`].
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, Key};
+///
+/// match rusty_threads::read_key(DeviceId::terminal(0))? {
+///     Key::Up => cursor_up(),
+///     Key::Char('q') | Key::Escape => return Ok(()),
+///     _ => {}
+/// }
+/// ```
+pub fn read_key(terminal: DeviceId) -> Result<Key, TerminalError> {
+    let unit = terminal_unit(terminal)?;
+    let guard = InterruptGuard::new();
+    let pid = KERNEL.with(&guard, |k| k.current()).ok_or(TerminalError::NoProcess)?;
+    let mut timed_out = false;
+    loop {
+        let (key, pending) = DRIVER.with(&guard, |d| {
+            let t = &mut d.terminals[unit];
+            if !t.present {
+                return Err(TerminalError::NotPresent);
+            }
+            let mut byte = [0];
+            while t.discipline.read(&mut byte) == Some(1) {
+                if let Some(key) = t.keys.push(byte[0]) {
+                    return Ok((Some(key), false));
+                }
+            }
+            if timed_out && let Some(key) = t.keys.flush() {
+                return Ok((Some(key), false));
+            }
+            t.waiting.push(pid);
+            Ok((None, t.keys.is_pending()))
+        })?;
+        if let Some(key) = key {
+            return Ok(key);
+        }
+        let timeout = pending.then_some(KEY_ESCAPE_TIMEOUT);
+        timed_out = scheduler::block_current(&guard, BlockReason::TerminalIo, timeout)
+            == WakeReason::TimedOut;
+        if timed_out {
+            DRIVER.with(&guard, |d| d.terminals[unit].waiting.retain(|&p| p != pid));
+        }
+    }
+}
+
+/// Writes `bytes` to `terminal`, blocking the calling process while the output buffer
+/// is full.
+///
+/// Returns once every byte is queued; they are sent in the background, one
+/// `TERMINAL_WRITE_CHAR` at a time.
+pub fn write_terminal(terminal: DeviceId, bytes: &[u8]) -> Result<(), TerminalError> {
+    let unit = terminal_unit(terminal)?;
+    let guard = InterruptGuard::new();
+    let pid = KERNEL.with(&guard, |k| k.current()).ok_or(TerminalError::NoProcess)?;
+    let mut rest = bytes;
+    loop {
+        let queued = DRIVER.with(&guard, |d| {
+            let t = &mut d.terminals[unit];
+            if !t.present {
+                return Err(TerminalError::NotPresent);
+            }
+            let queued = t.queue_output(rest);
+            if queued < rest.len() {
+                t.waiting.push(pid);
+            }
+            Ok(queued)
+        })?;
+        rest = &rest[queued..];
+        run(&guard, unit);
+        if rest.is_empty() {
+            return Ok(());
+        }
+        scheduler::block_current(&guard, BlockReason::TerminalIo, None);
+    }
+}
+
+/// Returns the line discipline settings of `terminal`.
+pub fn terminal_settings(terminal: DeviceId) -> Result<TerminalSettings, TerminalError> {
+    let unit = terminal_unit(terminal)?;
+    let guard = InterruptGuard::new();
+    Ok(DRIVER.with(&guard, |d| d.terminals[unit].discipline.settings()))
+}
+
+/// Changes the line discipline settings of `terminal`, e.g. to raw mode without echo.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, TerminalMode};
+///
+/// let term = DeviceId::terminal(1);
+/// let mut settings = rusty_threads::terminal_settings(term)?;
+/// settings.mode = TerminalMode::Raw;
+/// settings.echo = false;
+/// rusty_threads::set_terminal_settings(term, settings)?;
+/// ```
+pub fn set_terminal_settings(
+    terminal: DeviceId,
+    settings: TerminalSettings,
+) -> Result<(), TerminalError> {
+    let unit = terminal_unit(terminal)?;
+    let guard = InterruptGuard::new();
+    let waiting = DRIVER.with(&guard, |d| {
+        let t = &mut d.terminals[unit];
+        t.discipline.set_settings(settings);
+        core::mem::take(&mut t.waiting)
+    });
+    // Readers re-check: switching modes may have made input readable.
+    for pid in waiting {
+        scheduler::make_ready(&guard, pid);
+    }
+    Ok(())
+}
+
+pub(crate) fn terminal_unit(terminal: DeviceId) -> Result<usize, TerminalError> {
+    if terminal.kind != DeviceKind::Terminal || terminal.unit >= THREADS_MAX_TERMINALS {
+        return Err(TerminalError::NotATerminal);
+    }
+    Ok(terminal.unit as usize)
+}
+
+/// Starts whatever commands terminal `unit` can issue.
+fn run(guard: &InterruptGuard, unit: usize) {
+    let terminal = DeviceId::terminal(unit as u32);
+    let name = terminal.name();
+    while let Some(command) = DRIVER.with(guard, |d| d.terminals[unit].next_command()) {
+        if device_control(&name, command.control_block()).is_none() {
+            // Try again on the next interrupt or write rather than spinning here.
+            DRIVER.with(guard, |d| d.terminals[unit].reject(command.command));
+            return;
+        }
+        if command.command == TERMINAL_WRITE_CHAR {
+            let byte = DRIVER.with(guard, |d| d.terminals[unit].out_byte);
+            let mut listeners = LISTENERS.lock().unwrap_or_else(|e| e.into_inner());
+            for listener in listeners.iter_mut() {
+                listener(terminal, byte);
+            }
+        }
+    }
+}
+
+/// Observer of the bytes sent to terminals, see [`add_terminal_listener`].
+pub type TerminalListener = Box<dyn FnMut(DeviceId, u8) + Send>;
+
+/// Most bytes of host input queued per terminal; more are dropped, like a UART overrun.
+pub const HOST_INPUT_LIMIT: usize = 4096;
+
+// Host threads only touch these two queues, never the driver state: the kernel picks
+// the input up on its next clock interrupt.
+static HOST_INPUT: std::sync::Mutex<[VecDeque<u8>; THREADS_MAX_TERMINALS as usize]> =
+    std::sync::Mutex::new([const { VecDeque::new() }; THREADS_MAX_TERMINALS as usize]);
+static LISTENERS: std::sync::Mutex<Vec<TerminalListener>> = std::sync::Mutex::new(Vec::new());
+
+/// Calls `listener` with every byte sent to a terminal, as it is issued.
+///
+/// Listeners run on the kernel's side, with interrupts disabled, so they must only do
+/// quick work such as copying the byte into a host-side buffer.
+///
+/// # Example
+///
+/// ```ignore
+/// use std::sync::mpsc;
+///
+/// let (sender, receiver) = mpsc::channel();
+/// rusty_threads::add_terminal_listener(move |terminal, byte| {
+///     let _ = sender.send((terminal.unit, byte));
+/// });
+/// ```
+pub fn add_terminal_listener(listener: impl FnMut(DeviceId, u8) + Send + 'static) {
+    LISTENERS.lock().unwrap_or_else(|e| e.into_inner()).push(Box::new(listener));
+}
+
+/// Queues `bytes` as if typed on `terminal`. May be called from any host thread.
+///
+/// On the next clock interrupt each byte is raised as a `THREADS_IO_INTERRUPT` from the
+/// terminal with command [`TERMINAL_HOST_INPUT`], and the driver passes it through the
+/// line discipline exactly like a character read with `TERMINAL_READ_CHAR`.
+pub fn inject_terminal_input(terminal: DeviceId, bytes: &[u8]) -> Result<(), TerminalError> {
+    let unit = terminal_unit(terminal)?;
+    let mut input = HOST_INPUT.lock().unwrap_or_else(|e| e.into_inner());
+    let queue = &mut input[unit];
+    let room = HOST_INPUT_LIMIT.saturating_sub(queue.len());
+    queue.extend(&bytes[..bytes.len().min(room)]);
+    Ok(())
+}
+
+/// Raises the interrupts for the input queued by [`inject_terminal_input`]; called on
+/// every clock interrupt, with interrupts enabled again.
+pub(crate) fn on_clock() {
+    let input: Vec<Vec<u8>> = {
+        let Ok(mut queues) = HOST_INPUT.try_lock() else {
+            // A host thread is queueing; take the input on the next tick.
+            return;
+        };
+        queues.iter_mut().map(|q| q.drain(..).collect()).collect()
+    };
+    for (unit, bytes) in input.iter().enumerate() {
+        let name = DeviceId::terminal(unit as u32).name();
+        for &byte in bytes {
+            device::raise_io_interrupt(&name, TERMINAL_HOST_INPUT, u32::from(byte));
+        }
+    }
+}
+
+/// Forgets killed process `pid`, which may still be waiting on a terminal.
+pub(crate) fn cancel_requests(guard: &InterruptGuard, pid: Pid) {
+    DRIVER.with(guard, |d| d.terminals.iter_mut().for_each(|t| t.waiting.retain(|&p| p != pid)));
+}
+
+/// Handles a `THREADS_IO_INTERRUPT` from terminal `unit`.
+pub(crate) fn on_interrupt(unit: u32, command: u8, status: u32) {
+    let unit = unit as usize;
+    if unit >= THREADS_MAX_TERMINALS as usize {
+        return;
+    }
+    let guard = InterruptGuard::new();
+    if command == HOTPLUG_COMMAND {
+        return on_hotplug(&guard, unit, status);
+    }
+    let waiting = DRIVER.with(&guard, |d| {
+        let t = &mut d.terminals[unit];
+        let progress = match command {
+            TERMINAL_READ_CHAR => t.complete_read(status),
+            // Input typed on an unplugged terminal goes nowhere.
+            TERMINAL_HOST_INPUT => t.present && t.receive(status as u8),
+            TERMINAL_WRITE_CHAR => {
+                t.complete_write();
+                true
+            }
+            _ => false,
+        };
+        if progress { core::mem::take(&mut t.waiting) } else { Vec::new() }
+    });
+    for pid in waiting {
+        scheduler::make_ready(&guard, pid);
+    }
+    run(&guard, unit);
+}
+
+/// Wakes the processes blocked on terminal `unit` when it is unplugged, and initializes
+/// it again when it is plugged back in.
+fn on_hotplug(guard: &InterruptGuard, unit: usize, status: u32) {
+    match status {
+        DEVICE_STATUS_REMOVED => {
+            for pid in DRIVER.with(guard, |d| d.terminals[unit].remove()) {
+                scheduler::make_ready(guard, pid);
+            }
+        }
+        DEVICE_STATUS_ATTACHED
+            if device_initialize(&DeviceId::terminal(unit as u32).name()).is_some() =>
+        {
+            DRIVER.with(guard, |d| d.terminals[unit].present = true);
+            run(guard, unit);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_in(discipline: &mut LineDiscipline, bytes: &[u8]) -> Vec<u8> {
+        let mut echo = Vec::new();
+        for &byte in bytes {
+            discipline.receive(byte, &mut echo);
+        }
+        echo
+    }
+
+    fn read(discipline: &mut LineDiscipline, size: usize) -> Option<Vec<u8>> {
+        let mut buffer = vec![0; size];
+        let count = discipline.read(&mut buffer)?;
+        buffer.truncate(count);
+        Some(buffer)
+    }
+
+    #[test]
+    fn test_ring_buffer_wraps() {
+        let mut ring = RingBuffer::new();
+        for i in 0..TERMINAL_BUFFER_SIZE {
+            assert!(ring.push(i as u8));
+        }
+        assert!(!ring.push(0));
+        assert_eq!((ring.pop(), ring.pop()), (Some(0), Some(1)));
+        assert!(ring.push(0xAA) && ring.push(0xBB));
+        assert_eq!(ring.len(), TERMINAL_BUFFER_SIZE);
+        let drained: Vec<u8> = std::iter::from_fn(|| ring.pop()).collect();
+        assert_eq!(&drained[drained.len() - 3..], &[255, 0xAA, 0xBB]);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_canonical_editing() {
+        let mut discipline = LineDiscipline::new();
+        let echo = type_in(&mut discipline, b"lx\x7fs -k\x15ls\x08s -l");
+        assert!(!discipline.readable());
+        assert_eq!(read(&mut discipline, 16), None);
+        type_in(&mut discipline, b"\r");
+        assert_eq!(read(&mut discipline, 16), Some(b"ls -l\n".to_vec()));
+        assert!(echo.starts_with(b"lx\x08 \x08s -k"));
+        assert!(echo.ends_with(b"ls\x08 \x08s -l"));
+
+        // Long lines come out over several reads, but a read never crosses a line.
+        type_in(&mut discipline, b"abcdef\nxy\n");
+        assert_eq!(read(&mut discipline, 4), Some(b"abcd".to_vec()));
+        assert_eq!(read(&mut discipline, 16), Some(b"ef\n".to_vec()));
+        assert_eq!(read(&mut discipline, 16), Some(b"xy\n".to_vec()));
+    }
+
+    #[test]
+    fn test_reads_report_where_lines_end() {
+        let mut discipline = LineDiscipline::new();
+        type_in(&mut discipline, b"abc\x04abcdef\n");
+        let mut buffer = [0; 4];
+        // A line ended by EOF is complete without its newline; a long one only at its end.
+        assert_eq!(discipline.read_part(&mut buffer), Some((3, true)));
+        assert_eq!(discipline.read_part(&mut buffer), Some((4, false)));
+        assert_eq!(discipline.read_part(&mut buffer), Some((3, true)));
+    }
+
+    #[test]
+    fn test_eof_and_raw_mode() {
+        let mut discipline = LineDiscipline::new();
+        type_in(&mut discipline, b"no newline\x04\x04");
+        assert_eq!(read(&mut discipline, 32), Some(b"no newline".to_vec()));
+        assert_eq!(read(&mut discipline, 32), Some(Vec::new()));
+
+        type_in(&mut discipline, b"par");
+        let raw = TerminalSettings { mode: TerminalMode::Raw, echo: false, ..Default::default() };
+        discipline.set_settings(raw);
+        assert_eq!(type_in(&mut discipline, b"t\x7f\x04"), Vec::<u8>::new());
+        assert_eq!(read(&mut discipline, 32), Some(b"part\x7f\x04".to_vec()));
+        assert_eq!(read(&mut discipline, 32), None);
+    }
+
+    #[test]
+    fn test_terminal_output_and_echo() {
+        let mut terminal = Terminal::new();
+        terminal.present = true;
+        assert_eq!(terminal.next_command().unwrap().command, TERMINAL_READ_CHAR);
+        terminal.in_byte = b'\r';
+        assert!(terminal.complete_read(TERMINAL_STATUS_OK));
+
+        // The echoed newline goes out as "\r\n", one byte per write.
+        let command = terminal.next_command().unwrap();
+        assert_eq!((command.command, terminal.out_byte), (TERMINAL_WRITE_CHAR, b'\r'));
+        assert_eq!(terminal.next_command().unwrap().command, TERMINAL_READ_CHAR);
+        assert!(terminal.next_command().is_none());
+        terminal.complete_write();
+        terminal.next_command();
+        assert_eq!(terminal.out_byte, b'\n');
+
+        let long = vec![b'\n'; TERMINAL_BUFFER_SIZE];
+        assert_eq!(terminal.queue_output(&long), TERMINAL_BUFFER_SIZE / 2);
+    }
+
+    #[test]
+    fn test_removal_drops_buffers_and_wakes_waiters() {
+        let mut terminal = Terminal::new();
+        terminal.present = true;
+        terminal.next_command();
+        terminal.queue_output(b"queued");
+        terminal.waiting = vec![3, 4];
+
+        assert_eq!(terminal.remove(), [3, 4]);
+        assert!(terminal.next_command().is_none());
+        terminal.in_byte = b'\n';
+        assert!(!terminal.complete_read(TERMINAL_STATUS_OK));
+
+        // Back in, the terminal starts over with a fresh read and nothing to send.
+        terminal.present = true;
+        assert_eq!(terminal.next_command().unwrap().command, TERMINAL_READ_CHAR);
+        assert!(terminal.next_command().is_none() && !terminal.discipline.readable());
+    }
+}
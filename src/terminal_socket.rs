@@ -0,0 +1,442 @@
+// src/terminal_socket.rs
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use crate::constants::THREADS_MAX_TERMINALS;
+use crate::device::DeviceId;
+use crate::terminal::{TerminalError, add_terminal_listener, inject_terminal_input, terminal_unit};
+
+/// Sent to a client that connects while every terminal of the server is in use.
+pub const NO_FREE_TERMINAL: &[u8] = b"no free terminal\r\n";
+
+const TERMINALS: usize = THREADS_MAX_TERMINALS as usize;
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+/// Tells a telnet client that the server echoes and sends characters as they are typed,
+/// so the terminal's own line discipline does the editing.
+const TELNET_HANDSHAKE: [u8; 6] = [IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD];
+
+/// Errors returned by [`serve_terminals`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalSocketError {
+    /// No terminals were given.
+    NoTerminals,
+    /// A device is not a terminal.
+    Terminal(TerminalError),
+    /// The socket could not be bound.
+    Io(io::ErrorKind),
+}
+
+impl From<TerminalError> for TerminalSocketError {
+    fn from(error: TerminalError) -> Self {
+        TerminalSocketError::Terminal(error)
+    }
+}
+
+impl From<io::Error> for TerminalSocketError {
+    fn from(error: io::Error) -> Self {
+        TerminalSocketError::Io(error.kind())
+    }
+}
+
+/// Where [`serve_terminals`] listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerminalEndpoint {
+    /// A loopback TCP port carrying raw bytes, e.g. for `nc`. Port 0 picks a free port.
+    Tcp(u16),
+    /// A loopback TCP port speaking enough of the telnet protocol for `telnet` to send
+    /// characters as they are typed. Port 0 picks a free port.
+    Telnet(u16),
+    /// A Unix domain socket at this path, carrying raw bytes.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl fmt::Display for TerminalEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerminalEndpoint::Tcp(port) => write!(f, "tcp://127.0.0.1:{port}"),
+            TerminalEndpoint::Telnet(port) => write!(f, "telnet://127.0.0.1:{port}"),
+            #[cfg(unix)]
+            TerminalEndpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Strips telnet commands from the bytes a telnet client sends.
+///
+/// Option negotiation and subnegotiation are dropped, an escaped `IAC IAC` becomes one
+/// 255 byte, and the `CR LF` or `CR NUL` a client sends for Enter becomes a single `CR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum TelnetFilter {
+    #[default]
+    Data,
+    /// After a `CR`.
+    Return,
+    /// After an `IAC`.
+    Command,
+    /// After `IAC WILL`, `WONT`, `DO` or `DONT`, before the option byte.
+    Option,
+    /// Inside `IAC SB ... IAC SE`.
+    Subnegotiation,
+    /// After an `IAC` inside a subnegotiation.
+    SubnegotiationCommand,
+}
+
+impl TelnetFilter {
+    /// Returns the data byte `byte` stands for, if any.
+    pub(crate) fn push(&mut self, byte: u8) -> Option<u8> {
+        let (next, data) = match (*self, byte) {
+            (TelnetFilter::Return, b'\n' | 0) => (TelnetFilter::Data, None),
+            (TelnetFilter::Data | TelnetFilter::Return, IAC) => (TelnetFilter::Command, None),
+            (TelnetFilter::Data | TelnetFilter::Return, b'\r') => {
+                (TelnetFilter::Return, Some(byte))
+            }
+            (TelnetFilter::Data | TelnetFilter::Return, _) => (TelnetFilter::Data, Some(byte)),
+            (TelnetFilter::Command, IAC) => (TelnetFilter::Data, Some(IAC)),
+            (TelnetFilter::Command, WILL..=DONT) => (TelnetFilter::Option, None),
+            (TelnetFilter::Command, SB) => (TelnetFilter::Subnegotiation, None),
+            (TelnetFilter::Command | TelnetFilter::Option, _) => (TelnetFilter::Data, None),
+            (TelnetFilter::Subnegotiation, IAC) => (TelnetFilter::SubnegotiationCommand, None),
+            (TelnetFilter::Subnegotiation, _) => (TelnetFilter::Subnegotiation, None),
+            (TelnetFilter::SubnegotiationCommand, SE) => (TelnetFilter::Data, None),
+            (TelnetFilter::SubnegotiationCommand, _) => (TelnetFilter::Subnegotiation, None),
+        };
+        *self = next;
+        data
+    }
+}
+
+/// A running socket server for terminals, see [`serve_terminals`].
+#[derive(Debug)]
+pub struct TerminalServer {
+    id: u64,
+    endpoint: TerminalEndpoint,
+    running: Arc<AtomicBool>,
+}
+
+impl TerminalServer {
+    /// Where the server listens; a TCP port 0 is replaced by the port it got.
+    pub fn endpoint(&self) -> &TerminalEndpoint {
+        &self.endpoint
+    }
+
+    /// Stops accepting connections and closes the open ones. A Unix socket file is
+    /// removed.
+    pub fn shutdown(self) {
+        self.running.store(false, Ordering::SeqCst);
+        // Wake the accept loop so it sees the flag.
+        match &self.endpoint {
+            TerminalEndpoint::Tcp(port) | TerminalEndpoint::Telnet(port) => {
+                let _ = TcpStream::connect((Ipv4Addr::LOCALHOST, *port));
+            }
+            #[cfg(unix)]
+            TerminalEndpoint::Unix(path) => {
+                let _ = UnixStream::connect(path);
+                let _ = std::fs::remove_file(path);
+            }
+        }
+        for connection in connections().iter().flatten() {
+            if connection.server == self.id {
+                connection.stream.shutdown();
+            }
+        }
+    }
+}
+
+/// A client attached to a terminal.
+struct Connection {
+    server: u64,
+    /// Output for the client's writer thread.
+    output: Sender<u8>,
+    /// Kept to close the connection on shutdown.
+    stream: Stream,
+}
+
+static CONNECTIONS: Mutex<[Option<Connection>; TERMINALS]> =
+    Mutex::new([const { None }; TERMINALS]);
+static LISTENING: AtomicBool = AtomicBool::new(false);
+static NEXT_SERVER: AtomicU64 = AtomicU64::new(0);
+
+fn connections() -> MutexGuard<'static, [Option<Connection>; TERMINALS]> {
+    CONNECTIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Exposes `terminals` on a local socket so a test harness, `nc` or `telnet` can type
+/// input and read output.
+///
+/// Each connection is attached to the first of `terminals` that has no client yet, and
+/// is turned away with [`NO_FREE_TERMINAL`] if there is none. Each byte the client sends
+/// raises a `THREADS_IO_INTERRUPT` from the terminal on the next clock interrupt (command
+/// [`crate::terminal::TERMINAL_HOST_INPUT`]), which the terminal driver handles exactly
+/// like a character read with `TERMINAL_READ_CHAR` (line discipline, echo and waking
+/// readers included); every byte the driver writes to the terminal is sent to the client.
+/// The server runs on host threads until [`TerminalServer::shutdown`].
+///
+/// # Arguments
+///
+/// * `terminals` – The terminals connections may attach to.
+/// * `endpoint` – Where to listen. TCP ports are bound on the loopback address only.
+///
+/// # Returns
+///
+/// The running server, or an error if a device is not a terminal or the socket cannot be
+/// bound.
+///
+/// # Example
+///
+/// ```ignore
+/// use rusty_threads::{DeviceId, TerminalEndpoint};
+///
+/// rusty_threads::init_terminals();
+/// let server = rusty_threads::serve_terminals(
+///     &[DeviceId::terminal(0), DeviceId::terminal(1)],
+///     TerminalEndpoint::Telnet(2323),
+/// )?;
+/// rusty_threads::console_output(false, &format!("terminals on {}\n", server.endpoint()));
+/// ```
+pub fn serve_terminals(
+    terminals: &[DeviceId],
+    endpoint: TerminalEndpoint,
+) -> Result<TerminalServer, TerminalSocketError> {
+    if terminals.is_empty() {
+        return Err(TerminalSocketError::NoTerminals);
+    }
+    let units = terminals.iter().map(|&t| terminal_unit(t)).collect::<Result<Vec<_>, _>>()?;
+    let (listener, endpoint) = Listener::bind(endpoint)?;
+    if !LISTENING.swap(true, Ordering::SeqCst) {
+        add_terminal_listener(route_output);
+    }
+    let id = NEXT_SERVER.fetch_add(1, Ordering::SeqCst);
+    let running = Arc::new(AtomicBool::new(true));
+    let flag = Arc::clone(&running);
+    thread::spawn(move || accept_loop(listener, id, units, flag));
+    Ok(TerminalServer { id, endpoint, running })
+}
+
+/// Terminal listener that hands output to the client of the terminal, if any.
+fn route_output(terminal: DeviceId, byte: u8) {
+    if let Some(connection) = &connections()[terminal.unit as usize] {
+        let _ = connection.output.send(byte);
+    }
+}
+
+fn accept_loop(listener: Listener, server: u64, units: Vec<usize>, running: Arc<AtomicBool>) {
+    let telnet = matches!(listener, Listener::Tcp(_, true));
+    loop {
+        let stream = listener.accept();
+        if !running.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(stream) = stream {
+            let units = units.clone();
+            thread::spawn(move || serve_connection(stream, server, &units, telnet));
+        }
+    }
+}
+
+fn serve_connection(mut stream: Stream, server: u64, units: &[usize], telnet: bool) {
+    let (Ok(writer), Ok(control)) = (stream.try_clone(), stream.try_clone()) else {
+        return;
+    };
+    let (output, receiver) = mpsc::channel();
+    let unit = {
+        let mut connections = connections();
+        let unit = units.iter().copied().find(|&u| connections[u].is_none());
+        if let Some(unit) = unit {
+            connections[unit] = Some(Connection { server, output, stream: control });
+        }
+        unit
+    };
+    let Some(unit) = unit else {
+        let _ = stream.write_all(NO_FREE_TERMINAL);
+        return;
+    };
+    if telnet {
+        let _ = stream.write_all(&TELNET_HANDSHAKE);
+    }
+    thread::spawn(move || write_loop(writer, receiver, telnet));
+
+    let terminal = DeviceId::terminal(unit as u32);
+    let mut filter = TelnetFilter::default();
+    let mut buffer = [0; 256];
+    let mut input = Vec::new();
+    while let Ok(count @ 1..) = stream.read(&mut buffer) {
+        input.clear();
+        if telnet {
+            input.extend(buffer[..count].iter().filter_map(|&b| filter.push(b)));
+        } else {
+            input.extend_from_slice(&buffer[..count]);
+        }
+        let _ = inject_terminal_input(terminal, &input);
+    }
+    // Dropping the sender ends the writer thread.
+    connections()[unit] = None;
+    stream.shutdown();
+}
+
+fn write_loop(mut stream: Stream, output: Receiver<u8>, telnet: bool) {
+    let mut batch = Vec::new();
+    while let Ok(byte) = output.recv() {
+        batch.clear();
+        for byte in std::iter::once(byte).chain(output.try_iter()) {
+            batch.push(byte);
+            if telnet && byte == IAC {
+                batch.push(IAC);
+            }
+        }
+        if stream.write_all(&batch).is_err() {
+            return;
+        }
+    }
+}
+
+enum Listener {
+    /// The flag is set for telnet.
+    Tcp(TcpListener, bool),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    fn bind(endpoint: TerminalEndpoint) -> io::Result<(Self, TerminalEndpoint)> {
+        match endpoint {
+            TerminalEndpoint::Tcp(port) | TerminalEndpoint::Telnet(port) => {
+                let telnet = matches!(endpoint, TerminalEndpoint::Telnet(_));
+                let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+                let port = listener.local_addr()?.port();
+                let endpoint = match telnet {
+                    true => TerminalEndpoint::Telnet(port),
+                    false => TerminalEndpoint::Tcp(port),
+                };
+                Ok((Listener::Tcp(listener, telnet), endpoint))
+            }
+            #[cfg(unix)]
+            TerminalEndpoint::Unix(path) => {
+                let listener = UnixListener::bind(&path)?;
+                Ok((Listener::Unix(listener), TerminalEndpoint::Unix(path)))
+            }
+        }
+    }
+
+    fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener, _) => listener.accept().map(|(s, _)| Stream::Tcp(s)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().map(|(s, _)| Stream::Unix(s)),
+        }
+    }
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    fn shutdown(&self) {
+        let _ = match self {
+            Stream::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.shutdown(Shutdown::Both),
+        };
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_telnet_filter() {
+        let mut filter = TelnetFilter::default();
+        let sent = [
+            b'l', IAC, DONT, ECHO, b's', b'\r', 0, IAC, IAC, IAC, SB, 24, 0, b'x', IAC, SE, b'\r',
+            b'\n', b'\r', b'q',
+        ];
+        let data: Vec<u8> = sent.iter().filter_map(|&b| filter.push(b)).collect();
+        assert_eq!(data, [b'l', b's', b'\r', IAC, b'\r', b'\r', b'q']);
+    }
+
+    #[test]
+    fn test_tcp_connection_attaches_to_terminal() {
+        let terminal = DeviceId::terminal(2);
+        let server = serve_terminals(&[terminal], TerminalEndpoint::Tcp(0)).unwrap();
+        let TerminalEndpoint::Tcp(port) = *server.endpoint() else { panic!() };
+        let mut client = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while connections()[2].is_none() {
+            assert!(Instant::now() < deadline, "connection was not attached");
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        route_output(terminal, b'o');
+        route_output(terminal, b'k');
+        let mut received = [0; 2];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"ok");
+
+        let mut second = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).unwrap();
+        let mut refused = Vec::new();
+        second.read_to_end(&mut refused).unwrap();
+        assert_eq!(refused, NO_FREE_TERMINAL);
+
+        server.shutdown();
+        assert_eq!(client.read(&mut received).unwrap(), 0);
+    }
+}
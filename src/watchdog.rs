@@ -0,0 +1,239 @@
+// src/watchdog.rs
+
+use std::ffi::c_void;
+
+use crate::constants::THREADS_MIN_STACK_SIZE;
+use crate::interrupts::{InterruptGuard, KernelCell};
+use crate::process::{ProcessError, spawn_kernel_process};
+use crate::rusty_wrapper::{console_output, stop, system_clock};
+use crate::scheduler::{KERNEL, Pid, ProcessState};
+
+/// Priority of the watchdog process: below every user process, so it only runs when
+/// nothing else can.
+pub const WATCHDOG_PRIORITY: u32 = 0;
+
+/// Exit code passed to `stop` when the watchdog detects a deadlock.
+pub const WATCHDOG_DEADLOCK_EXIT_CODE: i32 = 0xDEAD;
+
+/// Exit code passed to `stop` when a process hogs the CPU past the configured threshold.
+pub const WATCHDOG_CPU_HOG_EXIT_CODE: i32 = 0xB0B;
+
+/// Default CPU hog threshold: five seconds of uninterrupted running.
+pub const DEFAULT_CPU_HOG_THRESHOLD: u32 = 5_000_000;
+
+/// Watchdog settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// Longest a process may run without blocking or being switched out, in
+    /// microseconds. `None` disables hog detection.
+    pub cpu_hog_threshold: Option<u32>,
+    /// Stack size of the watchdog process.
+    pub stack_size: u32,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            cpu_hog_threshold: Some(DEFAULT_CPU_HOG_THRESHOLD),
+            stack_size: THREADS_MIN_STACK_SIZE,
+        }
+    }
+}
+
+/// What the watchdog concluded from the process table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    /// Some process can still make progress.
+    Healthy,
+    /// Every process other than the watchdog has quit.
+    AllQuit,
+    /// Every remaining process is blocked and nothing but another process could wake it.
+    Deadlock,
+}
+
+struct Watchdog {
+    pid: Option<Pid>,
+    config: WatchdogConfig,
+}
+
+static WATCHDOG: KernelCell<Option<Watchdog>> = KernelCell::new(None);
+
+/// Spawns the watchdog process described in the THREADS `bootstrap()` documentation.
+///
+/// The watchdog runs at [`WATCHDOG_PRIORITY`], so it is only scheduled when every other
+/// process is blocked or has quit. When it runs it:
+///
+/// - halts with `stop(0)` once every other process has quit,
+/// - halts with [`WATCHDOG_DEADLOCK_EXIT_CODE`] if every remaining process is blocked with
+///   no pending timeout or I/O that could wake it.
+///
+/// In addition, the clock interrupt halts with [`WATCHDOG_CPU_HOG_EXIT_CODE`] if a process
+/// runs for longer than `cpu_hog_threshold` without blocking or being switched out.
+/// Before halting for a fault, the process table is dumped to the console.
+///
+/// # Example
+///
+/// ```ignore
+/// #[unsafe(no_mangle)]
+/// extern "C" fn bootstrap(_args: *mut core::ffi::c_void) -> i32 {
+///     rusty_threads::scheduler::init();
+///     rusty_threads::spawn_watchdog(rusty_threads::WatchdogConfig::default())
+///         .expect("watchdog");
+///     rusty_threads::spawn("startup", 3, rusty_threads::THREADS_MIN_STACK_SIZE, startup)
+///         .expect("startup process");
+///     rusty_threads::scheduler::start();
+/// }
+/// ```
+pub fn spawn_watchdog(config: WatchdogConfig) -> Result<Pid, ProcessError> {
+    {
+        let guard = InterruptGuard::new();
+        WATCHDOG.with(&guard, |w| *w = Some(Watchdog { pid: None, config }));
+    }
+
+    let pid = spawn_kernel_process(
+        "watchdog",
+        WATCHDOG_PRIORITY,
+        config.stack_size,
+        watchdog_main,
+        core::ptr::null_mut(),
+    )?;
+
+    let guard = InterruptGuard::new();
+    WATCHDOG.with(&guard, |w| {
+        if let Some(watchdog) = w {
+            watchdog.pid = Some(pid);
+        }
+    });
+    Ok(pid)
+}
+
+/// Prints the process table to the console.
+///
+/// # Example
+///
+/// ```text
+///  PID PARENT PRI BASE  CPU(us) STATE                NAME
+///    1      -   0    0       12 Running              watchdog
+///    2      -   3    3     4810 Blocked(Join)        startup
+/// ```
+pub fn dump_process_table() {
+    let guard = InterruptGuard::new();
+    let now = system_clock();
+    let rows: Vec<String> = KERNEL.with(&guard, |k| {
+        let current = k.current();
+        k.processes()
+            .map(|p| {
+                let mut cpu_time = p.cpu_time;
+                if Some(p.pid) == current {
+                    cpu_time = cpu_time.wrapping_add(now.wrapping_sub(p.running_since));
+                }
+                let parent = p.parent.map_or_else(|| "-".to_string(), |pid| pid.to_string());
+                // Derived `Debug` ignores width, so pad the rendered string instead.
+                let state = format!("{:?}", p.state);
+                format!(
+                    "{:>4} {:>6} {:>3} {:>4} {:>8} {:<20} {}\n",
+                    p.pid, parent, p.priority, p.base_priority, cpu_time, state, p.name
+                )
+            })
+            .collect()
+    });
+
+    console_output(false, " PID PARENT PRI BASE  CPU(us) STATE                NAME\n");
+    for row in rows {
+        console_output(false, &row);
+    }
+}
+
+/// Called from the clock interrupt; halts if the running process has hogged the CPU.
+pub(crate) fn check_cpu_hog(guard: &InterruptGuard, now: u32) {
+    let Some((watchdog, threshold)) = WATCHDOG
+        .with(guard, |w| w.as_ref().and_then(|w| w.config.cpu_hog_threshold.map(|t| (w.pid, t))))
+    else {
+        return;
+    };
+
+    let hog = KERNEL.with(guard, |k| {
+        let pid = k.current().filter(|&pid| Some(pid) != watchdog)?;
+        let process = k.process(pid).filter(|p| p.state == ProcessState::Running)?;
+        (now.wrapping_sub(process.running_since) >= threshold).then_some(pid)
+    });
+
+    if let Some(pid) = hog {
+        halt(
+            &format!("process {pid} ran for over {threshold} us without yielding"),
+            WATCHDOG_CPU_HOG_EXIT_CODE,
+        );
+    }
+}
+
+/// Classifies the processes other than the watchdog by `(state, awaits_event)`.
+fn assess(processes: impl Iterator<Item = (ProcessState, bool)>) -> Verdict {
+    let mut any_live = false;
+    for (state, awaits_event) in processes {
+        match state {
+            ProcessState::Quit => {}
+            ProcessState::Blocked(_) if !awaits_event => any_live = true,
+            _ => return Verdict::Healthy,
+        }
+    }
+    if any_live { Verdict::Deadlock } else { Verdict::AllQuit }
+}
+
+fn halt(reason: &str, code: i32) -> ! {
+    console_output(false, &format!("watchdog: {reason}, halting\n"));
+    dump_process_table();
+    stop(code)
+}
+
+extern "C" fn watchdog_main(_args: *mut c_void) -> i32 {
+    loop {
+        let verdict = {
+            let guard = InterruptGuard::new();
+            KERNEL.with(&guard, |k| {
+                let me = k.current();
+                assess(
+                    k.processes()
+                        .filter(|p| Some(p.pid) != me)
+                        .map(|p| (p.state, p.awaits_event())),
+                )
+            })
+        };
+
+        match verdict {
+            Verdict::Healthy => {}
+            Verdict::AllQuit => {
+                console_output(false, "watchdog: all processes have quit\n");
+                stop(0);
+            }
+            Verdict::Deadlock => halt("deadlock detected", WATCHDOG_DEADLOCK_EXIT_CODE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::BlockReason;
+
+    const BLOCKED: ProcessState = ProcessState::Blocked(BlockReason::Mutex);
+
+    #[test]
+    fn test_all_quit() {
+        assert_eq!(assess([].into_iter()), Verdict::AllQuit);
+        assert_eq!(assess([(ProcessState::Quit, false)].into_iter()), Verdict::AllQuit);
+    }
+
+    #[test]
+    fn test_deadlock_requires_every_process_blocked_without_events() {
+        assert_eq!(
+            assess([(BLOCKED, false), (ProcessState::Quit, false)].into_iter()),
+            Verdict::Deadlock
+        );
+        // A pending timeout will eventually wake someone up.
+        assert_eq!(assess([(BLOCKED, false), (BLOCKED, true)].into_iter()), Verdict::Healthy);
+        assert_eq!(
+            assess([(BLOCKED, false), (ProcessState::Ready, false)].into_iter()),
+            Verdict::Healthy
+        );
+    }
+}
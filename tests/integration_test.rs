@@ -82,3 +82,391 @@ fn test_public_wrapper_accessability() {
 }
 
 
+
+#[test]
+fn test_sync_primitives_accessibility() {
+    // Kernel objects must be constructible in statics; using them requires a running
+    // THREADS session, so only construction is checked here.
+    static _SEM: Semaphore = Semaphore::new(2, WakeOrder::Fifo);
+    static _LOCK: Mutex = Mutex::new(WakeOrder::Priority);
+    static _COND: Condvar = Condvar::new(WakeOrder::Fifo);
+
+    assert_ne!(SyncError::TimedOut, SyncError::NoProcess);
+    assert_ne!(ProcessState::Ready, ProcessState::Running);
+}
+
+#[test]
+fn test_priority_protocol_accessibility() {
+    static _INHERIT: Mutex = Mutex::with_protocol(WakeOrder::Priority, MutexProtocol::Inheritance);
+    static _CEILING: Mutex =
+        Mutex::with_protocol(WakeOrder::Fifo, MutexProtocol::Ceiling(HIGHEST_PRIORITY));
+
+    assert_eq!(PRIORITY_TRACE_CAPACITY, 256);
+    assert_ne!(PriorityCause::Inherited, PriorityCause::Restored);
+}
+
+#[test]
+fn test_mailbox_accessibility() {
+    static _SLOTTED: Mailbox = Mailbox::new(8, 64, WakeOrder::Fifo);
+    static _RENDEZVOUS: Mailbox = Mailbox::new(0, 64, WakeOrder::Priority);
+
+    assert_ne!(MailboxError::Released, MailboxError::WouldBlock);
+}
+
+#[test]
+fn test_process_lifecycle_accessibility() {
+    extern "C" fn entry(_args: *mut core::ffi::c_void) -> i32 {
+        0
+    }
+    let _spawn: fn(&str, u32, u32, ProcessEntry) -> Result<Pid, ProcessError> = spawn;
+    let _join: fn() -> Result<(Pid, i32), ProcessError> = join;
+    let _kill: fn(Pid) -> Result<(), ProcessError> = kill;
+    let _entry: ProcessEntry = entry;
+
+    assert_eq!(KILLED_EXIT_CODE, -1);
+    // Argument validation happens before THREADS is touched.
+    assert_eq!(
+        spawn("p", HIGHEST_PRIORITY + 1, THREADS_MIN_STACK_SIZE, entry),
+        Err(ProcessError::InvalidPriority)
+    );
+    assert_eq!(
+        spawn("p", LOWEST_PRIORITY, THREADS_MIN_STACK_SIZE - 1, entry),
+        Err(ProcessError::StackTooSmall)
+    );
+}
+
+#[test]
+fn test_watchdog_accessibility() {
+    let config = WatchdogConfig::default();
+    assert_eq!(config.cpu_hog_threshold, Some(DEFAULT_CPU_HOG_THRESHOLD));
+    assert_eq!(config.stack_size, THREADS_MIN_STACK_SIZE);
+    assert_ne!(WATCHDOG_DEADLOCK_EXIT_CODE, WATCHDOG_CPU_HOG_EXIT_CODE);
+    assert_ne!(WATCHDOG_DEADLOCK_EXIT_CODE, 0);
+}
+
+#[test]
+fn test_disk_driver_accessibility() {
+    let _read: fn(DeviceId, DiskAddress, &mut [u8]) -> Result<(), DiskError> = read_sectors;
+    let _write: fn(DeviceId, DiskAddress, &[u8]) -> Result<(), DiskError> = write_sectors;
+
+    assert_eq!(DeviceId::disk(1).name(), "disk1");
+    assert_eq!(DeviceId::from_name("term0"), Some(DeviceId::terminal(0)));
+    assert_eq!(DiskAddress::new(0, 0, THREADS_DISK_SECTOR_COUNT).offset(0).track, 1);
+
+    // Argument validation happens before THREADS is touched.
+    let mut sector = [0u8; THREADS_DISK_SECTOR_SIZE as usize];
+    let origin = DiskAddress::new(0, 0, 0);
+    let beyond_platters = DiskAddress::new(THREADS_DISK_MAX_PLATTERS, 0, 0);
+    assert_eq!(read_sectors(DeviceId::terminal(0), origin, &mut sector), Err(DiskError::NotADisk));
+    assert_eq!(
+        read_sectors(DeviceId::disk(0), origin, &mut sector[1..]),
+        Err(DiskError::InvalidBuffer)
+    );
+    assert_eq!(
+        write_sectors(DeviceId::disk(0), beyond_platters, &sector),
+        Err(DiskError::InvalidAddress)
+    );
+}
+
+#[test]
+fn test_disk_scheduling_accessibility() {
+    let _set: fn(DeviceId, DiskSchedulingPolicy) -> Result<(), DiskError> = set_disk_policy;
+    let _stats: fn(DeviceId) -> Result<DiskStats, DiskError> = disk_stats;
+
+    assert_eq!(DiskSchedulingPolicy::default(), DiskSchedulingPolicy::Fcfs);
+    assert_eq!(DiskStats::default().average_wait(), None);
+    assert_eq!(
+        set_disk_policy(DeviceId::terminal(0), DiskSchedulingPolicy::Look),
+        Err(DiskError::NotADisk)
+    );
+}
+
+#[test]
+fn test_disk_geometry_accessibility() {
+    let _info: fn(DeviceId) -> Result<DiskGeometry, DiskError> = disk_info;
+
+    let geometry = DiskGeometry::decode(&40u32.to_ne_bytes()).unwrap();
+    assert_eq!(geometry.platters, THREADS_DISK_MAX_PLATTERS);
+    assert_eq!(geometry.sectors_per_track, THREADS_DISK_SECTOR_COUNT);
+    assert_eq!(geometry.sector_size, THREADS_DISK_SECTOR_SIZE);
+
+    let last = geometry.address_of(geometry.total_sectors() - 1).unwrap();
+    assert_eq!(
+        last,
+        DiskAddress::new(THREADS_DISK_MAX_PLATTERS - 1, 39, THREADS_DISK_SECTOR_COUNT - 1)
+    );
+    assert_eq!(geometry.address_of(geometry.total_sectors()), Err(DiskError::InvalidAddress));
+}
+
+#[test]
+fn test_block_device_and_buffer_cache_accessibility() {
+    let device = MemBlockDevice::new(1024, 16).unwrap();
+    assert_eq!(MemBlockDevice::new(100, 1), Err(BlockError::InvalidBlockSize));
+
+    let mut cache = BufferCache::new(device, 4, WritePolicy::WriteBack);
+    assert_eq!(cache.block_size(), 1024);
+    assert_eq!(cache.block_count(), 16);
+    cache.write_block(1, &[5; 1024]).unwrap();
+    assert!(cache.is_dirty(1));
+
+    let mut block = [0; 1024];
+    cache.read_block(1, &mut block).unwrap();
+    assert_eq!(block, [5; 1024]);
+    assert_eq!(cache.stats().hits, 1);
+    assert_eq!(cache.into_inner().unwrap().writes(), 1);
+}
+
+#[test]
+fn test_file_system_accessibility() {
+    let device = MemBlockDevice::new(1024, 128).unwrap();
+    let mut fs = mkfs(device, MkfsOptions::default()).unwrap();
+    assert_eq!(fs.superblock().block_size, 1024);
+
+    fs.mkdir("/docs").unwrap();
+    let fd = fs.open("/docs/readme", OpenFlags::WRITE | OpenFlags::CREATE).unwrap();
+    assert_eq!(fs.write(fd, b"rusty threads"), Ok(13));
+    fs.close(fd).unwrap();
+
+    let mut fs = FileSystem::mount(fs.unmount().unwrap()).unwrap();
+    let entries = fs.read_dir("/docs").unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].kind, FileKind::File);
+    assert_eq!(fs.stat("/docs/readme").unwrap().size, 13);
+    assert_eq!(fs.unlink("/docs"), Err(FsError::IsADirectory));
+}
+
+#[test]
+fn test_journal_and_crash_injection_accessibility() {
+    let options = MkfsOptions { journal_blocks: 8, ..Default::default() };
+    let fs = mkfs(MemBlockDevice::new(1024, 128).unwrap(), options).unwrap();
+    assert!(fs.is_journaled());
+    let device = fs.unmount().unwrap();
+
+    let crashing = CrashingDevice::crash_after(device, 6, CrashAction::DropWrites);
+    let mut fs = FileSystem::mount(crashing).unwrap();
+    assert!(fs.mkdir("/a").is_err());
+    let device = fs.into_device();
+    assert!(device.has_crashed());
+
+    let mut fs = FileSystem::mount(device.into_inner()).unwrap();
+    assert!(fsck(&mut fs).unwrap().is_clean());
+}
+
+#[test]
+fn test_disk_image_and_repair_accessibility() {
+    let track = DiskGeometry::with_tracks(1).capacity();
+    assert_eq!(geometry_for_length(4 * track), Ok(DiskGeometry::with_tracks(4)));
+    assert!(hex_dump(b"abc", 0).starts_with("00000000  61 62 63"));
+
+    let path = std::env::temp_dir().join(format!("rusty_threads_{}.img", std::process::id()));
+    let image = DiskImage::create(&path, DiskGeometry::with_tracks(8), 1024).unwrap();
+    assert_eq!(image.block_count(), 8 * 3 * 16 / 2);
+    let mut fs = mkfs(image, MkfsOptions::default()).unwrap();
+    fs.mkdir("/etc").unwrap();
+    fs.unmount().unwrap();
+
+    let mut image = DiskImage::open(&path, 512).unwrap();
+    let mut sector = [0; 512];
+    image.read_sector(DiskAddress::new(0, 0, 0), &mut sector).unwrap();
+    assert_eq!(Superblock::probe(&sector), Ok(1024));
+
+    let mut fs = FileSystem::mount(DiskImage::open(&path, 1024).unwrap()).unwrap();
+    assert!(fsck(&mut fs).unwrap().is_clean());
+    assert!(repair(&mut fs).unwrap().is_clean());
+    drop(fs);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_partition_accessibility() {
+    let mut disk = MemBlockDevice::new(512, 512).unwrap();
+    let mut table = PartitionTable::new();
+    table.add("root", 1, 255).unwrap();
+    table.add("swap", 256, 256).unwrap();
+    table.write(&mut disk).unwrap();
+    assert_eq!(PartitionTable::read(&mut disk), Ok(table.clone()));
+    assert!(table.entries().len() <= MAX_PARTITIONS);
+
+    let swap = PartitionDevice::open(disk, table.find("swap").unwrap()).unwrap();
+    assert_eq!(swap.block_count(), 256);
+    let mut fs = mkfs(swap, MkfsOptions::default()).unwrap();
+    fs.mkdir("/pages").unwrap();
+    let disk = fs.unmount().unwrap().into_inner();
+    let past_end = PartitionEntry { name: "bad".to_string(), start: 500, sectors: 20 };
+    let error = PartitionDevice::open(disk, &past_end).err();
+    assert_eq!(error, Some(PartitionError::OutOfRange));
+}
+
+#[test]
+fn test_raid_accessibility() {
+    let disks = (0..3).map(|_| MemBlockDevice::new(512, 16).unwrap()).collect();
+    let mut raid = Raid::new(RaidLevel::Raid5, disks).unwrap();
+    assert_eq!(raid.block_count(), 32);
+    raid.write_block(9, &[3; 512]).unwrap();
+    raid.fail_disk(2).unwrap();
+    assert!(raid.is_degraded());
+
+    let mut block = [0; 512];
+    raid.read_block(9, &mut block).unwrap();
+    assert_eq!(block, [3; 512]);
+    raid.replace_disk(2, MemBlockDevice::new(512, 16).unwrap()).unwrap();
+    assert_eq!(raid.rebuild_step(u64::MAX), Ok(true));
+    assert_eq!(raid.disk_state(2), Some(MemberState::Online));
+}
+
+#[test]
+fn test_disk_fault_accessibility() {
+    let faults = DiskFaults {
+        bad_sectors: vec![DiskAddress::new(0, 1, 2)],
+        failure_rate: 0.05,
+        seed: 7,
+        ..Default::default()
+    };
+    assert_eq!(faults.command_timeout, DEFAULT_COMMAND_TIMEOUT);
+    assert_eq!(FaultStats::default().timeouts, 0);
+}
+
+#[test]
+fn test_cow_accessibility() {
+    let missing = CowImage::open("/nonexistent/base.img", "/nonexistent/run.cow", 512);
+    assert!(matches!(missing, Err(CowError::Block(BlockError::Io(_)))));
+    assert_eq!(CowError::from(BlockError::Crashed), CowError::Block(BlockError::Crashed));
+}
+
+#[test]
+fn test_terminal_accessibility() {
+    let settings = TerminalSettings::default();
+    assert_eq!((settings.mode, settings.echo), (TerminalMode::Canonical, true));
+    assert_eq!(write_terminal(DeviceId::disk(0), b"x"), Err(TerminalError::NotATerminal));
+    let raw = TerminalSettings { mode: TerminalMode::Raw, ..settings };
+    let error = set_terminal_settings(DeviceId::terminal(THREADS_MAX_TERMINALS), raw);
+    assert_eq!(error, Err(TerminalError::NotATerminal));
+}
+
+#[test]
+fn test_ansi_accessibility() {
+    let mut screen = Screen::new(2, 10);
+    let title = AnsiEvent::Graphics(vec![Sgr::Bold, Sgr::Foreground(Color::GREEN)]);
+    screen.feed(format!("{}{title}ok", AnsiEvent::CursorPosition { row: 1, column: 2 }).as_bytes());
+    assert_eq!(screen.row_text(1), "  ok");
+    assert!(screen.cell(1, 2).style.bold);
+
+    let mut decoder = KeyDecoder::new();
+    let keys: Vec<Key> = b"\x1b[B\x1b[6~".iter().filter_map(|&b| decoder.push(b)).collect();
+    assert_eq!(keys, [Key::Down, Key::PageDown]);
+    let mut parser = AnsiParser::new();
+    assert_eq!(parser.feed(b'\r'), Some(AnsiEvent::CarriageReturn));
+}
+
+#[test]
+fn test_console_view_accessibility() {
+    let mut view = ConsoleView::new(100, 30);
+    view.terminal_output(DeviceId::terminal(1), b"$ ");
+    view.console_output(false, "booted\n");
+    view.set_status(StatusLine { psr: 1, clock: 20, running: Some((1, "init".to_string())) });
+    assert_eq!(view.terminal_screen(1).unwrap().row_text(0), "$");
+    assert!(view.render().contains("running 1 init"));
+    assert_eq!(view.handle_input(CONSOLE_VIEW_PREFIX), ViewAction::Nothing);
+    assert_eq!(view.handle_input(b'1'), ViewAction::Nothing);
+    assert_eq!(view.focus(), Focus::Terminal(1));
+    assert_eq!(view.handle_input(b'a'), ViewAction::Forward(DeviceId::terminal(1), b'a'));
+}
+
+#[test]
+fn test_terminal_socket_accessibility() {
+    let no_terminals = serve_terminals(&[], TerminalEndpoint::Tcp(0));
+    assert_eq!(no_terminals.err(), Some(TerminalSocketError::NoTerminals));
+    let disk = serve_terminals(&[DeviceId::disk(0)], TerminalEndpoint::Tcp(0));
+    let error = TerminalSocketError::Terminal(TerminalError::NotATerminal);
+    assert_eq!(disk.err(), Some(error));
+
+    let server = serve_terminals(&[DeviceId::terminal(3)], TerminalEndpoint::Telnet(0)).unwrap();
+    let TerminalEndpoint::Telnet(port) = *server.endpoint() else { panic!("not telnet") };
+    assert!(port != 0);
+    assert_eq!(server.endpoint().to_string(), format!("telnet://127.0.0.1:{port}"));
+    server.shutdown();
+}
+
+struct NullDevice;
+
+impl Device for NullDevice {
+    fn control(&mut self, request: DeviceRequest<'_>, _now: u32) -> bool {
+        request.output.fill(0);
+        true
+    }
+
+    fn poll(&mut self, _now: u32) -> Option<(u8, u32)> {
+        None
+    }
+}
+
+#[test]
+fn test_custom_device_accessibility() {
+    let error = register_device("disk0", NullDevice).err();
+    assert_eq!(error, Some(CustomDeviceError::InvalidName));
+    let handler: DeviceInterruptHandler = |_device, _command, _status| {};
+    let error = set_device_interrupt_handler(DeviceId::terminal(0), handler);
+    assert_eq!(error, Err(CustomDeviceError::NotCustom));
+}
+
+#[test]
+fn test_nic_accessibility() {
+    let switch = VirtualSwitch::new();
+    let config = NicConfig { mtu: 256, ..NicConfig::new([2, 0, 0, 0, 0, 9]) };
+    let mut nic = Nic::new(&switch, config).unwrap();
+    let mut info = [0; NIC_INFO_SIZE];
+    let output = &mut info;
+    let request = DeviceRequest { command: NIC_INFO, control1: 0, control2: 0, input: &[], output };
+    assert!(nic.control(request, 0));
+    assert_eq!(info, [2, 0, 0, 0, 0, 9, 0, 1]);
+    assert_eq!(nic.poll(0), Some((NIC_INFO, 0)));
+    assert_eq!(switch.stats(), SwitchStats::default());
+    let config = NicConfig { mtu: 0, ..config };
+    assert_eq!(attach_nic("nic9", &switch, config), Err(NicError::InvalidMtu));
+}
+
+#[test]
+fn test_dma_accessibility() {
+    let mut disk = DmaDisk::new(MemBlockDevice::new(512, 8).unwrap(), DmaTiming::default());
+    let data = [7; 1024];
+    let slices = [std::io::IoSlice::new(&data)];
+    let buffers = DmaBuffers::ToDevice(&slices);
+    assert_eq!((buffers.len(), buffers.segments()), (1024, 1));
+    assert!(disk.dma(DmaRequest { command: DMA_DISK_WRITE, address: 6, buffers }, 0));
+    assert_eq!(disk.poll(1_100), Some((DMA_DISK_WRITE, DMA_STATUS_OK)));
+
+    let too_many = vec![std::io::IoSlice::new(&data); MAX_DMA_SEGMENTS + 1];
+    let buffers = DmaBuffers::ToDevice(&too_many);
+    let request = DmaRequest { command: DMA_DISK_WRITE, address: 0, buffers };
+    assert_eq!(dma_transfer(DeviceId::disk(0), request), Err(DmaError::TooLarge));
+    let buffers = DmaBuffers::ToDevice(&slices);
+    let request = DmaRequest { command: DMA_DISK_WRITE, address: 0, buffers };
+    assert_eq!(dma_transfer(DeviceId::disk(0), request), Err(DmaError::NotCustom));
+}
+
+#[test]
+fn test_hotplug_accessibility() {
+    let terminal = DeviceId::terminal(2);
+    assert_eq!(detach_device(DeviceId::clock()), Err(HotplugError::NotHotpluggable));
+    assert_eq!(attach_device(terminal), Err(HotplugError::Attached));
+    detach_device(terminal).unwrap();
+    assert!(!is_attached(terminal));
+    assert_eq!(detach_device(terminal), Err(HotplugError::Detached));
+    assert_eq!(device_handle("term2"), None);
+    assert_eq!(DeviceHandle::open(terminal), None);
+    attach_device(terminal).unwrap();
+    assert!(is_attached(terminal) && is_attached(DeviceId::disk(0)));
+}
+
+#[test]
+fn test_io_stats_accessibility() {
+    let mut times = ServiceTimes::new();
+    times.record(300);
+    times.record(700);
+    assert_eq!((times.count, times.mean(), times.percentile(99)), (2, Some(500), Some(700)));
+    assert_eq!(times.buckets[ServiceTimes::bucket(300)], 1);
+
+    let stats = DeviceStats::default();
+    assert_eq!((stats.requests(), stats.bytes(), stats.errors()), (0, 0, 0));
+    assert_eq!((stats.command(DISK_READ), stats.average_queue_depth()), (None, None));
+}